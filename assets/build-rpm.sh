@@ -118,6 +118,7 @@ install -Dm644 ./assets/kumo-tsa-daemon.service -t %{buildroot}/usr/lib/systemd/
 %files
 /opt/kumomta/sbin/kcli
 /opt/kumomta/sbin/kumod
+/opt/kumomta/sbin/log-parquet
 /opt/kumomta/sbin/proxy-server
 /opt/kumomta/sbin/resolve-site-name
 /opt/kumomta/sbin/resolve-queue-config
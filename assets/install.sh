@@ -18,6 +18,7 @@ install -Dsm755 ${CARGO_TARGET_DIR}/${TRIPLE}release/proxy-server -t ${PREFIX}/s
 install -Dm755  ${STRIP} ${CARGO_TARGET_DIR}/${TRIPLE}release/kumod -t ${PREFIX}/sbin
 install -Dsm755 ${CARGO_TARGET_DIR}/${TRIPLE}release/kcli -t ${PREFIX}/sbin
 install -Dsm755 ${CARGO_TARGET_DIR}/${TRIPLE}release/traffic-gen -t ${PREFIX}/sbin
+install -Dsm755 ${CARGO_TARGET_DIR}/${TRIPLE}release/log-parquet -t ${PREFIX}/sbin
 install -Dsm755 ${CARGO_TARGET_DIR}/${TRIPLE}release/tailer -t ${PREFIX}/sbin
 install -Dsm755 ${CARGO_TARGET_DIR}/${TRIPLE}release/toml2jsonc -t ${PREFIX}/sbin
 install -Dsm755 ${CARGO_TARGET_DIR}/${TRIPLE}release/tls-probe -t ${PREFIX}/sbin
@@ -64,7 +64,11 @@ end
 local UNINTERESTING_LOG_RECORD_TYPES = {
   AdminRebind = true,
   DeferredInjectionRebind = true,
+  DelayWarning = true,
   Delayed = true,
+  Quarantine = true,
+  QuarantineDelete = true,
+  QuarantineRelease = true,
   Reception = true,
   Rejection = true,
   XferIn = true,
@@ -103,7 +107,23 @@ local function should_enq(publish, msg, hook_name, options)
     -- for the records that don't match
     local status, result = pcall(function()
       local shaping = mod.CONFIGURED.load_shaping_data()
-      return #shaping:match_rules(log_record) > 0
+      if #shaping:match_rules(log_record) > 0 then
+        return true
+      end
+      -- Deliveries and transient failures feed the deferral ratio
+      -- that is used to slow down warm-up schedules and to drive
+      -- AdaptiveRate actions
+      if
+        log_record.type == 'Delivery'
+        or log_record.type == 'TransientFailure'
+      then
+        if #shaping:match_adaptive_rules(log_record) > 0 then
+          return true
+        end
+        local warmup = shaping:get_warmup_schedule(log_record)
+        return warmup ~= nil and warmup.max_deferral_ratio ~= nil
+      end
+      return false
     end)
     if not status then
       return false
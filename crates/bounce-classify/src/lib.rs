@@ -1,7 +1,9 @@
 use ordermap::OrderMap;
-use regex::{RegexSet, RegexSetBuilder};
+use regex::{Regex, RegexSet, RegexSetBuilder};
+use rfc5321::EnhancedStatusCode;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+use std::sync::Arc;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Ord, PartialOrd)]
 #[serde(from = "String", into = "String")]
@@ -120,13 +122,183 @@ pub enum PreDefinedBounceClass {
 /// Defines the content of bounce classifier rules files
 #[derive(Deserialize, Serialize, Debug)]
 pub struct BounceClassifierFile {
+    /// Maps a classification to a list of regular expressions
+    /// that are matched against the single line form of the response
+    #[serde(default)]
     pub rules: OrderMap<BounceClass, Vec<String>>,
+
+    /// When set, all of the rules in this file apply only to responses
+    /// from the named provider, and take precedence over the rules
+    /// from files that do not specify a provider.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+
+    /// Rules that can match on several properties of a response
+    /// at once.  These take precedence over the regex based `rules`.
+    #[serde(default, rename = "match", skip_serializing_if = "Vec::is_empty")]
+    pub match_rules: Vec<BounceClassifierRule>,
+}
+
+/// A rule that classifies a response based on a combination of its
+/// SMTP status code, enhanced status code and text, and the site
+/// and provider from which the response was received.
+/// All of the conditions that are specified must match.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BounceClassifierRule {
+    /// The classification to assign when the rule matches
+    pub class: BounceClass,
+    /// An optional name used to identify the rule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Match the SMTP status code, eg: `"550"` or `"55x"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<CodePattern>,
+    /// Match the enhanced status code, eg: `"5.7.*"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enhanced_code: Option<EnhancedCodePattern>,
+    /// A regex that is matched against the single line form
+    /// of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    /// A regex that is matched against the site name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site: Option<String>,
+    /// The provider name that must match
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>,
+}
+
+/// Matches an SMTP status code. Each of the digits may be
+/// replaced by `x` or `*` in order to match any digit.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "CodePatternValue", into = "String")]
+pub struct CodePattern([Option<u8>; 3]);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CodePatternValue {
+    Number(u16),
+    Text(String),
+}
+
+impl TryFrom<CodePatternValue> for CodePattern {
+    type Error = String;
+    fn try_from(value: CodePatternValue) -> Result<Self, String> {
+        match value {
+            CodePatternValue::Number(n) => n.to_string().parse(),
+            CodePatternValue::Text(s) => s.parse(),
+        }
+    }
+}
+
+impl FromStr for CodePattern {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut digits = [None; 3];
+        let mut chars = s.chars();
+        for digit in digits.iter_mut() {
+            *digit = match chars.next() {
+                Some('x' | 'X' | '*') => None,
+                Some(c @ '0'..='9') => Some(c as u8 - b'0'),
+                _ => return Err(format!("invalid SMTP code pattern {s:?}")),
+            };
+        }
+        if chars.next().is_some() {
+            return Err(format!("invalid SMTP code pattern {s:?}"));
+        }
+        Ok(Self(digits))
+    }
+}
+
+impl From<CodePattern> for String {
+    fn from(pattern: CodePattern) -> String {
+        pattern
+            .0
+            .iter()
+            .map(|d| match d {
+                Some(d) => char::from(b'0' + d),
+                None => 'x',
+            })
+            .collect()
+    }
+}
+
+impl CodePattern {
+    pub fn matches(&self, code: u16) -> bool {
+        let digits = [(code / 100) % 10, (code / 10) % 10, code % 10];
+        code < 1000
+            && self
+                .0
+                .iter()
+                .zip(digits)
+                .all(|(pattern, digit)| pattern.map(|p| p as u16 == digit).unwrap_or(true))
+    }
+}
+
+/// Matches an enhanced status code such as `5.7.1`. Each of the
+/// components may be replaced by `*` in order to match any value.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub struct EnhancedCodePattern([Option<u16>; 3]);
+
+impl TryFrom<String> for EnhancedCodePattern {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, String> {
+        s.parse()
+    }
+}
+
+impl FromStr for EnhancedCodePattern {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut components = [None; 3];
+        let mut fields = s.split('.');
+        for component in components.iter_mut() {
+            *component = match fields.next() {
+                Some("*" | "x" | "X") => None,
+                Some(n) => Some(
+                    n.parse::<u16>()
+                        .map_err(|_| format!("invalid enhanced status code pattern {s:?}"))?,
+                ),
+                None => return Err(format!("invalid enhanced status code pattern {s:?}")),
+            };
+        }
+        if fields.next().is_some() {
+            return Err(format!("invalid enhanced status code pattern {s:?}"));
+        }
+        Ok(Self(components))
+    }
+}
+
+impl From<EnhancedCodePattern> for String {
+    fn from(pattern: EnhancedCodePattern) -> String {
+        let components: Vec<String> = pattern
+            .0
+            .iter()
+            .map(|c| match c {
+                Some(c) => c.to_string(),
+                None => "*".to_string(),
+            })
+            .collect();
+        components.join(".")
+    }
+}
+
+impl EnhancedCodePattern {
+    pub fn matches(&self, code: &EnhancedStatusCode) -> bool {
+        let [class, subject, detail] = self.0;
+        class.map(|c| c == code.class as u16).unwrap_or(true)
+            && subject.map(|s| s == code.subject).unwrap_or(true)
+            && detail.map(|d| d == code.detail).unwrap_or(true)
+    }
 }
 
 /// Holds state for compiling rules files into a classifier
 #[derive(Default)]
 pub struct BounceClassifierBuilder {
-    rules: Vec<(BounceClass, String)>,
+    rules: Vec<(BounceClass, String, Arc<str>)>,
+    match_rules: Vec<(BounceClassifierRule, Arc<str>)>,
 }
 
 impl BounceClassifierBuilder {
@@ -135,15 +307,59 @@ impl BounceClassifierBuilder {
     }
 
     pub fn add_rule(&mut self, class: BounceClass, rule: String) {
-        self.rules.push((class, rule));
+        let id = format!("{}: {rule}", String::from(class.clone()));
+        self.rules.push((class, rule, id.into()));
+    }
+
+    pub fn add_match_rule(&mut self, rule: BounceClassifierRule, id: impl Into<Arc<str>>) {
+        self.match_rules.push((rule, id.into()));
     }
 
     pub fn merge(&mut self, decoded_file: BounceClassifierFile) {
+        self.merge_with_source(decoded_file, None);
+    }
+
+    /// Merge the rules from decoded_file.  source, if provided,
+    /// is used to identify the origin of the rules
+    pub fn merge_with_source(&mut self, decoded_file: BounceClassifierFile, source: Option<&str>) {
+        let make_id = |label: String| -> Arc<str> {
+            match source {
+                Some(source) => format!("{source}: {label}").into(),
+                None => label.into(),
+            }
+        };
+
         for (class, rules) in decoded_file.rules {
             for rule in rules {
-                self.add_rule(class.clone(), rule);
+                let id = make_id(format!("{}: {rule}", String::from(class.clone())));
+                match &decoded_file.provider {
+                    // Rules in a provider specific file are scoped to
+                    // that provider, so they are evaluated along with
+                    // the match rules
+                    Some(provider) => self.match_rules.push((
+                        BounceClassifierRule {
+                            class: class.clone(),
+                            name: None,
+                            code: None,
+                            enhanced_code: None,
+                            response: Some(rule),
+                            site: None,
+                            provider: Some(provider.clone()),
+                        },
+                        id,
+                    )),
+                    None => self.rules.push((class.clone(), rule, id)),
+                }
             }
         }
+
+        for (idx, mut rule) in decoded_file.match_rules.into_iter().enumerate() {
+            let id = make_id(rule.name.clone().unwrap_or_else(|| format!("match[{idx}]")));
+            if rule.provider.is_none() {
+                rule.provider = decoded_file.provider.clone();
+            }
+            self.match_rules.push((rule, id));
+        }
     }
 
     pub fn merge_json_file(&mut self, file_name: &str) -> Result<(), String> {
@@ -151,7 +367,7 @@ impl BounceClassifierBuilder {
             .map_err(|err| format!("reading file: {file_name}: {err:#}"))?;
         let decoded: BounceClassifierFile = serde_json::from_reader(&mut f)
             .map_err(|err| format!("decoding {file_name} as BounceClassifierFile: {err:#}"))?;
-        self.merge(decoded);
+        self.merge_with_source(decoded, Some(file_name));
         Ok(())
     }
 
@@ -160,14 +376,14 @@ impl BounceClassifierBuilder {
             .map_err(|err| format!("reading file: {file_name}: {err:#}"))?;
         let decoded: BounceClassifierFile = toml::from_str(&data)
             .map_err(|err| format!("decoding {file_name} as BounceClassifierFile: {err:#}"))?;
-        self.merge(decoded);
+        self.merge_with_source(decoded, Some(file_name));
         Ok(())
     }
 
     pub fn build(self) -> Result<BounceClassifier, String> {
         let mut pattern_to_class = vec![];
         let mut patterns = vec![];
-        for (class, rule) in self.rules {
+        for (class, rule, id) in self.rules {
             // Build a simple implicit reverse map from pattern
             // index to the bounce classification. This gives
             // an O(1) mapping from the regex result at the
@@ -175,7 +391,7 @@ impl BounceClassifierBuilder {
             // this could be changed to a structure that tracks
             // start/end ranges of pattern indices and uses a
             // binary search.
-            pattern_to_class.push(class.clone());
+            pattern_to_class.push((class, id));
             patterns.push(rule);
         }
 
@@ -184,34 +400,189 @@ impl BounceClassifierBuilder {
         let set = RegexSetBuilder::new(patterns)
             .build()
             .map_err(|err| format!("compiling rules: {err:#}"))?;
+
+        // Provider specific rules take precedence over the others
+        let mut provider_rules = vec![];
+        let mut general_rules = vec![];
+        for (rule, id) in self.match_rules {
+            let compiled = CompiledRule::compile(rule, id)?;
+            if compiled.provider.is_some() {
+                provider_rules.push(compiled);
+            } else {
+                general_rules.push(compiled);
+            }
+        }
+        let mut match_rules = provider_rules;
+        match_rules.append(&mut general_rules);
+        match_rules.shrink_to_fit();
+
+        let uses_site = match_rules.iter().any(|rule| rule.site.is_some());
+        let uses_provider = match_rules.iter().any(|rule| rule.provider.is_some());
+
         Ok(BounceClassifier {
             set,
             pattern_to_class,
+            match_rules,
+            uses_site,
+            uses_provider,
         })
     }
 }
 
+struct CompiledRule {
+    id: Arc<str>,
+    class: BounceClass,
+    code: Option<CodePattern>,
+    enhanced_code: Option<EnhancedCodePattern>,
+    response: Option<Regex>,
+    site: Option<Regex>,
+    provider: Option<String>,
+}
+
+impl CompiledRule {
+    fn compile(rule: BounceClassifierRule, id: Arc<str>) -> Result<Self, String> {
+        if rule.code.is_none()
+            && rule.enhanced_code.is_none()
+            && rule.response.is_none()
+            && rule.site.is_none()
+            && rule.provider.is_none()
+        {
+            return Err(format!("{id}: rule must specify at least one condition"));
+        }
+
+        let compile = |pattern: Option<String>| -> Result<Option<Regex>, String> {
+            pattern
+                .map(|p| Regex::new(&p).map_err(|err| format!("{id}: compiling {p}: {err:#}")))
+                .transpose()
+        };
+
+        Ok(Self {
+            class: rule.class,
+            code: rule.code,
+            enhanced_code: rule.enhanced_code,
+            response: compile(rule.response)?,
+            site: compile(rule.site)?,
+            provider: rule.provider,
+            id,
+        })
+    }
+
+    fn matches(
+        &self,
+        response: &rfc5321::Response,
+        line: &str,
+        site: &str,
+        provider: Option<&str>,
+    ) -> bool {
+        if let Some(code) = &self.code {
+            if !code.matches(response.code) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.enhanced_code {
+            match &response.enhanced_code {
+                Some(enhanced) if pattern.matches(enhanced) => {}
+                _ => return false,
+            }
+        }
+        if let Some(expected) = &self.provider {
+            if provider != Some(expected.as_str()) {
+                return false;
+            }
+        }
+        if let Some(re) = &self.site {
+            if !re.is_match(site) {
+                return false;
+            }
+        }
+        if let Some(re) = &self.response {
+            if !re.is_match(line) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The result of classifying a response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    pub class: BounceClass,
+    /// Identifies the rule that matched, if any
+    pub rule: Option<Arc<str>>,
+}
+
 pub struct BounceClassifier {
     set: RegexSet,
-    pattern_to_class: Vec<BounceClass>,
+    pattern_to_class: Vec<(BounceClass, Arc<str>)>,
+    match_rules: Vec<CompiledRule>,
+    uses_site: bool,
+    uses_provider: bool,
 }
 
 impl BounceClassifier {
-    pub fn classify_str(&self, s: &str) -> BounceClass {
-        self.set
-            .matches(s)
+    fn classify_line(&self, line: &str) -> Classification {
+        match self
+            .set
+            .matches(line)
             .into_iter()
             .next()
             .and_then(|idx| self.pattern_to_class.get(idx))
-            .cloned()
-            .unwrap_or(BounceClass::PreDefined(
-                PreDefinedBounceClass::Uncategorized,
-            ))
+        {
+            Some((class, id)) => Classification {
+                class: class.clone(),
+                rule: Some(id.clone()),
+            },
+            None => Classification {
+                class: BounceClass::PreDefined(PreDefinedBounceClass::Uncategorized),
+                rule: None,
+            },
+        }
+    }
+
+    pub fn classify_str(&self, s: &str) -> BounceClass {
+        self.classify_line(s).class
     }
 
+    /// Classify a response without regard to the site or provider
+    /// from which it was received.  Rules that are restricted to a
+    /// site or provider, including all of the rules from a provider
+    /// specific file, will not match.  Use `classify` when the site
+    /// and provider are known.
     pub fn classify_response(&self, response: &rfc5321::Response) -> BounceClass {
+        self.classify(response, "", None).class
+    }
+
+    /// Classify a response that was received from the specified
+    /// site and provider.  Provider specific rules are considered
+    /// first, then the other match rules, and finally the regex
+    /// based rules.
+    pub fn classify(
+        &self,
+        response: &rfc5321::Response,
+        site: &str,
+        provider: Option<&str>,
+    ) -> Classification {
         let line = response.to_single_line();
-        self.classify_str(&line)
+        for rule in &self.match_rules {
+            if rule.matches(response, &line, site, provider) {
+                return Classification {
+                    class: rule.class.clone(),
+                    rule: Some(rule.id.clone()),
+                };
+            }
+        }
+        self.classify_line(&line)
+    }
+
+    /// Returns true if any rule considers the site name
+    pub fn uses_site(&self) -> bool {
+        self.uses_site
+    }
+
+    /// Returns true if any rule considers the provider name
+    pub fn uses_provider(&self) -> bool {
+        self.uses_provider
     }
 }
 
@@ -263,6 +634,146 @@ second_file = ["bbb", "ccc"]
         );
     }
 
+    #[test]
+    fn test_code_patterns() {
+        let p: CodePattern = "55x".parse().unwrap();
+        assert!(p.matches(550));
+        assert!(p.matches(554));
+        assert!(!p.matches(450));
+        assert_eq!(String::from(p), "55x");
+        assert!("5xxx".parse::<CodePattern>().is_err());
+        assert!("5a0".parse::<CodePattern>().is_err());
+
+        let p: EnhancedCodePattern = "5.7.*".parse().unwrap();
+        let code = |class, subject, detail| EnhancedStatusCode {
+            class,
+            subject,
+            detail,
+        };
+        assert!(p.matches(&code(5, 7, 1)));
+        assert!(p.matches(&code(5, 7, 26)));
+        assert!(!p.matches(&code(4, 7, 1)));
+        assert_eq!(String::from(p), "5.7.*");
+        assert!("5.7".parse::<EnhancedCodePattern>().is_err());
+    }
+
+    #[test]
+    fn test_match_rules() {
+        let defaults: BounceClassifierFile = toml::from_str(
+            r#"
+[rules]
+PolicyRelated = ["^5\\d{2} 5\\.7\\."]
+
+[[match]]
+class = "SpamBlock"
+name = "spamhaus"
+code = 550
+enhanced_code = "5.7.*"
+response = "(?i)spamhaus"
+"#,
+        )
+        .unwrap();
+        let provider: BounceClassifierFile = toml::from_str(
+            r#"
+provider = "yahoo"
+
+[rules]
+SpamRelated = ["\\[TS0[1-3]\\]"]
+
+[[match]]
+class = "InvalidRecipient"
+enhanced_code = "5.7.*"
+site = "yahoodns"
+"#,
+        )
+        .unwrap();
+
+        let mut builder = BounceClassifierBuilder::new();
+        builder.merge_with_source(defaults, Some("defaults.toml"));
+        builder.merge_with_source(provider, Some("yahoo.toml"));
+        let classifier = builder.build().unwrap();
+        assert!(classifier.uses_site());
+        assert!(classifier.uses_provider());
+
+        let response = |code, content: &str| rfc5321::Response {
+            code,
+            enhanced_code: Some(EnhancedStatusCode {
+                class: (code / 100) as u8,
+                subject: 7,
+                detail: 1,
+            }),
+            content: content.to_string(),
+            command: None,
+        };
+
+        let c = classifier.classify(&response(550, "listed by Spamhaus"), "mx.example.com", None);
+        assert_eq!(c.class, PreDefinedBounceClass::SpamBlock.into());
+        assert_eq!(c.rule.as_deref(), Some("defaults.toml: spamhaus"));
+
+        let c = classifier.classify(&response(554, "blocked"), "mx.example.com", None);
+        assert_eq!(c.class, PreDefinedBounceClass::PolicyRelated.into());
+        assert_eq!(
+            c.rule.as_deref(),
+            Some("defaults.toml: PolicyRelated: ^5\\d{2} 5\\.7\\.")
+        );
+
+        // The provider specific rules take precedence, but only
+        // for that provider
+        let c = classifier.classify(
+            &response(554, "[TS01] listed by Spamhaus"),
+            "mta5.am0.yahoodns.net",
+            Some("yahoo"),
+        );
+        assert_eq!(c.class, PreDefinedBounceClass::SpamRelated.into());
+        let c = classifier.classify(
+            &response(554, "go away"),
+            "mta5.am0.yahoodns.net",
+            Some("yahoo"),
+        );
+        assert_eq!(c.class, PreDefinedBounceClass::InvalidRecipient.into());
+        assert_eq!(c.rule.as_deref(), Some("yahoo.toml: match[0]"));
+        let c = classifier.classify(
+            &response(554, "go away"),
+            "mta5.am0.yahoodns.net",
+            Some("other"),
+        );
+        assert_eq!(c.class, PreDefinedBounceClass::PolicyRelated.into());
+
+        // Without a site or provider, the rules that depend
+        // on them cannot match
+        assert_eq!(
+            classifier.classify_response(&response(554, "[TS01] listed by Spamhaus")),
+            PreDefinedBounceClass::PolicyRelated.into()
+        );
+        assert_eq!(
+            classifier.classify_response(&response(550, "listed by Spamhaus")),
+            PreDefinedBounceClass::SpamBlock.into()
+        );
+
+        let c = classifier.classify(&response(421, "try later"), "mx.example.com", None);
+        assert_eq!(
+            c,
+            Classification {
+                class: PreDefinedBounceClass::Uncategorized.into(),
+                rule: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_match_rule_requires_condition() {
+        let file: BounceClassifierFile = toml::from_str(
+            r#"
+[[match]]
+class = "SpamBlock"
+"#,
+        )
+        .unwrap();
+        let mut builder = BounceClassifierBuilder::new();
+        builder.merge(file);
+        assert!(builder.build().is_err());
+    }
+
     #[test]
     fn test_bounce_classify_iana() {
         let mut builder = BounceClassifierBuilder::new();
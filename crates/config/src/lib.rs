@@ -165,8 +165,20 @@ pub async fn load_config() -> anyhow::Result<LuaConfig> {
             chunk.into_function()?
         };
 
-        let _timer = latency_timer("context-creation");
-        func.call_async::<()>(()).await?;
+        // The histogram is resolved after running the policy, rather than
+        // via latency_timer, so that kumo.set_histogram_buckets can be
+        // used at the top level to configure lua_event_latency
+        EVENT_STARTED_COUNT
+            .get_metric_with_label_values(&["context-creation"])
+            .expect("to get counter")
+            .inc();
+        let start = Instant::now();
+        let result = func.call_async::<()>(()).await;
+        LATENCY_HIST
+            .get_metric_with_label_values(&["context-creation"])
+            .expect("to get histo")
+            .observe(start.elapsed().as_secs_f64());
+        result?;
     }
     LUA_COUNT.inc();
 
@@ -99,6 +99,16 @@ impl<'de> Deserialize<'de> for Wrap<Option<Duration>> {
     }
 }
 
+impl<'de> Deserialize<'de> for Wrap<Vec<Duration>> {
+    fn deserialize<D>(d: D) -> Result<Wrap<Vec<Duration>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let list = Vec::<Wrap<Duration>>::deserialize(d)?;
+        Ok(Wrap(list.into_iter().map(|Wrap(dur)| dur).collect()))
+    }
+}
+
 impl Serialize for Wrap<&Duration> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -142,6 +152,15 @@ impl Serialize for Wrap<Option<Duration>> {
     }
 }
 
+impl Serialize for Wrap<&Vec<Duration>> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.iter().map(Wrap))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -186,4 +205,26 @@ mod test {
         let foo = serde_json::from_str::<Foo>(json).unwrap();
         assert_eq!(foo.time, Duration::from_secs(15));
     }
+
+    #[test]
+    fn list() {
+        #[derive(Serialize, Deserialize)]
+        struct Foo {
+            #[serde(with = "super")]
+            times: Vec<Duration>,
+        }
+
+        let json = r#"{"times": ["5 minutes", 600, "1h"]}"#;
+        let foo = serde_json::from_str::<Foo>(json).unwrap();
+        assert_eq!(
+            foo.times,
+            vec![
+                Duration::from_secs(300),
+                Duration::from_secs(600),
+                Duration::from_secs(3600)
+            ]
+        );
+        let reverse = serde_json::to_string(&foo).unwrap();
+        assert_eq!(reverse, r#"{"times":["5m","10m","1h"]}"#);
+    }
 }
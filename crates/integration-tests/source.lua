@@ -64,6 +64,23 @@ kumo.on('init', function()
     }
   end
 
+  if os.getenv 'KUMOD_SUPPRESSION' then
+    kumo.configure_suppression {
+      path = TEST_DIR .. '/suppression.db',
+      -- There is no bounce classifier in the test environment
+      bounce_classes = { 'Uncategorized' },
+    }
+  end
+
+  if os.getenv 'KUMOD_LIST_UNSUBSCRIBE' then
+    kumo.configure_list_unsubscribe {
+      url = 'https://mta.example.com/unsubscribe/v1',
+      mailto = 'unsubscribe@example.com',
+      key = { key_data = 'secret' },
+      suppress = true,
+    }
+  end
+
   kumo.define_spool {
     name = 'data',
     path = TEST_DIR .. '/data-spool',
@@ -263,6 +280,10 @@ kumo.on('smtp_server_message_received', function(msg)
   -- This tenant header import is used by xfer.rs as a way to set
   -- the tenant metadata for the incoming message
   msg:import_x_headers { 'tenant' }
+
+  if os.getenv 'KUMOD_LIST_UNSUBSCRIBE' then
+    kumo.list_unsubscribe.add_headers(msg)
+  end
 end)
 
 kumo.on('get_queue_config', function(domain, tenant, campaign, routing_domain)
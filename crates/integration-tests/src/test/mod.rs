@@ -55,11 +55,13 @@ mod source_selection_rate;
 mod source_selection_rate_pool;
 mod spf_basic;
 mod spool_write_stopped;
+mod suppression;
 mod suspend_delivery_ready_q;
 mod suspend_delivery_ready_q_and_deliver;
 mod suspend_delivery_scheduled_q;
 mod suspend_delivery_scheduled_q_and_deliver;
 mod temp_fail;
+mod tenant_accounting;
 mod tls_client_certificate;
 mod tls_info_log;
 mod tls_opportunistic_fail;
@@ -72,5 +74,6 @@ mod tsa_campaign_suspension;
 mod tsa_skip_record_types;
 mod tsa_tenant_suspension;
 mod tsa_tenant_suspension_issue290;
+mod unsubscribe;
 mod xclient;
 mod xfer;
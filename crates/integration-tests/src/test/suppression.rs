@@ -0,0 +1,62 @@
+use crate::kumod::{DaemonWithMaildir, MailGenParams};
+use kumo_api_types::suppression::{SuppressionSource, SuppressionV1Entry};
+use kumo_log_types::RecordType::Bounce;
+use rfc5321::ClientError;
+use std::time::Duration;
+
+/// Verify that a hard bounce adds the recipient to the suppression
+/// list, that subsequent mail for that recipient is rejected at
+/// RCPT TO, and that the entry can be managed via kcli
+#[tokio::test]
+async fn suppression() -> anyhow::Result<()> {
+    let mut daemon = DaemonWithMaildir::start_with_env(vec![("KUMOD_SUPPRESSION", "1")]).await?;
+    let mut client = daemon.smtp_client().await?;
+
+    let response = MailGenParams {
+        recip: Some("permfail@example.com"),
+        ..Default::default()
+    }
+    .send(&mut client)
+    .await?;
+    anyhow::ensure!(response.code == 250);
+
+    daemon
+        .wait_for_source_summary(
+            |summary| summary.get(&Bounce).copied().unwrap_or(0) > 0,
+            Duration::from_secs(50),
+        )
+        .await;
+
+    let entries: Vec<SuppressionV1Entry> = daemon.kcli_json(["suppression-list", "--json"]).await?;
+    k9::assert_equal!(entries.len(), 1);
+    k9::assert_equal!(
+        entries[0].recipient.as_deref(),
+        Some("permfail@example.com")
+    );
+    k9::assert_equal!(entries[0].source, SuppressionSource::Bounce);
+
+    let failed_send = MailGenParams {
+        recip: Some("permfail@example.com"),
+        ..Default::default()
+    }
+    .send(&mut client)
+    .await
+    .unwrap_err();
+    let ClientError::Rejected(response) = failed_send.downcast_ref::<ClientError>().unwrap() else {
+        panic!("expected ClientError::Rejected, got {failed_send:#?}");
+    };
+    k9::assert_equal!(response.code, 550);
+    anyhow::ensure!(
+        response.content.contains("recipient is suppressed"),
+        "{response:?}"
+    );
+
+    daemon
+        .kcli(["suppression-delete", "--recipient", "permfail@example.com"])
+        .await?;
+    let entries: Vec<SuppressionV1Entry> = daemon.kcli_json(["suppression-list", "--json"]).await?;
+    k9::assert_equal!(entries.len(), 0);
+
+    daemon.stop_both().await?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use crate::kumod::{DaemonWithMaildir, MailGenParams};
+use kumo_api_types::accounting::AccountingV1Entry;
+use kumo_log_types::RecordType::Delivery;
+use std::time::Duration;
+
+/// Verify that receptions and deliveries are accounted against
+/// the tenant of the message, and that the hourly rollups can
+/// be retrieved via kcli
+#[tokio::test]
+async fn tenant_accounting() -> anyhow::Result<()> {
+    let mut daemon = DaemonWithMaildir::start().await?;
+
+    let mut client = daemon.smtp_client().await?;
+
+    let body = "X-Tenant: mytenant\r\n\r\nFoo";
+    for _ in 0..2 {
+        let response = MailGenParams {
+            full_content: Some(body),
+            ..Default::default()
+        }
+        .send(&mut client)
+        .await?;
+        anyhow::ensure!(response.code == 250);
+    }
+
+    daemon
+        .wait_for_source_summary(
+            |summary| summary.get(&Delivery).copied().unwrap_or(0) == 2,
+            Duration::from_secs(10),
+        )
+        .await;
+
+    let entries: Vec<AccountingV1Entry> = daemon
+        .kcli_json(["accounting", "--tenant", "mytenant", "--format", "json"])
+        .await?;
+
+    daemon.stop_both().await?;
+
+    let received: u64 = entries.iter().map(|e| e.received).sum();
+    let delivered: u64 = entries.iter().map(|e| e.delivered).sum();
+    let received_bytes: u64 = entries.iter().map(|e| e.received_bytes).sum();
+    anyhow::ensure!(received == 2, "received {received}: {entries:#?}");
+    anyhow::ensure!(delivered == 2, "delivered {delivered}: {entries:#?}");
+    anyhow::ensure!(received_bytes > 0, "received_bytes: {entries:#?}");
+    for entry in &entries {
+        anyhow::ensure!(entry.tenant.as_deref() == Some("mytenant"));
+        anyhow::ensure!(entry.campaign.is_none());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use crate::kumod::{DaemonWithMaildir, MailGenParams};
+use kumo_api_types::suppression::{SuppressionSource, SuppressionV1Entry};
+use kumo_log_types::RecordType;
+use std::time::Duration;
+
+/// Verify that List-Unsubscribe headers are added to the message,
+/// and that a one-click POST of the token logs an Unsubscribe
+/// record and suppresses the recipient
+#[tokio::test]
+async fn unsubscribe() -> anyhow::Result<()> {
+    let mut daemon = DaemonWithMaildir::start_with_env(vec![
+        ("KUMOD_LIST_UNSUBSCRIBE", "1"),
+        ("KUMOD_SUPPRESSION", "1"),
+    ])
+    .await?;
+    let mut client = daemon.smtp_client().await?;
+
+    let response = MailGenParams {
+        recip: Some("user@example.com"),
+        ..Default::default()
+    }
+    .send(&mut client)
+    .await?;
+    anyhow::ensure!(response.code == 250);
+
+    daemon
+        .wait_for_maildir_count(1, Duration::from_secs(50))
+        .await;
+
+    let mut messages = daemon.extract_maildir_messages()?;
+    k9::assert_equal!(messages.len(), 1);
+    let parsed = messages[0].parsed()?;
+    let headers = parsed.headers();
+
+    let post = headers
+        .get_first("List-Unsubscribe-Post")
+        .expect("List-Unsubscribe-Post")
+        .as_unstructured()?;
+    k9::assert_equal!(post.to_string(), "List-Unsubscribe=One-Click");
+
+    let list_unsubscribe = headers
+        .get_first("List-Unsubscribe")
+        .expect("List-Unsubscribe")
+        .as_unstructured()?
+        .to_string();
+    println!("List-Unsubscribe: {list_unsubscribe}");
+    let (_, token) = list_unsubscribe
+        .split_once("https://mta.example.com/unsubscribe/v1?token=")
+        .expect("https uri");
+    let (token, _) = token.split_once('>').expect("uri terminator");
+    anyhow::ensure!(
+        list_unsubscribe.contains(&format!(
+            "<mailto:unsubscribe@example.com?subject=unsubscribe:{token}>"
+        )),
+        "{list_unsubscribe}"
+    );
+
+    let endpoint = format!("http://{}/unsubscribe/v1", daemon.source.listener("http"));
+    let http = reqwest::Client::new();
+
+    // A forged token is rejected
+    let response = http
+        .post(&endpoint)
+        .query(&[("token", format!("{token}x"))])
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await?;
+    k9::assert_equal!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let response = http
+        .post(&endpoint)
+        .query(&[("token", token)])
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await?;
+    k9::assert_equal!(response.status(), reqwest::StatusCode::OK);
+
+    let entries: Vec<SuppressionV1Entry> = daemon.kcli_json(["suppression-list", "--json"]).await?;
+    k9::assert_equal!(entries.len(), 1);
+    k9::assert_equal!(entries[0].recipient.as_deref(), Some("user@example.com"));
+    k9::assert_equal!(entries[0].source, SuppressionSource::Unsubscribe);
+
+    daemon.stop_both().await?;
+
+    let logs = daemon.source.collect_logs().await?;
+    let unsubscribes: Vec<_> = logs
+        .iter()
+        .filter(|record| record.kind == RecordType::Unsubscribe)
+        .collect();
+    k9::assert_equal!(unsubscribes.len(), 1);
+    k9::assert_equal!(
+        unsubscribes[0].recipient,
+        vec!["user@example.com".to_string()]
+    );
+    k9::assert_equal!(unsubscribes[0].reception_protocol.as_deref(), Some("HTTP"));
+
+    Ok(())
+}
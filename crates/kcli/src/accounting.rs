@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
+use kumo_api_client::KumoApiClient;
+use kumo_api_types::accounting::{AccountingV1Entry, AccountingV1Request};
+use reqwest::Url;
+use tabout::{Alignment, Column};
+
+#[derive(ValueEnum, Default, Debug, Clone, Copy)]
+pub enum OutputFormat {
+    /// Human readable tabulated output
+    #[default]
+    Table,
+    /// Comma separated values, with a header row
+    Csv,
+    /// The underlying json data
+    Json,
+}
+
+/// Parse either an RFC 3339 timestamp, or a duration like `24h`
+/// which is interpreted as that amount of time before now.
+pub(crate) fn parse_time(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(t) = s.parse::<DateTime<Utc>>() {
+        return Ok(t);
+    }
+    let duration = humantime::parse_duration(s)
+        .map_err(|err| format!("{s} is neither an RFC 3339 timestamp nor a duration: {err}"))?;
+    let duration = chrono::Duration::from_std(duration).map_err(|err| format!("{s}: {err}"))?;
+    Utc::now()
+        .checked_sub_signed(duration)
+        .ok_or_else(|| format!("{s} is out of range"))
+}
+
+#[derive(Debug, Parser)]
+/// Report on the hourly volume of messages that were received,
+/// delivered, bounced and expired, broken down by tenant and campaign.
+///
+/// The accounting data is held in the accounting database and is
+/// retained for the period configured via `kumo.configure_accounting_retention`.
+pub struct AccountingCommand {
+    /// The tenant name to match.
+    /// If omitted, any tenant will match!
+    #[arg(long)]
+    tenant: Option<String>,
+
+    /// The campaign name to match.
+    /// If omitted, any campaign will match!
+    #[arg(long)]
+    campaign: Option<String>,
+
+    /// Only report on the hours ending after this time.
+    /// Accepts either an RFC 3339 timestamp like `2024-01-31T00:00:00Z`
+    /// or a duration like `24h`, which is interpreted as that
+    /// amount of time before the current time.
+    #[arg(long, value_parser=parse_time)]
+    since: Option<DateTime<Utc>>,
+
+    /// Only report on the hours starting before this time.
+    /// Accepts the same syntax as `--since`.
+    #[arg(long, value_parser=parse_time)]
+    until: Option<DateTime<Utc>>,
+
+    /// How to format the output
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+}
+
+/// Quote a field for CSV output, per RFC 4180
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn entry_to_row(entry: &AccountingV1Entry) -> Vec<String> {
+    vec![
+        entry.event_time.to_rfc3339(),
+        entry.tenant.clone().unwrap_or_default(),
+        entry.campaign.clone().unwrap_or_default(),
+        entry.received.to_string(),
+        entry.delivered.to_string(),
+        entry.bounced.to_string(),
+        entry.expired.to_string(),
+        entry.received_bytes.to_string(),
+        entry.delivered_bytes.to_string(),
+    ]
+}
+
+const HEADINGS: &[&str] = &[
+    "EVENT_TIME",
+    "TENANT",
+    "CAMPAIGN",
+    "RECEIVED",
+    "DELIVERED",
+    "BOUNCED",
+    "EXPIRED",
+    "RECEIVED_BYTES",
+    "DELIVERED_BYTES",
+];
+
+impl AccountingCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let client = KumoApiClient::new(endpoint.clone());
+        let result = client
+            .admin_accounting_v1(&AccountingV1Request {
+                tenant: self.tenant.clone(),
+                campaign: self.campaign.clone(),
+                since: self.since,
+                until: self.until,
+            })
+            .await?;
+
+        match self.format {
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(&result)?);
+            }
+            OutputFormat::Csv => {
+                println!("{}", HEADINGS.join(",").to_lowercase());
+                for entry in &result {
+                    let row: Vec<String> =
+                        entry_to_row(entry).iter().map(|f| csv_field(f)).collect();
+                    println!("{}", row.join(","));
+                }
+            }
+            OutputFormat::Table => {
+                let columns: Vec<Column> = HEADINGS
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, name)| Column {
+                        name: name.to_string(),
+                        alignment: if idx < 3 {
+                            Alignment::Left
+                        } else {
+                            Alignment::Right
+                        },
+                    })
+                    .collect();
+                let rows: Vec<_> = result.iter().map(entry_to_row).collect();
+                tabout::tabulate_output(&columns, &rows, &mut std::io::stdout())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv_quoting() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn time_parsing() {
+        assert_eq!(
+            parse_time("2024-01-31T00:00:00Z").unwrap().to_rfc3339(),
+            "2024-01-31T00:00:00+00:00"
+        );
+        let since = parse_time("1h").unwrap();
+        let delta = Utc::now() - since;
+        assert!(delta.num_minutes() >= 59 && delta.num_minutes() <= 61);
+        assert!(parse_time("bogus").is_err());
+    }
+}
@@ -5,6 +5,7 @@ use std::collections::HashMap;
 use std::time::Duration;
 
 mod abort_ready_q_conn;
+mod accounting;
 mod bounce;
 mod bounce_cancel;
 mod bounce_list;
@@ -13,17 +14,26 @@ mod inspect_ready_q;
 mod inspect_sched_q;
 mod logfilter;
 mod provider_summary;
+mod quarantine_delete;
+mod quarantine_inspect;
+mod quarantine_list;
+mod quarantine_release;
 mod queue_summary;
 mod rebind;
 mod resolve_egress_path;
 mod spool_compact;
+mod suppression_add;
+mod suppression_delete;
+mod suppression_list;
 mod suspend;
 mod suspend_cancel;
 mod suspend_list;
 mod suspend_ready_q;
 mod suspend_ready_q_cancel;
 mod suspend_ready_q_list;
+mod tail_log;
 mod top;
+mod trace_message;
 mod trace_smtp_client;
 mod trace_smtp_server;
 mod xfer;
@@ -79,6 +89,16 @@ enum SubCommand {
     ResolveEgressPath(resolve_egress_path::ResolveEgressPathCommand),
     ProviderSummary(provider_summary::ProviderSummaryCommand),
     QueueSummary(queue_summary::QueueSummaryCommand),
+    Accounting(accounting::AccountingCommand),
+    QuarantineList(quarantine_list::QuarantineListCommand),
+    QuarantineInspect(quarantine_inspect::QuarantineInspectCommand),
+    QuarantineRelease(quarantine_release::QuarantineReleaseCommand),
+    QuarantineDelete(quarantine_delete::QuarantineDeleteCommand),
+    SuppressionList(suppression_list::SuppressionListCommand),
+    SuppressionAdd(suppression_add::SuppressionAddCommand),
+    SuppressionDelete(suppression_delete::SuppressionDeleteCommand),
+    TailLog(tail_log::TailLogCommand),
+    TraceMessage(trace_message::TraceMessageCommand),
     TraceSmtpClient(trace_smtp_client::TraceSmtpClientCommand),
     TraceSmtpServer(trace_smtp_server::TraceSmtpServerCommand),
     Top(top::TopCommand),
@@ -117,6 +137,16 @@ impl SubCommand {
                     ("inspect-sched-q", &["debugging"]),
                     ("provider-summary", &["ops"]),
                     ("queue-summary", &["ops"]),
+                    ("accounting", &["ops", "accounting"]),
+                    ("quarantine-list", &["quarantine"]),
+                    ("quarantine-inspect", &["quarantine", "message"]),
+                    ("quarantine-release", &["quarantine"]),
+                    ("quarantine-delete", &["quarantine"]),
+                    ("suppression-list", &["suppression"]),
+                    ("suppression-add", &["suppression"]),
+                    ("suppression-delete", &["suppression"]),
+                    ("tail-log", &["ops", "logging"]),
+                    ("trace-message", &["ops", "logging"]),
                     ("trace-smtp-client", &["ops", "debugging"]),
                     ("trace-smtp-server", &["ops", "debugging"]),
                     ("top", &["ops", "debugging"]),
@@ -183,6 +213,16 @@ impl SubCommand {
             Self::ResolveEgressPath(cmd) => cmd.run(endpoint).await,
             Self::ProviderSummary(cmd) => cmd.run(endpoint).await,
             Self::QueueSummary(cmd) => cmd.run(endpoint).await,
+            Self::Accounting(cmd) => cmd.run(endpoint).await,
+            Self::QuarantineList(cmd) => cmd.run(endpoint).await,
+            Self::QuarantineInspect(cmd) => cmd.run(endpoint).await,
+            Self::QuarantineRelease(cmd) => cmd.run(endpoint).await,
+            Self::QuarantineDelete(cmd) => cmd.run(endpoint).await,
+            Self::SuppressionList(cmd) => cmd.run(endpoint).await,
+            Self::SuppressionAdd(cmd) => cmd.run(endpoint).await,
+            Self::SuppressionDelete(cmd) => cmd.run(endpoint).await,
+            Self::TailLog(cmd) => cmd.run(endpoint).await,
+            Self::TraceMessage(cmd) => cmd.run(endpoint).await,
             Self::TraceSmtpClient(cmd) => cmd.run(endpoint).await,
             Self::TraceSmtpServer(cmd) => cmd.run(endpoint).await,
             Self::Top(cmd) => cmd.run(endpoint).await,
@@ -0,0 +1,26 @@
+use crate::quarantine_release::QuarantineSelection;
+use clap::Parser;
+use kumo_api_client::KumoApiClient;
+use reqwest::Url;
+
+#[derive(Debug, Parser)]
+/// Delete messages that are held in quarantine.
+///
+/// Each matching message is removed from the spool and a
+/// `QuarantineDelete` record is logged for it.
+///
+/// There is no way to undo this action!
+pub struct QuarantineDeleteCommand {
+    #[command(flatten)]
+    selection: QuarantineSelection,
+}
+
+impl QuarantineDeleteCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let request = self.selection.to_request()?;
+        let client = KumoApiClient::new(endpoint.clone());
+        let result = client.admin_quarantine_delete_v1(&request).await?;
+        println!("Deleted {} message(s)", result.count);
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+use clap::Parser;
+use kumo_api_client::KumoApiClient;
+use kumo_api_types::quarantine::QuarantineV1InspectRequest;
+use reqwest::Url;
+
+#[derive(Debug, Parser)]
+/// Returns information about a message that is held in quarantine
+pub struct QuarantineInspectCommand {
+    #[arg(long)]
+    pub want_body: bool,
+
+    pub id: String,
+}
+
+impl QuarantineInspectCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let client = KumoApiClient::new(endpoint.clone());
+        let result = client
+            .admin_quarantine_inspect_v1(&QuarantineV1InspectRequest {
+                id: self.id.clone().try_into()?,
+                want_body: self.want_body,
+            })
+            .await?;
+
+        println!("{}", serde_json::to_string_pretty(&result)?);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use clap::Parser;
+use kumo_api_client::KumoApiClient;
+use kumo_api_types::quarantine::QuarantineV1ListRequest;
+use reqwest::Url;
+use tabout::{Alignment, Column};
+
+#[derive(Debug, Parser)]
+/// Returns the list of messages that are held in quarantine.
+///
+/// Messages are placed into quarantine via `msg:quarantine(reason)`,
+/// and are held until they are released via `kcli quarantine-release`
+/// or deleted via `kcli quarantine-delete`.
+pub struct QuarantineListCommand {
+    /// The domain name to match.
+    /// If omitted, any domains will match!
+    #[arg(long)]
+    domain: Option<String>,
+
+    /// The routing_domain name to match.
+    /// If omitted, any routing domain will match!
+    #[arg(long)]
+    routing_domain: Option<String>,
+
+    /// The campaign name to match.
+    /// If omitted, any campaigns will match!
+    #[arg(long)]
+    campaign: Option<String>,
+
+    /// The tenant name to match.
+    /// If omitted, any tenant will match!
+    #[arg(long)]
+    tenant: Option<String>,
+
+    /// Match only messages quarantined with exactly this reason.
+    #[arg(long)]
+    reason: Option<String>,
+
+    /// Show at most this many entries
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Instead of showing the human readable tabulated output,
+    /// return the underlying json data.
+    #[arg(long)]
+    json: bool,
+}
+
+impl QuarantineListCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let client = KumoApiClient::new(endpoint.clone());
+        let result = client
+            .admin_quarantine_list_v1(&QuarantineV1ListRequest {
+                campaign: self.campaign.clone(),
+                tenant: self.tenant.clone(),
+                domain: self.domain.clone(),
+                routing_domain: self.routing_domain.clone(),
+                reason: self.reason.clone(),
+                limit: self.limit,
+            })
+            .await?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        } else {
+            let columns = [
+                Column {
+                    name: "ID".to_string(),
+                    alignment: Alignment::Left,
+                },
+                Column {
+                    name: "SINCE".to_string(),
+                    alignment: Alignment::Left,
+                },
+                Column {
+                    name: "QUEUE".to_string(),
+                    alignment: Alignment::Left,
+                },
+                Column {
+                    name: "SENDER".to_string(),
+                    alignment: Alignment::Left,
+                },
+                Column {
+                    name: "REASON".to_string(),
+                    alignment: Alignment::Left,
+                },
+            ];
+            let rows: Vec<_> = result
+                .into_iter()
+                .map(|entry| {
+                    vec![
+                        entry.id.to_string(),
+                        entry.since.to_rfc3339(),
+                        entry.queue,
+                        entry.sender,
+                        entry.reason,
+                    ]
+                })
+                .collect();
+            tabout::tabulate_output(&columns, &rows, &mut std::io::stdout())?;
+        }
+
+        Ok(())
+    }
+}
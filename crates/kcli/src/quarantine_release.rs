@@ -0,0 +1,106 @@
+use clap::{Args, Parser};
+use kumo_api_client::KumoApiClient;
+use kumo_api_types::quarantine::{QuarantineV1ActionRequest, QuarantineV1Selector};
+use reqwest::Url;
+
+/// Selects quarantined messages either by their ids,
+/// or by the criteria of their scheduled queue and
+/// quarantine reason.
+#[derive(Debug, Args)]
+pub struct QuarantineSelection {
+    /// The spool id(s) of the messages to match.
+    /// May be specified multiple times.
+    #[arg(long)]
+    id: Vec<String>,
+
+    /// The domain name to match.
+    #[arg(long)]
+    domain: Option<String>,
+
+    /// The routing_domain name to match.
+    #[arg(long)]
+    routing_domain: Option<String>,
+
+    /// The campaign name to match.
+    #[arg(long)]
+    campaign: Option<String>,
+
+    /// The tenant name to match.
+    #[arg(long)]
+    tenant: Option<String>,
+
+    /// Match only messages quarantined with exactly this reason.
+    #[arg(long)]
+    reason: Option<String>,
+
+    /// Match every quarantined message.
+    /// Required when no other criteria are specified.
+    #[arg(long)]
+    everything: bool,
+
+    /// A comment to include in the resulting log records
+    #[arg(long)]
+    comment: Option<String>,
+}
+
+impl QuarantineSelection {
+    pub fn to_request(&self) -> anyhow::Result<QuarantineV1ActionRequest> {
+        let selector = QuarantineV1Selector {
+            ids: self
+                .id
+                .iter()
+                .map(|id| id.clone().try_into())
+                .collect::<Result<_, _>>()?,
+            campaign: self.campaign.clone(),
+            tenant: self.tenant.clone(),
+            domain: self.domain.clone(),
+            routing_domain: self.routing_domain.clone(),
+            reason: self.reason.clone(),
+        };
+
+        if selector.is_match_all() && !self.everything {
+            anyhow::bail!(
+                "No selection criteria were specified. \
+                 Did you mean to use --everything?"
+            );
+        }
+
+        Ok(QuarantineV1ActionRequest {
+            selector,
+            all: self.everything,
+            comment: self.comment.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Parser)]
+/// Release messages from quarantine.
+///
+/// Each matching message has its quarantine state cleared and
+/// is inserted into its scheduled queue, where it becomes eligible
+/// for delivery. A `QuarantineRelease` record is logged for
+/// each released message.
+///
+/// ## Examples
+///
+/// Release a specific message:
+///
+///    kcli quarantine-release --id d7ef132b5d7711eea8c8000c29c33806
+///
+/// Release everything that was quarantined for a given reason:
+///
+///    kcli quarantine-release --reason "suspicious attachment"
+pub struct QuarantineReleaseCommand {
+    #[command(flatten)]
+    selection: QuarantineSelection,
+}
+
+impl QuarantineReleaseCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let request = self.selection.to_request()?;
+        let client = KumoApiClient::new(endpoint.clone());
+        let result = client.admin_quarantine_release_v1(&request).await?;
+        println!("Released {} message(s)", result.count);
+        Ok(())
+    }
+}
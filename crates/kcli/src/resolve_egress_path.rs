@@ -100,5 +100,12 @@ fn render_default(r: &ResolveEgressPathV1Response, out: &mut dyn Write) -> anyho
     writeln!(out, "--- effective ceilings ---")?;
     writeln!(out)?;
     write!(out, "{}", r.constraints.to_human_string())?;
+
+    if let Some(warmup) = &r.warmup {
+        writeln!(out)?;
+        writeln!(out, "--- warm-up ---")?;
+        writeln!(out)?;
+        writeln!(out, "  {}", warmup.to_human_string())?;
+    }
     Ok(())
 }
@@ -0,0 +1,59 @@
+use clap::{ArgGroup, Parser};
+use kumo_api_client::KumoApiClient;
+use kumo_api_types::suppression::SuppressionV1AddRequest;
+use reqwest::Url;
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+/// Add a recipient or domain to the suppression list.
+///
+/// Messages for suppressed recipients are rejected at reception.
+/// Any existing entry for the same recipient or domain and tenant
+/// is replaced.
+#[clap(
+    group(ArgGroup::new("target")
+        .required(true)
+        .args(&["recipient", "domain"])),
+)]
+pub struct SuppressionAddCommand {
+    /// The recipient address to suppress.
+    #[arg(long)]
+    recipient: Option<String>,
+
+    /// The domain to suppress.
+    #[arg(long)]
+    domain: Option<String>,
+
+    /// Suppress only for messages of this tenant.
+    /// If omitted, the entry applies to all tenants.
+    #[arg(long)]
+    tenant: Option<String>,
+
+    /// The reason for the suppression
+    #[arg(long)]
+    reason: String,
+
+    /// How long the entry remains active.
+    /// If omitted, the entry remains until it is deleted.
+    #[arg(long, value_parser=humantime::parse_duration)]
+    duration: Option<Duration>,
+}
+
+impl SuppressionAddCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let client = KumoApiClient::new(endpoint.clone());
+        let result = client
+            .admin_suppression_add_v1(&SuppressionV1AddRequest {
+                recipient: self.recipient.clone(),
+                domain: self.domain.clone(),
+                tenant: self.tenant.clone(),
+                reason: self.reason.clone(),
+                duration: self.duration,
+            })
+            .await?;
+
+        println!("{}", serde_json::to_string_pretty(&result)?);
+
+        Ok(())
+    }
+}
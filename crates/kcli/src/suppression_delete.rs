@@ -0,0 +1,44 @@
+use clap::{ArgGroup, Parser};
+use kumo_api_client::KumoApiClient;
+use kumo_api_types::suppression::SuppressionV1DeleteRequest;
+use reqwest::Url;
+
+#[derive(Debug, Parser)]
+/// Remove a recipient or domain from the suppression list.
+///
+/// The recipient or domain and the tenant must exactly match
+/// those of the entry.
+#[clap(
+    group(ArgGroup::new("target")
+        .required(true)
+        .args(&["recipient", "domain"])),
+)]
+pub struct SuppressionDeleteCommand {
+    /// The suppressed recipient address.
+    #[arg(long)]
+    recipient: Option<String>,
+
+    /// The suppressed domain.
+    #[arg(long)]
+    domain: Option<String>,
+
+    /// The tenant of the entry.
+    /// If omitted, the entry that applies to all tenants is deleted.
+    #[arg(long)]
+    tenant: Option<String>,
+}
+
+impl SuppressionDeleteCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let client = KumoApiClient::new(endpoint.clone());
+        let result = client
+            .admin_suppression_delete_v1(&SuppressionV1DeleteRequest {
+                recipient: self.recipient.clone(),
+                domain: self.domain.clone(),
+                tenant: self.tenant.clone(),
+            })
+            .await?;
+        println!("Deleted {} entry(s)", result.count);
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use clap::{Parser, ValueEnum};
+use kumo_api_client::KumoApiClient;
+use kumo_api_types::suppression::{SuppressionSource, SuppressionV1ListRequest};
+use reqwest::Url;
+use tabout::{Alignment, Column};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Source {
+    Bounce,
+    Complaint,
+    Admin,
+    Unsubscribe,
+}
+
+impl From<Source> for SuppressionSource {
+    fn from(source: Source) -> Self {
+        match source {
+            Source::Bounce => Self::Bounce,
+            Source::Complaint => Self::Complaint,
+            Source::Admin => Self::Admin,
+            Source::Unsubscribe => Self::Unsubscribe,
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+/// Returns the entries in the suppression list.
+///
+/// Entries are added automatically for hard bounces and
+/// complaints, when configured via `kumo.configure_suppression`,
+/// or explicitly via `kcli suppression-add`.
+pub struct SuppressionListCommand {
+    /// Match entries for this recipient address, including
+    /// entries for its domain.
+    #[arg(long)]
+    recipient: Option<String>,
+
+    /// Match entries for this domain, including entries for
+    /// recipients in that domain.
+    #[arg(long)]
+    domain: Option<String>,
+
+    /// Match only entries that are scoped to this tenant.
+    #[arg(long)]
+    tenant: Option<String>,
+
+    /// Match only entries with this source.
+    #[arg(long)]
+    source: Option<Source>,
+
+    /// Show at most this many entries
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Instead of showing the human readable tabulated output,
+    /// return the underlying json data.
+    #[arg(long)]
+    json: bool,
+}
+
+impl SuppressionListCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let client = KumoApiClient::new(endpoint.clone());
+        let result = client
+            .admin_suppression_list_v1(&SuppressionV1ListRequest {
+                recipient: self.recipient.clone(),
+                domain: self.domain.clone(),
+                tenant: self.tenant.clone(),
+                source: self.source.map(Into::into),
+                limit: self.limit,
+            })
+            .await?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        } else {
+            let columns = [
+                Column {
+                    name: "RECIPIENT".to_string(),
+                    alignment: Alignment::Left,
+                },
+                Column {
+                    name: "TENANT".to_string(),
+                    alignment: Alignment::Left,
+                },
+                Column {
+                    name: "SOURCE".to_string(),
+                    alignment: Alignment::Left,
+                },
+                Column {
+                    name: "CREATED".to_string(),
+                    alignment: Alignment::Left,
+                },
+                Column {
+                    name: "EXPIRES".to_string(),
+                    alignment: Alignment::Left,
+                },
+                Column {
+                    name: "REASON".to_string(),
+                    alignment: Alignment::Left,
+                },
+            ];
+            let rows: Vec<_> = result
+                .into_iter()
+                .map(|entry| {
+                    vec![
+                        entry.recipient.or(entry.domain).unwrap_or_default(),
+                        entry.tenant.unwrap_or_default(),
+                        entry.source.to_string(),
+                        entry.created.to_rfc3339(),
+                        entry
+                            .expires
+                            .map(|expires| expires.to_rfc3339())
+                            .unwrap_or_else(|| "never".to_string()),
+                        entry.reason,
+                    ]
+                })
+                .collect();
+            tabout::tabulate_output(&columns, &rows, &mut std::io::stdout())?;
+        }
+
+        Ok(())
+    }
+}
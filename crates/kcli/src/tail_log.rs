@@ -0,0 +1,127 @@
+use clap::Parser;
+use futures::{SinkExt, StreamExt};
+use kumo_api_types::tail_log::{RecordType, TailLogV1Event, TailLogV1Request};
+use reqwest::Url;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+fn parse_record_type(s: &str) -> Result<RecordType, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string()))
+        .map_err(|_| format!("{s} is not a valid record type"))
+}
+
+/// Stream log records from the server as they are logged.
+///
+/// This is a diagnostic tool for the server operator, which allows
+/// watching the disposition of messages in real time, without
+/// needing access to the log files.
+///
+/// Filtering works by specifying an allow-list for specific properties
+/// of a log record. If an allow-list for a given property is set,
+/// then only records whose corresponding property is contained in
+/// the allow-list will be shown.  When multiple properties are
+/// filtered, records must match all of them.
+///
+/// The streamed records do not include any meta or header fields.
+///
+/// Take care on a busy server with live traffic, as there is limited
+/// capacity for streaming records.  If the stream cannot keep up,
+/// a warning is printed and some records will be skipped, so you
+/// should use the filtering options to focus on the records that
+/// are of interest.
+#[derive(Debug, Parser)]
+pub struct TailLogCommand {
+    /// The record type to match, for example `Delivery`,
+    /// `TransientFailure` or `Bounce`.
+    /// If omitted, any type will match!
+    ///
+    /// Can be used multiple times to add multiple candidate types.
+    #[arg(long="type", value_parser=parse_record_type)]
+    pub record_type: Vec<RecordType>,
+
+    /// The scheduled queue name to match.
+    /// If omitted, any queue will match!
+    #[arg(long)]
+    pub queue: Vec<String>,
+
+    /// The recipient domain name to match.
+    /// If omitted, any domain will match!
+    #[arg(long)]
+    pub domain: Vec<String>,
+
+    /// The tenant name to match.
+    /// If omitted, any tenant will match!
+    #[arg(long)]
+    pub tenant: Vec<String>,
+
+    /// The campaign name to match.
+    /// If omitted, any campaign will match!
+    #[arg(long)]
+    pub campaign: Vec<String>,
+
+    /// The bounce classification to match, for example
+    /// `InvalidRecipient`.
+    /// If omitted, any classification will match!
+    #[arg(long)]
+    pub bounce_class: Vec<String>,
+
+    /// Instead of a summary line, output each record as json
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl TailLogCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let mut endpoint = endpoint.join("/api/admin/tail-log/v1")?;
+        endpoint.set_scheme("ws").expect("ws to be valid scheme");
+
+        let (mut socket, _response) = connect_async(endpoint.to_string()).await?;
+
+        socket
+            .send(Message::Text(
+                serde_json::to_string(&TailLogV1Request {
+                    record_type: self.record_type.clone(),
+                    queue: self.queue.clone(),
+                    domain: self.domain.clone(),
+                    tenant: self.tenant.clone(),
+                    campaign: self.campaign.clone(),
+                    bounce_class: self.bounce_class.clone(),
+                })?
+                .into(),
+            ))
+            .await?;
+
+        while let Some(msg) = socket.next().await {
+            match msg? {
+                Message::Text(s) => match serde_json::from_str(&s)? {
+                    TailLogV1Event::Record(record) => {
+                        if self.json {
+                            println!("{}", serde_json::to_string(&record)?);
+                        } else {
+                            println!(
+                                "{} {:?} id={} queue={} recipient={} site={} {}",
+                                record.timestamp.to_rfc3339(),
+                                record.kind,
+                                record.id,
+                                record.queue,
+                                record.recipient.join(","),
+                                record.site,
+                                record.response.to_single_line()
+                            );
+                        }
+                    }
+                    TailLogV1Event::Lagged { missed } => {
+                        eprintln!(
+                            "Warning: the stream lagged behind and {missed} records were skipped"
+                        );
+                    }
+                },
+                Message::Close(_) => break,
+                msg => {
+                    anyhow::bail!("Unexpected {msg:?} response");
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use crate::accounting::parse_time;
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use kumo_api_client::KumoApiClient;
+use kumo_api_types::tracking::TraceMessageV1Request;
+use reqwest::Url;
+use tabout::{Alignment, Column};
+
+#[derive(Debug, Parser)]
+/// Show the history of a message, or of the messages sent to
+/// a recipient, as recorded by the message tracking store.
+///
+/// Message tracking must be enabled via `kumo.configure_message_tracking`
+/// in order to use this command.  At least one of `--id`, `--recipient`,
+/// `--sender`, `--campaign` or `--tenant` must be specified; when
+/// multiple options are used, events must match all of them.
+///
+/// The matching events are shown in chronological order.
+pub struct TraceMessageCommand {
+    /// The spool id of the message to trace
+    #[arg(long)]
+    id: Option<String>,
+
+    /// Match messages addressed to this recipient.
+    /// The comparison is case insensitive.
+    #[arg(long)]
+    recipient: Option<String>,
+
+    /// Match messages with this envelope sender.
+    /// The comparison is case insensitive.
+    #[arg(long)]
+    sender: Option<String>,
+
+    /// Match messages in this campaign
+    #[arg(long)]
+    campaign: Option<String>,
+
+    /// Match messages in this tenant
+    #[arg(long)]
+    tenant: Option<String>,
+
+    /// Only show events that occurred at or after this time.
+    /// Accepts either an RFC 3339 timestamp like `2024-01-31T00:00:00Z`
+    /// or a duration like `24h`, which is interpreted as that
+    /// amount of time before the current time.
+    #[arg(long, value_parser=parse_time)]
+    since: Option<DateTime<Utc>>,
+
+    /// Only show events that occurred before this time.
+    /// Accepts the same syntax as `--since`.
+    #[arg(long, value_parser=parse_time)]
+    until: Option<DateTime<Utc>>,
+
+    /// The maximum number of events to show.
+    /// If omitted, the server applies a default limit of 1000 events.
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Instead of a table, output the full log records as json
+    #[arg(long)]
+    json: bool,
+}
+
+impl TraceMessageCommand {
+    pub async fn run(&self, endpoint: &Url) -> anyhow::Result<()> {
+        let request = TraceMessageV1Request {
+            message_id: self.id.clone(),
+            recipient: self.recipient.clone(),
+            sender: self.sender.clone(),
+            campaign: self.campaign.clone(),
+            tenant: self.tenant.clone(),
+            since: self.since,
+            until: self.until,
+            limit: self.limit,
+        };
+        anyhow::ensure!(
+            request.has_selector(),
+            "at least one of --id, --recipient, --sender, --campaign \
+             or --tenant must be specified"
+        );
+
+        let client = KumoApiClient::new(endpoint.clone());
+        let result = client.admin_trace_message_v1(&request).await?;
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        } else {
+            let columns: Vec<Column> = ["TIMESTAMP", "TYPE", "ID", "RECIPIENT", "SITE", "RESPONSE"]
+                .iter()
+                .map(|name| Column {
+                    name: name.to_string(),
+                    alignment: Alignment::Left,
+                })
+                .collect();
+            let rows: Vec<Vec<String>> = result
+                .records
+                .iter()
+                .map(|record| {
+                    vec![
+                        record.timestamp.to_rfc3339(),
+                        format!("{:?}", record.kind),
+                        record.id.clone(),
+                        record.recipient.join(", "),
+                        record.site.clone(),
+                        record.response.to_single_line(),
+                    ]
+                })
+                .collect();
+            tabout::tabulate_output(&columns, &rows, &mut std::io::stdout())?;
+        }
+
+        if result.truncated {
+            eprintln!(
+                "Warning: there are more matching events than were shown. \
+                 Use --limit, --since or --until to see more."
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
 use anyhow::Context;
 use futures::{Stream, StreamExt};
+use kumo_api_types::accounting::*;
+use kumo_api_types::quarantine::*;
 use kumo_api_types::rebind::{RebindV1Request, RebindV1Response};
+use kumo_api_types::suppression::*;
+use kumo_api_types::tracking::*;
 use kumo_api_types::xfer::*;
 use kumo_api_types::*;
 pub use kumo_prometheus::parser::Metric;
@@ -158,6 +162,78 @@ impl KumoApiClient {
         XferCancelV1Response
     );
 
+    method!(
+        admin_accounting_v1,
+        GET,
+        "/api/admin/accounting/v1",
+        AccountingV1Request,
+        Vec<AccountingV1Entry>
+    );
+
+    method!(
+        admin_trace_message_v1,
+        GET,
+        "/api/admin/trace-message/v1",
+        TraceMessageV1Request,
+        TraceMessageV1Response
+    );
+
+    method!(
+        admin_quarantine_list_v1,
+        GET,
+        "/api/admin/quarantine/v1",
+        QuarantineV1ListRequest,
+        Vec<QuarantineV1ListEntry>
+    );
+
+    method!(
+        admin_quarantine_inspect_v1,
+        GET,
+        "/api/admin/quarantine/inspect/v1",
+        QuarantineV1InspectRequest,
+        QuarantineV1InspectResponse
+    );
+
+    method!(
+        admin_quarantine_release_v1,
+        POST,
+        "/api/admin/quarantine/release/v1",
+        QuarantineV1ActionRequest,
+        QuarantineV1ActionResponse
+    );
+
+    method!(
+        admin_quarantine_delete_v1,
+        POST,
+        "/api/admin/quarantine/delete/v1",
+        QuarantineV1ActionRequest,
+        QuarantineV1ActionResponse
+    );
+
+    method!(
+        admin_suppression_list_v1,
+        GET,
+        "/api/admin/suppression/v1",
+        SuppressionV1ListRequest,
+        Vec<SuppressionV1Entry>
+    );
+
+    method!(
+        admin_suppression_add_v1,
+        POST,
+        "/api/admin/suppression/v1",
+        SuppressionV1AddRequest,
+        SuppressionV1Entry
+    );
+
+    method!(
+        admin_suppression_delete_v1,
+        POST,
+        "/api/admin/suppression/delete/v1",
+        SuppressionV1DeleteRequest,
+        SuppressionV1DeleteResponse
+    );
+
     method!(
         admin_rebind_v1,
         POST,
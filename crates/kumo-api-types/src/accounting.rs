@@ -0,0 +1,71 @@
+use crate::ApplyToUrl;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, Debug, Default, IntoParams, ToSchema)]
+pub struct AccountingV1Request {
+    /// The tenant to match. If omitted, any tenant will match.
+    #[serde(default)]
+    pub tenant: Option<String>,
+
+    /// The campaign name to match. If omitted, any campaign will match.
+    #[serde(default)]
+    pub campaign: Option<String>,
+
+    /// Only return hourly buckets that end after this time.
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only return hourly buckets that start before this time.
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl ApplyToUrl for AccountingV1Request {
+    fn apply_to_url(&self, url: &mut Url) {
+        let mut query = url.query_pairs_mut();
+        if let Some(tenant) = &self.tenant {
+            query.append_pair("tenant", tenant);
+        }
+        if let Some(campaign) = &self.campaign {
+            query.append_pair("campaign", campaign);
+        }
+        if let Some(since) = &self.since {
+            query.append_pair("since", &since.to_rfc3339());
+        }
+        if let Some(until) = &self.until {
+            query.append_pair("until", &until.to_rfc3339());
+        }
+    }
+}
+
+/// The accumulated volume for a given tenant and campaign
+/// over a one hour period.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct AccountingV1Entry {
+    /// The start of the hour covered by this entry
+    pub event_time: DateTime<Utc>,
+    /// The tenant, or null if the messages had no tenant
+    #[schema(example = "mytenant")]
+    pub tenant: Option<String>,
+    /// The campaign, or null if the messages had no campaign
+    #[schema(example = "mycampaign")]
+    pub campaign: Option<String>,
+    /// The number of recipients received
+    pub received: u64,
+    /// The number of recipients successfully delivered
+    pub delivered: u64,
+    /// The number of recipients that were bounced, either by the
+    /// destination or administratively
+    pub bounced: u64,
+    /// The number of recipients that expired from the queue
+    pub expired: u64,
+    /// The total size of the received messages, in bytes,
+    /// counted once per recipient
+    pub received_bytes: u64,
+    /// The total size of the delivered messages, in bytes,
+    /// counted once per recipient
+    pub delivered_bytes: u64,
+}
@@ -1,3 +1,4 @@
+use crate::warmup::WarmupSchedule;
 use cidr_map::CidrSet;
 use data_loader::KeySource;
 use dns_resolver::{IpLookupStrategy, MailExchanger};
@@ -370,6 +371,24 @@ pub struct EgressPathConfig {
     #[serde(default)]
     pub ignore_8bit_checks: bool,
 
+    /// If true, log an SmtpClientSession record summarizing each
+    /// SMTP connection made via this path when it is closed.
+    #[serde(default)]
+    pub log_session: bool,
+
+    /// An optional IP warm-up schedule. While the schedule is in
+    /// progress, the number of messages per day that may be sent via
+    /// this path is limited to the volume for the current day.
+    #[serde(default)]
+    pub warmup: Option<WarmupSchedule>,
+
+    /// When true, the `slowdown_factor` of the `warmup` schedule is
+    /// applied to its daily volume.  This is normally set by the
+    /// tsa-daemon when the deferral ratio exceeds the
+    /// `max_deferral_ratio` of the schedule.
+    #[serde(default)]
+    pub warmup_slowdown: bool,
+
     /// When set, dispatcher tasks for this egress path that fail to
     /// make any forward progress for this duration are aborted by the
     /// maintainer. When omitted the effective value is derived at
@@ -442,6 +461,9 @@ impl Default for EgressPathConfig {
             dispatcher_wakeup_strategy: WakeupStrategy::default(),
             try_next_host_on_transport_error: false,
             ignore_8bit_checks: false,
+            log_session: false,
+            warmup: None,
+            warmup_slowdown: false,
             ip_lookup_strategy: IpLookupStrategy::default(),
             dispatcher_progress_watchdog_timeout: None,
         }
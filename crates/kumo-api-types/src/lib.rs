@@ -10,10 +10,16 @@ use url::Url;
 use utoipa::{IntoParams, ToResponse, ToSchema};
 use uuid::Uuid;
 
+pub mod accounting;
 pub mod egress_path;
+pub mod quarantine;
 pub mod rebind;
 pub mod shaping;
+pub mod suppression;
+pub mod tail_log;
+pub mod tracking;
 pub mod tsa;
+pub mod warmup;
 pub mod xfer;
 
 /// Describes which messages should be bounced.
@@ -824,6 +830,10 @@ pub struct ResolveEgressPathV1Response {
     #[schema(value_type = Object)]
     pub path_config: crate::egress_path::EgressPathConfig,
     pub constraints: crate::egress_path::EffectiveConstraints,
+    /// The warm-up stage that applies today, when the path
+    /// has a warm-up schedule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warmup: Option<crate::warmup::WarmupStage>,
 }
 
 #[derive(Serialize, Clone, Deserialize, Debug, PartialEq, ToSchema)]
@@ -0,0 +1,196 @@
+use crate::{ApplyToUrl, MessageInformation};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use spool::SpoolId;
+use url::Url;
+use utoipa::{IntoParams, ToResponse, ToSchema};
+
+/// Describes which quarantined messages should be matched.
+/// The domain/tenant/campaign/routing_domain criteria apply to
+/// the scheduled queue associated with a given message.
+/// Omitted fields match anything.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, ToSchema)]
+pub struct QuarantineV1Selector {
+    /// Match only the messages with these spool ids
+    #[serde(default)]
+    pub ids: Vec<SpoolId>,
+
+    /// The campaign name to match. If omitted, any campaign will match.
+    #[serde(default)]
+    pub campaign: Option<String>,
+
+    /// The tenant to match. If omitted, any tenant will match.
+    #[serde(default)]
+    pub tenant: Option<String>,
+
+    /// The domain name to match. If omitted, any domain will match.
+    #[serde(default)]
+    #[schema(example = "example.com")]
+    pub domain: Option<String>,
+
+    /// The routing_domain name to match. If omitted, any routing_domain will match.
+    #[serde(default)]
+    pub routing_domain: Option<String>,
+
+    /// Match only messages that were quarantined with exactly
+    /// this reason.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl QuarantineV1Selector {
+    /// Returns true if no criteria have been specified, which means
+    /// that every quarantined message would be matched
+    pub fn is_match_all(&self) -> bool {
+        self.ids.is_empty()
+            && self.campaign.is_none()
+            && self.tenant.is_none()
+            && self.domain.is_none()
+            && self.routing_domain.is_none()
+            && self.reason.is_none()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, IntoParams, ToSchema)]
+pub struct QuarantineV1ListRequest {
+    /// The campaign name to match. If omitted, any campaign will match.
+    #[serde(default)]
+    pub campaign: Option<String>,
+
+    /// The tenant to match. If omitted, any tenant will match.
+    #[serde(default)]
+    pub tenant: Option<String>,
+
+    /// The domain name to match. If omitted, any domain will match.
+    #[serde(default)]
+    #[schema(example = "example.com")]
+    pub domain: Option<String>,
+
+    /// The routing_domain name to match. If omitted, any routing_domain will match.
+    #[serde(default)]
+    pub routing_domain: Option<String>,
+
+    /// Match only messages that were quarantined with exactly
+    /// this reason.
+    #[serde(default)]
+    pub reason: Option<String>,
+
+    /// Return up to `limit` entries.
+    /// If no limit is provided, all matching entries are returned.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl QuarantineV1ListRequest {
+    /// Express the filter portion of the request as a selector
+    pub fn selector(&self) -> QuarantineV1Selector {
+        QuarantineV1Selector {
+            ids: vec![],
+            campaign: self.campaign.clone(),
+            tenant: self.tenant.clone(),
+            domain: self.domain.clone(),
+            routing_domain: self.routing_domain.clone(),
+            reason: self.reason.clone(),
+        }
+    }
+}
+
+impl ApplyToUrl for QuarantineV1ListRequest {
+    fn apply_to_url(&self, url: &mut Url) {
+        let mut query = url.query_pairs_mut();
+        if let Some(campaign) = &self.campaign {
+            query.append_pair("campaign", campaign);
+        }
+        if let Some(tenant) = &self.tenant {
+            query.append_pair("tenant", tenant);
+        }
+        if let Some(domain) = &self.domain {
+            query.append_pair("domain", domain);
+        }
+        if let Some(routing_domain) = &self.routing_domain {
+            query.append_pair("routing_domain", routing_domain);
+        }
+        if let Some(reason) = &self.reason {
+            query.append_pair("reason", reason);
+        }
+        if let Some(limit) = self.limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct QuarantineV1ListEntry {
+    /// The spool identifier of the message
+    pub id: SpoolId,
+    /// The scheduled queue to which the message will be
+    /// inserted when it is released
+    #[schema(example = "campaign_name:tenant_name@example.com")]
+    pub queue: String,
+    /// The reason that was given when the message was quarantined
+    #[schema(example = "suspicious attachment")]
+    pub reason: String,
+    /// The time at which the message was quarantined
+    pub since: DateTime<Utc>,
+    /// The envelope sender
+    #[schema(example = "sender@sender.example.com")]
+    pub sender: String,
+    /// The envelope recipient(s)
+    pub recipient: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, IntoParams, ToSchema)]
+pub struct QuarantineV1InspectRequest {
+    /// The spool identifier of the quarantined message
+    pub id: SpoolId,
+    /// If true, return the message body in addition to the
+    /// metadata
+    #[serde(default)]
+    pub want_body: bool,
+}
+
+impl ApplyToUrl for QuarantineV1InspectRequest {
+    fn apply_to_url(&self, url: &mut Url) {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("id", &self.id.to_string());
+        if self.want_body {
+            query.append_pair("want_body", "true");
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToResponse, ToSchema)]
+pub struct QuarantineV1InspectResponse {
+    /// Summary of the quarantine state of the message
+    pub entry: QuarantineV1ListEntry,
+    /// The message information
+    pub message: MessageInformation,
+}
+
+/// Describes which quarantined messages should be released
+/// or deleted.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct QuarantineV1ActionRequest {
+    #[serde(flatten)]
+    pub selector: QuarantineV1Selector,
+
+    /// Since releasing or deleting every quarantined message
+    /// is not something that should happen by accident, a request
+    /// with no selection criteria is rejected unless this is
+    /// set to true.
+    #[serde(default)]
+    pub all: bool,
+
+    /// Optional comment to include in the delivery log. Each matching
+    /// message will log with either a QuarantineRelease or
+    /// QuarantineDelete record.
+    #[serde(default)]
+    #[schema(example = "false positive")]
+    pub comment: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, ToResponse)]
+pub struct QuarantineV1ActionResponse {
+    /// The number of messages that were released or deleted
+    pub count: usize,
+}
@@ -1,5 +1,7 @@
 use crate::egress_path::EgressPathConfig;
 #[cfg(feature = "lua")]
+use crate::warmup::WarmupSchedule;
+#[cfg(feature = "lua")]
 use anyhow::Context;
 #[cfg(feature = "lua")]
 use config::any_err;
@@ -8,7 +10,7 @@ use config::serialize_options;
 #[cfg(feature = "lua")]
 use dns_resolver::{fully_qualify, MailExchanger};
 #[cfg(feature = "lua")]
-use kumo_log_types::JsonLogRecord;
+use kumo_log_types::{JsonLogRecord, RecordType};
 #[cfg(feature = "lua")]
 use mlua::prelude::LuaUserData;
 #[cfg(feature = "lua")]
@@ -203,6 +205,143 @@ pub enum Action {
     Bounce,
     BounceTenant,
     BounceCampaign,
+    AdaptiveRate(AdaptiveRate),
+}
+
+impl Action {
+    pub fn is_adaptive(&self) -> bool {
+        matches!(self, Self::AdaptiveRate(_))
+    }
+}
+
+/// The egress path options that can be managed by an
+/// `AdaptiveRate` controller
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum AdaptiveOption {
+    MaxMessageRate,
+    ConnectionLimit,
+}
+
+impl AdaptiveOption {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::MaxMessageRate => "max_message_rate",
+            Self::ConnectionLimit => "connection_limit",
+        }
+    }
+}
+
+/// Defines an additive-increase/multiplicative-decrease (AIMD)
+/// feedback controller for an egress path option.
+///
+/// The controller observes the deliveries and transient failures
+/// for the egress path over successive `interval`s.  At the end of
+/// each interval, if the ratio of deferrals to attempts exceeds
+/// `target_deferral_ratio` the value is multiplied by
+/// `decrease_factor`, otherwise `increase_step` is added to it.
+/// The value is always kept within `floor` and `ceiling`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveRate {
+    /// Which option to manage
+    pub name: AdaptiveOption,
+    /// The smallest value that the controller will set
+    pub floor: u64,
+    /// The largest value that the controller will set.
+    /// The controller starts out at this value.
+    pub ceiling: u64,
+    /// For `max_message_rate`, the period over which the
+    /// value applies.
+    #[serde(default = "AdaptiveRate::default_period", with = "duration_serde")]
+    pub period: Duration,
+    /// The deferral ratio above which the value is reduced
+    pub target_deferral_ratio: f64,
+    #[serde(default = "AdaptiveRate::default_decrease_factor")]
+    pub decrease_factor: f64,
+    /// How much to add to the value after a clean interval.
+    /// Defaults to 10% of the difference between `floor`
+    /// and `ceiling`, or 1, whichever is larger.
+    #[serde(default)]
+    pub increase_step: Option<u64>,
+    /// How often to evaluate the deferral ratio
+    #[serde(default = "AdaptiveRate::default_interval", with = "duration_serde")]
+    pub interval: Duration,
+    /// The minimum number of attempts in an interval before
+    /// the value is adjusted
+    #[serde(default = "AdaptiveRate::default_min_samples")]
+    pub min_samples: u64,
+}
+
+impl Hash for AdaptiveRate {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.name.hash(h);
+        self.floor.hash(h);
+        self.ceiling.hash(h);
+        self.period.hash(h);
+        self.target_deferral_ratio.to_ne_bytes().hash(h);
+        self.decrease_factor.to_ne_bytes().hash(h);
+        self.increase_step.hash(h);
+        self.interval.hash(h);
+        self.min_samples.hash(h);
+    }
+}
+
+impl AdaptiveRate {
+    fn default_period() -> Duration {
+        Duration::from_secs(60)
+    }
+
+    fn default_decrease_factor() -> f64 {
+        0.5
+    }
+
+    fn default_interval() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    fn default_min_samples() -> u64 {
+        50
+    }
+
+    pub fn increase_step(&self) -> u64 {
+        self.increase_step
+            .unwrap_or_else(|| self.ceiling.saturating_sub(self.floor) / 10)
+            .max(1)
+    }
+
+    /// Compute the next value given the current value and the
+    /// attempts and deferrals observed during the last interval.
+    /// Returns None if there were too few samples to make a decision.
+    pub fn next_value(&self, current: u64, attempts: u64, deferrals: u64) -> Option<u64> {
+        if attempts == 0 || attempts < self.min_samples {
+            return None;
+        }
+        let ratio = deferrals as f64 / attempts as f64;
+        let next = if ratio > self.target_deferral_ratio {
+            let factor = self.decrease_factor.clamp(0.0, 1.0);
+            (current as f64 * factor) as u64
+        } else {
+            current.saturating_add(self.increase_step())
+        };
+        Some(next.clamp(self.floor, self.ceiling.max(self.floor)))
+    }
+
+    /// Produce the configuration value that corresponds to `value`
+    pub fn config_value(&self, value: u64) -> toml::Value {
+        match self.name {
+            AdaptiveOption::MaxMessageRate => toml::Value::String(
+                ThrottleSpec {
+                    limit: value,
+                    period: self.period.as_secs().max(1),
+                    max_burst: None,
+                    force_local: false,
+                }
+                .as_string(),
+            ),
+            AdaptiveOption::ConnectionLimit => toml::Value::Integer(value as i64),
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
@@ -266,6 +405,20 @@ struct ShapingInner {
     warnings: Vec<String>,
     errors: Vec<String>,
     hash: String,
+    /// true if any entry defines a warm-up schedule
+    has_warmup: bool,
+    /// true if any automation rule has an AdaptiveRate action
+    has_adaptive: bool,
+}
+
+/// A rule with an `AdaptiveRate` action that applies to
+/// the egress path of a record
+#[cfg(feature = "lua")]
+#[derive(Debug, Clone)]
+pub struct AdaptiveRuleMatch {
+    pub rule: Rule,
+    /// true if the record counts as a deferral for this rule
+    pub is_deferral: bool,
 }
 
 #[cfg(feature = "lua")]
@@ -342,6 +495,45 @@ impl ShapingInner {
     }
 
     pub async fn match_rules(&self, record: &JsonLogRecord) -> anyhow::Result<Vec<Rule>> {
+        match Self::domain_and_site_for_record(record)? {
+            Some((domain, site_name)) => {
+                Ok(self.match_rules_impl(record, &domain, &site_name).await)
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    pub async fn get_warmup_schedule(
+        &self,
+        record: &JsonLogRecord,
+    ) -> anyhow::Result<Option<WarmupSchedule>> {
+        if !self.has_warmup {
+            return Ok(None);
+        }
+        let Some((domain, site_name)) = Self::domain_and_site_for_record(record)? else {
+            return Ok(None);
+        };
+        let source = record.egress_source.as_deref().unwrap_or("unspecified");
+        let partial = self
+            .get_egress_path_config(&domain, source, &site_name)
+            .await;
+        match partial.params.get("warmup") {
+            Some(warmup) => Ok(Some(
+                warmup
+                    .clone()
+                    .try_into()
+                    .context("parsing warmup schedule")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the destination domain and the site_name of the
+    /// egress path described by record, or None if the record
+    /// is not eligible for automation
+    fn domain_and_site_for_record(
+        record: &JsonLogRecord,
+    ) -> anyhow::Result<Option<(String, String)>> {
         use rfc5321::parser::ForwardPath;
         // Extract the domain from the recipient.
         let recipient = ForwardPath::try_from(
@@ -357,7 +549,7 @@ impl ShapingInner {
             ForwardPath::Postmaster => {
                 // It doesn't make sense to apply automation on the
                 // local postmaster address, so we ignore this.
-                return Ok(vec![]);
+                return Ok(None);
             }
             ForwardPath::Path(path) => path.mailbox,
         };
@@ -383,7 +575,75 @@ impl ShapingInner {
             .trim_end_matches("@smtp_client")
             .to_string();
 
-        Ok(self.match_rules_impl(record, &domain, &site_name).await)
+        Ok(Some((domain, site_name)))
+    }
+
+    /// Returns the rules with `AdaptiveRate` actions that apply
+    /// to the egress path of the record.  Unlike `match_rules`, the
+    /// rules are returned regardless of whether their regex matches,
+    /// because the controller needs to observe every attempt; the
+    /// regex instead determines whether a transient failure counts
+    /// as a deferral.
+    pub async fn match_adaptive_rules(
+        &self,
+        record: &JsonLogRecord,
+    ) -> anyhow::Result<Vec<AdaptiveRuleMatch>> {
+        if !self.has_adaptive {
+            return Ok(vec![]);
+        }
+        let is_transient = match record.kind {
+            RecordType::Delivery => false,
+            RecordType::TransientFailure => true,
+            _ => return Ok(vec![]),
+        };
+        let Some((domain, site_name)) = Self::domain_and_site_for_record(record)? else {
+            return Ok(vec![]);
+        };
+
+        let response = record.response.to_single_line();
+        let is_internal = record.response.content.starts_with("KumoMTA internal: ");
+
+        let mut result = vec![];
+        let mut consider = |rule: &Rule, rollup: bool| {
+            if rule.action.iter().any(Action::is_adaptive) {
+                result.push(AdaptiveRuleMatch {
+                    is_deferral: is_transient && rule.matches(is_internal, &response),
+                    rule: if rollup {
+                        rule.clone_and_set_rollup()
+                    } else {
+                        rule.clone()
+                    },
+                });
+            }
+        };
+
+        if let Some(default) = self.by_domain.get("default") {
+            for rule in &default.automation {
+                consider(rule, true);
+            }
+        }
+
+        for prov in self.by_provider.values() {
+            if prov.domain_matches(&domain).await {
+                for rule in &prov.automation {
+                    consider(rule, false);
+                }
+            }
+        }
+
+        if let Some(by_site) = self.by_site.get(&site_name) {
+            for rule in &by_site.automation {
+                consider(rule, true);
+            }
+        }
+
+        if let Some(by_domain) = self.by_domain.get(&domain) {
+            for rule in &by_domain.automation {
+                consider(rule, false);
+            }
+        }
+
+        Ok(result)
     }
 
     pub async fn match_rules_impl(
@@ -894,6 +1154,19 @@ impl Shaping {
         let hash = ctx.finalize();
         let hash = data_encoding::HEXLOWER.encode(&hash);
 
+        let has_warmup = by_site
+            .values()
+            .chain(by_domain.values())
+            .any(|entry| entry.defines_warmup())
+            || by_provider.values().any(|prov| prov.defines_warmup());
+
+        let has_adaptive = by_site
+            .values()
+            .chain(by_domain.values())
+            .flat_map(|entry| entry.automation.iter())
+            .chain(by_provider.values().flat_map(|prov| prov.automation.iter()))
+            .any(|rule| rule.action.iter().any(Action::is_adaptive));
+
         Ok(Self {
             inner: Arc::new(ShapingInner {
                 by_site,
@@ -902,6 +1175,8 @@ impl Shaping {
                 warnings: collector.warnings,
                 errors: collector.errors,
                 hash,
+                has_warmup,
+                has_adaptive,
             }),
         })
     }
@@ -941,6 +1216,22 @@ impl Shaping {
         self.inner.match_rules(record).await
     }
 
+    pub async fn match_adaptive_rules(
+        &self,
+        record: &JsonLogRecord,
+    ) -> anyhow::Result<Vec<AdaptiveRuleMatch>> {
+        self.inner.match_adaptive_rules(record).await
+    }
+
+    /// Returns the warm-up schedule that applies to the egress path
+    /// described by record, if any
+    pub async fn get_warmup_schedule(
+        &self,
+        record: &JsonLogRecord,
+    ) -> anyhow::Result<Option<WarmupSchedule>> {
+        self.inner.get_warmup_schedule(record).await
+    }
+
     pub fn get_referenced_sources(&self) -> BTreeMap<String, Vec<String>> {
         let mut result = BTreeMap::new();
 
@@ -1007,6 +1298,30 @@ impl LuaUserData for Shaping {
             Ok(result)
         });
 
+        methods.add_async_method(
+            "match_adaptive_rules",
+            |lua, this, record: mlua::Value| async move {
+                let record: JsonLogRecord = lua.from_value(record)?;
+                let matches = this.match_adaptive_rules(&record).await.map_err(any_err)?;
+                let mut result = vec![];
+                for m in matches {
+                    result.push(lua.to_value(&m.rule)?);
+                }
+                Ok(result)
+            },
+        );
+
+        methods.add_async_method(
+            "get_warmup_schedule",
+            |lua, this, record: mlua::Value| async move {
+                let record: JsonLogRecord = lua.from_value(record)?;
+                match this.get_warmup_schedule(&record).await.map_err(any_err)? {
+                    Some(schedule) => lua.to_value(&schedule),
+                    None => Ok(mlua::Value::Nil),
+                }
+            },
+        );
+
         methods.add_method("hash", move |_, this, ()| Ok(this.hash()));
     }
 }
@@ -1132,6 +1447,11 @@ fn test_host_matches() {
 
 #[cfg(feature = "lua")]
 impl ProviderEntry {
+    fn defines_warmup(&self) -> bool {
+        self.params.contains_key("warmup")
+            || self.sources.values().any(|tbl| tbl.contains_key("warmup"))
+    }
+
     async fn domain_matches(&self, domain: &str) -> bool {
         // We'd like to avoid doing DNS if we can do a simple suffix match,
         // so we bias to looking at those first
@@ -1371,6 +1691,11 @@ fn toml_table_merge_from(tbl: &mut toml::Table, source: &toml::Table) {
 
 #[cfg(feature = "lua")]
 impl PartialEntry {
+    fn defines_warmup(&self) -> bool {
+        self.params.contains_key("warmup")
+            || self.sources.values().any(|tbl| tbl.contains_key("warmup"))
+    }
+
     fn merge_from(&mut self, mut other: Self) {
         if other.replace_base {
             self.params = other.params;
@@ -1543,7 +1868,6 @@ mod test {
     use rfc5321::Response;
     use std::io::Write;
     use tempfile::NamedTempFile;
-    use uuid::Uuid;
 
     async fn make_shaping_configs(inputs: &[&str]) -> Shaping {
         let mut files = vec![];
@@ -1748,38 +2072,16 @@ match_internal = true
 
         fn make_record(content: &str, recipient: &str, site: &str) -> JsonLogRecord {
             JsonLogRecord {
-                kind: RecordType::TransientFailure,
-                id: String::new(),
-                sender: String::new(),
                 recipient: vec![recipient.to_string()],
-                queue: String::new(),
                 site: site.to_string(),
-                size: 0,
                 response: Response {
                     code: 400,
                     command: None,
                     enhanced_code: None,
                     content: content.to_string(),
                 },
-                peer_address: None,
-                timestamp: Default::default(),
-                created: Default::default(),
                 num_attempts: 1,
-                bounce_classification: Default::default(),
-                egress_pool: None,
-                egress_source: None,
-                source_address: None,
-                feedback_report: None,
-                meta: Default::default(),
-                headers: Default::default(),
-                delivery_protocol: None,
-                reception_protocol: None,
-                nodeid: Uuid::default(),
-                tls_cipher: None,
-                tls_protocol_version: None,
-                tls_peer_subject_name: None,
-                provider_name: None,
-                session_id: None,
+                ..JsonLogRecord::empty(RecordType::TransientFailure)
             }
         }
 
@@ -1852,6 +2154,181 @@ match_internal = true
         );
     }
 
+    #[tokio::test]
+    async fn test_warmup_schedule() {
+        let shaping = make_shaping_configs(&[r#"
+[provider."provider"]
+match=[{DomainSuffix=".provider"}]
+
+[provider."provider".sources."ip-1"]
+warmup = {start="2026-10-01", daily_volume=[50, 100], max_deferral_ratio=0.2}
+"#])
+        .await;
+        assert!(shaping.inner.has_warmup);
+
+        fn make_record(recipient: &str, source: &str) -> JsonLogRecord {
+            JsonLogRecord {
+                recipient: vec![recipient.to_string()],
+                site: format!("{source}->dummy_site@smtp_client"),
+                response: Response {
+                    code: 421,
+                    command: None,
+                    enhanced_code: None,
+                    content: "try later".to_string(),
+                },
+                num_attempts: 1,
+                egress_source: Some(source.to_string()),
+                ..JsonLogRecord::empty(RecordType::TransientFailure)
+            }
+        }
+
+        let schedule = shaping
+            .get_warmup_schedule(&make_record("user@woot.provider", "ip-1"))
+            .await
+            .unwrap()
+            .expect("ip-1 has a schedule");
+        k9::assert_equal!(schedule.daily_volume, vec![50, 100]);
+        k9::assert_equal!(schedule.max_deferral_ratio, Some(0.2));
+
+        assert!(shaping
+            .get_warmup_schedule(&make_record("user@woot.provider", "ip-2"))
+            .await
+            .unwrap()
+            .is_none());
+        assert!(shaping
+            .get_warmup_schedule(&make_record("user@example.com", "ip-1"))
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_adaptive_rate_next_value() {
+        let spec: AdaptiveRate = toml::from_str(
+            r#"
+name = "max_message_rate"
+floor = 10
+ceiling = 100
+target_deferral_ratio = 0.1
+"#,
+        )
+        .unwrap();
+        k9::assert_equal!(spec.period, Duration::from_secs(60));
+        k9::assert_equal!(spec.interval, Duration::from_secs(300));
+        k9::assert_equal!(spec.increase_step(), 9);
+
+        // Too few samples to decide
+        k9::assert_equal!(spec.next_value(100, 10, 10), None);
+        // Multiplicative decrease, bounded by floor
+        k9::assert_equal!(spec.next_value(100, 100, 20), Some(50));
+        k9::assert_equal!(spec.next_value(12, 100, 20), Some(10));
+        // Additive increase, bounded by ceiling
+        k9::assert_equal!(spec.next_value(50, 100, 5), Some(59));
+        k9::assert_equal!(spec.next_value(95, 100, 0), Some(100));
+
+        k9::assert_equal!(
+            spec.config_value(50),
+            toml::Value::String("50/m".to_string())
+        );
+
+        let spec: AdaptiveRate = toml::from_str(
+            r#"
+name = "connection_limit"
+floor = 1
+ceiling = 5
+target_deferral_ratio = 0.2
+"#,
+        )
+        .unwrap();
+        k9::assert_equal!(spec.increase_step(), 1);
+        k9::assert_equal!(spec.config_value(3), toml::Value::Integer(3));
+    }
+
+    #[tokio::test]
+    async fn test_match_adaptive_rules() {
+        let shaping = make_shaping_configs(&[r#"
+[provider."provider"]
+match=[{DomainSuffix=".provider"}]
+
+[[provider."provider".automation]]
+regex = "try later"
+action = {AdaptiveRate={name="connection_limit", floor=1, ceiling=10, target_deferral_ratio=0.1}}
+duration = "1 day"
+"#])
+        .await;
+        assert!(shaping.inner.has_adaptive);
+
+        fn make_record(kind: RecordType, recipient: &str, content: &str) -> JsonLogRecord {
+            JsonLogRecord {
+                recipient: vec![recipient.to_string()],
+                site: "ip-1->dummy_site@smtp_client".to_string(),
+                response: Response {
+                    code: 421,
+                    command: None,
+                    enhanced_code: None,
+                    content: content.to_string(),
+                },
+                num_attempts: 1,
+                egress_source: Some("ip-1".to_string()),
+                ..JsonLogRecord::empty(kind)
+            }
+        }
+
+        let matches = shaping
+            .match_adaptive_rules(&make_record(
+                RecordType::TransientFailure,
+                "user@woot.provider",
+                "try later",
+            ))
+            .await
+            .unwrap();
+        k9::assert_equal!(matches.len(), 1);
+        assert!(matches[0].is_deferral);
+
+        let matches = shaping
+            .match_adaptive_rules(&make_record(
+                RecordType::TransientFailure,
+                "user@woot.provider",
+                "mailbox busy",
+            ))
+            .await
+            .unwrap();
+        k9::assert_equal!(matches.len(), 1);
+        assert!(!matches[0].is_deferral);
+
+        let matches = shaping
+            .match_adaptive_rules(&make_record(
+                RecordType::Delivery,
+                "user@woot.provider",
+                "OK",
+            ))
+            .await
+            .unwrap();
+        k9::assert_equal!(matches.len(), 1);
+        assert!(!matches[0].is_deferral);
+
+        // Bounces don't feed the controller, and other domains
+        // are not in scope
+        assert!(shaping
+            .match_adaptive_rules(&make_record(
+                RecordType::Bounce,
+                "user@woot.provider",
+                "try later"
+            ))
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(shaping
+            .match_adaptive_rules(&make_record(
+                RecordType::TransientFailure,
+                "user@example.com",
+                "try later"
+            ))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
     #[tokio::test]
     async fn test_defaults() {
         let shaping = make_shaping_configs(&[
@@ -2007,6 +2484,9 @@ MergedEntry {
         no_memory_reduction_policy: ShrinkDataAndMeta,
         try_next_host_on_transport_error: false,
         ignore_8bit_checks: false,
+        log_session: false,
+        warmup: None,
+        warmup_slowdown: false,
         dispatcher_progress_watchdog_timeout: None,
     },
     sources: {},
@@ -2163,6 +2643,9 @@ MergedEntry {
         no_memory_reduction_policy: ShrinkDataAndMeta,
         try_next_host_on_transport_error: false,
         ignore_8bit_checks: false,
+        log_session: false,
+        warmup: None,
+        warmup_slowdown: false,
         dispatcher_progress_watchdog_timeout: None,
     },
     sources: {
@@ -2232,6 +2715,9 @@ MergedEntry {
             no_memory_reduction_policy: ShrinkDataAndMeta,
             try_next_host_on_transport_error: false,
             ignore_8bit_checks: false,
+            log_session: false,
+            warmup: None,
+            warmup_slowdown: false,
             dispatcher_progress_watchdog_timeout: None,
         },
     },
@@ -2394,6 +2880,9 @@ MergedEntry {
         no_memory_reduction_policy: ShrinkDataAndMeta,
         try_next_host_on_transport_error: false,
         ignore_8bit_checks: false,
+        log_session: false,
+        warmup: None,
+        warmup_slowdown: false,
         dispatcher_progress_watchdog_timeout: None,
     },
     sources: {},
@@ -0,0 +1,192 @@
+use crate::ApplyToUrl;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use url::Url;
+use utoipa::{IntoParams, ToResponse, ToSchema};
+
+/// Describes how a suppression entry was created
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
+pub enum SuppressionSource {
+    /// Added because of a Bounce or OOB record
+    Bounce,
+    /// Added because of a Feedback (complaint) record
+    Complaint,
+    /// Added by an administrator
+    Admin,
+    /// Added because the recipient unsubscribed via List-Unsubscribe
+    Unsubscribe,
+}
+
+impl SuppressionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Bounce => "Bounce",
+            Self::Complaint => "Complaint",
+            Self::Admin => "Admin",
+            Self::Unsubscribe => "Unsubscribe",
+        }
+    }
+}
+
+impl std::str::FromStr for SuppressionSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "Bounce" => Ok(Self::Bounce),
+            "Complaint" => Ok(Self::Complaint),
+            "Admin" => Ok(Self::Admin),
+            "Unsubscribe" => Ok(Self::Unsubscribe),
+            _ => Err(format!("invalid suppression source {s}")),
+        }
+    }
+}
+
+impl std::fmt::Display for SuppressionSource {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fmt.write_str(self.as_str())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, ToSchema)]
+pub struct SuppressionV1Entry {
+    /// The suppressed recipient address.
+    /// Exactly one of `recipient` or `domain` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "user@example.com")]
+    pub recipient: Option<String>,
+
+    /// The suppressed recipient domain.
+    /// Exactly one of `recipient` or `domain` is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "example.com")]
+    pub domain: Option<String>,
+
+    /// The tenant to which the entry applies.
+    /// If omitted, the entry applies to all tenants.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+
+    /// How the entry was created
+    pub source: SuppressionSource,
+
+    /// Why the recipient is suppressed
+    #[schema(example = "550 5.1.1 no such user")]
+    pub reason: String,
+
+    /// When the entry was created
+    pub created: DateTime<Utc>,
+
+    /// When the entry expires. If omitted, the entry remains
+    /// until it is deleted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SuppressionV1AddRequest {
+    /// The recipient address to suppress.
+    /// Exactly one of `recipient` or `domain` must be set.
+    #[serde(default)]
+    #[schema(example = "user@example.com")]
+    pub recipient: Option<String>,
+
+    /// The recipient domain to suppress.
+    /// Exactly one of `recipient` or `domain` must be set.
+    #[serde(default)]
+    #[schema(example = "example.com")]
+    pub domain: Option<String>,
+
+    /// The tenant to which the entry applies.
+    /// If omitted, the entry applies to all tenants.
+    #[serde(default)]
+    pub tenant: Option<String>,
+
+    /// Why the recipient is suppressed
+    #[schema(example = "requested removal via support ticket")]
+    pub reason: String,
+
+    /// How long the entry remains active.
+    /// If omitted, the entry remains until it is deleted.
+    #[serde(
+        default,
+        with = "duration_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub duration: Option<Duration>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, IntoParams, ToSchema)]
+pub struct SuppressionV1ListRequest {
+    /// Match only entries for this recipient address, including
+    /// entries for its domain.
+    #[serde(default)]
+    pub recipient: Option<String>,
+
+    /// Match only entries for this domain, including entries
+    /// for recipients in that domain.
+    #[serde(default)]
+    pub domain: Option<String>,
+
+    /// Match only entries that are scoped to this tenant.
+    #[serde(default)]
+    pub tenant: Option<String>,
+
+    /// Match only entries with this source.
+    #[serde(default)]
+    pub source: Option<SuppressionSource>,
+
+    /// Return up to `limit` entries.
+    /// If no limit is provided, all matching entries are returned.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl ApplyToUrl for SuppressionV1ListRequest {
+    fn apply_to_url(&self, url: &mut Url) {
+        let mut query = url.query_pairs_mut();
+        if let Some(recipient) = &self.recipient {
+            query.append_pair("recipient", recipient);
+        }
+        if let Some(domain) = &self.domain {
+            query.append_pair("domain", domain);
+        }
+        if let Some(tenant) = &self.tenant {
+            query.append_pair("tenant", tenant);
+        }
+        if let Some(source) = &self.source {
+            query.append_pair("source", source.as_str());
+        }
+        if let Some(limit) = self.limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+    }
+}
+
+/// Identifies the entry to be deleted. The recipient or domain
+/// and the tenant must match the entry exactly.
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct SuppressionV1DeleteRequest {
+    /// The suppressed recipient address.
+    /// Exactly one of `recipient` or `domain` must be set.
+    #[serde(default)]
+    #[schema(example = "user@example.com")]
+    pub recipient: Option<String>,
+
+    /// The suppressed recipient domain.
+    /// Exactly one of `recipient` or `domain` must be set.
+    #[serde(default)]
+    pub domain: Option<String>,
+
+    /// The tenant of the entry. If omitted, the entry that
+    /// applies to all tenants is deleted.
+    #[serde(default)]
+    pub tenant: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema, ToResponse)]
+pub struct SuppressionV1DeleteResponse {
+    /// The number of entries that were deleted
+    pub count: usize,
+}
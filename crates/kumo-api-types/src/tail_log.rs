@@ -0,0 +1,55 @@
+pub use kumo_log_types::{JsonLogRecord, RecordType};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The filter that is sent by the client as the first message
+/// on the `/api/admin/tail-log/v1` websocket.
+/// Each field is an allow-list; if a list is non-empty then only
+/// records whose corresponding property is in the list will be
+/// streamed.  When multiple fields are set, records must match all
+/// of them.
+#[derive(Serialize, Deserialize, Debug, Default, ToSchema)]
+pub struct TailLogV1Request {
+    /// The record types to match. If omitted, any type will match.
+    #[serde(default)]
+    #[schema(value_type=Vec<String>, example=json!(["Delivery", "Bounce"]))]
+    pub record_type: Vec<RecordType>,
+
+    /// The scheduled queue name to match. If omitted, any queue will match.
+    #[serde(default)]
+    #[schema(example = "campaign_name:tenant_name@example.com")]
+    pub queue: Vec<String>,
+
+    /// The recipient domain to match. If omitted, any domain will match.
+    /// The comparison is case insensitive.
+    #[serde(default)]
+    #[schema(example = "example.com")]
+    pub domain: Vec<String>,
+
+    /// The tenant to match. If omitted, any tenant will match.
+    #[serde(default)]
+    #[schema(example = "tenant_name")]
+    pub tenant: Vec<String>,
+
+    /// The campaign name to match. If omitted, any campaign will match.
+    #[serde(default)]
+    #[schema(example = "campaign_name")]
+    pub campaign: Vec<String>,
+
+    /// The bounce classification to match. If omitted, any
+    /// classification will match.
+    /// The comparison is case insensitive.
+    #[serde(default)]
+    #[schema(example = "InvalidRecipient")]
+    pub bounce_class: Vec<String>,
+}
+
+/// An event streamed by the `/api/admin/tail-log/v1` websocket
+#[derive(Serialize, Deserialize, Debug)]
+pub enum TailLogV1Event {
+    /// A log record that matched the filter
+    Record(Box<JsonLogRecord>),
+    /// The server was unable to keep up with the volume of
+    /// log records, and this many records were not streamed
+    Lagged { missed: u64 },
+}
@@ -0,0 +1,98 @@
+use crate::ApplyToUrl;
+use chrono::{DateTime, Utc};
+use kumo_log_types::JsonLogRecord;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Serialize, Deserialize, Debug, Default, IntoParams, ToSchema)]
+pub struct TraceMessageV1Request {
+    /// Match events for the message with this spool id
+    #[serde(default)]
+    pub message_id: Option<String>,
+
+    /// Match events for messages addressed to this recipient.
+    /// The comparison is case insensitive.
+    #[serde(default)]
+    #[schema(example = "user@example.com")]
+    pub recipient: Option<String>,
+
+    /// Match events for messages from this envelope sender.
+    /// The comparison is case insensitive.
+    #[serde(default)]
+    pub sender: Option<String>,
+
+    /// Match events for messages in this campaign
+    #[serde(default)]
+    pub campaign: Option<String>,
+
+    /// Match events for messages in this tenant
+    #[serde(default)]
+    pub tenant: Option<String>,
+
+    /// Only return events that occurred at or after this time
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+
+    /// Only return events that occurred before this time
+    #[serde(default)]
+    pub until: Option<DateTime<Utc>>,
+
+    /// The maximum number of events to return.
+    /// If omitted, a default limit of 1000 events applies.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl TraceMessageV1Request {
+    /// Returns true if at least one of the fields that identify
+    /// a message or group of messages has been specified
+    pub fn has_selector(&self) -> bool {
+        self.message_id.is_some()
+            || self.recipient.is_some()
+            || self.sender.is_some()
+            || self.campaign.is_some()
+            || self.tenant.is_some()
+    }
+}
+
+impl ApplyToUrl for TraceMessageV1Request {
+    fn apply_to_url(&self, url: &mut Url) {
+        let mut query = url.query_pairs_mut();
+        if let Some(message_id) = &self.message_id {
+            query.append_pair("message_id", message_id);
+        }
+        if let Some(recipient) = &self.recipient {
+            query.append_pair("recipient", recipient);
+        }
+        if let Some(sender) = &self.sender {
+            query.append_pair("sender", sender);
+        }
+        if let Some(campaign) = &self.campaign {
+            query.append_pair("campaign", campaign);
+        }
+        if let Some(tenant) = &self.tenant {
+            query.append_pair("tenant", tenant);
+        }
+        if let Some(since) = &self.since {
+            query.append_pair("since", &since.to_rfc3339());
+        }
+        if let Some(until) = &self.until {
+            query.append_pair("until", &until.to_rfc3339());
+        }
+        if let Some(limit) = self.limit {
+            query.append_pair("limit", &limit.to_string());
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct TraceMessageV1Response {
+    /// The matching log records, in chronological order
+    #[schema(value_type=Vec<Object>)]
+    pub records: Vec<JsonLogRecord>,
+
+    /// true if there were more matching records than the
+    /// requested limit
+    pub truncated: bool,
+}
@@ -0,0 +1,296 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use throttle::ThrottleSpec;
+use utoipa::ToSchema;
+
+/// A declarative IP warm-up plan.
+///
+/// The schedule is a ladder of daily volumes; the first entry applies
+/// on the `start` date, the second on the following day and so on.
+/// Once the ladder has been exhausted the schedule no longer limits
+/// the source.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WarmupSchedule {
+    /// The date (in UTC) on which the first stage of the schedule
+    /// applies. Before this date, the volume for the first stage
+    /// is used.
+    pub start: NaiveDate,
+
+    /// The maximum number of messages per day for each successive
+    /// day of the schedule
+    pub daily_volume: Vec<u64>,
+
+    /// Constrain how quickly the daily volume can be consumed.
+    /// Has the same meaning as the `max_burst` parameter of
+    /// a throttle.  Defaults to the daily volume.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_burst: Option<u64>,
+
+    /// When set, tsa-daemon will track the ratio of transient
+    /// failures to delivery attempts for the source, and slow
+    /// down the warm-up when it exceeds this value.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_deferral_ratio: Option<f64>,
+
+    /// The time window over which the deferral ratio is computed
+    #[serde(
+        default = "WarmupSchedule::default_deferral_window",
+        with = "duration_serde"
+    )]
+    pub deferral_window: Duration,
+
+    /// The minimum number of delivery attempts within the
+    /// `deferral_window` before the deferral ratio is considered
+    #[serde(default = "WarmupSchedule::default_min_deferral_samples")]
+    pub min_deferral_samples: u64,
+
+    /// While slowed down, the daily volume is multiplied by
+    /// this factor
+    #[serde(default = "WarmupSchedule::default_slowdown_factor")]
+    pub slowdown_factor: f64,
+}
+
+impl WarmupSchedule {
+    fn default_deferral_window() -> Duration {
+        Duration::from_secs(3600)
+    }
+
+    fn default_min_deferral_samples() -> u64 {
+        100
+    }
+
+    fn default_slowdown_factor() -> f64 {
+        0.5
+    }
+
+    /// Compute the stage of the schedule that applies on `date`.
+    /// `slowed_down` indicates whether the slowdown factor should
+    /// be applied to the daily volume.
+    pub fn stage_at(&self, date: NaiveDate, slowed_down: bool) -> WarmupStage {
+        let num_days = self.daily_volume.len();
+        let elapsed = (date - self.start).num_days();
+
+        if elapsed < 0 {
+            return match self.daily_volume.first() {
+                Some(&volume) => WarmupStage::Pending {
+                    start: self.start,
+                    num_days,
+                    daily_volume: self.effective_volume(volume, slowed_down),
+                    slowed_down,
+                },
+                None => WarmupStage::Complete,
+            };
+        }
+
+        match self.daily_volume.get(elapsed as usize) {
+            Some(&volume) => WarmupStage::Active {
+                day: elapsed as usize + 1,
+                num_days,
+                daily_volume: self.effective_volume(volume, slowed_down),
+                slowed_down,
+            },
+            None => WarmupStage::Complete,
+        }
+    }
+
+    /// Returns the throttle that should be applied to source
+    /// selection for the specified stage, or None if the stage
+    /// doesn't limit the source
+    pub fn throttle_for_stage(&self, stage: &WarmupStage) -> Option<ThrottleSpec> {
+        let limit = stage.daily_volume()?;
+        Some(ThrottleSpec {
+            limit,
+            period: 86400,
+            max_burst: self.max_burst,
+            force_local: false,
+        })
+    }
+
+    fn effective_volume(&self, volume: u64, slowed_down: bool) -> u64 {
+        if slowed_down {
+            let factor = self.slowdown_factor.clamp(0.0, 1.0);
+            ((volume as f64 * factor) as u64).max(1)
+        } else {
+            volume
+        }
+    }
+}
+
+/// Describes the current position within a `WarmupSchedule`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum WarmupStage {
+    /// The schedule has not started yet; the volume of its
+    /// first stage applies
+    Pending {
+        start: NaiveDate,
+        num_days: usize,
+        daily_volume: u64,
+        slowed_down: bool,
+    },
+    /// The schedule is in progress
+    Active {
+        /// The current day of the schedule, starting from 1
+        day: usize,
+        num_days: usize,
+        /// The effective daily volume, after applying any slowdown
+        daily_volume: u64,
+        slowed_down: bool,
+    },
+    /// The schedule has been completed and no longer limits
+    /// the source
+    Complete,
+}
+
+impl WarmupStage {
+    /// Returns the effective daily volume for this stage,
+    /// or None if the volume is not limited
+    pub fn daily_volume(&self) -> Option<u64> {
+        match self {
+            Self::Pending { daily_volume, .. } | Self::Active { daily_volume, .. } => {
+                Some(*daily_volume)
+            }
+            Self::Complete => None,
+        }
+    }
+
+    /// Returns the current day of the schedule; 0 before the
+    /// schedule starts, and one more than the length of the
+    /// schedule once it has completed
+    pub fn day(&self, schedule: &WarmupSchedule) -> usize {
+        match self {
+            Self::Pending { .. } => 0,
+            Self::Active { day, .. } => *day,
+            Self::Complete => schedule.daily_volume.len() + 1,
+        }
+    }
+
+    pub fn is_slowed_down(&self) -> bool {
+        match self {
+            Self::Pending { slowed_down, .. } | Self::Active { slowed_down, .. } => *slowed_down,
+            Self::Complete => false,
+        }
+    }
+
+    /// Render a one line summary of the stage
+    pub fn to_human_string(&self) -> String {
+        let slowed = |slowed_down: bool| {
+            if slowed_down {
+                " (slowed down)"
+            } else {
+                ""
+            }
+        };
+        match self {
+            Self::Pending {
+                start,
+                num_days,
+                daily_volume,
+                slowed_down,
+            } => format!(
+                "pending: {num_days} day schedule starts on {start}, \
+                 {daily_volume}/d{}",
+                slowed(*slowed_down)
+            ),
+            Self::Active {
+                day,
+                num_days,
+                daily_volume,
+                slowed_down,
+            } => format!(
+                "day {day} of {num_days}: {daily_volume}/d{}",
+                slowed(*slowed_down)
+            ),
+            Self::Complete => "complete".to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn schedule() -> WarmupSchedule {
+        toml::from_str(
+            r#"
+start = "2026-10-01"
+daily_volume = [50, 100, 500, 1000]
+"#,
+        )
+        .unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn defaults() {
+        let s = schedule();
+        assert_eq!(s.deferral_window, Duration::from_secs(3600));
+        assert_eq!(s.min_deferral_samples, 100);
+        assert_eq!(s.slowdown_factor, 0.5);
+        assert_eq!(s.max_deferral_ratio, None);
+    }
+
+    #[test]
+    fn stages() {
+        let s = schedule();
+        assert_eq!(
+            s.stage_at(date("2026-09-28"), false),
+            WarmupStage::Pending {
+                start: date("2026-10-01"),
+                num_days: 4,
+                daily_volume: 50,
+                slowed_down: false,
+            }
+        );
+        assert_eq!(
+            s.stage_at(date("2026-10-01"), false),
+            WarmupStage::Active {
+                day: 1,
+                num_days: 4,
+                daily_volume: 50,
+                slowed_down: false,
+            }
+        );
+        assert_eq!(
+            s.stage_at(date("2026-10-04"), false),
+            WarmupStage::Active {
+                day: 4,
+                num_days: 4,
+                daily_volume: 1000,
+                slowed_down: false,
+            }
+        );
+        assert_eq!(s.stage_at(date("2026-10-05"), false), WarmupStage::Complete);
+        assert_eq!(
+            s.throttle_for_stage(&s.stage_at(date("2026-10-05"), false)),
+            None
+        );
+    }
+
+    #[test]
+    fn slowdown() {
+        let s = schedule();
+        let stage = s.stage_at(date("2026-10-03"), true);
+        assert_eq!(stage.daily_volume(), Some(250));
+        assert!(stage.is_slowed_down());
+        assert_eq!(stage.to_human_string(), "day 3 of 4: 250/d (slowed down)");
+
+        let throttle = s.throttle_for_stage(&stage).unwrap();
+        assert_eq!(throttle.limit, 250);
+        assert_eq!(throttle.period, 86400);
+    }
+
+    #[test]
+    fn empty_schedule() {
+        let s = WarmupSchedule {
+            daily_volume: vec![],
+            ..schedule()
+        };
+        assert_eq!(s.stage_at(date("2026-09-01"), false), WarmupStage::Complete);
+    }
+}
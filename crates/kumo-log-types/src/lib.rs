@@ -9,7 +9,7 @@ use serde_json::Value;
 use serde_with::formats::PreferOne;
 use serde_with::{serde_as, OneOrMany};
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::net::SocketAddr;
 use uuid::Uuid;
 
@@ -79,6 +79,33 @@ pub enum RecordType {
     /// and we are now responsible for delivery.
     XferIn,
 
+    /// Message was placed into quarantine and is being held
+    /// until it is administratively released or deleted
+    Quarantine,
+
+    /// Message was administratively released from quarantine
+    /// and re-inserted into its scheduled queue
+    QuarantineRelease,
+
+    /// Message was administratively deleted while in quarantine
+    QuarantineDelete,
+
+    /// Message has been queued for longer than the delay_warning
+    /// threshold configured for its queue
+    DelayWarning,
+
+    /// Summarizes an incoming SMTP session; logged when the
+    /// connection is closed, if enabled for the listener
+    SmtpServerSession,
+
+    /// Summarizes an outgoing SMTP connection; logged when the
+    /// connection is closed, if enabled for the egress path
+    SmtpClientSession,
+
+    /// A recipient unsubscribed via the List-Unsubscribe
+    /// mechanism of a message that we sent
+    Unsubscribe,
+
     /// Special for matching anything in the logging config
     Any,
 }
@@ -98,6 +125,13 @@ impl RecordType {
             | Self::AdminRebind
             | Self::XferOut
             | Self::XferIn
+            | Self::Quarantine
+            | Self::QuarantineRelease
+            | Self::QuarantineDelete
+            | Self::DelayWarning
+            | Self::SmtpServerSession
+            | Self::SmtpClientSession
+            | Self::Unsubscribe
             | Self::Delayed => false,
             Self::Bounce
             | Self::TransientFailure
@@ -250,6 +284,12 @@ pub struct JsonLogRecord {
 
     pub bounce_classification: BounceClass,
 
+    /// Identifies the bounce classifier rule that produced
+    /// the bounce_classification, when enabled via the
+    /// `log_matched_rule` classifier option
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounce_classifier_rule: Option<String>,
+
     pub egress_pool: Option<String>,
     pub egress_source: Option<String>,
     pub source_address: Option<MaybeProxiedSourceAddress>,
@@ -292,6 +332,152 @@ pub struct JsonLogRecord {
     /// the same connection for either ingress or egress
     #[serde(skip_serializing_if = "Option::is_none")]
     pub session_id: Option<Uuid>,
+
+    /// Summary of the SMTP session; present only
+    /// for SmtpServerSession records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_session: Option<Box<SmtpServerSessionSummary>>,
+
+    /// Summary of the outgoing SMTP connection; present only
+    /// for SmtpClientSession records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_session: Option<Box<SmtpClientSessionSummary>>,
+}
+
+impl JsonLogRecord {
+    /// Returns a record of the specified kind in which all of the
+    /// other fields are empty.  This is a convenient starting point
+    /// for code, such as tests, that synthesizes records and only
+    /// cares about a subset of the fields.
+    pub fn empty(kind: RecordType) -> Self {
+        Self {
+            kind,
+            id: String::new(),
+            sender: String::new(),
+            recipient: vec![],
+            queue: String::new(),
+            site: String::new(),
+            size: 0,
+            response: Response {
+                code: 0,
+                enhanced_code: None,
+                content: String::new(),
+                command: None,
+            },
+            peer_address: None,
+            timestamp: Default::default(),
+            created: Default::default(),
+            num_attempts: 0,
+            bounce_classification: Default::default(),
+            bounce_classifier_rule: None,
+            egress_pool: None,
+            egress_source: None,
+            source_address: None,
+            feedback_report: None,
+            meta: Default::default(),
+            headers: Default::default(),
+            delivery_protocol: None,
+            reception_protocol: None,
+            nodeid: Uuid::default(),
+            tls_cipher: None,
+            tls_protocol_version: None,
+            tls_peer_subject_name: None,
+            provider_name: None,
+            session_id: None,
+            server_session: None,
+            client_session: None,
+        }
+    }
+}
+
+/// Connection-level information about an incoming SMTP session
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SmtpServerSessionSummary {
+    /// The server name indication requested by the client
+    /// in the TLS handshake, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_sni: Option<String>,
+    /// The authorization identity, if the client authenticated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authz_id: Option<String>,
+    /// The authentication identity, if the client authenticated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub authn_id: Option<String>,
+    /// The number of times each command was issued by the client,
+    /// keyed by the command verb.  Commands that could not be
+    /// parsed are counted under `INVALID`.
+    #[serde(default)]
+    pub commands: BTreeMap<String, usize>,
+    /// The number of message transactions that were accepted
+    #[serde(default)]
+    pub messages_accepted: usize,
+    /// The number of message transactions that were rejected
+    #[serde(default)]
+    pub messages_rejected: usize,
+    /// The number of bytes read from the client
+    #[serde(default)]
+    pub bytes_read: u64,
+    /// The number of bytes written to the client
+    #[serde(default)]
+    pub bytes_written: u64,
+    /// How long the session lasted, in seconds
+    #[serde(default)]
+    pub duration: f64,
+}
+
+/// Explains why an outgoing SMTP connection was closed
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SmtpClientSessionCloseReason {
+    /// No more messages became ready within the idle_timeout,
+    /// or the ready queue was suspended
+    #[default]
+    Idle,
+    /// The max_deliveries_per_connection limit was reached
+    MaxDeliveriesPerConnection,
+    /// The connection plan for the session was completed and
+    /// a fresh session will be started for any remaining messages
+    SessionTerminated,
+    /// The peer closed the connection, or sent an unsolicited
+    /// response indicating that it is about to close it
+    PeerClosed,
+    /// A transport error or timeout occurred
+    Error,
+    /// kumod is shutting down
+    ShuttingDown,
+}
+
+/// Connection-level information about an outgoing SMTP connection
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SmtpClientSessionSummary {
+    /// The name of the MX host that we connected to
+    pub mx_host: String,
+    /// The effective TLS policy that was applied to the connection,
+    /// after taking DANE and MTA-STS into account
+    pub tls_policy: String,
+    /// true if STARTTLS was successfully negotiated
+    #[serde(default)]
+    pub tls_enabled: bool,
+    /// true if usable DANE TLSA records were used to
+    /// authenticate the peer
+    #[serde(default)]
+    pub dane: bool,
+    /// The MTA-STS policy mode for the destination domain,
+    /// if an MTA-STS policy was found
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mta_sts_mode: Option<String>,
+    /// The ESMTP extension keywords advertised by the peer in its
+    /// final EHLO/LHLO response
+    #[serde(default)]
+    pub ehlo_capabilities: Vec<String>,
+    /// The number of messages that were delivered over this connection
+    #[serde(default)]
+    pub messages_delivered: usize,
+    /// Why the connection was closed
+    #[serde(default)]
+    pub close_reason: SmtpClientSessionCloseReason,
+    /// How long the connection lasted, in seconds
+    #[serde(default)]
+    pub duration: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -306,5 +492,5 @@ pub struct MaybeProxiedSourceAddress {
 #[cfg(all(test, target_pointer_width = "64"))]
 #[test]
 fn sizes() {
-    assert_eq!(std::mem::size_of::<JsonLogRecord>(), 720);
+    assert_eq!(std::mem::size_of::<JsonLogRecord>(), 760);
 }
@@ -459,6 +459,7 @@ impl Report {
                 ReportAction::Failed
             }
             RecordType::Expiration if params.enable_expiration => ReportAction::Failed,
+            RecordType::DelayWarning if params.enable_delay => ReportAction::Delayed,
             _ => return Ok(None),
         };
 
@@ -543,6 +544,20 @@ impl Report {
                     status = log.response.to_single_line()
                 )
             }
+            RecordType::DelayWarning => {
+                format!(
+                    "The message was received at {created}\r\n\
+                    from {sender} and addressed to {recip_list}.\r\n\
+                    Status: {status}\r\n\
+                    The message has not yet been delivered.\r\n\
+                    Delivery will continue to be attempted; no action\r\n\
+                    is required on your part.\r\n\
+                    ",
+                    created = log.created.to_rfc2822(),
+                    sender = log.sender,
+                    status = log.response.to_single_line()
+                )
+            }
             _ => unreachable!(),
         };
 
@@ -600,7 +615,10 @@ impl Report {
             .context("set_content_type")?;
         report_msg
             .headers_mut()
-            .set_subject("Returned mail")
+            .set_subject(match action {
+                ReportAction::Delayed => "Delayed mail (still being retried)",
+                _ => "Returned mail",
+            })
             .context("set_subject")?;
         report_msg
             .headers_mut()
@@ -650,13 +668,11 @@ pub struct ReportGenerationParams {
     pub enable_expiration: bool,
     #[serde(default)]
     pub enable_bounce: bool,
-    // If we decide to allow generating for delays in the future,
-    // we'll probably add `enable_delay` here, but we'll also need
-    // to have some kind of discriminating logic to decide when
-    // to emit a DSN; probably should be a list of num_attempts
-    // on which to emit? This is too fiddly to design for right
-    // now, considering that none of our target userbase will
-    // emit DSNs for delayed mail.
+    /// Generate `Action: delayed` reports for DelayWarning records,
+    /// which are logged at most once per message when it exceeds
+    /// the delay_warning threshold of its queue
+    #[serde(default)]
+    pub enable_delay: bool,
     pub reporting_mta: RemoteMta,
 
     /// When used for testing, use a stable mime boundary
@@ -688,6 +704,7 @@ mod test {
             nodeid,
             created: created.into(),
             bounce_classification: Default::default(),
+            bounce_classifier_rule: None,
             delivery_protocol: Some("ESMTP".to_string()),
             egress_pool: None,
             egress_source: None,
@@ -706,6 +723,8 @@ mod test {
             recipient: vec!["recip@target.example.com".to_string()],
             sender: "sender@sender.example.com".to_string(),
             session_id: None,
+            server_session: None,
+            client_session: None,
             response: Response {
                 code: 550,
                 command: None,
@@ -737,6 +756,7 @@ mod test {
             nodeid,
             created: created.into(),
             bounce_classification: Default::default(),
+            bounce_classifier_rule: None,
             delivery_protocol: None,
             egress_pool: None,
             egress_source: None,
@@ -751,6 +771,8 @@ mod test {
             recipient: vec!["recip@target.example.com".to_string()],
             sender: "sender@sender.example.com".to_string(),
             session_id: None,
+            server_session: None,
+            client_session: None,
             response: Response {
                 code: 551,
                 command: None,
@@ -780,6 +802,7 @@ mod test {
             },
             enable_bounce: false,
             enable_expiration: true,
+            enable_delay: false,
             include_original_message: IncludeOriginalMessage::HeadersOnly,
             stable_content: true,
         };
@@ -902,6 +925,47 @@ Subject: Hello!
         );
     }
 
+    #[test]
+    fn generate_delay_warning() {
+        let params = ReportGenerationParams {
+            reporting_mta: RemoteMta {
+                mta_type: "dns".to_string(),
+                name: "mta1.example.com".to_string(),
+            },
+            enable_bounce: false,
+            enable_expiration: false,
+            enable_delay: true,
+            include_original_message: IncludeOriginalMessage::No,
+            stable_content: true,
+        };
+
+        let mut log = make_expiration();
+        log.kind = RecordType::DelayWarning;
+        log.response = Response {
+            code: 451,
+            command: None,
+            content: "Message has been queued for 4h, which exceeds delay_warning=4h".to_string(),
+            enhanced_code: Some(EnhancedStatusCode {
+                class: 4,
+                subject: 4,
+                detail: 7,
+            }),
+        };
+
+        let report_msg = Report::generate(&params, None, &log).unwrap().unwrap();
+        let report_eml = BString::from(report_msg.to_message_bytes());
+        assert!(report_eml.contains_str("Subject: Delayed mail (still being retried)"));
+        assert!(report_eml.contains_str("Action: delayed\r\n"));
+        assert!(report_eml.contains_str("Status: 4.4.7 Message has been queued"));
+
+        // Delay reports are opt-in
+        let params = ReportGenerationParams {
+            enable_delay: false,
+            ..params
+        };
+        assert!(Report::generate(&params, None, &log).unwrap().is_none());
+    }
+
     #[test]
     fn generate_bounce_with_headers() {
         let params = ReportGenerationParams {
@@ -911,6 +975,7 @@ Subject: Hello!
             },
             enable_bounce: true,
             enable_expiration: true,
+            enable_delay: false,
             include_original_message: IncludeOriginalMessage::HeadersOnly,
             stable_content: true,
         };
@@ -1044,6 +1109,7 @@ Subject: Hello!
             },
             enable_bounce: true,
             enable_expiration: true,
+            enable_delay: false,
             include_original_message: IncludeOriginalMessage::FullContent,
             stable_content: true,
         };
@@ -1182,6 +1248,7 @@ hello there
             },
             enable_bounce: true,
             enable_expiration: true,
+            enable_delay: false,
             include_original_message: IncludeOriginalMessage::No,
             stable_content: true,
         };
@@ -0,0 +1,94 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// The names of all of the histograms declared via `declare_metric!`.
+/// This allows validating the name passed to `set_histogram_buckets`
+/// without having to vivify every metric in the process.
+#[linkme::distributed_slice]
+pub static HISTOGRAM_NAMES: [&'static str];
+
+#[derive(Default)]
+struct BucketState {
+    /// Bucket thresholds requested by the operator, keyed by metric name
+    overrides: HashMap<String, Vec<f64>>,
+    /// The bucket thresholds that were used to create each histogram
+    in_use: HashMap<String, Vec<f64>>,
+}
+
+static BUCKETS: LazyLock<Mutex<BucketState>> = LazyLock::new(Default::default);
+
+/// Override the bucket thresholds of the named histogram.
+///
+/// The histogram buckets are fixed at the point where the histogram
+/// is first used, so this must be called before that happens in order
+/// to have any effect.
+///
+/// Returns `Ok(true)` if the buckets will be used, or `Ok(false)` if
+/// the histogram has already been created with different buckets,
+/// in which case the change will only take effect after a restart.
+pub fn set_histogram_buckets(name: &str, buckets: Vec<f64>) -> anyhow::Result<bool> {
+    if !HISTOGRAM_NAMES.iter().any(|n| *n == name) {
+        anyhow::bail!("{name} is not a known histogram metric");
+    }
+    if buckets.is_empty() {
+        anyhow::bail!("{name}: at least one bucket threshold must be specified");
+    }
+    if buckets.iter().any(|b| !b.is_finite()) {
+        anyhow::bail!("{name}: bucket thresholds must be finite numbers");
+    }
+    if buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+        anyhow::bail!("{name}: bucket thresholds must be in strictly increasing order");
+    }
+
+    let mut state = BUCKETS.lock();
+    let applied = match state.in_use.get(name) {
+        Some(current) => *current == buckets,
+        None => true,
+    };
+    state.overrides.insert(name.to_string(), buckets);
+    Ok(applied)
+}
+
+/// Returns the bucket thresholds to use when creating the named
+/// histogram; either the override set via `set_histogram_buckets`,
+/// or the provided default buckets.
+/// This is used by the `declare_metric!` macro.
+pub fn histogram_buckets(name: &str, default_buckets: Vec<f64>) -> Vec<f64> {
+    let mut state = BUCKETS.lock();
+    let buckets = state
+        .overrides
+        .get(name)
+        .cloned()
+        .unwrap_or(default_buckets);
+    state.in_use.insert(name.to_string(), buckets.clone());
+    buckets
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[linkme::distributed_slice(HISTOGRAM_NAMES)]
+    static TEST_HISTOGRAM: &str = "test_histogram_buckets";
+
+    #[test]
+    fn bucket_overrides() {
+        assert!(set_histogram_buckets("not_a_histogram", vec![1.0]).is_err());
+        assert!(set_histogram_buckets("test_histogram_buckets", vec![]).is_err());
+        assert!(set_histogram_buckets("test_histogram_buckets", vec![2.0, 1.0]).is_err());
+        assert!(set_histogram_buckets("test_histogram_buckets", vec![1.0, 1.0]).is_err());
+        assert!(set_histogram_buckets("test_histogram_buckets", vec![f64::NAN]).is_err());
+
+        assert!(set_histogram_buckets("test_histogram_buckets", vec![0.5, 1.0]).unwrap());
+        assert_eq!(
+            histogram_buckets("test_histogram_buckets", vec![10.0]),
+            vec![0.5, 1.0]
+        );
+
+        // Same buckets as are already in use is fine
+        assert!(set_histogram_buckets("test_histogram_buckets", vec![0.5, 1.0]).unwrap());
+        // but changing them after creation cannot be applied
+        assert!(!set_histogram_buckets("test_histogram_buckets", vec![0.5, 2.0]).unwrap());
+    }
+}
@@ -1,4 +1,5 @@
 pub use crate::counter::*;
+pub use crate::histogram::*;
 use crate::labels::MetricLabel;
 use crate::registry::StreamingCollector;
 use async_stream::stream;
@@ -14,6 +15,7 @@ use std::sync::{Arc, LazyLock};
 
 mod counter;
 pub mod counter_bundle;
+mod histogram;
 
 #[macro_use]
 pub mod labels;
@@ -331,6 +333,19 @@ macro_rules! __histogram_buckets {
     };
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_histogram_name {
+    ($sym:ident, $name:expr) => {
+        // Link into HISTOGRAM_NAMES so that set_histogram_buckets
+        // can validate the name
+        $crate::paste::paste! {
+            #[linkme::distributed_slice($crate::HISTOGRAM_NAMES)]
+            static [<HISTOGRAM_NAME_ $sym>]: &str = $name;
+        }
+    };
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! __register_metric {
@@ -562,11 +577,13 @@ macro_rules! declare_metric {
                     $crate::prometheus::register_histogram_vec!(
                         $name,
                         help,
-                        $labels
-                        $(,$buckets)?
+                        $labels,
+                        $crate::histogram_buckets($name, $crate::__histogram_buckets!($($buckets)?))
                     ).unwrap()
                 });
 
+        $crate::__register_histogram_name!($sym, $name);
+
         $crate::__register_metric!($sym, $name, $($doc)*, $crate::MetricType::Histogram,
             &$labels[..],
             $crate::MetricPrune::NonPruning,
@@ -589,10 +606,12 @@ macro_rules! declare_metric {
                     $crate::prometheus::register_histogram!(
                         $name,
                         help,
-                        $($buckets)?
+                        $crate::histogram_buckets($name, $crate::__histogram_buckets!($($buckets)?))
                     ).unwrap()
                 });
 
+        $crate::__register_histogram_name!($sym, $name);
+
         $crate::__register_metric!($sym, $name, $($doc)*, $crate::MetricType::Histogram, &[],
             $crate::MetricPrune::NonPruning,
             $crate::__histogram_buckets!($($buckets)?)
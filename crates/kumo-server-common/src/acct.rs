@@ -1,6 +1,6 @@
 use crate::authn_authz::{Access, AuditRecord, AuthInfo, Identity};
 use crate::disk_space::{MinFree, MonitoredPath};
-use crate::log::{mark_existing_logs_as_done_in_dir, OpenedFile};
+use crate::log::{mark_existing_logs_as_done_in_dir, LogFileWriter, OpenedFile};
 use anyhow::Context;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -231,7 +231,9 @@ impl AuditLogParams {
         };
 
         let file = OpenedFile {
-            file: Encoder::new(f, self.compression_level).context("set up zstd encoder")?,
+            file: LogFileWriter::Compressed(
+                Encoder::new(f, self.compression_level).context("set up zstd encoder")?,
+            ),
             name,
             written: 0,
             expires: self
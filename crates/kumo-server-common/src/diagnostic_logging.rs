@@ -1,5 +1,9 @@
 use anyhow::Context;
 use clap::ValueEnum;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::span_processor_with_async_runtime::BatchSpanProcessor;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
 use std::path::PathBuf;
 use std::sync::OnceLock;
 use tracing_subscriber::fmt::writer::BoxMakeWriter;
@@ -32,6 +36,84 @@ pub fn set_diagnostic_log_filter(new_filter: &str) -> anyhow::Result<()> {
     (func)(new_filter)
 }
 
+/// Holds the OTLP tracer provider, if OTLP export was enabled,
+/// so that buffered spans can be flushed during shutdown
+static OTLP_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+/// Returns true if OTLP trace export was enabled via LoggingConfig.
+/// Callers should avoid the overhead of producing spans when
+/// this returns false.
+pub fn otlp_enabled() -> bool {
+    OTLP_PROVIDER.get().is_some()
+}
+
+/// Flush any buffered spans to the OTLP collector and stop the
+/// exporter. This is a no-op if OTLP export is not enabled.
+pub async fn shutdown_otlp() {
+    if let Some(provider) = OTLP_PROVIDER.get() {
+        let provider = provider.clone();
+        match tokio::task::spawn_blocking(move || provider.shutdown()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => tracing::error!("error shutting down OTLP exporter: {err:#}"),
+            Err(err) => tracing::error!("error shutting down OTLP exporter: {err:#}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+#[clap(rename_all = "kebab_case")]
+pub enum OtlpProtocol {
+    /// OTLP/gRPC, typically on port 4317
+    Grpc,
+    /// OTLP/HTTP with binary protobuf payloads, typically on port 4318
+    Http,
+}
+
+pub struct OtlpConfig {
+    /// The collector endpoint. For Http, this is the full URL
+    /// including the `/v1/traces` path.
+    pub endpoint: String,
+    pub protocol: OtlpProtocol,
+    pub service_name: String,
+}
+
+impl OtlpConfig {
+    fn init(&self) -> anyhow::Result<()> {
+        let provider = self.build_provider()?;
+        opentelemetry::global::set_tracer_provider(provider.clone());
+        OTLP_PROVIDER
+            .set(provider)
+            .map_err(|_| anyhow::anyhow!("OTLP exporter was already configured"))?;
+        Ok(())
+    }
+
+    fn build_provider(&self) -> anyhow::Result<SdkTracerProvider> {
+        let exporter = match self.protocol {
+            OtlpProtocol::Grpc => SpanExporter::builder()
+                .with_tonic()
+                .with_endpoint(&self.endpoint)
+                .build(),
+            OtlpProtocol::Http => SpanExporter::builder()
+                .with_http()
+                .with_endpoint(&self.endpoint)
+                .build(),
+        }
+        .with_context(|| format!("configuring OTLP exporter for {}", self.endpoint))?;
+
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(
+                BatchSpanProcessor::builder(exporter, opentelemetry_sdk::runtime::Tokio).build(),
+            )
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(self.service_name.clone())
+                    .build(),
+            )
+            .build();
+        Ok(provider)
+    }
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 #[clap(rename_all = "kebab_case")]
 pub enum DiagnosticFormat {
@@ -46,6 +128,8 @@ pub struct LoggingConfig<'a> {
     pub filter_env_var: &'a str,
     pub default_filter: &'a str,
     pub diag_format: DiagnosticFormat,
+    /// When set, enables export of traces to an OTLP collector
+    pub otlp: Option<OtlpConfig>,
 }
 
 impl LoggingConfig<'_> {
@@ -85,7 +169,87 @@ impl LoggingConfig<'_> {
             }))
             .map_err(|_| anyhow::anyhow!("failed to assign reloadable logging filter"))?;
 
+        if let Some(otlp) = &self.otlp {
+            otlp.init()?;
+        }
+
         metrics::set_global_recorder(metrics_prometheus::Recorder::builder().build())?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::extract::Extension;
+    use axum::http::HeaderMap;
+    use axum::routing::post;
+    use axum::Router;
+    use axum_server::Handle;
+    use bstr::ByteSlice;
+    use opentelemetry::trace::{Tracer, TracerProvider};
+    use std::sync::{Arc, Mutex};
+
+    type Received = Arc<Mutex<Vec<(String, Bytes)>>>;
+
+    /// Stands in for an OTLP/HTTP collector, recording the
+    /// content type and body of each export request
+    async fn collect(
+        Extension(received): Extension<Received>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> &'static str {
+        let content_type = headers
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        received.lock().unwrap().push((content_type, body));
+        ""
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn otlp_http_export() {
+        let received = Received::default();
+        let app = Router::new()
+            .route("/v1/traces", post(collect))
+            .layer(Extension(Arc::clone(&received)));
+
+        let socket = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let handle = Handle::new();
+        let server = axum_server::from_tcp(socket);
+        let handle_copy = handle.clone();
+        tokio::spawn(async move {
+            server
+                .handle(handle_copy)
+                .serve(app.into_make_service())
+                .await
+                .unwrap();
+        });
+
+        let config = OtlpConfig {
+            endpoint: format!("http://{addr}/v1/traces"),
+            protocol: OtlpProtocol::Http,
+            service_name: "otlp-export-test".to_string(),
+        };
+        let provider = config.build_provider().unwrap();
+        provider.tracer("test").in_span("exported-span", |_cx| {});
+
+        // Shutting down flushes the batch to the collector
+        tokio::task::spawn_blocking(move || provider.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+        handle.shutdown();
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        let (content_type, body) = &received[0];
+        assert_eq!(content_type, "application/x-protobuf");
+        // Strings are embedded verbatim in the protobuf encoding
+        assert!(body.contains_str("exported-span"));
+        assert!(body.contains_str("otlp-export-test"));
+    }
+}
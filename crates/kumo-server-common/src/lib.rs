@@ -328,6 +328,22 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         })?,
     )?;
 
+    kumo_mod.set(
+        "set_histogram_buckets",
+        lua.create_function(move |_, (name, buckets): (String, Vec<f64>)| {
+            let applied =
+                kumo_prometheus::set_histogram_buckets(&name, buckets).map_err(any_err)?;
+            if !applied {
+                tracing::warn!(
+                    "kumo.set_histogram_buckets: histogram {name} is already in use \
+                    with different buckets; the new buckets will take effect \
+                    when kumod is restarted"
+                );
+            }
+            Ok(())
+        })?,
+    )?;
+
     kumo_mod.set(
         "set_config_monitor_globs",
         lua.create_function(move |_, globs: Vec<String>| {
@@ -1,11 +1,47 @@
 use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 use std::time::Instant;
 use zstd::stream::write::Encoder;
 
+/// The writer for an opened log file
+pub enum LogFileWriter {
+    /// The file is zstd compressed
+    Compressed(Encoder<'static, File>),
+    /// The file is written as plain text
+    Plain(BufWriter<File>),
+}
+
+impl LogFileWriter {
+    /// Flush any buffered data and, for compressed files,
+    /// write the end of the zstd frame
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Compressed(encoder) => encoder.do_finish(),
+            Self::Plain(writer) => writer.flush(),
+        }
+    }
+}
+
+impl Write for LogFileWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Compressed(encoder) => encoder.write(buf),
+            Self::Plain(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Compressed(encoder) => encoder.flush(),
+            Self::Plain(writer) => writer.flush(),
+        }
+    }
+}
+
 /// Represents an opened log file
 pub struct OpenedFile {
-    pub file: Encoder<'static, File>,
+    pub file: LogFileWriter,
     pub name: PathBuf,
     pub written: u64,
     pub expires: Option<Instant>,
@@ -13,7 +49,7 @@ pub struct OpenedFile {
 
 impl Drop for OpenedFile {
     fn drop(&mut self) {
-        self.file.do_finish().ok();
+        self.file.finish().ok();
         mark_path_as_done(&self.name).ok();
         tracing::debug!("Flushed {:?}", self.name);
     }
@@ -83,6 +83,7 @@ impl StartConfig<'_> {
 
         // after waiting for those to idle out, shut down logging
         shutdown_future.await;
+        crate::diagnostic_logging::shutdown_otlp().await;
 
         tracing::info!("Shutdown completed OK!");
 
@@ -1,16 +1,23 @@
 //! The purpose of this module is to keep an overall accounting
 //! of the volume of messages that were received and delivered
-//! by this instance
+//! by this instance, along with hourly rollups of the volume
+//! handled on behalf of each tenant and campaign
 
 use anyhow::Context;
 use chrono::prelude::*;
 use core::sync::atomic::AtomicUsize;
+use kumo_api_types::accounting::{AccountingV1Entry, AccountingV1Request};
+use kumo_log_types::RecordType;
 use kumo_server_lifecycle::ShutdownSubcription;
 use kumo_server_runtime::get_main_runtime;
+use message::queue_name::QueueNameComponents;
+use message::Message;
 use parking_lot::FairMutex as Mutex;
-use sqlite::{Connection, ConnectionThreadSafe};
+use sqlite::{Connection, ConnectionThreadSafe, State};
+use std::collections::HashMap;
 use std::sync::atomic::Ordering;
 use std::sync::LazyLock;
+use std::time::Duration;
 use tokio::task::JoinHandle;
 
 pub static ACCT: LazyLock<Accounting> = LazyLock::new(Accounting::default);
@@ -18,11 +25,47 @@ static FLUSHER: LazyLock<Mutex<Option<JoinHandle<()>>>> =
     LazyLock::new(|| Mutex::new(Some(get_main_runtime().spawn(flusher()))));
 pub static DB_PATH: LazyLock<Mutex<String>> =
     LazyLock::new(|| Mutex::new("/var/spool/kumomta/accounting.db".to_string()));
+/// How long to keep the per-tenant hourly rollups
+pub static RETENTION: LazyLock<Mutex<Duration>> =
+    LazyLock::new(|| Mutex::new(Duration::from_secs(90 * 86400)));
+
+const HOUR_FORMAT: &str = "%Y-%m-%d %H:00:00";
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct TenantKey {
+    event_time: String,
+    /// Empty if the message had no tenant
+    tenant: String,
+    /// Empty if the message had no campaign
+    campaign: String,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct TenantCounts {
+    received: u64,
+    delivered: u64,
+    bounced: u64,
+    expired: u64,
+    received_bytes: u64,
+    delivered_bytes: u64,
+}
+
+impl TenantCounts {
+    fn merge(&mut self, other: &TenantCounts) {
+        self.received += other.received;
+        self.delivered += other.delivered;
+        self.bounced += other.bounced;
+        self.expired += other.expired;
+        self.received_bytes += other.received_bytes;
+        self.delivered_bytes += other.delivered_bytes;
+    }
+}
 
 #[derive(Default)]
 pub struct Accounting {
     received: AtomicUsize,
     delivered: AtomicUsize,
+    by_tenant: Mutex<HashMap<TenantKey, TenantCounts>>,
 }
 
 impl Accounting {
@@ -40,6 +83,23 @@ impl Accounting {
         LazyLock::force(&FLUSHER);
     }
 
+    /// Accumulate counts into the current hourly bucket for
+    /// the tenant/campaign
+    fn inc_tenant(&self, key: TenantKey, counts: &TenantCounts) {
+        self.by_tenant.lock().entry(key).or_default().merge(counts);
+        // and ensure that the flusher gets started
+        LazyLock::force(&FLUSHER);
+    }
+
+    /// Put back counts that we failed to flush, so that we
+    /// can retry later
+    fn restore_tenants(&self, by_tenant: HashMap<TenantKey, TenantCounts>) {
+        let mut current = self.by_tenant.lock();
+        for (key, counts) in by_tenant {
+            current.entry(key).or_default().merge(&counts);
+        }
+    }
+
     /// Grab the current counters, zeroing the state out.
     fn grab(&self) -> (usize, usize) {
         let mut received;
@@ -91,40 +151,21 @@ impl Accounting {
         }
 
         let (received, delivered) = self.grab();
+        let by_tenant = std::mem::take(&mut *self.by_tenant.lock());
 
-        if !force && (received + delivered == 0) {
+        if !force && (received + delivered == 0) && by_tenant.is_empty() {
             // Nothing to do
             return Ok(());
         }
 
+        // We keep a copy so that we can put them back if the flush fails
+        let tenants_to_flush = by_tenant.clone();
+
         let res = get_main_runtime()
             .spawn_blocking(move || {
                 tracing::trace!("flushing");
                 let db = open_accounting_db().context("open_accounting_db")?;
-
-                let now = Utc::now().date_naive();
-                let now = now.format("%Y-%m-01 00:00:00").to_string();
-
-                let mut insert = db
-                    .prepare(
-                        "INSERT INTO accounting
-                    (event_time, received, delivered)
-                    values ($now, $received, $delivered)
-                    on conflict (event_time)
-                    do update set received=received+$received, delivered=delivered+$delivered
-                ",
-                    )
-                    .context("prepare")?;
-
-                insert.bind(("$now", now.as_str())).context("bind $now")?;
-                insert
-                    .bind(("$received", received as i64))
-                    .context("bind $received")?;
-                insert
-                    .bind(("$delivered", delivered as i64))
-                    .context("bind $delivered")?;
-
-                insert.next()?;
+                write_counts(&db, Utc::now(), received, delivered, &tenants_to_flush)?;
                 tracing::trace!("flushed");
                 Ok(())
             })
@@ -135,6 +176,7 @@ impl Accounting {
         if res.is_err() {
             self.inc_received(received);
             self.inc_delivered(delivered);
+            self.restore_tenants(by_tenant);
 
             tracing::error!(
                 "Failed to record {received} receptions + \
@@ -169,10 +211,271 @@ pub fn account_delivery(protocol: &str) {
     ACCT.inc_delivered(1);
 }
 
+/// Returns true if records of the specified kind are counted
+/// by account_tenant_record.
+/// A Reception may turn out to be a Feedback report, which is not
+/// counted, but that isn't known until the message is parsed.
+pub fn is_tenant_accounted(kind: RecordType) -> bool {
+    matches!(
+        kind,
+        RecordType::Reception
+            | RecordType::Delivery
+            | RecordType::Bounce
+            | RecordType::AdminBounce
+            | RecordType::Expiration
+    )
+}
+
+/// Called by log_disposition to account for a record against
+/// the tenant and campaign of the associated message.
+/// Unlike account_reception and account_delivery, which are
+/// called for each logger that accepts the record, this is called
+/// exactly once for each record, regardless of which loggers are
+/// configured, their enabled record types or filter_event, so
+/// the tenant rollups reflect the volume that was actually handled
+/// even when nothing is being logged.
+pub async fn account_tenant_record(
+    kind: RecordType,
+    msg: &Message,
+    reception_protocol: Option<&str>,
+    num_recipients: usize,
+) {
+    // Messages generated by logging hooks are not part of the
+    // volume handled on behalf of a tenant
+    if !is_accounted_protocol(reception_protocol.unwrap_or("unknown")) {
+        return;
+    }
+
+    let num_recipients = num_recipients as u64;
+    let size = msg.get_data_maybe_not_loaded().len() as u64 * num_recipients;
+    let counts = match kind {
+        RecordType::Reception => TenantCounts {
+            received: num_recipients,
+            received_bytes: size,
+            ..Default::default()
+        },
+        RecordType::Delivery => TenantCounts {
+            delivered: num_recipients,
+            delivered_bytes: size,
+            ..Default::default()
+        },
+        RecordType::Bounce | RecordType::AdminBounce => TenantCounts {
+            bounced: num_recipients,
+            ..Default::default()
+        },
+        RecordType::Expiration => TenantCounts {
+            expired: num_recipients,
+            ..Default::default()
+        },
+        _ => return,
+    };
+
+    let queue_name = match msg.get_queue_name().await {
+        Ok(name) => name,
+        Err(err) => {
+            tracing::error!("account_tenant_record: get_queue_name: {err:#}");
+            return;
+        }
+    };
+    let components = QueueNameComponents::parse(&queue_name);
+
+    ACCT.inc_tenant(
+        TenantKey {
+            event_time: Utc::now().format(HOUR_FORMAT).to_string(),
+            tenant: components.tenant.unwrap_or("").to_string(),
+            campaign: components.campaign.unwrap_or("").to_string(),
+        },
+        &counts,
+    );
+}
+
+/// Write the accumulated counts into the database.
+/// The overall and the per-tenant counts are written in a single
+/// transaction, so that if any part of the write fails, none of
+/// the counts are recorded and they can all be retried later.
+fn write_counts(
+    db: &ConnectionThreadSafe,
+    now: DateTime<Utc>,
+    received: usize,
+    delivered: usize,
+    by_tenant: &HashMap<TenantKey, TenantCounts>,
+) -> anyhow::Result<()> {
+    db.execute("BEGIN").context("begin")?;
+
+    let result = (|| -> anyhow::Result<()> {
+        let month = now.format("%Y-%m-01 00:00:00").to_string();
+
+        let mut insert = db
+            .prepare(
+                "INSERT INTO accounting
+            (event_time, received, delivered)
+            values ($now, $received, $delivered)
+            on conflict (event_time)
+            do update set received=received+$received, delivered=delivered+$delivered
+            ",
+            )
+            .context("prepare")?;
+
+        insert.bind(("$now", month.as_str())).context("bind $now")?;
+        insert
+            .bind(("$received", received as i64))
+            .context("bind $received")?;
+        insert
+            .bind(("$delivered", delivered as i64))
+            .context("bind $delivered")?;
+        insert.next()?;
+
+        let mut insert = db
+            .prepare(
+                "INSERT INTO tenant_accounting
+            (event_time, tenant, campaign, received, delivered, bounced,
+             expired, received_bytes, delivered_bytes)
+            values ($now, $tenant, $campaign, $received, $delivered, $bounced,
+             $expired, $received_bytes, $delivered_bytes)
+            on conflict (event_time, tenant, campaign)
+            do update set received=received+$received,
+                delivered=delivered+$delivered,
+                bounced=bounced+$bounced,
+                expired=expired+$expired,
+                received_bytes=received_bytes+$received_bytes,
+                delivered_bytes=delivered_bytes+$delivered_bytes
+            ",
+            )
+            .context("prepare tenant insert")?;
+
+        for (key, counts) in by_tenant {
+            insert.reset()?;
+            insert.bind(("$now", key.event_time.as_str()))?;
+            insert.bind(("$tenant", key.tenant.as_str()))?;
+            insert.bind(("$campaign", key.campaign.as_str()))?;
+            insert.bind(("$received", counts.received as i64))?;
+            insert.bind(("$delivered", counts.delivered as i64))?;
+            insert.bind(("$bounced", counts.bounced as i64))?;
+            insert.bind(("$expired", counts.expired as i64))?;
+            insert.bind(("$received_bytes", counts.received_bytes as i64))?;
+            insert.bind(("$delivered_bytes", counts.delivered_bytes as i64))?;
+            insert.next()?;
+        }
+
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => {
+            db.execute("COMMIT").context("commit")?;
+            Ok(())
+        }
+        Err(err) => {
+            db.execute("ROLLBACK").ok();
+            Err(err)
+        }
+    }
+}
+
+/// Remove the hourly tenant rows that are older than the retention
+/// period. This is independent of recording the counts, so that
+/// a failure to prune doesn't affect the counts.
+fn prune_tenants(
+    db: &ConnectionThreadSafe,
+    now: DateTime<Utc>,
+    retention: Duration,
+) -> anyhow::Result<()> {
+    let Some(cutoff) = chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| now.checked_sub_signed(retention))
+    else {
+        return Ok(());
+    };
+    let mut prune = db
+        .prepare("DELETE FROM tenant_accounting WHERE event_time < $cutoff")
+        .context("prepare prune")?;
+    prune.bind(("$cutoff", cutoff.format(HOUR_FORMAT).to_string().as_str()))?;
+    prune.next()?;
+    Ok(())
+}
+
+/// Apply the retention period to the hourly tenant rollups
+async fn prune() -> anyhow::Result<()> {
+    if config::is_validating() {
+        return Ok(());
+    }
+    let retention = *RETENTION.lock();
+    get_main_runtime()
+        .spawn_blocking(move || {
+            let db = open_accounting_db().context("open_accounting_db")?;
+            prune_tenants(&db, Utc::now(), retention)
+        })
+        .await?
+}
+
+/// Query the hourly tenant rollups. Any pending counts are
+/// flushed to the database first, so that the results are
+/// up to date.
+pub async fn query_tenant_accounting(
+    request: AccountingV1Request,
+) -> anyhow::Result<Vec<AccountingV1Entry>> {
+    ACCT.flush(false).await?;
+
+    get_main_runtime()
+        .spawn_blocking(move || {
+            let db = open_accounting_db().context("open_accounting_db")?;
+
+            let mut query = db
+                .prepare(
+                    "SELECT * FROM tenant_accounting
+                WHERE ($tenant IS NULL OR tenant = $tenant)
+                AND ($campaign IS NULL OR campaign = $campaign)
+                AND ($since IS NULL OR event_time >= $since)
+                AND ($until IS NULL OR event_time < $until)
+                ORDER BY event_time, tenant, campaign",
+                )
+                .context("prepare")?;
+
+            // Include the bucket that contains `since`
+            let since = request.since.map(|t| t.format(HOUR_FORMAT).to_string());
+            let until = request
+                .until
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+            query.bind(("$tenant", request.tenant.as_deref()))?;
+            query.bind(("$campaign", request.campaign.as_deref()))?;
+            query.bind(("$since", since.as_deref()))?;
+            query.bind(("$until", until.as_deref()))?;
+
+            let mut entries = vec![];
+            while let State::Row = query.next()? {
+                let event_time: String = query.read("event_time")?;
+                let event_time = NaiveDateTime::parse_from_str(&event_time, "%Y-%m-%d %H:%M:%S")
+                    .with_context(|| format!("parsing event_time {event_time}"))?
+                    .and_utc();
+                let tenant: String = query.read("tenant")?;
+                let campaign: String = query.read("campaign")?;
+
+                entries.push(AccountingV1Entry {
+                    event_time,
+                    tenant: (!tenant.is_empty()).then_some(tenant),
+                    campaign: (!campaign.is_empty()).then_some(campaign),
+                    received: query.read::<i64, _>("received")? as u64,
+                    delivered: query.read::<i64, _>("delivered")? as u64,
+                    bounced: query.read::<i64, _>("bounced")? as u64,
+                    expired: query.read::<i64, _>("expired")? as u64,
+                    received_bytes: query.read::<i64, _>("received_bytes")? as u64,
+                    delivered_bytes: query.read::<i64, _>("delivered_bytes")? as u64,
+                });
+            }
+
+            Ok(entries)
+        })
+        .await?
+}
+
 fn open_accounting_db() -> anyhow::Result<ConnectionThreadSafe> {
     let path = DB_PATH.lock().clone();
     tracing::trace!("using path {path:?} for accounting db");
-    let mut db = Connection::open_thread_safe(&path)
+    open_accounting_db_at(&path)
+}
+
+fn open_accounting_db_at(path: &str) -> anyhow::Result<ConnectionThreadSafe> {
+    let mut db = Connection::open_thread_safe(path)
         .with_context(|| format!("opening accounting database {path}"))?;
     db.set_busy_timeout(30_000)?;
 
@@ -182,6 +485,19 @@ CREATE TABLE IF NOT EXISTS accounting (
     received int NOT NULL,
     delivered int NOT NULL
 );
+
+CREATE TABLE IF NOT EXISTS tenant_accounting (
+    event_time DATETIME NOT NULL,
+    tenant text NOT NULL,
+    campaign text NOT NULL,
+    received int NOT NULL,
+    delivered int NOT NULL,
+    bounced int NOT NULL,
+    expired int NOT NULL,
+    received_bytes int NOT NULL,
+    delivered_bytes int NOT NULL,
+    PRIMARY KEY (event_time, tenant, campaign)
+);
     "#;
 
     db.execute(query)?;
@@ -207,5 +523,106 @@ async fn flusher() {
         if let Err(err) = result {
             tracing::error!("Error flushing accounting logs: {err:#}");
         }
+
+        if let Err(err) = prune().await {
+            tracing::error!("Error pruning tenant accounting logs: {err:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn counts(db: &ConnectionThreadSafe) -> (Vec<(String, i64, i64)>, Vec<(String, String, i64)>) {
+        let mut query = db
+            .prepare("SELECT event_time, received, delivered FROM accounting")
+            .unwrap();
+        let mut accounting = vec![];
+        while let State::Row = query.next().unwrap() {
+            accounting.push((
+                query.read::<String, _>("event_time").unwrap(),
+                query.read::<i64, _>("received").unwrap(),
+                query.read::<i64, _>("delivered").unwrap(),
+            ));
+        }
+
+        let mut query = db
+            .prepare(
+                "SELECT event_time, tenant, received FROM tenant_accounting ORDER BY event_time",
+            )
+            .unwrap();
+        let mut tenants = vec![];
+        while let State::Row = query.next().unwrap() {
+            tenants.push((
+                query.read::<String, _>("event_time").unwrap(),
+                query.read::<String, _>("tenant").unwrap(),
+                query.read::<i64, _>("received").unwrap(),
+            ));
+        }
+        (accounting, tenants)
+    }
+
+    fn tenant_counts(event_time: &str, received: u64) -> HashMap<TenantKey, TenantCounts> {
+        let mut by_tenant = HashMap::new();
+        by_tenant.insert(
+            TenantKey {
+                event_time: event_time.to_string(),
+                tenant: "mytenant".to_string(),
+                campaign: "".to_string(),
+            },
+            TenantCounts {
+                received,
+                ..Default::default()
+            },
+        );
+        by_tenant
+    }
+
+    #[test]
+    fn write_counts_is_atomic() {
+        let db = open_accounting_db_at(":memory:").unwrap();
+        let now = DateTime::parse_from_rfc3339("2024-03-10T12:30:00Z")
+            .unwrap()
+            .to_utc();
+
+        write_counts(&db, now, 3, 2, &tenant_counts("2024-03-10 12:00:00", 3)).unwrap();
+        write_counts(&db, now, 1, 1, &tenant_counts("2024-03-10 12:00:00", 1)).unwrap();
+        k9::assert_equal!(
+            counts(&db),
+            (
+                vec![("2024-03-01 00:00:00".to_string(), 4, 3)],
+                vec![("2024-03-10 12:00:00".to_string(), "mytenant".to_string(), 4)]
+            )
+        );
+
+        // If the tenant counts cannot be written, neither are the
+        // overall counts, so that retrying doesn't count them twice
+        db.execute("DROP TABLE tenant_accounting").unwrap();
+        assert!(write_counts(&db, now, 5, 5, &tenant_counts("2024-03-10 12:00:00", 5)).is_err());
+        let mut query = db.prepare("SELECT received FROM accounting").unwrap();
+        assert!(matches!(query.next().unwrap(), State::Row));
+        assert_eq!(query.read::<i64, _>("received").unwrap(), 4);
+    }
+
+    #[test]
+    fn prune_tenants_applies_retention() {
+        let db = open_accounting_db_at(":memory:").unwrap();
+        let now = DateTime::parse_from_rfc3339("2024-03-10T12:30:00Z")
+            .unwrap()
+            .to_utc();
+
+        let mut by_tenant = tenant_counts("2024-03-08 11:00:00", 1);
+        by_tenant.extend(tenant_counts("2024-03-09 13:00:00", 2));
+        write_counts(&db, now, 3, 0, &by_tenant).unwrap();
+
+        prune_tenants(&db, now, Duration::from_secs(86400)).unwrap();
+        k9::assert_equal!(
+            counts(&db),
+            (
+                vec![("2024-03-01 00:00:00".to_string(), 3, 0)],
+                vec![("2024-03-09 13:00:00".to_string(), "mytenant".to_string(), 2)]
+            )
+        );
     }
 }
@@ -0,0 +1,259 @@
+//! Built-in processing of asynchronous (out-of-band) bounces and
+//! feedback reports that are sent to a listener domain.
+use kumo_log_types::rfc5965::ARFReport;
+use mailparsing::MimePart;
+use message::verp::{VerpData, VerpScheme};
+use regex::Regex;
+use rfc5321::{EnhancedStatusCode, Response};
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+static STATUS_LINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\b([245]\d\d)[ -]#?([245])\.(\d{1,3})\.(\d{1,3})\b").unwrap());
+static ENHANCED_CODE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|[\s#(])([245])\.(\d{1,3})\.(\d{1,3})\b").unwrap());
+static ADDRESS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"<?([^\s<>():;,"'@]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,})>?"#).unwrap()
+});
+static BOUNCE_SUBJECT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)undeliver|not (be )?delivered|delivery (status|failure|has failed|problem)|returned mail|failure notice|mail delivery|delivery notification",
+    )
+    .unwrap()
+});
+static BOUNCE_FROM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)mailer-daemon|postmaster|mail delivery").unwrap());
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct BounceProcessorParams {
+    /// How to decode the VERP addresses that receive bounces
+    #[serde(default)]
+    pub verp: VerpScheme,
+
+    /// Whether to look for a status code and recipient in
+    /// bounces that are not RFC 3464 delivery status notifications
+    #[serde(default = "BounceProcessorParams::default_true")]
+    pub heuristics: bool,
+}
+
+impl BounceProcessorParams {
+    fn default_true() -> bool {
+        true
+    }
+
+    /// Recover the original message id and recipient from the
+    /// address to which the bounce was sent, falling back to
+    /// the original envelope sender recorded in a feedback report
+    pub async fn decode_verp(&self, bounce_address: &str, arf: Option<&ARFReport>) -> VerpData {
+        let candidates = std::iter::once(bounce_address)
+            .chain(arf.and_then(|report| report.original_mail_from.as_deref()));
+        for address in candidates {
+            let address = address.trim().trim_start_matches('<').trim_end_matches('>');
+            let local_part = match address.rsplit_once('@') {
+                Some((local, _domain)) => local,
+                None => address,
+            };
+            match self.verp.decode(local_part).await {
+                Ok(Some(data)) => return data,
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::debug!("ignoring VERP address {address}: {err:#}");
+                }
+            }
+        }
+        VerpData::default()
+    }
+}
+
+/// The result of heuristically examining a non-standard bounce
+#[derive(Debug, PartialEq, Eq)]
+pub struct HeuristicBounce {
+    pub recipient: Option<String>,
+    pub response: Response,
+}
+
+/// Examine a message that doesn't contain an RFC 3464 report and
+/// attempt to extract the failing recipient and the status from
+/// the text of the message.  Returns None if the message doesn't
+/// look like a bounce, or no status could be found.
+pub fn parse_heuristic(data: &[u8]) -> Option<HeuristicBounce> {
+    let mail = MimePart::parse(data).ok()?;
+    let structure = mail.simplified_structure().ok()?;
+    let header_text = |name: &str| -> String {
+        structure
+            .headers
+            .get_first(name)
+            .and_then(|h| h.as_unstructured().ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default()
+    };
+
+    if header_text("Auto-Submitted")
+        .to_ascii_lowercase()
+        .contains("auto-replied")
+    {
+        // Vacation and other auto-responses are not bounces
+        return None;
+    }
+    if !BOUNCE_SUBJECT.is_match(&header_text("Subject"))
+        && !BOUNCE_FROM.is_match(&header_text("From"))
+    {
+        return None;
+    }
+
+    let text = structure.text?;
+    let text = text.to_str_lossy();
+
+    let mut last_address = None;
+    for line in text.lines() {
+        let line = line.trim();
+        let address_in_line = ADDRESS.captures(line).map(|c| c[1].to_string());
+
+        let status = if let Some(caps) = STATUS_LINE.captures(line) {
+            Some((
+                caps[1].parse::<u16>().ok()?,
+                enhanced_code(&caps[2], &caps[3], &caps[4])?,
+            ))
+        } else if let Some(caps) = ENHANCED_CODE.captures(line) {
+            let enhanced = enhanced_code(&caps[1], &caps[2], &caps[3])?;
+            Some((enhanced.class as u16 * 100 + 50, enhanced))
+        } else {
+            None
+        };
+
+        match status {
+            Some((code, enhanced)) => {
+                return Some(HeuristicBounce {
+                    recipient: address_in_line.or(last_address),
+                    response: Response {
+                        code,
+                        enhanced_code: Some(enhanced),
+                        content: line.to_string(),
+                        command: None,
+                    },
+                });
+            }
+            None => {
+                if address_in_line.is_some() {
+                    last_address = address_in_line;
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn enhanced_code(class: &str, subject: &str, detail: &str) -> Option<EnhancedStatusCode> {
+    Some(EnhancedStatusCode {
+        class: class.parse().ok()?,
+        subject: subject.parse().ok()?,
+        detail: detail.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn qmail_bounce() {
+        let bounce = parse_heuristic(
+            b"From: MAILER-DAEMON@mx.example.com\r\n\
+Subject: failure notice\r\n\
+\r\n\
+Hi. This is the qmail-send program at mx.example.com.\r\n\
+I'm afraid I wasn't able to deliver your message to the following addresses.\r\n\
+This is a permanent error; I've given up. Sorry it didn't work out.\r\n\
+\r\n\
+<user@example.com>:\r\n\
+Remote host said: 550 5.1.1 mailbox unavailable\r\n",
+        )
+        .unwrap();
+        assert_eq!(bounce.recipient.as_deref(), Some("user@example.com"));
+        assert_eq!(bounce.response.code, 550);
+        assert_eq!(
+            bounce.response.enhanced_code,
+            Some(EnhancedStatusCode {
+                class: 5,
+                subject: 1,
+                detail: 1
+            })
+        );
+        assert_eq!(
+            bounce.response.content,
+            "Remote host said: 550 5.1.1 mailbox unavailable"
+        );
+    }
+
+    #[test]
+    fn enhanced_code_only() {
+        let bounce = parse_heuristic(
+            b"From: postmaster@example.net\r\n\
+Subject: Undeliverable: hello\r\n\
+\r\n\
+Delivery has failed to these recipients or groups:\r\n\
+\r\n\
+Remote Server returned '#5.2.2 mailbox full' for user@example.net\r\n",
+        )
+        .unwrap();
+        assert_eq!(bounce.recipient.as_deref(), Some("user@example.net"));
+        assert_eq!(bounce.response.code, 550);
+        assert_eq!(
+            bounce.response.enhanced_code,
+            Some(EnhancedStatusCode {
+                class: 5,
+                subject: 2,
+                detail: 2
+            })
+        );
+    }
+
+    #[test]
+    fn not_a_bounce() {
+        assert_eq!(
+            parse_heuristic(
+                b"From: someone@example.com\r\n\
+Subject: hello\r\n\
+\r\n\
+version 5.1.1 is out\r\n"
+            ),
+            None
+        );
+        assert_eq!(
+            parse_heuristic(
+                b"From: someone@example.com\r\n\
+Subject: Undeliverable mail?\r\n\
+Auto-Submitted: auto-replied\r\n\
+\r\n\
+I am away until 5.1.1\r\n"
+            ),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn decode_verp() {
+        let params = BounceProcessorParams {
+            verp: VerpScheme::default(),
+            heuristics: true,
+        };
+        let data = params
+            .decode_verp(
+                "bounce-1d98076abbbc11ed940250ebf67f93bd-user=example.com@bounce.example.com",
+                None,
+            )
+            .await;
+        assert_eq!(
+            data.id.unwrap().to_string(),
+            "1d98076abbbc11ed940250ebf67f93bd"
+        );
+        assert_eq!(data.recipient.as_deref(), Some("user@example.com"));
+
+        assert_eq!(
+            params.decode_verp("fbl@example.com", None).await,
+            VerpData::default()
+        );
+    }
+}
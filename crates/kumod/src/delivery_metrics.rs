@@ -6,11 +6,15 @@ use crate::metrics_helper::{
     TOTAL_MSGS_FAIL_BY_PROVIDER, TOTAL_MSGS_FAIL_BY_PROVIDER_AND_SOURCE,
     TOTAL_MSGS_TRANSFAIL_BY_PROVIDER, TOTAL_MSGS_TRANSFAIL_BY_PROVIDER_AND_SOURCE,
 };
+use chrono::Utc;
+use kumo_api_types::warmup::{WarmupSchedule, WarmupStage};
 use kumo_prometheus::prometheus::Histogram;
 use kumo_prometheus::{counter_bundle, AtomicCounter};
 use parking_lot::Mutex;
+use spool::SpoolId;
 use std::collections::HashMap;
 use std::sync::{Arc, LazyLock};
+use std::time::Instant;
 
 counter_bundle! {
     pub struct ReadyCountBundle {
@@ -53,6 +57,10 @@ pub struct DeliveryMetrics {
     fail: DispositionBundle,
 
     pub deliver_message_rollup: Histogram,
+
+    age_at_first_attempt: Histogram,
+    age_at_delivery: Histogram,
+    session_duration: Histogram,
 }
 
 impl std::fmt::Debug for DeliveryMetrics {
@@ -70,6 +78,7 @@ impl DeliveryMetrics {
             client: Some(client),
             metrics: self.clone(),
             armed: true,
+            started: Instant::now(),
         }
     }
 
@@ -139,6 +148,16 @@ impl DeliveryMetrics {
         };
         let provider_key = BorrowedProviderKey { provider };
 
+        // The histograms are not pruned, so we avoid using the
+        // site name as a fallback label value for them
+        let histogram_provider = provider_name.as_deref().unwrap_or("");
+        let age_at_first_attempt = crate::metrics_helper::MESSAGE_AGE_AT_FIRST_ATTEMPT
+            .with_label_values(&[histogram_provider, pool]);
+        let age_at_delivery = crate::metrics_helper::MESSAGE_AGE_AT_DELIVERY
+            .with_label_values(&[histogram_provider, pool]);
+        let session_duration = crate::metrics_helper::CONNECTION_SESSION_DURATION
+            .with_label_values(&[service_type, histogram_provider, pool]);
+
         let source_provider_msgs_fail = TOTAL_MSGS_FAIL_BY_PROVIDER_AND_SOURCE
             .get_or_create(&provider_source_key as &dyn ProviderAndSourceKeyTrait);
         let source_provider_msgs_delivered = TOTAL_MSGS_DELIVERED_BY_PROVIDER_AND_SOURCE
@@ -210,6 +229,9 @@ impl DeliveryMetrics {
             delivered,
             transfail,
             fail,
+            age_at_first_attempt,
+            age_at_delivery,
+            session_duration,
         }
     }
 
@@ -233,6 +255,17 @@ impl DeliveryMetrics {
         self.delivered.inc();
     }
 
+    /// Record the age of a message that is about to be attempted
+    /// for the first time
+    pub fn observe_first_attempt_age(&self, id: &SpoolId) {
+        self.age_at_first_attempt.observe(message_age_seconds(id));
+    }
+
+    /// Record the age of a message that was successfully delivered
+    pub fn observe_delivery_age(&self, id: &SpoolId) {
+        self.age_at_delivery.observe(message_age_seconds(id));
+    }
+
     /// Fork this `DeliveryMetrics` so the returned view carries
     /// per-dispatcher counters.
     pub fn for_dispatcher(&self) -> (DeliveryMetrics, DispatcherDispositionCounters) {
@@ -249,6 +282,41 @@ impl DeliveryMetrics {
     }
 }
 
+fn message_age_seconds(id: &SpoolId) -> f64 {
+    (Utc::now() - id.created())
+        .to_std()
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Gauges describing the warm-up schedule of a ready queue.
+/// These are only created for queues whose egress path has
+/// a warm-up schedule, so that the metrics are not reported
+/// for every queue in the system.
+#[derive(Debug)]
+pub struct WarmupGauges {
+    day: AtomicCounter,
+    daily_volume: AtomicCounter,
+    slowed_down: AtomicCounter,
+}
+
+impl WarmupGauges {
+    pub fn new(service: &str) -> Self {
+        Self {
+            day: crate::metrics_helper::warmup_day_gauge_for_service(service),
+            daily_volume: crate::metrics_helper::warmup_daily_volume_gauge_for_service(service),
+            slowed_down: crate::metrics_helper::warmup_slowed_down_gauge_for_service(service),
+        }
+    }
+
+    pub fn update(&self, schedule: &WarmupSchedule, stage: &WarmupStage) {
+        self.day.set(stage.day(schedule));
+        self.daily_volume
+            .set(stage.daily_volume().unwrap_or(0) as usize);
+        self.slowed_down.set(stage.is_slowed_down() as usize);
+    }
+}
+
 /// Per-dispatcher disposition counters.
 #[derive(Clone, Debug)]
 pub struct DispatcherDispositionCounters {
@@ -265,6 +333,7 @@ pub struct MetricsWrappedConnection<T> {
     client: Option<T>,
     metrics: DeliveryMetrics,
     armed: bool,
+    started: Instant,
 }
 
 impl<T> MetricsWrappedConnection<T> {
@@ -275,23 +344,29 @@ impl<T> MetricsWrappedConnection<T> {
             client: Some(client),
             metrics: self.metrics.clone(),
             armed: true,
+            started: self.started,
         }
     }
 
     pub fn take(mut self) -> T {
+        self.disarm();
+        self.client.take().expect("to take only once")
+    }
+
+    fn disarm(&mut self) {
         if self.armed {
             self.metrics.connection_gauge.dec();
+            self.metrics
+                .session_duration
+                .observe(self.started.elapsed().as_secs_f64());
             self.armed = false;
         }
-        self.client.take().expect("to take only once")
     }
 }
 
 impl<T> Drop for MetricsWrappedConnection<T> {
     fn drop(&mut self) {
-        if self.armed {
-            self.metrics.connection_gauge.dec();
-        }
+        self.disarm();
     }
 }
 
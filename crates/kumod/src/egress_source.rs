@@ -2,13 +2,16 @@ use crate::http_server::admin_suspend_ready_q_v1::AdminSuspendReadyQEntry;
 use crate::queue::{opt_timeout_at, Queue, QueueConfig, ReadyQueueFull};
 use crate::ready_queue::{ReadyQueueHandle, ReadyQueueManager, ReadyQueueName};
 use anyhow::Context;
+use chrono::Utc;
 use config::epoch::ConfigEpoch;
 use config::{CallbackSignature, LuaConfig};
 use data_loader::KeySource;
 use dns_resolver::{resolve_socket_addr, IpLookupStrategy};
 use gcd::Gcd;
 use kumo_address::resolvable::ResolvableSocketAddr;
+use kumo_api_types::egress_path::EgressPathConfig;
 use kumo_api_types::shaping::Trigger;
+use kumo_api_types::warmup::WarmupStage;
 use kumo_log_types::MaybeProxiedSourceAddress;
 use kumo_prometheus::declare_metric;
 use kumo_server_common::config_handle::ConfigHandle;
@@ -958,6 +961,13 @@ impl EgressPoolSourceSelector {
     }
 }
 
+/// Compute the warm-up stage that applies today for the
+/// provided path configuration, if it has a warm-up schedule
+pub fn current_warmup_stage(path_config: &EgressPathConfig) -> Option<WarmupStage> {
+    let schedule = path_config.warmup.as_ref()?;
+    Some(schedule.stage_at(Utc::now().date_naive(), path_config.warmup_slowdown))
+}
+
 /// If selection is throttled, return Some(delay)
 async fn get_source_selection_throttle_delay(
     deadline: Option<Instant>,
@@ -971,7 +981,8 @@ async fn get_source_selection_throttle_delay(
             1
         } else {
             0
-        } + path_config.additional_source_selection_rates.len(),
+        } + path_config.additional_source_selection_rates.len()
+            + path_config.warmup.is_some() as usize,
     );
 
     let rate_name;
@@ -988,6 +999,23 @@ async fn get_source_selection_throttle_delay(
         throttles.push((key, throttle));
     }
 
+    let warmup_name;
+    let warmup_throttle;
+
+    match (&path_config.warmup, current_warmup_stage(&path_config)) {
+        (Some(schedule), Some(stage)) => {
+            site.update_warmup_gauges(Some((schedule, &stage)));
+            if let Some(throttle) = schedule.throttle_for_stage(&stage) {
+                warmup_name = format!("kumomta.warmup.{}.{source_name}", site.name());
+                warmup_throttle = throttle;
+                throttles.push((&warmup_name, &warmup_throttle));
+            }
+        }
+        _ => {
+            site.update_warmup_gauges(None);
+        }
+    }
+
     if throttles.is_empty() {
         return Ok(None);
     }
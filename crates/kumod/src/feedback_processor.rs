@@ -0,0 +1,350 @@
+//! Built-in processing of complaint feedback loop (FBL) reports
+//! that are sent to a listener domain.
+//!
+//! The original message that is embedded in an ARF report is
+//! examined in order to recover the spool id, recipient, tenant
+//! and campaign of the message that was complained about, and the
+//! mailbox provider that sent the report is identified so that
+//! complaints can be counted per provider.
+use crate::metrics_helper::feedback_reports_for_provider;
+use kumo_log_types::rfc5965::ARFReport;
+use mailparsing::{Header, HeaderMap, HeaderParseResult};
+use rfc5321::Response;
+use serde::{Deserialize, Serialize};
+use spool::SpoolId;
+use std::collections::BTreeMap;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct FeedbackProcessorParams {
+    /// The name of a header in the original message that holds
+    /// its spool id
+    #[serde(default)]
+    pub correlation_header: Option<String>,
+
+    /// Whether to recover the spool id from the Message-ID of the
+    /// original message, as generated by kumod when fixing a
+    /// missing Message-ID header
+    #[serde(default = "FeedbackProcessorParams::default_true")]
+    pub message_id_correlation: bool,
+
+    /// The header in the original message that holds its tenant
+    #[serde(default = "FeedbackProcessorParams::default_tenant_header")]
+    pub tenant_header: String,
+
+    /// The header in the original message that holds its campaign
+    #[serde(default = "FeedbackProcessorParams::default_campaign_header")]
+    pub campaign_header: String,
+
+    /// Maps a provider name to the list of domain suffixes that
+    /// identify it.  The domain of the original recipient and the
+    /// domain of the sender of the report are considered.
+    #[serde(default)]
+    pub providers: BTreeMap<String, Vec<String>>,
+}
+
+/// What was learned about the original message and the provider
+/// that sent the report
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeedbackAttribution {
+    pub id: Option<SpoolId>,
+    pub recipient: Option<String>,
+    pub tenant: Option<String>,
+    pub campaign: Option<String>,
+    pub provider: String,
+}
+
+impl FeedbackAttribution {
+    /// Produce a response that describes the report, so that
+    /// traffic shaping automation rules can match complaints
+    pub fn response(&self, report: &ARFReport) -> Response {
+        Response {
+            code: 250,
+            enhanced_code: None,
+            content: format!(
+                "{} feedback report from {}",
+                report.feedback_type, self.provider
+            ),
+            command: None,
+        }
+    }
+}
+
+impl FeedbackProcessorParams {
+    fn default_true() -> bool {
+        true
+    }
+
+    fn default_tenant_header() -> String {
+        "X-Tenant".to_string()
+    }
+
+    fn default_campaign_header() -> String {
+        "X-Campaign".to_string()
+    }
+
+    /// Examine the report and the original message that it contains.
+    /// `report_sender` is the envelope sender of the report.
+    pub fn attribute(&self, report: &ARFReport, report_sender: &str) -> FeedbackAttribution {
+        let original = report.original_message.as_ref().map(|m| m.as_slice());
+        let headers = original.and_then(|original| Header::parse_headers(original).ok());
+        let headers = headers
+            .as_ref()
+            .map(|HeaderParseResult { headers, .. }| headers);
+
+        let trace_field = |name: &str| -> Option<String> {
+            report
+                .supplemental_trace
+                .as_ref()
+                .and_then(|trace| trace.get(name))
+                .and_then(|value| match value {
+                    serde_json::Value::String(s) => Some(s.to_string()),
+                    serde_json::Value::Array(list) if list.len() == 1 => {
+                        list[0].as_str().map(|s| s.to_string())
+                    }
+                    _ => None,
+                })
+        };
+
+        let id = headers.and_then(|headers| self.correlate(headers));
+
+        let recipient = trace_field("recipient").or_else(|| {
+            report
+                .original_rcpto_to
+                .first()
+                .map(|r| r.trim_start_matches('<').trim_end_matches('>').to_string())
+        });
+
+        let tenant = headers
+            .and_then(|headers| header_value(headers, &self.tenant_header))
+            .or_else(|| trace_field("tenant"));
+        let campaign = headers
+            .and_then(|headers| header_value(headers, &self.campaign_header))
+            .or_else(|| trace_field("campaign"));
+
+        let provider = self.identify_provider(recipient.as_deref(), report_sender);
+
+        FeedbackAttribution {
+            id,
+            recipient,
+            tenant,
+            campaign,
+            provider,
+        }
+    }
+
+    fn correlate(&self, headers: &HeaderMap) -> Option<SpoolId> {
+        if let Some(name) = &self.correlation_header {
+            if let Some(id) = header_value(headers, name).and_then(|v| parse_spool_id(&v)) {
+                return Some(id);
+            }
+        }
+        if self.message_id_correlation {
+            let message_id = header_value(headers, "Message-ID")?;
+            let message_id = message_id
+                .trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>');
+            let (local_part, _domain) = message_id.split_once('@')?;
+            return parse_spool_id(local_part);
+        }
+        None
+    }
+
+    /// Returns the name of the first provider whose domain suffixes
+    /// match the domain of the recipient or of the report sender,
+    /// or "unknown" if none match
+    fn identify_provider(&self, recipient: Option<&str>, report_sender: &str) -> String {
+        let domains = [recipient, Some(report_sender)]
+            .into_iter()
+            .flatten()
+            .filter_map(|address| address.rsplit_once('@').map(|(_, domain)| domain))
+            .map(|domain| {
+                domain
+                    .trim_end_matches('>')
+                    .trim_end_matches('.')
+                    .to_ascii_lowercase()
+            })
+            .collect::<Vec<_>>();
+
+        for domain in &domains {
+            for (provider, suffixes) in &self.providers {
+                if suffixes
+                    .iter()
+                    .any(|suffix| domain_matches_suffix(domain, suffix))
+                {
+                    return provider.to_string();
+                }
+            }
+        }
+        "unknown".to_string()
+    }
+}
+
+fn header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_first(name)
+        .and_then(|header| header.as_unstructured().ok())
+        .map(|value| value.to_string().trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Parses a spool id, accepting only the time based ids that are
+/// generated by kumod, as the creation time is derived from it
+fn parse_spool_id(s: &str) -> Option<SpoolId> {
+    SpoolId::from_str(s.trim()).filter(|id| id.as_bytes()[6] >> 4 == 1)
+}
+
+fn domain_matches_suffix(domain: &str, suffix: &str) -> bool {
+    let suffix = suffix.trim_start_matches('.').to_ascii_lowercase();
+    domain == suffix
+        || domain
+            .strip_suffix(&suffix)
+            .map(|prefix| prefix.ends_with('.'))
+            .unwrap_or(false)
+}
+
+/// Account for a complaint against the provider that sent it
+pub fn count_complaint(report: &ARFReport, attribution: &FeedbackAttribution) {
+    if report.feedback_type.eq_ignore_ascii_case("not-spam") {
+        return;
+    }
+    feedback_reports_for_provider(&attribution.provider).inc();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn params() -> FeedbackProcessorParams {
+        FeedbackProcessorParams {
+            correlation_header: Some("X-Kumo-Id".to_string()),
+            message_id_correlation: true,
+            tenant_header: FeedbackProcessorParams::default_tenant_header(),
+            campaign_header: FeedbackProcessorParams::default_campaign_header(),
+            providers: [
+                ("yahoo".to_string(), vec!["yahoo.com".to_string()]),
+                (
+                    "microsoft".to_string(),
+                    vec!["outlook.com".to_string(), "hotmail.com".to_string()],
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    fn report(original: &str, original_rcpt_to: Option<&str>) -> ARFReport {
+        let rcpt = original_rcpt_to
+            .map(|r| format!("Original-Rcpt-To: {r}\r\n"))
+            .unwrap_or_default();
+        let data = format!(
+            "From: <feedback@arf.mail.yahoo.com>\r\n\
+Subject: FW: hello\r\n\
+Content-Type: multipart/report; report-type=feedback-report;\r\n\
+\tboundary=\"boundary\"\r\n\
+\r\n\
+--boundary\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+This is an abuse report\r\n\
+--boundary\r\n\
+Content-Type: message/feedback-report\r\n\
+\r\n\
+Feedback-Type: abuse\r\n\
+User-Agent: SomeGenerator/1.0\r\n\
+Version: 1\r\n\
+{rcpt}\
+\r\n\
+--boundary\r\n\
+Content-Type: message/rfc822\r\n\
+\r\n\
+{original}\
+--boundary--\r\n"
+        );
+        ARFReport::parse(data.as_bytes()).unwrap().unwrap()
+    }
+
+    #[test]
+    fn correlate_message_id() {
+        let report = report(
+            "From: sender@example.com\r\n\
+To: user@yahoo.com\r\n\
+Message-ID: <1d98076abbbc11ed940250ebf67f93bd@example.com>\r\n\
+X-Tenant: mytenant\r\n\
+X-Campaign: mycampaign\r\n\
+Subject: hello\r\n\
+\r\n\
+hello\r\n",
+            Some("user@yahoo.com"),
+        );
+        let attribution = params().attribute(&report, "feedback@arf.mail.yahoo.com");
+        assert_eq!(
+            attribution,
+            FeedbackAttribution {
+                id: SpoolId::from_str("1d98076abbbc11ed940250ebf67f93bd"),
+                recipient: Some("user@yahoo.com".to_string()),
+                tenant: Some("mytenant".to_string()),
+                campaign: Some("mycampaign".to_string()),
+                provider: "yahoo".to_string(),
+            }
+        );
+        assert_eq!(
+            attribution.response(&report).content,
+            "abuse feedback report from yahoo"
+        );
+    }
+
+    #[test]
+    fn correlate_header() {
+        let report = report(
+            "From: sender@example.com\r\n\
+Message-ID: <not-a-spool-id@example.com>\r\n\
+X-Kumo-Id: 1d98076abbbc11ed940250ebf67f93bd\r\n\
+Subject: hello\r\n\
+\r\n\
+hello\r\n",
+            None,
+        );
+        let attribution = params().attribute(&report, "staff@hotmail.com");
+        assert_eq!(
+            attribution,
+            FeedbackAttribution {
+                id: SpoolId::from_str("1d98076abbbc11ed940250ebf67f93bd"),
+                recipient: None,
+                tenant: None,
+                campaign: None,
+                provider: "microsoft".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn ignore_non_spool_id() {
+        // A random (v4) uuid has no timestamp and cannot be a spool id
+        let report = report(
+            "From: sender@example.com\r\n\
+Message-ID: <b4a3c7f2d9e84c1a9f3e2d1c0b9a8f7e@example.com>\r\n\
+Subject: hello\r\n\
+\r\n\
+hello\r\n",
+            None,
+        );
+        let attribution = params().attribute(&report, "fbl@example.com");
+        assert_eq!(attribution.id, None);
+    }
+
+    #[test]
+    fn unknown_provider() {
+        let report = report(
+            "From: sender@example.com\r\n\
+Subject: hello\r\n\
+\r\n\
+hello\r\n",
+            Some("user@example.net"),
+        );
+        let attribution = params().attribute(&report, "fbl@notyahoo.com");
+        assert_eq!(attribution.id, None);
+        assert_eq!(attribution.provider, "unknown");
+    }
+}
@@ -0,0 +1,24 @@
+use axum::extract::{Json, Query};
+use kumo_api_types::accounting::{AccountingV1Entry, AccountingV1Request};
+use kumo_server_common::http_server::AppError;
+
+/// Returns the hourly accounting rollups of the volume of messages
+/// that were received, delivered, bounced and expired for each
+/// tenant and campaign, optionally filtered by tenant, campaign
+/// and time range.
+#[utoipa::path(
+    get,
+    tags=["accounting", "kcli:accounting"],
+    path="/api/admin/accounting/v1",
+    params(AccountingV1Request),
+    responses(
+        (status = 200, description = "Returned the matching accounting entries", body=[AccountingV1Entry])
+    ),
+)]
+pub async fn accounting_v1(
+    Query(request): Query<AccountingV1Request>,
+) -> Result<Json<Vec<AccountingV1Entry>>, AppError> {
+    Ok(Json(
+        crate::accounting::query_tenant_accounting(request).await?,
+    ))
+}
@@ -0,0 +1,144 @@
+use crate::quarantine;
+use axum::extract::{Json, Query};
+use axum::http::StatusCode;
+use kumo_api_types::quarantine::{
+    QuarantineV1ActionRequest, QuarantineV1ActionResponse, QuarantineV1InspectRequest,
+    QuarantineV1InspectResponse, QuarantineV1ListEntry, QuarantineV1ListRequest,
+};
+use kumo_api_types::MessageInformation;
+use kumo_server_common::http_server::AppError;
+use kumo_server_runtime::rt_spawn;
+
+/// Allows the system operator to list the messages that are
+/// currently held in quarantine, optionally filtered by the
+/// scheduled queue criteria and quarantine reason.
+#[utoipa::path(
+    get,
+    tags=["quarantine", "kcli:quarantine-list"],
+    path="/api/admin/quarantine/v1",
+    params(QuarantineV1ListRequest),
+    responses(
+        (status = 200, description = "Returned the matching quarantined messages", body=[QuarantineV1ListEntry])
+    ),
+)]
+pub async fn list_v1(
+    Query(request): Query<QuarantineV1ListRequest>,
+) -> Result<Json<Vec<QuarantineV1ListEntry>>, AppError> {
+    Ok(Json(quarantine::list(&request.selector(), request.limit)))
+}
+
+/// Retrieve information about a quarantined message given its spool id.
+#[utoipa::path(
+    get,
+    tags=["quarantine", "kcli:quarantine-inspect"],
+    path="/api/admin/quarantine/inspect/v1",
+    params(QuarantineV1InspectRequest),
+    responses(
+        (status = 200, description = "Obtained message information", body=QuarantineV1InspectResponse),
+        (status = 404, description = "The requested id is not in quarantine"),
+    ),
+)]
+pub async fn inspect_v1(
+    Query(request): Query<QuarantineV1InspectRequest>,
+) -> Result<Json<QuarantineV1InspectResponse>, AppError> {
+    let Some((entry, msg)) = quarantine::get(&request.id) else {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            format!("{} is not in quarantine", request.id),
+        ));
+    };
+
+    let meta = msg.get_meta_obj().await?;
+    let scheduling = msg
+        .get_scheduling()
+        .await?
+        .and_then(|s| serde_json::to_value(s).ok());
+    let data = if request.want_body {
+        Some(String::from_utf8_lossy(&msg.data().await?).into())
+    } else {
+        None
+    };
+    // Don't keep the body resident in memory while it is held
+    msg.shrink()?;
+
+    Ok(Json(QuarantineV1InspectResponse {
+        message: MessageInformation {
+            sender: entry.sender.clone(),
+            recipient: entry.recipient.clone(),
+            meta,
+            data,
+            due: None,
+            num_attempts: Some(msg.get_num_attempts()),
+            scheduling,
+        },
+        entry,
+    }))
+}
+
+fn check_action_request(request: &QuarantineV1ActionRequest) -> Result<(), AppError> {
+    if request.selector.is_match_all() && !request.all {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "no selection criteria were specified; set `all` to act \
+             on every quarantined message",
+        ));
+    }
+    Ok(())
+}
+
+/// Allows the system operator to release quarantined messages
+/// back into their scheduled queues, making them eligible for
+/// delivery. Each released message is logged with a
+/// QuarantineRelease record.
+#[utoipa::path(
+    post,
+    tags=["quarantine", "kcli:quarantine-release"],
+    path="/api/admin/quarantine/release/v1",
+    request_body=QuarantineV1ActionRequest,
+    responses(
+        (status = 200, description = "Released the matching messages", body=QuarantineV1ActionResponse)
+    ),
+)]
+pub async fn release_v1(
+    // Note: Json<> must be last in the param list
+    Json(request): Json<QuarantineV1ActionRequest>,
+) -> Result<Json<QuarantineV1ActionResponse>, AppError> {
+    check_action_request(&request)?;
+
+    // Move into a lua-capable thread so that logging related
+    // lua events can be triggered by log_disposition.
+    let count = rt_spawn("quarantine_release_v1", async move {
+        quarantine::release(&request.selector, request.comment.as_deref()).await
+    })?
+    .await?;
+
+    Ok(Json(QuarantineV1ActionResponse { count }))
+}
+
+/// Allows the system operator to delete quarantined messages.
+/// Each deleted message is logged with a QuarantineDelete record.
+///
+/// !!! danger
+///     There is no way to undo the actions carried out by this request!
+#[utoipa::path(
+    post,
+    tags=["quarantine", "kcli:quarantine-delete"],
+    path="/api/admin/quarantine/delete/v1",
+    request_body=QuarantineV1ActionRequest,
+    responses(
+        (status = 200, description = "Deleted the matching messages", body=QuarantineV1ActionResponse)
+    ),
+)]
+pub async fn delete_v1(
+    // Note: Json<> must be last in the param list
+    Json(request): Json<QuarantineV1ActionRequest>,
+) -> Result<Json<QuarantineV1ActionResponse>, AppError> {
+    check_action_request(&request)?;
+
+    let count = rt_spawn("quarantine_delete_v1", async move {
+        quarantine::delete(&request.selector, request.comment.as_deref()).await
+    })?
+    .await?;
+
+    Ok(Json(QuarantineV1ActionResponse { count }))
+}
@@ -0,0 +1,85 @@
+use crate::suppression;
+use axum::extract::{Json, Query};
+use axum::http::StatusCode;
+use kumo_api_types::suppression::{
+    SuppressionV1AddRequest, SuppressionV1DeleteRequest, SuppressionV1DeleteResponse,
+    SuppressionV1Entry, SuppressionV1ListRequest,
+};
+use kumo_server_common::http_server::AppError;
+
+fn check_enabled() -> Result<(), AppError> {
+    if !suppression::is_enabled() {
+        return Err(AppError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "the suppression list has not been configured; \
+             see kumo.configure_suppression",
+        ));
+    }
+    Ok(())
+}
+
+/// Allows the system operator to list the entries in the
+/// suppression list, optionally filtered by recipient, domain,
+/// tenant or source.
+#[utoipa::path(
+    get,
+    tags=["suppression", "kcli:suppression-list"],
+    path="/api/admin/suppression/v1",
+    params(SuppressionV1ListRequest),
+    responses(
+        (status = 200, description = "Returned the matching entries", body=[SuppressionV1Entry])
+    ),
+)]
+pub async fn list_v1(
+    Query(request): Query<SuppressionV1ListRequest>,
+) -> Result<Json<Vec<SuppressionV1Entry>>, AppError> {
+    check_enabled()?;
+    Ok(Json(suppression::list(&request)))
+}
+
+/// Allows the system operator to add a recipient or domain to
+/// the suppression list. Any existing entry for the same recipient
+/// or domain and tenant is replaced.
+#[utoipa::path(
+    post,
+    tags=["suppression", "kcli:suppression-add"],
+    path="/api/admin/suppression/v1",
+    request_body=SuppressionV1AddRequest,
+    responses(
+        (status = 200, description = "Added the entry", body=SuppressionV1Entry),
+        (status = 400, description = "The request is invalid"),
+    ),
+)]
+pub async fn add_v1(
+    // Note: Json<> must be last in the param list
+    Json(request): Json<SuppressionV1AddRequest>,
+) -> Result<Json<SuppressionV1Entry>, AppError> {
+    check_enabled()?;
+    let entry = suppression::add_admin(request)
+        .await
+        .map_err(|err| AppError::new(StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+    Ok(Json(entry))
+}
+
+/// Allows the system operator to remove a recipient or domain
+/// from the suppression list.
+#[utoipa::path(
+    post,
+    tags=["suppression", "kcli:suppression-delete"],
+    path="/api/admin/suppression/delete/v1",
+    request_body=SuppressionV1DeleteRequest,
+    responses(
+        (status = 200, description = "Deleted the matching entry", body=SuppressionV1DeleteResponse),
+        (status = 400, description = "The request is invalid"),
+    ),
+)]
+pub async fn delete_v1(
+    // Note: Json<> must be last in the param list
+    Json(request): Json<SuppressionV1DeleteRequest>,
+) -> Result<Json<SuppressionV1DeleteResponse>, AppError> {
+    check_enabled()?;
+    let count = suppression::delete(request)
+        .await
+        .map_err(|err| AppError::new(StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+    Ok(Json(SuppressionV1DeleteResponse { count }))
+}
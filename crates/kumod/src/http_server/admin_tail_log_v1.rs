@@ -0,0 +1,288 @@
+use crate::logging::classify::apply_classification;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::response::IntoResponse;
+use kumo_api_types::tail_log::{JsonLogRecord, TailLogV1Event, TailLogV1Request};
+use message::queue_name::QueueNameComponents;
+use std::sync::{Arc, LazyLock};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::{channel, Sender};
+
+static MGR: LazyLock<LogTailManager> = LazyLock::new(LogTailManager::new);
+
+pub struct LogTailManager {
+    tx: Sender<Arc<JsonLogRecord>>,
+}
+
+impl LogTailManager {
+    pub fn new() -> Self {
+        let (tx, _rx) = channel(16 * 1024);
+        Self { tx }
+    }
+
+    /// Returns true if there are any connected tail-log subscribers
+    pub fn is_active() -> bool {
+        MGR.tx.receiver_count() > 0
+    }
+
+    /// Submit a log record to any connected tail-log subscribers.
+    /// The record is classified prior to submission so that
+    /// subscribers can filter on its bounce classification.
+    pub async fn submit(record: &JsonLogRecord) {
+        if !Self::is_active() {
+            return;
+        }
+        let mut record = record.clone();
+        apply_classification(&mut record).await;
+        MGR.tx.send(Arc::new(record)).ok();
+    }
+}
+
+fn is_match(request: &TailLogV1Request, record: &JsonLogRecord) -> bool {
+    fn allowed(candidates: &[String], value: Option<&str>) -> bool {
+        if candidates.is_empty() {
+            return true;
+        }
+        match value {
+            Some(value) => candidates.iter().any(|c| c == value),
+            None => false,
+        }
+    }
+
+    if !request.record_type.is_empty() && !request.record_type.contains(&record.kind) {
+        return false;
+    }
+    if !allowed(&request.queue, Some(&record.queue)) {
+        return false;
+    }
+
+    if !request.domain.is_empty() {
+        let domain_matches = record
+            .recipient
+            .iter()
+            .any(|recip| match recip.rsplit_once('@') {
+                Some((_, domain)) => request
+                    .domain
+                    .iter()
+                    .any(|candidate| candidate.eq_ignore_ascii_case(domain)),
+                None => false,
+            });
+        if !domain_matches {
+            return false;
+        }
+    }
+
+    let components = QueueNameComponents::parse(&record.queue);
+    if !allowed(&request.tenant, components.tenant) {
+        return false;
+    }
+    if !allowed(&request.campaign, components.campaign) {
+        return false;
+    }
+
+    if !request.bounce_class.is_empty() {
+        let class: String = record.bounce_classification.clone().into();
+        if !request
+            .bounce_class
+            .iter()
+            .any(|candidate| candidate.eq_ignore_ascii_case(&class))
+        {
+            return false;
+        }
+    }
+
+    true
+}
+
+async fn process_websocket_inner(mut socket: WebSocket) -> anyhow::Result<()> {
+    let mut rx = MGR.tx.subscribe();
+    let mut has_lagged = false;
+
+    let request: TailLogV1Request = match socket
+        .recv()
+        .await
+        .ok_or_else(|| anyhow::anyhow!("websocket closed"))??
+    {
+        Message::Text(json) => serde_json::from_str(&json)?,
+        message => anyhow::bail!("unexpected {message:?}"),
+    };
+
+    loop {
+        tokio::select! {
+            record = rx.recv() => {
+                let event = match record {
+                    Ok(record) => {
+                        if !is_match(&request, &record) {
+                            continue;
+                        }
+                        TailLogV1Event::Record(Box::new((*record).clone()))
+                    }
+                    Err(RecvError::Closed) => {
+                        return Ok(());
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        if !has_lagged {
+                            tracing::error!(
+                                "Log tail lagged behind and missed {missed} records \
+                                 (this message is shown only once per tail session)"
+                            );
+                            has_lagged = true;
+                        }
+                        TailLogV1Event::Lagged { missed }
+                    }
+                };
+
+                let json = serde_json::to_string(&event)?;
+                socket.send(Message::Text(json.into())).await?;
+            }
+
+            msg = socket.recv() => {
+                match msg {
+                    Some(Ok(Message::Close(_))) => {
+                        return Ok(());
+                    }
+                    Some(Ok(Message::Ping(ping))) => {
+                        socket.send(Message::Pong(ping)).await?;
+                    }
+                    Some(Ok(Message::Pong(_))) => {
+                        continue;
+                    }
+                    Some(Ok(Message::Text(_) | Message::Binary(_))) => {
+                        tracing::error!("Received unexpected {msg:?} from client");
+                        return Ok(());
+                    }
+                    Some(Err(err)) => {
+                        tracing::error!("{err:#}, closing tail session");
+                        return Ok(());
+                    }
+                    None => {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn process_websocket(socket: WebSocket) {
+    if let Err(err) = process_websocket_inner(socket).await {
+        tracing::error!("error in websocket: {err:#}");
+    }
+}
+
+/// This is a websocket endpoint that streams log records as they are logged.
+/// It cannot be described via auto-generated docs extracted from the JSON Schema.
+#[utoipa::path(get, tags = ["logging", "kcli:tail-log"], path = "/api/admin/tail-log/v1")]
+pub async fn tail(ws: WebSocketUpgrade) -> impl IntoResponse {
+    ws.on_upgrade(|socket| process_websocket(socket))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kumo_api_types::tail_log::RecordType;
+
+    fn make_record(kind: RecordType, queue: &str, recipient: &str) -> JsonLogRecord {
+        JsonLogRecord {
+            queue: queue.to_string(),
+            recipient: vec![recipient.to_string()],
+            ..JsonLogRecord::empty(kind)
+        }
+    }
+
+    #[test]
+    fn empty_request_matches_everything() {
+        let request = TailLogV1Request::default();
+        assert!(is_match(
+            &request,
+            &make_record(RecordType::Delivery, "example.com", "user@example.com")
+        ));
+        assert!(is_match(
+            &request,
+            &make_record(RecordType::Reception, "", "")
+        ));
+    }
+
+    #[test]
+    fn record_type_and_queue() {
+        let request = TailLogV1Request {
+            record_type: vec![RecordType::Bounce, RecordType::TransientFailure],
+            queue: vec!["example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(is_match(
+            &request,
+            &make_record(RecordType::Bounce, "example.com", "user@example.com")
+        ));
+        assert!(!is_match(
+            &request,
+            &make_record(RecordType::Delivery, "example.com", "user@example.com")
+        ));
+        assert!(!is_match(
+            &request,
+            &make_record(RecordType::Bounce, "other.com", "user@other.com")
+        ));
+    }
+
+    #[test]
+    fn domain_is_case_insensitive() {
+        let request = TailLogV1Request {
+            domain: vec!["Example.COM".to_string()],
+            ..Default::default()
+        };
+        assert!(is_match(
+            &request,
+            &make_record(RecordType::Delivery, "example.com", "user@example.com")
+        ));
+        assert!(!is_match(
+            &request,
+            &make_record(RecordType::Delivery, "example.com", "user@sub.example.com")
+        ));
+        assert!(!is_match(
+            &request,
+            &make_record(RecordType::Delivery, "example.com", "postmaster")
+        ));
+    }
+
+    #[test]
+    fn tenant_and_campaign() {
+        let request = TailLogV1Request {
+            tenant: vec!["acme".to_string()],
+            campaign: vec!["newsletter".to_string()],
+            ..Default::default()
+        };
+        assert!(is_match(
+            &request,
+            &make_record(
+                RecordType::Delivery,
+                "newsletter:acme@example.com",
+                "user@example.com"
+            )
+        ));
+        assert!(!is_match(
+            &request,
+            &make_record(
+                RecordType::Delivery,
+                "newsletter:other@example.com",
+                "user@example.com"
+            )
+        ));
+        // No tenant or campaign in the queue name
+        assert!(!is_match(
+            &request,
+            &make_record(RecordType::Delivery, "example.com", "user@example.com")
+        ));
+    }
+
+    #[test]
+    fn bounce_class_is_case_insensitive() {
+        let request = TailLogV1Request {
+            bounce_class: vec!["invalidrecipient".to_string()],
+            ..Default::default()
+        };
+        let mut record = make_record(RecordType::Bounce, "example.com", "user@example.com");
+        assert!(!is_match(&request, &record));
+
+        record.bounce_classification = "InvalidRecipient".to_string().into();
+        assert!(is_match(&request, &record));
+    }
+}
@@ -0,0 +1,40 @@
+use axum::extract::{Json, Query};
+use axum::http::StatusCode;
+use kumo_api_types::tracking::{TraceMessageV1Request, TraceMessageV1Response};
+use kumo_server_common::http_server::AppError;
+
+/// Returns the chronological list of log records for the messages
+/// that match the specified message id, recipient, sender, tenant
+/// and/or campaign.
+/// This requires that message tracking has been enabled via
+/// `kumo.configure_message_tracking`.
+#[utoipa::path(
+    get,
+    tags=["inspect", "kcli:trace-message"],
+    path="/api/admin/trace-message/v1",
+    params(TraceMessageV1Request),
+    responses(
+        (status = 200, description = "Returned the matching log records", body=TraceMessageV1Response),
+        (status = 400, description = "No message id, recipient, sender, tenant or campaign was specified"),
+        (status = 404, description = "Message tracking is not enabled"),
+    ),
+)]
+pub async fn trace_message_v1(
+    Query(request): Query<TraceMessageV1Request>,
+) -> Result<Json<TraceMessageV1Response>, AppError> {
+    if !crate::logging::tracking::is_enabled() {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "message tracking is not enabled; see kumo.configure_message_tracking",
+        ));
+    }
+    if !request.has_selector() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "at least one of message_id, recipient, sender, tenant or campaign must be specified",
+        ));
+    }
+    Ok(Json(
+        crate::logging::tracking::query_message_trace(request).await?,
+    ))
+}
@@ -1,6 +1,7 @@
 use crate::delivery_metrics::MetricsWrappedConnection;
 use crate::logging::disposition::{log_disposition, LogDisposition, RecordType};
 use crate::queue::{DeliveryProto, QueueConfig, QueueManager};
+use crate::quota::QuotaExceeded;
 use crate::ready_queue::{AttemptConnectionDisposition, Dispatcher, QueueDispatcher};
 use crate::smtp_server::{default_hostname, TraceHeaders};
 use crate::spool::SpoolManager;
@@ -8,6 +9,7 @@ use anyhow::Context;
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use axum::extract::{Json, State};
+use axum::http::HeaderMap;
 use axum_client_ip::ClientIp;
 use config::{any_err, get_or_create_sub_module, load_config, LuaConfig, SerdeWrappedValue};
 use kumo_api_types::InjectV1Response;
@@ -32,7 +34,7 @@ use std::collections::{BTreeMap, HashMap};
 use std::net::{IpAddr, Ipv4Addr};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, LazyLock};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 use throttle::ThrottleSpec;
 use utoipa::ToSchema;
 
@@ -357,6 +359,18 @@ pub struct InjectV1Request {
     #[serde(default)]
     #[schema(default = "Jinja")]
     pub template_dialect: TemplateDialectWithSchema,
+
+    /// {{since('dev', inline=True)}}
+    ///
+    /// An optional W3C trace context `traceparent` value. When OTLP
+    /// trace export is enabled, the traces for the generated messages
+    /// will be linked as children of this context.
+    ///
+    /// If omitted, the `traceparent` HTTP request header, if present,
+    /// will be used instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")]
+    pub traceparent: Option<String>,
 }
 
 #[derive(Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug, ToSchema)]
@@ -816,6 +830,10 @@ async fn make_message<'a>(
             )
             .await?;
     }
+    request
+        .trace_headers
+        .assign_trace_context(&message, request.traceparent.as_deref())
+        .await?;
     Ok(message)
 }
 
@@ -845,21 +863,50 @@ async fn process_recipient<'a>(
     .await?;
 
     // call callback to assign to queue
-    config
+    let event_start = SystemTime::now();
+    let result = config
         .async_call_callback(
             &HTTP_MESSAGE_GENERATED,
             (message.clone(), SerdeWrappedValue(auth.clone())),
         )
-        .await?;
+        .await;
+    crate::message_trace::record_span(
+        &message,
+        "http_message_generated",
+        event_start,
+        vec![],
+        result
+            .as_ref()
+            .map(|_| ())
+            .map_err(|err| format!("{err:#}")),
+    )
+    .await;
+    result?;
 
     // spool and insert to queue
     let queue_name = message.get_queue_name().await?;
 
     if queue_name != "null" {
+        let _reservation =
+            crate::quota::reserve(&queue_name, message.get_data_maybe_not_loaded().len())?;
+        crate::suppression::check_message(&message, &queue_name).await?;
         request.trace_headers.apply_supplemental(&message).await?;
 
         if !request.deferred_spool {
-            message.save(None).await?;
+            let save_start = SystemTime::now();
+            let result = message.save(None).await;
+            crate::message_trace::record_span(
+                &message,
+                "spool_write",
+                save_start,
+                vec![],
+                result
+                    .as_ref()
+                    .map(|_| ())
+                    .map_err(|err| format!("{err:#}")),
+            )
+            .await;
+            result?;
         }
         log_disposition(LogDisposition {
             kind: RecordType::Reception,
@@ -993,6 +1040,7 @@ async fn inject_v1_impl(
     let mut fail_count = 0;
     let mut errors = vec![];
     let mut failed_recipients = vec![];
+    let mut quota_exceeded = None;
     let mut config = load_config().await?;
     for recip in &request.recipients {
         match process_recipient(
@@ -1015,11 +1063,22 @@ async fn inject_v1_impl(
                 fail_count += 1;
                 failed_recipients.push(recip.email.to_string());
                 errors.push(format!("{}: {err:#}", recip.email));
+                if let Some(exceeded) = err.downcast_ref::<QuotaExceeded>() {
+                    quota_exceeded.replace(exceeded.to_string());
+                }
             }
         }
     }
     config.put();
 
+    // If nothing could be accepted because of a quota, report
+    // that via the status so that the client knows to back off
+    if success_count == 0 {
+        if let Some(reason) = quota_exceeded {
+            return Err(AppError::new(StatusCode::TOO_MANY_REQUESTS, reason));
+        }
+    }
+
     Ok(Json(InjectV1Response {
         success_count,
         fail_count,
@@ -1235,17 +1294,26 @@ pub fn activity_for_peer(
     responses(
         (status = 200, description = "Message(s) injected successfully", body=InjectV1Response),
         (status = 422, description = "One or more fields in the content section have syntax errors"),
+        (status = 429, description = "None of the recipients could be accepted because a message quota has been reached"),
     ),
 )]
 pub async fn inject_v1(
     auth: AuthInfo,
     ClientIp(peer_address): ClientIp,
     State(app_state): State<AppState>,
+    headers: HeaderMap,
     // Note: Json<> must be last in the param list
-    Json(request): Json<InjectV1Request>,
+    Json(mut request): Json<InjectV1Request>,
 ) -> Result<Json<InjectV1Response>, AppError> {
     let activity = activity_for_peer("inject_v1", peer_address)?;
 
+    if request.traceparent.is_none() {
+        request.traceparent = headers
+            .get("traceparent")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+    }
+
     let limit = LIMIT.load();
     if let Some(limit) = limit.as_ref() {
         loop {
@@ -1465,6 +1533,7 @@ This is a test message to {{ name }}, with some 👻🍉💩 emoji!
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: Default::default(),
+            traceparent: None,
         };
 
         let compiled = request.compile().unwrap();
@@ -1515,6 +1584,7 @@ This is a test message to {{ name }}, with some 👻🍉💩 emoji!
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: Default::default(),
+            traceparent: None,
         };
 
         let compiled = request.compile().unwrap();
@@ -1577,6 +1647,7 @@ This is a test message to James Smythe, with some =F0=9F=91=BB=F0=9F=8D=89=\r
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: Default::default(),
+            traceparent: None,
         };
 
         request.normalize().unwrap();
@@ -1660,6 +1731,7 @@ Some(
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: Default::default(),
+            traceparent: None,
         };
 
         request.normalize().unwrap();
@@ -1756,6 +1828,7 @@ Ok(
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: TemplateDialectWithSchema::Static,
+            traceparent: None,
         };
 
         request.normalize().unwrap();
@@ -1855,6 +1928,7 @@ Some(
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: TemplateDialectWithSchema::Handlebars,
+            traceparent: None,
         };
 
         request.normalize().unwrap();
@@ -1925,6 +1999,7 @@ Some(
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: TemplateDialectWithSchema::Handlebars,
+            traceparent: None,
         };
 
         request.normalize().unwrap();
@@ -2011,6 +2086,7 @@ Some(
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: TemplateDialectWithSchema::Handlebars,
+            traceparent: None,
         };
 
         request.normalize().unwrap();
@@ -2114,6 +2190,7 @@ Some(
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: TemplateDialectWithSchema::Handlebars,
+            traceparent: None,
         };
 
         request.normalize().unwrap();
@@ -2209,6 +2286,7 @@ Don't expand in here {{ name }}
             deferred_generation: false,
             trace_headers: Default::default(),
             template_dialect: TemplateDialectWithSchema::Handlebars,
+            traceparent: None,
         };
 
         request.normalize().unwrap();
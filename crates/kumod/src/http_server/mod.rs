@@ -3,14 +3,19 @@ use kumo_server_common::router_with_docs;
 use utoipa::OpenApi;
 
 pub mod abort_ready_q_conn_v1;
+pub mod admin_accounting_v1;
 pub mod admin_bounce_v1;
 pub mod admin_inspect_message;
 pub mod admin_inspect_scheduled_queue;
+pub mod admin_quarantine_v1;
 pub mod admin_ready_queue_states;
 pub mod admin_rebind_v1;
 pub mod admin_spool_compact_v1;
+pub mod admin_suppression_v1;
 pub mod admin_suspend_ready_q_v1;
 pub mod admin_suspend_v1;
+pub mod admin_tail_log_v1;
+pub mod admin_trace_message_v1;
 pub mod admin_trace_smtp_client_v1;
 pub mod admin_trace_smtp_server_v1;
 pub mod check_liveness_v1;
@@ -18,27 +23,38 @@ pub mod inject_v1;
 pub mod inspect_ready_q_v1;
 pub mod queue_name_multi_index;
 pub mod resolve_egress_path_v1;
+pub mod unsubscribe_v1;
 
 pub fn make_router() -> RouterAndDocs {
     router_with_docs!(
         title = "kumod",
         handlers = [
             abort_ready_q_conn_v1::abort_v1,
+            admin_accounting_v1::accounting_v1,
             admin_bounce_v1::bounce_v1,
             admin_bounce_v1::bounce_v1_delete,
             admin_bounce_v1::bounce_v1_list,
             admin_inspect_message::inspect_v1,
             admin_inspect_scheduled_queue::inspect_v1,
+            admin_quarantine_v1::delete_v1,
+            admin_quarantine_v1::inspect_v1,
+            admin_quarantine_v1::list_v1,
+            admin_quarantine_v1::release_v1,
             admin_ready_queue_states::readyq_states,
             inspect_ready_q_v1::inspect_v1,
             admin_rebind_v1::rebind_v1,
             admin_spool_compact_v1::spool_compact_v1,
+            admin_suppression_v1::add_v1,
+            admin_suppression_v1::delete_v1,
+            admin_suppression_v1::list_v1,
             admin_suspend_ready_q_v1::delete,
             admin_suspend_ready_q_v1::list,
             admin_suspend_ready_q_v1::suspend,
             admin_suspend_v1::delete,
             admin_suspend_v1::list,
             admin_suspend_v1::suspend,
+            admin_tail_log_v1::tail,
+            admin_trace_message_v1::trace_message_v1,
             admin_trace_smtp_client_v1::trace,
             admin_trace_smtp_server_v1::trace,
             check_liveness_v1::check_liveness_v1,
@@ -47,6 +63,7 @@ pub fn make_router() -> RouterAndDocs {
             crate::xfer::request::xfer_v1,
             inject_v1::inject_v1,
             resolve_egress_path_v1::resolve_v1,
+            unsubscribe_v1::unsubscribe_v1,
         ]
     )
 }
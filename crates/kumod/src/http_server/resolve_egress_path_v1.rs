@@ -1,3 +1,4 @@
+use crate::egress_source::current_warmup_stage;
 use crate::queue::Queue;
 use crate::ready_queue::{ReadyQueueManager, GET_EGRESS_PATH_CONFIG_SIG};
 use axum::extract::{Json, Query};
@@ -72,6 +73,7 @@ pub async fn resolve_v1(
 
     let constraints: EffectiveConstraints =
         path_config.compute_constraints(Some(&queue_constraints));
+    let warmup = current_warmup_stage(&path_config);
 
     Ok(Json(ResolveEgressPathV1Response {
         domain: request.domain,
@@ -81,5 +83,6 @@ pub async fn resolve_v1(
         queue_config: queue_config_value,
         path_config,
         constraints,
+        warmup,
     }))
 }
@@ -0,0 +1,72 @@
+use crate::http_server::inject_v1::activity_for_peer;
+use axum::extract::Query;
+use axum::http::StatusCode;
+use axum_client_ip::ClientIp;
+use kumo_server_common::http_server::AppError;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct UnsubscribeV1Params {
+    /// The signed token from the List-Unsubscribe header
+    pub token: String,
+}
+
+/// Accepts the RFC 8058 one-click unsubscribe request for the url
+/// that was placed into the List-Unsubscribe header by
+/// `kumo.list_unsubscribe.add_headers`.
+///
+/// The body must be `List-Unsubscribe=One-Click`.  When the token
+/// is valid, an Unsubscribe record is logged and, if configured,
+/// the recipient is added to the suppression list.
+///
+/// This endpoint is accessible without authentication in the
+/// default ACL, as it is intended to be called by mailbox providers.
+#[utoipa::path(
+    post,
+    tag="unsubscribe",
+    path="/unsubscribe/v1",
+    params(UnsubscribeV1Params),
+    request_body(
+        content=String,
+        content_type="application/x-www-form-urlencoded",
+        description="`List-Unsubscribe=One-Click`"
+    ),
+    responses(
+        (status = 200, description = "The recipient was unsubscribed"),
+        (status = 400, description = "The request or its token is invalid"),
+        (status = 503, description = "List-Unsubscribe has not been configured"),
+    ),
+)]
+pub async fn unsubscribe_v1(
+    ClientIp(peer_address): ClientIp,
+    Query(params): Query<UnsubscribeV1Params>,
+    // Note: the body must be last in the param list
+    body: String,
+) -> Result<(), AppError> {
+    let _activity = activity_for_peer("unsubscribe_v1", peer_address)?;
+
+    if !crate::list_unsubscribe::is_enabled() {
+        return Err(AppError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "List-Unsubscribe has not been configured; \
+             see kumo.configure_list_unsubscribe",
+        ));
+    }
+
+    if !body
+        .split('&')
+        .any(|pair| pair.trim() == "List-Unsubscribe=One-Click")
+    {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "expected List-Unsubscribe=One-Click",
+        ));
+    }
+
+    crate::list_unsubscribe::unsubscribe(&params.token, Some(peer_address), Some("HTTP"))
+        .await
+        .map_err(|err| AppError::new(StatusCode::BAD_REQUEST, format!("{err:#}")))?;
+
+    Ok(())
+}
@@ -0,0 +1,455 @@
+//! One-click unsubscribe (RFC 8058) support.
+//!
+//! `kumo.list_unsubscribe.add_headers` adds `List-Unsubscribe` and
+//! `List-Unsubscribe-Post` headers to a message.  The URIs in those
+//! headers carry a token that identifies the recipient, tenant and
+//! campaign of the message, along with an hmac signature so that the
+//! token cannot be forged:
+//!
+//! `eyJyIjoidXNlckBleGFtcGxlLmNvbSJ9.7bGk3lqNC0ZkTkR4yGm5b0vX1bVx1QGfaa6qV0ey2vQ`
+//!
+//! The http listener accepts the one-click POST for the https form,
+//! while the mailto form must be handled by policy, which can pass
+//! the token to `kumo.list_unsubscribe.unsubscribe`.
+use crate::logging::unsubscribe::{log_unsubscribe, LogUnsubscribe};
+use chrono::Utc;
+use config::{any_err, get_or_create_sub_module, serialize_options};
+use data_encoding::BASE64URL_NOPAD;
+use data_loader::KeySource;
+use kumo_log_types::ResolvedAddress;
+use kumo_prometheus::declare_metric;
+use lruttl::declare_cache;
+use message::queue_name::QueueNameComponents;
+use message::Message;
+use mlua::{Lua, LuaSerdeExt, UserDataRef};
+use reqwest::Url;
+use rfc5321::Response;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+static CONFIG: OnceLock<ListUnsubscribeParams> = OnceLock::new();
+
+declare_cache! {
+/// Caches the List-Unsubscribe hmac key based on its KeySource spec
+static KEY_CACHE: LruCacheWithTtl<KeySource, Arc<Vec<u8>>>::new("list_unsubscribe_key_cache", 16);
+}
+
+declare_metric! {
+/// The number of recipients that unsubscribed via List-Unsubscribe.
+static UNSUBSCRIBE_COUNT: IntCounter("list_unsubscribe_count");
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ListUnsubscribeParams {
+    /// The url of the one-click unsubscribe endpoint.  The token
+    /// is appended as the `token` query parameter.
+    #[serde(default)]
+    pub url: Option<String>,
+
+    /// The address to which mailto unsubscribe requests are sent.
+    /// The token is passed via the subject.
+    #[serde(default)]
+    pub mailto: Option<String>,
+
+    /// The shared secret used to sign the tokens
+    pub key: KeySource,
+
+    /// The digest algorithm; one of the algorithms supported
+    /// by the kumo.digest.hmac_XXX functions
+    #[serde(default = "ListUnsubscribeParams::default_algorithm")]
+    pub algorithm: String,
+
+    /// How long to cache the loaded key
+    #[serde(
+        default = "ListUnsubscribeParams::default_key_ttl",
+        with = "duration_serde"
+    )]
+    pub key_ttl: Duration,
+
+    /// How long the generated tokens remain valid.
+    /// If not set, they never expire.
+    #[serde(default, with = "duration_serde")]
+    pub expiration: Option<Duration>,
+
+    /// Whether to add unsubscribed recipients to the suppression list
+    #[serde(default)]
+    pub suppress: bool,
+
+    /// How long suppression entries that are added because of an
+    /// unsubscribe remain active.  If not set, they remain until
+    /// they are deleted.
+    #[serde(default, with = "duration_serde")]
+    pub suppression_duration: Option<Duration>,
+}
+
+impl ListUnsubscribeParams {
+    fn default_algorithm() -> String {
+        "sha256".to_string()
+    }
+
+    fn default_key_ttl() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.url.is_some() || self.mailto.is_some(),
+            "at least one of url or mailto must be specified"
+        );
+        if let Some(url) = &self.url {
+            let parsed =
+                Url::parse(url).map_err(|err| anyhow::anyhow!("invalid url {url}: {err:#}"))?;
+            // RFC 8058 requires an https url for one-click unsubscribe
+            anyhow::ensure!(parsed.scheme() == "https", "url {url} must be an https url");
+        }
+        // Verify that the algorithm is supported
+        mod_digest::hmac_sign(&self.algorithm, b"", b"")?;
+        Ok(())
+    }
+
+    async fn load_key(&self) -> anyhow::Result<Arc<Vec<u8>>> {
+        KEY_CACHE
+            .get_or_try_insert(&self.key, |_| self.key_ttl, async {
+                Ok::<Arc<Vec<u8>>, anyhow::Error>(Arc::new(self.key.get().await?))
+            })
+            .await
+            .map_err(|err| anyhow::anyhow!("loading List-Unsubscribe hmac key: {err:#}"))
+            .map(|lookup| lookup.item)
+    }
+
+    fn sign(&self, key: &[u8], payload: &str) -> anyhow::Result<String> {
+        let signature = mod_digest::hmac_sign(&self.algorithm, key, payload.as_bytes())?;
+        Ok(BASE64URL_NOPAD.encode(&signature))
+    }
+
+    fn encode_with_key(&self, token: &UnsubscribeToken, key: &[u8]) -> anyhow::Result<String> {
+        let payload = BASE64URL_NOPAD.encode(&serde_json::to_vec(token)?);
+        let signature = self.sign(key, &payload)?;
+        Ok(format!("{payload}.{signature}"))
+    }
+
+    fn decode_with_key(&self, token: &str, key: &[u8]) -> anyhow::Result<UnsubscribeToken> {
+        let (payload, signature) = token
+            .split_once('.')
+            .ok_or_else(|| anyhow::anyhow!("token is not signed"))?;
+        let signature = BASE64URL_NOPAD
+            .decode(signature.as_bytes())
+            .map_err(|_| anyhow::anyhow!("token signature is invalid"))?;
+        anyhow::ensure!(
+            mod_digest::hmac_verify(&self.algorithm, key, payload.as_bytes(), &signature)?,
+            "token signature is invalid"
+        );
+        let payload = BASE64URL_NOPAD
+            .decode(payload.as_bytes())
+            .map_err(|err| anyhow::anyhow!("token is malformed: {err:#}"))?;
+        let token: UnsubscribeToken = serde_json::from_slice(&payload)
+            .map_err(|err| anyhow::anyhow!("token is malformed: {err:#}"))?;
+        if let Some(expires) = token.expires {
+            anyhow::ensure!(expires > Utc::now().timestamp(), "token has expired");
+        }
+        Ok(token)
+    }
+
+    fn list_unsubscribe_header(&self, token: &str) -> anyhow::Result<String> {
+        let mut uris = vec![];
+        if let Some(url) = &self.url {
+            let mut url = Url::parse(url)?;
+            url.query_pairs_mut().append_pair("token", token);
+            uris.push(format!("<{url}>"));
+        }
+        if let Some(mailto) = &self.mailto {
+            uris.push(format!("<mailto:{mailto}?subject=unsubscribe:{token}>"));
+        }
+        Ok(uris.join(", "))
+    }
+}
+
+/// The information carried by the token in the List-Unsubscribe URIs.
+/// The field names are abbreviated to keep the token short.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct UnsubscribeToken {
+    #[serde(rename = "r")]
+    pub recipient: String,
+    #[serde(rename = "i", default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "t", default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(rename = "c", default, skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<String>,
+    /// The unix timestamp at which the token expires
+    #[serde(rename = "x", default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<i64>,
+}
+
+/// Returns true if List-Unsubscribe has been configured
+pub fn is_enabled() -> bool {
+    CONFIG.get().is_some()
+}
+
+fn get_config() -> anyhow::Result<&'static ListUnsubscribeParams> {
+    CONFIG
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("kumo.configure_list_unsubscribe has not been called"))
+}
+
+/// Add List-Unsubscribe and, if a url is configured,
+/// List-Unsubscribe-Post headers to msg, replacing any that
+/// are already present
+pub async fn add_headers(msg: &Message) -> anyhow::Result<()> {
+    let params = get_config()?;
+
+    let recipients = msg.recipient_list_string().await?;
+    let [recipient] = recipients.as_slice() else {
+        anyhow::bail!("List-Unsubscribe requires a message with exactly one recipient");
+    };
+
+    let queue_name = msg.get_queue_name().await?;
+    let components = QueueNameComponents::parse(&queue_name);
+    let expires = match params.expiration {
+        Some(expiration) => {
+            Some((Utc::now() + chrono::Duration::from_std(expiration)?).timestamp())
+        }
+        None => None,
+    };
+
+    let token = UnsubscribeToken {
+        recipient: recipient.to_string(),
+        id: Some(msg.id().to_string()),
+        tenant: components.tenant.map(|t| t.to_string()),
+        campaign: components.campaign.map(|c| c.to_string()),
+        expires,
+    };
+    let key = params.load_key().await?;
+    let token = params.encode_with_key(&token, &key)?;
+    let header = params.list_unsubscribe_header(&token)?;
+
+    msg.remove_all_named_headers("List-Unsubscribe").await?;
+    msg.remove_all_named_headers("List-Unsubscribe-Post")
+        .await?;
+    if params.url.is_some() {
+        msg.prepend_header(Some("List-Unsubscribe-Post"), b"List-Unsubscribe=One-Click")
+            .await?;
+    }
+    msg.prepend_header(Some("List-Unsubscribe"), header.as_bytes())
+        .await?;
+    Ok(())
+}
+
+/// Validate token and process the unsubscribe request that it
+/// represents: log an Unsubscribe record and, if configured, add
+/// the recipient to the suppression list.
+pub async fn unsubscribe(
+    token: &str,
+    peer_address: Option<IpAddr>,
+    reception_protocol: Option<&str>,
+) -> anyhow::Result<UnsubscribeToken> {
+    let params = get_config()?;
+    let key = params.load_key().await?;
+    let token = params.decode_with_key(token, &key)?;
+
+    let domain = token
+        .recipient
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.to_ascii_lowercase())
+        .unwrap_or_default();
+    let queue = QueueNameComponents::format(
+        token.campaign.as_deref(),
+        token.tenant.as_deref(),
+        domain,
+        None::<&str>,
+    );
+
+    UNSUBSCRIBE_COUNT.inc();
+    log_unsubscribe(LogUnsubscribe {
+        id: token.id.clone(),
+        recipient: token.recipient.clone(),
+        queue,
+        peer_address: peer_address.map(|addr| ResolvedAddress {
+            name: "".to_string(),
+            addr: addr.into(),
+            is_secure: false,
+        }),
+        response: Response {
+            code: 250,
+            enhanced_code: None,
+            content: "unsubscribed via List-Unsubscribe".to_string(),
+            command: None,
+        },
+        meta: serde_json::json!({
+            "tenant": token.tenant,
+            "campaign": token.campaign,
+        }),
+        reception_protocol: reception_protocol.map(|p| p.to_string()),
+    })
+    .await;
+
+    if params.suppress {
+        crate::suppression::process_unsubscribe(
+            &token.recipient,
+            token.tenant.as_deref(),
+            params.suppression_duration,
+        )
+        .await?;
+    }
+
+    Ok(token)
+}
+
+/// Configure List-Unsubscribe header generation and processing
+pub async fn configure(params: ListUnsubscribeParams) -> anyhow::Result<()> {
+    params.validate()?;
+
+    if config::is_validating() {
+        return Ok(());
+    }
+
+    CONFIG
+        .set(params)
+        .map_err(|_| anyhow::anyhow!("List-Unsubscribe has already been configured"))
+}
+
+pub fn register(lua: &Lua) -> anyhow::Result<()> {
+    let unsub_mod = get_or_create_sub_module(lua, "list_unsubscribe")?;
+
+    unsub_mod.set(
+        "add_headers",
+        lua.create_async_function(|_lua, msg: UserDataRef<Message>| async move {
+            add_headers(&msg).await.map_err(any_err)
+        })?,
+    )?;
+
+    unsub_mod.set(
+        "unsubscribe",
+        lua.create_async_function(|lua, token: String| async move {
+            let token = unsubscribe(&token, None, None).await.map_err(any_err)?;
+            lua.to_value_with(&token, serialize_options())
+        })?,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn params() -> ListUnsubscribeParams {
+        ListUnsubscribeParams {
+            url: Some("https://mta.example.com/unsubscribe/v1".to_string()),
+            mailto: Some("unsubscribe@example.com".to_string()),
+            key: KeySource::Data {
+                key_data: b"secret".to_vec(),
+            },
+            algorithm: ListUnsubscribeParams::default_algorithm(),
+            key_ttl: ListUnsubscribeParams::default_key_ttl(),
+            expiration: None,
+            suppress: false,
+            suppression_duration: None,
+        }
+    }
+
+    #[test]
+    fn token_round_trip() {
+        let params = params();
+        let token = UnsubscribeToken {
+            recipient: "user@example.com".to_string(),
+            id: Some("1d98076abbbc11ed940250ebf67f93bd".to_string()),
+            tenant: Some("mytenant".to_string()),
+            campaign: None,
+            expires: None,
+        };
+        let encoded = params.encode_with_key(&token, b"secret").unwrap();
+        assert_eq!(params.decode_with_key(&encoded, b"secret").unwrap(), token);
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                params.decode_with_key(&encoded, b"other").unwrap_err()
+            ),
+            "token signature is invalid"
+        );
+
+        let (payload, signature) = encoded.split_once('.').unwrap();
+        for forged in [
+            format!("{payload}.{}", &signature[1..]),
+            format!("{payload}.!{signature}"),
+            format!("{payload}."),
+        ] {
+            assert_eq!(
+                format!(
+                    "{:#}",
+                    params.decode_with_key(&forged, b"secret").unwrap_err()
+                ),
+                "token signature is invalid"
+            );
+        }
+
+        assert_eq!(
+            format!(
+                "{:#}",
+                params.decode_with_key(payload, b"secret").unwrap_err()
+            ),
+            "token is not signed"
+        );
+    }
+
+    #[test]
+    fn token_expiry() {
+        let params = params();
+        let token = UnsubscribeToken {
+            recipient: "user@example.com".to_string(),
+            id: None,
+            tenant: None,
+            campaign: None,
+            expires: Some(Utc::now().timestamp() - 60),
+        };
+        let encoded = params.encode_with_key(&token, b"secret").unwrap();
+        assert_eq!(
+            format!(
+                "{:#}",
+                params.decode_with_key(&encoded, b"secret").unwrap_err()
+            ),
+            "token has expired"
+        );
+    }
+
+    #[test]
+    fn validate() {
+        assert!(params().validate().is_ok());
+
+        let http = ListUnsubscribeParams {
+            url: Some("http://mta.example.com/unsubscribe/v1".to_string()),
+            ..params()
+        };
+        assert_eq!(
+            format!("{:#}", http.validate().unwrap_err()),
+            "url http://mta.example.com/unsubscribe/v1 must be an https url"
+        );
+
+        let mailto_only = ListUnsubscribeParams {
+            url: None,
+            ..params()
+        };
+        assert!(mailto_only.validate().is_ok());
+
+        let neither = ListUnsubscribeParams {
+            url: None,
+            mailto: None,
+            ..params()
+        };
+        assert!(neither.validate().is_err());
+    }
+
+    #[test]
+    fn header() {
+        let params = params();
+        assert_eq!(
+            params.list_unsubscribe_header("abc.def").unwrap(),
+            "<https://mta.example.com/unsubscribe/v1?token=abc.def>, \
+             <mailto:unsubscribe@example.com?subject=unsubscribe:abc.def>"
+        );
+    }
+}
@@ -1,6 +1,6 @@
 use anyhow::anyhow;
 use bounce_classify::{
-    BounceClass, BounceClassifier, BounceClassifierBuilder, PreDefinedBounceClass,
+    BounceClass, BounceClassifier, BounceClassifierBuilder, Classification, PreDefinedBounceClass,
 };
 use config::epoch::{get_current_epoch, ConfigEpoch};
 use kumo_log_types::JsonLogRecord;
@@ -36,6 +36,11 @@ pub struct ClassifierParams {
 
     #[serde(default = "ClassifierParams::default_cache_size")]
     pub uncategorized_cache_size: usize,
+
+    /// When enabled, the identity of the rule that produced the
+    /// classification is recorded in the log record
+    #[serde(default)]
+    pub log_matched_rule: bool,
 }
 
 impl ClassifierParams {
@@ -81,9 +86,20 @@ impl ClassifierParams {
     }
 }
 
-struct ClassifyRequest {
+/// The inputs to the classifier.  The site and provider are
+/// only populated when the classifier has rules that use them,
+/// so that the cache can be shared across sites and providers
+/// in the common case.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ClassifyKey {
     response: Response,
-    tx: oneshot::Sender<BounceClass>,
+    site: String,
+    provider: Option<String>,
+}
+
+struct ClassifyRequest {
+    key: ClassifyKey,
+    tx: oneshot::Sender<Classification>,
     epoch: ConfigEpoch,
 }
 
@@ -93,20 +109,38 @@ struct ClassifyRequest {
 /// and we don't want those to thrash and out-compete the actual
 /// classifications and render the whole class completely ineffective.
 struct State {
-    cache: LruCache<Response, BounceClass>,
-    uncat_cache: LruCache<Response, BounceClass>,
+    cache: LruCache<ClassifyKey, Classification>,
+    uncat_cache: LruCache<ClassifyKey, Classification>,
     classifier: Arc<BounceClassifier>,
     classifier_epoch: ConfigEpoch,
 }
 
 impl State {
-    fn insert(&mut self, response: Response, result: BounceClass) {
-        let cache = match &result {
+    fn insert(&mut self, key: ClassifyKey, result: Classification) {
+        let cache = match &result.class {
             BounceClass::PreDefined(PreDefinedBounceClass::Uncategorized) => &mut self.uncat_cache,
             _ => &mut self.cache,
         };
 
-        cache.insert(response.clone(), result.clone());
+        cache.insert(key, result);
+    }
+
+    /// Build the cache key for a response, discarding the site
+    /// and provider if the current classifier doesn't consider them
+    fn make_key(&self, response: &Response, site: &str, provider: Option<&str>) -> ClassifyKey {
+        ClassifyKey {
+            response: response.clone(),
+            site: if self.classifier.uses_site() {
+                site.to_string()
+            } else {
+                String::new()
+            },
+            provider: if self.classifier.uses_provider() {
+                provider.map(|p| p.to_string())
+            } else {
+                None
+            },
+        }
     }
 
     /// clear the caches and return a copy of the classifier.
@@ -144,6 +178,7 @@ impl State {
 struct ClassifierWrapper {
     tx: flume::Sender<ClassifyRequest>,
     state: Arc<Mutex<State>>,
+    log_matched_rule: bool,
 }
 
 impl ClassifierWrapper {
@@ -172,12 +207,7 @@ impl ClassifierWrapper {
                 .spawn(move || {
                     let mut my_epoch = epoch;
                     let mut classifier = classifier;
-                    while let Ok(ClassifyRequest {
-                        response,
-                        tx,
-                        epoch,
-                    }) = rx.recv()
-                    {
+                    while let Ok(ClassifyRequest { key, tx, epoch }) = rx.recv() {
                         tracing::trace!("classify request with {epoch:?}");
                         if epoch != my_epoch {
                             if let Some(c) = state.lock().get_updated_classifier(epoch) {
@@ -187,11 +217,12 @@ impl ClassifierWrapper {
                             }
                         }
 
-                        let result = classifier.classify_response(&response);
+                        let result =
+                            classifier.classify(&key.response, &key.site, key.provider.as_deref());
                         if epoch == my_epoch {
                             // Only cache if the epochs match up, as a cheap defensive
                             // measure to avoid poisoning the cache with a stale result
-                            state.lock().insert(response.clone(), result.clone());
+                            state.lock().insert(key, result.clone());
                         }
                         if tx.send(result).is_err() {
                             break;
@@ -233,26 +264,40 @@ impl ClassifierWrapper {
             });
         }
 
-        Ok(Self { tx, state })
+        Ok(Self {
+            tx,
+            state,
+            log_matched_rule: params.log_matched_rule,
+        })
     }
 
-    fn check_cache(&self, response: &Response) -> Option<BounceClass> {
+    /// Returns the cache key for the inputs, along with any
+    /// cached classification for it
+    fn check_cache(
+        &self,
+        response: &Response,
+        site: &str,
+        provider: Option<&str>,
+    ) -> (ClassifyKey, Option<Classification>) {
         let mut state = self.state.lock();
-        if let Some(result) = state.cache.get_mut(response) {
-            return Some(result.clone());
+        let key = state.make_key(response, site, provider);
+        if let Some(result) = state.cache.get_mut(&key) {
+            let result = result.clone();
+            return (key, Some(result));
         }
-        if let Some(result) = state.uncat_cache.get_mut(response) {
-            return Some(result.clone());
+        if let Some(result) = state.uncat_cache.get_mut(&key) {
+            let result = result.clone();
+            return (key, Some(result));
         }
 
-        None
+        (key, None)
     }
 
-    async fn classify(&self, response: Response) -> anyhow::Result<BounceClass> {
+    async fn classify(&self, key: ClassifyKey) -> anyhow::Result<Classification> {
         let (tx, rx) = oneshot::channel();
         self.tx
             .send_async(ClassifyRequest {
-                response,
+                key,
                 tx,
                 epoch: get_current_epoch(),
             })
@@ -261,7 +306,13 @@ impl ClassifierWrapper {
     }
 }
 
-pub async fn classify_response(response: &Response) -> BounceClass {
+/// Classify a response that was received from the specified site
+/// and provider
+pub async fn classify_response(
+    response: &Response,
+    site: &str,
+    provider: Option<&str>,
+) -> BounceClass {
     // If you have no classifier, you pay no cost
     let Some(classifier) = CLASSIFY.get() else {
         return BounceClass::default();
@@ -271,13 +322,14 @@ pub async fn classify_response(response: &Response) -> BounceClass {
 
     // Check the caches before we commit any serious resources to
     // classifying this response
-    if let Some(result) = classifier.check_cache(response) {
-        return result;
+    let (key, cached) = classifier.check_cache(response, site, provider);
+    if let Some(result) = cached {
+        return result.class;
     }
 
-    // clone data and pass to the classifier thread pool
-    match classifier.classify(response.clone()).await {
-        Ok(res) => res,
+    // pass to the classifier thread pool
+    match classifier.classify(key).await {
+        Ok(res) => res.class,
         Err(_) => BounceClass::default(),
     }
 }
@@ -296,13 +348,22 @@ pub async fn apply_classification(record: &mut JsonLogRecord) {
 
     // Check the caches before we commit any serious resources to
     // classifying this response
-    if let Some(result) = classifier.check_cache(&record.response) {
-        record.bounce_classification = result;
-        return;
-    }
+    let (key, cached) = classifier.check_cache(
+        &record.response,
+        &record.site,
+        record.provider_name.as_deref(),
+    );
+    let result = match cached {
+        Some(result) => result,
+        // pass to the classifier thread pool
+        None => match classifier.classify(key).await {
+            Ok(result) => result,
+            Err(_) => return,
+        },
+    };
 
-    // clone data and pass to the classifier thread pool
-    if let Ok(result) = classifier.classify(record.response.clone()).await {
-        record.bounce_classification = result;
+    if classifier.log_matched_rule {
+        record.bounce_classifier_rule = result.rule.map(|rule| rule.to_string());
     }
+    record.bounce_classification = result.class;
 }
@@ -1,3 +1,5 @@
+use crate::bounce_processor::parse_heuristic;
+use crate::http_server::admin_tail_log_v1::LogTailManager;
 use crate::logging::Logger;
 use crate::smtp_server::RelayDisposition;
 use bounce_classify::BounceClass;
@@ -6,8 +8,11 @@ use config::{load_config, CallbackSignature};
 use kumo_log_types::rfc3464::ReportAction;
 use kumo_log_types::MaybeProxiedSourceAddress;
 pub use kumo_log_types::*;
+use message::queue_name::QueueNameComponents;
+use message::verp::VerpData;
 use message::Message;
 use rfc5321::{EnhancedStatusCode, Response, TlsInformation};
+use std::collections::HashMap;
 use std::net::Ipv4Addr;
 use uuid::Uuid;
 
@@ -49,8 +54,21 @@ pub async fn log_disposition(args: LogDisposition<'_>) {
         recipient_list,
     } = args;
 
+    crate::message_trace::log_record(&msg, kind, &response, site).await;
+
     let loggers = Logger::get_loggers();
-    if loggers.is_empty() {
+    let tailing = LogTailManager::is_active();
+    let suppressing = crate::suppression::is_enabled();
+    // The feedback processor maintains complaint metrics even
+    // when nothing is being logged
+    let processing_feedback = relay_disposition
+        .as_ref()
+        .is_some_and(|disp| disp.feedback_processor.is_some());
+    // Tenant accounting is maintained regardless of whether
+    // anything is being logged
+    let tenant_accounting = crate::accounting::is_tenant_accounted(kind);
+    if loggers.is_empty() && !tailing && !suppressing && !processing_feedback && !tenant_accounting
+    {
         return;
     }
 
@@ -66,6 +84,13 @@ pub async fn log_disposition(args: LogDisposition<'_>) {
 
     let mut feedback_report = None;
 
+    let bounce_processor = relay_disposition
+        .as_ref()
+        .and_then(|disp| disp.bounce_processor.clone());
+    let feedback_processor = relay_disposition
+        .as_ref()
+        .and_then(|disp| disp.feedback_processor.clone());
+
     let reception_protocol = msg
         .get_meta_string("reception_protocol")
         .await
@@ -74,7 +99,11 @@ pub async fn log_disposition(args: LogDisposition<'_>) {
     if kind == RecordType::Reception {
         if relay_disposition
             .as_ref()
-            .map(|disp| disp.log_arf.should_log())
+            .map(|disp| {
+                disp.log_arf.should_log()
+                    || disp.bounce_processor.is_some()
+                    || disp.feedback_processor.is_some()
+            })
             .unwrap_or(false)
         {
             if let Ok(Some(report)) = msg.parse_rfc5965().await {
@@ -84,9 +113,317 @@ pub async fn log_disposition(args: LogDisposition<'_>) {
         }
     }
 
+    crate::accounting::account_tenant_record(
+        kind,
+        &msg,
+        reception_protocol.as_deref(),
+        recipient_list.len(),
+    )
+    .await;
+
     let now = Utc::now();
     let nodeid = kumo_server_common::nodeid::NodeId::get_uuid();
 
+    let mut tls_cipher = None;
+    let mut tls_protocol_version = None;
+    let mut tls_peer_subject_name = None;
+    if let Some(info) = tls_info {
+        tls_cipher.replace(info.cipher.clone());
+        tls_protocol_version.replace(info.protocol_version.clone());
+        tls_peer_subject_name.replace(info.subject_name.clone());
+    }
+
+    // When processing bounces, recover the original message id
+    // and recipient from the VERP address to which the bounce
+    // or report was sent, so that the records that we produce
+    // can be linked with the original message
+    let verp = match &bounce_processor {
+        Some(processor) if matches!(kind, RecordType::Reception | RecordType::Feedback) => {
+            let bounce_address = msg
+                .first_recipient()
+                .await
+                .map(|addr| addr.to_string())
+                .unwrap_or_default();
+            processor
+                .decode_verp(&bounce_address, feedback_report.as_deref())
+                .await
+        }
+        _ => VerpData::default(),
+    };
+    let linked_id = verp.id.map(|id| id.to_string());
+    let linked_created = verp.id.map(|id| id.created());
+
+    // The headers and meta are populated separately for each logger,
+    // based on its configuration
+    let mut record = JsonLogRecord {
+        kind,
+        id: msg.id().to_string(),
+        size: msg.get_data_maybe_not_loaded().len() as u64,
+        sender: msg
+            .sender()
+            .await
+            .map(|addr| addr.to_string())
+            .unwrap_or_else(|err| format!("{err:#}")),
+        recipient: recipient_list.clone(),
+        queue: msg
+            .get_queue_name()
+            .await
+            .unwrap_or_else(|err| format!("{err:#}")),
+        site: site.to_string(),
+        peer_address: peer_address.cloned(),
+        response: response.clone(),
+        timestamp: now,
+        created: msg.id().created(),
+        num_attempts: msg.get_num_attempts(),
+        egress_pool: egress_pool.map(|s| s.to_string()),
+        egress_source: egress_source.map(|s| s.to_string()),
+        bounce_classification: BounceClass::default(),
+        bounce_classifier_rule: None,
+        feedback_report: feedback_report.clone(),
+        headers: HashMap::new(),
+        meta: HashMap::new(),
+        delivery_protocol: delivery_protocol.map(|s| s.to_string()),
+        reception_protocol: reception_protocol.clone(),
+        nodeid,
+        tls_cipher,
+        tls_protocol_version,
+        tls_peer_subject_name,
+        source_address: source_address.clone(),
+        provider_name: provider.map(|s| s.to_string()),
+        session_id,
+        server_session: None,
+        client_session: None,
+    };
+
+    if kind == RecordType::Feedback && bounce_processor.is_some() {
+        if let Some(id) = &linked_id {
+            record.id = id.clone();
+        }
+        if let Some(created) = linked_created {
+            record.created = created;
+        }
+        if let Some(recipient) = &verp.recipient {
+            record.recipient = vec![recipient.clone()];
+        } else if let Some(report) = &feedback_report {
+            if !report.original_rcpto_to.is_empty() {
+                record.recipient = report.original_rcpto_to.clone();
+            }
+        }
+    }
+
+    // When processing complaint feedback loops, link the record with
+    // the original message and attribute it to its tenant, campaign
+    // and the provider that sent the report
+    let attribution = match (&feedback_processor, &feedback_report) {
+        (Some(processor), Some(report)) if kind == RecordType::Feedback => {
+            let report_sender = msg
+                .sender()
+                .await
+                .map(|addr| addr.to_string())
+                .unwrap_or_default();
+            let attribution = processor.attribute(report, &report_sender);
+
+            if let Some(id) = attribution.id {
+                record.id = id.to_string();
+                record.created = id.created();
+            }
+            if let Some(recipient) = &attribution.recipient {
+                record.recipient = vec![recipient.clone()];
+            }
+            let domain = attribution
+                .recipient
+                .as_deref()
+                .and_then(|recipient| recipient.rsplit_once('@'))
+                .map(|(_, domain)| domain)
+                .unwrap_or("");
+            record.queue = QueueNameComponents::format(
+                attribution.campaign.as_deref(),
+                attribution.tenant.as_deref(),
+                domain,
+                None::<&str>,
+            );
+            record.provider_name.replace(attribution.provider.clone());
+            record.response = attribution.response(report);
+
+            crate::feedback_processor::count_complaint(report, &attribution);
+            Some(attribution)
+        }
+        _ => None,
+    };
+
+    let mut oob_records = vec![];
+    let mut parsed_oob = false;
+    if kind == RecordType::Reception {
+        if relay_disposition
+            .as_ref()
+            .map(|disp| disp.log_oob.should_log() || disp.bounce_processor.is_some())
+            .unwrap_or(false)
+        {
+            if let Ok(Some(report)) = msg.parse_rfc3464().await {
+                parsed_oob = true;
+                // This incoming bounce report is addressed to
+                // the envelope from of the original message
+                let sender = msg
+                    .first_recipient()
+                    .await
+                    .map(|addr| addr.to_string())
+                    .unwrap_or_else(|err| format!("{err:#}"));
+
+                for recip in &report.per_recipient {
+                    if recip.action != ReportAction::Failed {
+                        continue;
+                    }
+
+                    let enhanced_code = EnhancedStatusCode {
+                        class: recip.status.class,
+                        subject: recip.status.subject,
+                        detail: recip.status.detail,
+                    };
+
+                    let (code, content) = match &recip.diagnostic_code {
+                        Some(diag) if diag.diagnostic_type == "smtp" => {
+                            if let Some((code, content)) = diag.diagnostic.split_once(' ') {
+                                if let Ok(code) = code.parse() {
+                                    (code, content.to_string())
+                                } else {
+                                    (550, diag.diagnostic.to_string())
+                                }
+                            } else {
+                                (550, diag.diagnostic.to_string())
+                            }
+                        }
+                        _ => (550, "".to_string()),
+                    };
+
+                    let recipient = match &verp.recipient {
+                        Some(recipient) => recipient.clone(),
+                        None => recip
+                            .original_recipient
+                            .as_ref()
+                            .unwrap_or(&recip.final_recipient)
+                            .recipient
+                            .to_string(),
+                    };
+
+                    oob_records.push(JsonLogRecord {
+                        kind: RecordType::OOB,
+                        id: linked_id.clone().unwrap_or_else(|| msg.id().to_string()),
+                        size: 0,
+                        sender: sender.clone(),
+                        recipient: vec![recipient],
+                        queue: record.queue.clone(),
+                        site: site.to_string(),
+                        peer_address: Some(ResolvedAddress {
+                            name: report.per_message.reporting_mta.name.to_string(),
+                            addr: peer_address
+                                .map(|a| a.addr.clone())
+                                .unwrap_or_else(|| Ipv4Addr::UNSPECIFIED.into()),
+                            is_secure: false,
+                        }),
+                        response: Response {
+                            code,
+                            enhanced_code: Some(enhanced_code),
+                            content,
+                            command: None,
+                        },
+                        timestamp: recip.last_attempt_date.unwrap_or_else(|| Utc::now()),
+                        created: linked_created.unwrap_or_else(|| msg.id().created()),
+                        num_attempts: 0,
+                        egress_pool: None,
+                        egress_source: None,
+                        bounce_classification: BounceClass::default(),
+                        bounce_classifier_rule: None,
+                        feedback_report: None,
+                        headers: HashMap::new(),
+                        meta: HashMap::new(),
+                        delivery_protocol: None,
+                        reception_protocol: reception_protocol.clone(),
+                        nodeid,
+                        tls_cipher: None,
+                        tls_protocol_version: None,
+                        tls_peer_subject_name: None,
+                        source_address: None,
+                        provider_name: provider.map(|s| s.to_string()),
+                        session_id,
+                        server_session: None,
+                        client_session: None,
+                    });
+                }
+            }
+        }
+    }
+
+    if kind == RecordType::Reception
+        && !parsed_oob
+        && bounce_processor.as_ref().is_some_and(|p| p.heuristics)
+    {
+        if let Some(bounce) = msg
+            .data()
+            .await
+            .ok()
+            .and_then(|data| parse_heuristic(&data))
+        {
+            match verp.recipient.clone().or(bounce.recipient) {
+                Some(recipient) if bounce.response.is_permanent() => {
+                    oob_records.push(JsonLogRecord {
+                        kind: RecordType::OOB,
+                        id: linked_id.clone().unwrap_or_else(|| msg.id().to_string()),
+                        size: 0,
+                        sender: msg
+                            .first_recipient()
+                            .await
+                            .map(|addr| addr.to_string())
+                            .unwrap_or_else(|err| format!("{err:#}")),
+                        recipient: vec![recipient],
+                        response: bounce.response,
+                        created: linked_created.unwrap_or_else(|| msg.id().created()),
+                        num_attempts: 0,
+                        tls_cipher: None,
+                        tls_protocol_version: None,
+                        tls_peer_subject_name: None,
+                        ..record.clone()
+                    });
+                }
+                _ => {
+                    tracing::debug!(
+                        "bounce processor: ignoring {} with response {:?}",
+                        msg.id(),
+                        bounce.response
+                    );
+                }
+            }
+        }
+    }
+
+    if suppressing {
+        // The recipients of a Feedback record are those of the report
+        // itself, rather than the original recipient that is the
+        // subject of the complaint
+        let recipients = match &feedback_report {
+            Some(report) if kind == RecordType::Feedback => {
+                match (
+                    &verp.recipient,
+                    attribution.as_ref().and_then(|a| a.recipient.as_ref()),
+                ) {
+                    (Some(recipient), _) | (None, Some(recipient)) => vec![recipient.clone()],
+                    (None, None) => report.original_rcpto_to.clone(),
+                }
+            }
+            _ => record.recipient.clone(),
+        };
+        crate::suppression::process_record(&record, &recipients).await;
+        for oob in &oob_records {
+            crate::suppression::process_record(oob, &oob.recipient).await;
+        }
+    }
+
+    if tailing {
+        LogTailManager::submit(&record).await;
+        for oob in &oob_records {
+            LogTailManager::submit(oob).await;
+        }
+    }
+
     for logger in loggers.iter() {
         if !logger.record_is_enabled(kind) {
             continue;
@@ -141,156 +478,25 @@ pub async fn log_disposition(args: LogDisposition<'_>) {
 
         let (headers, meta) = logger.extract_fields(&msg).await;
 
-        let mut tls_cipher = None;
-        let mut tls_protocol_version = None;
-        let mut tls_peer_subject_name = None;
-        if let Some(info) = tls_info {
-            tls_cipher.replace(info.cipher.clone());
-            tls_protocol_version.replace(info.protocol_version.clone());
-            tls_peer_subject_name.replace(info.subject_name.clone());
-        }
-
-        let record = JsonLogRecord {
-            kind,
-            id: msg.id().to_string(),
-            size: msg.get_data_maybe_not_loaded().len() as u64,
-            sender: msg
-                .sender()
-                .await
-                .map(|addr| addr.to_string())
-                .unwrap_or_else(|err| format!("{err:#}")),
-            recipient: recipient_list.clone(),
-            queue: msg
-                .get_queue_name()
-                .await
-                .unwrap_or_else(|err| format!("{err:#}")),
-            site: site.to_string(),
-            peer_address: peer_address.cloned(),
-            response: response.clone(),
-            timestamp: now,
-            created: msg.id().created(),
-            num_attempts: msg.get_num_attempts(),
-            egress_pool: egress_pool.map(|s| s.to_string()),
-            egress_source: egress_source.map(|s| s.to_string()),
-            bounce_classification: BounceClass::default(),
-            feedback_report: feedback_report.clone(),
+        let logged = JsonLogRecord {
             headers: headers.clone(),
             meta: meta.clone(),
-            delivery_protocol: delivery_protocol.map(|s| s.to_string()),
-            reception_protocol: reception_protocol.clone(),
-            nodeid,
-            tls_cipher,
-            tls_protocol_version,
-            tls_peer_subject_name,
-            source_address: source_address.clone(),
-            provider_name: provider.map(|s| s.to_string()),
-            session_id,
+            ..record.clone()
         };
-        if let Err(err) = logger.log(record, Some(msg.clone())).await {
+        if let Err(err) = logger.log(logged, Some(msg.clone())).await {
             tracing::error!("failed to log: {err:#}");
         }
 
-        if kind == RecordType::Reception {
-            if relay_disposition
-                .as_ref()
-                .map(|disp| disp.log_oob.should_log())
-                .unwrap_or(false)
-            {
-                if let Ok(Some(report)) = msg.parse_rfc3464().await {
-                    // This incoming bounce report is addressed to
-                    // the envelope from of the original message
-                    let sender = msg
-                        .first_recipient()
-                        .await
-                        .map(|addr| addr.to_string())
-                        .unwrap_or_else(|err| format!("{err:#}"));
-                    let queue = msg
-                        .get_queue_name()
-                        .await
-                        .unwrap_or_else(|err| format!("{err:#}"));
-
-                    let reconstructed_original_msg = None; // FIXME: try to build this from the
-                                                           // parsed rfc3464 report?
-
-                    for recip in &report.per_recipient {
-                        if recip.action != ReportAction::Failed {
-                            continue;
-                        }
-
-                        let enhanced_code = EnhancedStatusCode {
-                            class: recip.status.class,
-                            subject: recip.status.subject,
-                            detail: recip.status.detail,
-                        };
-
-                        let (code, content) = match &recip.diagnostic_code {
-                            Some(diag) if diag.diagnostic_type == "smtp" => {
-                                if let Some((code, content)) = diag.diagnostic.split_once(' ') {
-                                    if let Ok(code) = code.parse() {
-                                        (code, content.to_string())
-                                    } else {
-                                        (550, diag.diagnostic.to_string())
-                                    }
-                                } else {
-                                    (550, diag.diagnostic.to_string())
-                                }
-                            }
-                            _ => (550, "".to_string()),
-                        };
-
-                        let record = JsonLogRecord {
-                            kind: RecordType::OOB,
-                            id: msg.id().to_string(),
-                            size: 0,
-                            sender: sender.clone(),
-                            recipient: vec![recip
-                                .original_recipient
-                                .as_ref()
-                                .unwrap_or(&recip.final_recipient)
-                                .recipient
-                                .to_string()],
-                            queue: queue.to_string(),
-                            site: site.to_string(),
-                            peer_address: Some(ResolvedAddress {
-                                name: report.per_message.reporting_mta.name.to_string(),
-                                addr: peer_address
-                                    .map(|a| a.addr.clone())
-                                    .unwrap_or_else(|| Ipv4Addr::UNSPECIFIED.into()),
-                                is_secure: false,
-                            }),
-                            response: Response {
-                                code,
-                                enhanced_code: Some(enhanced_code),
-                                content,
-                                command: None,
-                            },
-                            timestamp: recip.last_attempt_date.unwrap_or_else(|| Utc::now()),
-                            created: msg.id().created(),
-                            num_attempts: 0,
-                            egress_pool: None,
-                            egress_source: None,
-                            bounce_classification: BounceClass::default(),
-                            feedback_report: None,
-                            headers: headers.clone(),
-                            meta: meta.clone(),
-                            delivery_protocol: None,
-                            reception_protocol: reception_protocol.clone(),
-                            nodeid,
-                            tls_cipher: None,
-                            tls_protocol_version: None,
-                            tls_peer_subject_name: None,
-                            source_address: None,
-                            provider_name: provider.map(|s| s.to_string()),
-                            session_id,
-                        };
-
-                        if let Err(err) =
-                            logger.log(record, reconstructed_original_msg.clone()).await
-                        {
-                            tracing::error!("failed to log: {err:#}");
-                        }
-                    }
-                }
+        let reconstructed_original_msg = None; // FIXME: try to build this from the
+                                               // parsed rfc3464 report?
+        for oob in &oob_records {
+            let logged = JsonLogRecord {
+                headers: headers.clone(),
+                meta: meta.clone(),
+                ..oob.clone()
+            };
+            if let Err(err) = logger.log(logged, reconstructed_original_msg.clone()).await {
+                tracing::error!("failed to log: {err:#}");
             }
         }
     }
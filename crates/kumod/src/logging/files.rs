@@ -1,15 +1,15 @@
-use crate::logging::{LogCommand, LogRecordParams};
+use crate::logging::{default_true, resolve_template, LogCommand, LogRecordParams};
 use anyhow::Context;
 use chrono::Utc;
 use flume::Receiver;
 pub use kumo_log_types::*;
 use kumo_server_common::disk_space::MinFree;
-use kumo_server_common::log::{mark_existing_logs_as_done_in_dir, OpenedFile};
+use kumo_server_common::log::{mark_existing_logs_as_done_in_dir, LogFileWriter, OpenedFile};
 use kumo_server_memory::subscribe_to_memory_status_changes_async;
-use kumo_template::{Template, TemplateEngine};
+use kumo_template::TemplateEngine;
 use serde::Deserialize;
 use std::collections::HashMap;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use zstd::stream::write::Encoder;
@@ -34,6 +34,11 @@ pub struct LogFileParams {
     #[serde(default = "LogFileParams::default_compression_level")]
     pub compression_level: i32,
 
+    /// When false, segments are written as plain, uncompressed,
+    /// JSONL files
+    #[serde(default = "default_true")]
+    pub compressed: bool,
+
     #[serde(default, with = "duration_serde")]
     pub max_segment_duration: Option<Duration>,
 
@@ -192,26 +197,6 @@ impl LogThreadState {
             .or_else(|| self.params.per_record.get(&RecordType::Any))
     }
 
-    fn resolve_template<'a>(
-        params: &LogFileParams,
-        template_engine: &'a TemplateEngine,
-        kind: RecordType,
-    ) -> Option<Template<'a, 'a>> {
-        if let Some(pr) = params.per_record.get(&kind) {
-            if pr.template.is_some() {
-                let label = format!("{kind:?}");
-                return template_engine.get_template(&label).ok();
-            }
-            return None;
-        }
-        if let Some(pr) = params.per_record.get(&RecordType::Any) {
-            if pr.template.is_some() {
-                return template_engine.get_template("Any").ok();
-            }
-        }
-        None
-    }
-
     fn do_record(&mut self, record: JsonLogRecord) -> anyhow::Result<()> {
         tracing::trace!("do_record {record:?}");
         let file_key = if let Some(per_rec) = self.per_record(record.kind) {
@@ -278,9 +263,17 @@ impl LogThreadState {
                 }
             };
 
+            let writer = if self.params.compressed {
+                LogFileWriter::Compressed(
+                    Encoder::new(f, self.params.compression_level)
+                        .context("set up zstd encoder")?,
+                )
+            } else {
+                LogFileWriter::Plain(BufWriter::new(f))
+            };
+
             let mut file = OpenedFile {
-                file: Encoder::new(f, self.params.compression_level)
-                    .context("set up zstd encoder")?,
+                file: writer,
                 name,
                 written: 0,
                 expires: self
@@ -312,7 +305,7 @@ impl LogThreadState {
             self.template_engine.add_global("log_record", &record)?;
 
             if let Some(template) =
-                Self::resolve_template(&self.params, &self.template_engine, record.kind)
+                resolve_template(&self.params.per_record, &self.template_engine, record.kind)
             {
                 template.render_to_write(&record, &mut record_text)?;
             } else {
@@ -1,12 +1,12 @@
 use crate::logging::files::LogFileParams;
-use crate::logging::{LogCommand, LogRecordParams, LOGGING_RUNTIME};
+use crate::logging::{resolve_template, LogCommand, LogRecordParams, LOGGING_RUNTIME};
 use crate::queue::{InsertReason, QueueManager};
 use anyhow::Context;
 use config::{declare_event, load_config};
 use flume::Receiver;
 pub use kumo_log_types::*;
 use kumo_prometheus::declare_metric;
-use kumo_template::{Template, TemplateEngine};
+use kumo_template::TemplateEngine;
 use message::Message;
 use rfc5321::parser::EnvelopeAddress;
 use serde::Deserialize;
@@ -142,7 +142,7 @@ impl LogHookState {
         self.template_engine.add_global("log_record", &record)?;
 
         if let Some(template) =
-            Self::resolve_template(&self.params, &self.template_engine, record.kind)
+            resolve_template(&self.params.per_record, &self.template_engine, record.kind)
         {
             template.render_to_write(&record, &mut record_text)?;
         } else {
@@ -211,24 +211,4 @@ impl LogHookState {
 
         Ok(())
     }
-
-    fn resolve_template<'a>(
-        params: &LogHookParams,
-        template_engine: &'a TemplateEngine,
-        kind: RecordType,
-    ) -> Option<Template<'a, 'a>> {
-        if let Some(pr) = params.per_record.get(&kind) {
-            if pr.template.is_some() {
-                let label = format!("{kind:?}");
-                return template_engine.get_template(&label).ok();
-            }
-            return None;
-        }
-        if let Some(pr) = params.per_record.get(&RecordType::Any) {
-            if pr.template.is_some() {
-                return template_engine.get_template("Any").ok();
-            }
-        }
-        None
-    }
 }
@@ -1,7 +1,11 @@
+use crate::http_server::admin_tail_log_v1::LogTailManager;
 use crate::logging::classify::{apply_classification, ClassifierParams};
 use crate::logging::disposition_hooks::{DispHookParams, RecordWrapper};
 use crate::logging::files::{LogFileParams, LogThreadState};
 use crate::logging::hooks::{LogHookParams, LogHookState};
+use crate::logging::sinks::{LogSinkParams, LogSinkState};
+use crate::logging::tracking::{MessageTrackingParams, MessageTrackingState};
+use crate::logging::webhook::{LogWebhookParams, LogWebhookState};
 use anyhow::Context;
 use bstr::ByteSlice;
 use config::{any_err, from_lua_value, get_or_create_module, CallbackSignature};
@@ -11,13 +15,14 @@ use kumo_prometheus::declare_metric;
 use kumo_prometheus::prometheus::Histogram;
 use kumo_server_common::disk_space::MonitoredPath;
 use kumo_server_runtime::Runtime;
-use kumo_template::TemplateEngine;
+use kumo_template::{Template, TemplateEngine};
 use message::Message;
 use mlua::{Lua, Value as LuaValue};
 use parking_lot::FairMutex as Mutex;
 use serde::Deserialize;
 use serde_json::Value;
 use std::collections::HashMap;
+use std::future::Future;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, LazyLock};
@@ -30,6 +35,11 @@ pub(crate) mod disposition_hooks;
 pub(crate) mod files;
 pub(crate) mod hooks;
 pub(crate) mod rejection;
+pub(crate) mod session;
+pub(crate) mod sinks;
+pub(crate) mod tracking;
+pub(crate) mod unsubscribe;
+pub(crate) mod webhook;
 
 declare_metric! {
 /// how many times submission of a log event hit the back_pressure
@@ -83,6 +93,66 @@ fn default_true() -> bool {
     true
 }
 
+/// Compile the templates specified by per_record, naming each
+/// one after its record type
+fn compile_templates(
+    per_record: &HashMap<RecordType, LogRecordParams>,
+) -> anyhow::Result<TemplateEngine> {
+    let mut template_engine = TemplateEngine::new();
+
+    for (kind, per_rec) in per_record {
+        if let Some(template_source) = &per_rec.template {
+            template_engine
+                .add_template(format!("{kind:?}"), template_source.clone())
+                .with_context(|| {
+                    format!("compiling template:\n{template_source}\nfor log record type {kind:?}")
+                })?;
+        }
+    }
+
+    Ok(template_engine)
+}
+
+/// Returns the template that was compiled by compile_templates
+/// for records of the specified kind, if any
+pub(crate) fn resolve_template<'a>(
+    per_record: &HashMap<RecordType, LogRecordParams>,
+    template_engine: &'a TemplateEngine,
+    kind: RecordType,
+) -> Option<Template<'a, 'a>> {
+    if let Some(pr) = per_record.get(&kind) {
+        if pr.template.is_some() {
+            let label = format!("{kind:?}");
+            return template_engine.get_template(&label).ok();
+        }
+        return None;
+    }
+    if let Some(pr) = per_record.get(&RecordType::Any) {
+        if pr.template.is_some() {
+            return template_engine.get_template("Any").ok();
+        }
+    }
+    None
+}
+
+fn enabled_by_kind(per_record: &HashMap<RecordType, LogRecordParams>) -> HashMap<RecordType, bool> {
+    per_record
+        .iter()
+        .map(|(kind, cfg)| (*kind, cfg.enable))
+        .collect()
+}
+
+/// The properties of a logger that dispatches records via a queue
+/// to a task running in the logging runtime
+struct QueuedLoggerConfig {
+    name: String,
+    meta: Vec<String>,
+    headers: Vec<String>,
+    enabled: HashMap<RecordType, bool>,
+    filter_event: Option<String>,
+    hook_name: Option<String>,
+}
+
 #[derive(Debug)]
 pub(crate) enum LogCommand {
     Record(JsonLogRecord, Option<Message>),
@@ -120,6 +190,35 @@ impl Logger {
         LOGGER.lock().iter().map(Arc::clone).collect()
     }
 
+    /// Spawn task_future, which processes the records received from
+    /// the queue fed by sender, and return the logger that feeds it
+    fn spawn_queued<FUT>(
+        config: QueuedLoggerConfig,
+        sender: Sender<LogCommand>,
+        task_name: &str,
+        task_future: FUT,
+    ) -> anyhow::Result<Self>
+    where
+        FUT: Future<Output = ()> + Send + 'static,
+    {
+        let thread = LOGGING_RUNTIME.spawn(task_name, task_future)?;
+        let submit_latency = SUBMIT_LATENCY.get_metric_with_label_values(&[&config.name])?;
+
+        Ok(Self {
+            implementation: LoggerImpl::Queue {
+                sender,
+                thread: TokioMutex::new(Some(thread)),
+            },
+            meta: config.meta,
+            headers: config.headers,
+            enabled: config.enabled,
+            filter_event: config.filter_event,
+            hook_name: config.hook_name,
+            name: config.name,
+            submit_latency,
+        })
+    }
+
     pub async fn init_disp_hook(params: DispHookParams) -> anyhow::Result<()> {
         let mut loggers = LOGGER.lock();
 
@@ -133,10 +232,7 @@ impl Logger {
             );
         }
 
-        let mut enabled = HashMap::new();
-        for (kind, cfg) in &params.per_record {
-            enabled.insert(*kind, cfg.enable);
-        }
+        let enabled = enabled_by_kind(&params.per_record);
 
         let hook_name = params.name.to_string();
         let name = format!("hook-{hook_name}");
@@ -173,87 +269,130 @@ impl Logger {
             );
         }
 
-        let mut template_engine = TemplateEngine::new();
-
-        for (kind, per_rec) in &params.per_record {
-            if let Some(template_source) = &per_rec.template {
-                template_engine
-                    .add_template(format!("{kind:?}"), template_source.clone())
-                    .with_context(|| {
-                        format!(
-                            "compiling template:\n{template_source}\nfor log record type {kind:?}"
-                        )
-                    })?;
-            }
-        }
+        let template_engine = compile_templates(&params.per_record)?;
+        let config = QueuedLoggerConfig {
+            name: format!("hook-{}", params.name),
+            meta: params.meta.clone(),
+            headers: params.headers.clone(),
+            enabled: enabled_by_kind(&params.per_record),
+            filter_event: None,
+            hook_name: Some(params.name.to_string()),
+        };
+        let (sender, receiver) = bounded(params.back_pressure);
+
+        let mut state = LogHookState::new(params, receiver, template_engine);
 
-        let mut enabled = HashMap::new();
-        for (kind, cfg) in &params.per_record {
-            enabled.insert(*kind, cfg.enable);
+        let logger = Self::spawn_queued(config, sender, "log hook", async move {
+            tracing::debug!("calling state.logger_thread()");
+            state.logger_thread().await
+        })?;
+
+        loggers.push(Arc::new(logger));
+        Ok(())
+    }
+
+    pub async fn init_sink(params: LogSinkParams) -> anyhow::Result<()> {
+        let name = format!("sink-{}", params.name);
+        if LOGGER.lock().iter().any(|existing| existing.name == name) {
+            anyhow::bail!(
+                "A log sink with name `{}` has already been registered",
+                params.name
+            );
         }
 
-        let headers = params.headers.clone();
-        let meta = params.meta.clone();
-        let hook_name = params.name.to_string();
-        let name = format!("hook-{hook_name}");
+        let template_engine = compile_templates(&params.per_record)?;
+        let config = QueuedLoggerConfig {
+            name,
+            meta: params.meta.clone(),
+            headers: params.headers.clone(),
+            enabled: enabled_by_kind(&params.per_record),
+            filter_event: params.filter_event.clone(),
+            hook_name: None,
+        };
         let (sender, receiver) = bounded(params.back_pressure);
 
-        let mut state = LogHookState::new(params, receiver, template_engine);
+        let mut state = LogSinkState::new(params, receiver, template_engine)?;
 
-        let thread = LOGGING_RUNTIME.spawn("log hook".to_string(), async move {
+        let logger = Self::spawn_queued(config, sender, "log sink", async move {
             tracing::debug!("calling state.logger_thread()");
             state.logger_thread().await
         })?;
 
-        let submit_latency = SUBMIT_LATENCY.get_metric_with_label_values(&[&name])?;
-        let implementation = LoggerImpl::Queue {
-            sender,
-            thread: TokioMutex::new(Some(thread)),
+        LOGGER.lock().push(Arc::new(logger));
+        Ok(())
+    }
+
+    pub async fn init_webhook(params: LogWebhookParams) -> anyhow::Result<()> {
+        let template_engine = compile_templates(&params.per_record)?;
+        let config = QueuedLoggerConfig {
+            name: format!("webhook-{}", params.name),
+            meta: params.meta.clone(),
+            headers: params.headers.clone(),
+            enabled: enabled_by_kind(&params.per_record),
+            filter_event: params.filter_event.clone(),
+            hook_name: None,
         };
+        let (sender, receiver) = bounded(params.back_pressure);
 
-        let logger = Self {
-            implementation,
-            meta,
-            headers,
-            enabled,
-            filter_event: None,
-            hook_name: Some(hook_name),
-            name,
-            submit_latency,
+        // This will fail if the name is already in use
+        let mut state = LogWebhookState::new(params, receiver, template_engine)?;
+
+        let logger = Self::spawn_queued(config, sender, "log webhook", async move {
+            tracing::debug!("calling state.logger_thread()");
+            state.logger_thread().await
+        })?;
+
+        LOGGER.lock().push(Arc::new(logger));
+        Ok(())
+    }
+
+    pub async fn init_tracking(params: MessageTrackingParams) -> anyhow::Result<()> {
+        if config::is_validating() {
+            return Ok(());
+        }
+
+        let path = params.path.clone();
+        let config = QueuedLoggerConfig {
+            name: "message-tracking".to_string(),
+            meta: params.meta.clone(),
+            headers: params.headers.clone(),
+            enabled: enabled_by_kind(&params.per_record),
+            filter_event: params.filter_event.clone(),
+            hook_name: None,
         };
+        let (sender, receiver) = bounded(params.back_pressure);
 
-        loggers.push(Arc::new(logger));
+        // This will fail if tracking has already been configured
+        let mut state = MessageTrackingState::new(params, receiver)?;
+
+        let logger = Self::spawn_queued(config, sender, "message tracking", async move {
+            tracing::debug!("calling state.logger_thread()");
+            state.logger_thread().await
+        })?;
+
+        // Only make the database available for queries once
+        // the task that populates it is running
+        tracking::activate(&path)?;
+
+        LOGGER.lock().push(Arc::new(logger));
         Ok(())
     }
 
     pub async fn init(params: LogFileParams) -> anyhow::Result<()> {
-        let mut template_engine = TemplateEngine::new();
-
-        for (kind, per_rec) in &params.per_record {
-            if let Some(template_source) = &per_rec.template {
-                template_engine
-                    .add_template(format!("{kind:?}"), template_source.clone())
-                    .with_context(|| {
-                        format!(
-                            "compiling template:\n{template_source}\nfor log record type {kind:?}"
-                        )
-                    })?;
-            }
-        }
+        let template_engine = compile_templates(&params.per_record)?;
 
         std::fs::create_dir_all(&params.log_dir)
             .with_context(|| format!("creating log directory {}", params.log_dir.display()))?;
 
-        let mut enabled = HashMap::new();
-        for (kind, cfg) in &params.per_record {
-            enabled.insert(*kind, cfg.enable);
-        }
-
-        let headers = params.headers.clone();
-        let meta = params.meta.clone();
+        let config = QueuedLoggerConfig {
+            name: format!("dir-{}", params.log_dir.display()),
+            meta: params.meta.clone(),
+            headers: params.headers.clone(),
+            enabled: enabled_by_kind(&params.per_record),
+            filter_event: params.filter_event.clone(),
+            hook_name: None,
+        };
         let (sender, receiver) = bounded(params.back_pressure);
-        let filter_event = params.filter_event.clone();
-        let name = format!("dir-{}", params.log_dir.display());
 
         MonitoredPath {
             name: format!("log dir {}", params.log_dir.display()),
@@ -263,7 +402,7 @@ impl Logger {
         }
         .register();
 
-        let thread = LOGGING_RUNTIME.spawn("log file".to_string(), async move {
+        let logger = Self::spawn_queued(config, sender, "log file", async move {
             tracing::debug!("calling state.logger_thread()");
             let mut state = LogThreadState {
                 params,
@@ -274,24 +413,6 @@ impl Logger {
             state.logger_thread().await
         })?;
 
-        let submit_latency = SUBMIT_LATENCY.get_metric_with_label_values(&[&name])?;
-
-        let implementation = LoggerImpl::Queue {
-            sender,
-            thread: TokioMutex::new(Some(thread)),
-        };
-
-        let logger = Self {
-            implementation,
-            meta,
-            headers,
-            enabled,
-            filter_event,
-            hook_name: None,
-            name,
-            submit_latency,
-        };
-
         LOGGER.lock().push(Arc::new(logger));
         Ok(())
     }
@@ -439,6 +560,53 @@ impl Logger {
     }
 }
 
+/// Delivers records that are not associated with a message, such as
+/// rejections and session summaries, to the tail-log subscribers and
+/// to each logger that is enabled for the record type.
+pub(crate) struct RecordSubmitter {
+    loggers: Vec<Arc<Logger>>,
+    tailing: bool,
+}
+
+impl RecordSubmitter {
+    /// Returns None if there is nothing to receive a record, in which
+    /// case the caller can avoid building it
+    pub fn new() -> Option<Self> {
+        let loggers = Logger::get_loggers();
+        let tailing = LogTailManager::is_active();
+        if loggers.is_empty() && !tailing {
+            return None;
+        }
+        Some(Self { loggers, tailing })
+    }
+
+    /// Submit the record. When meta is provided, the meta of the
+    /// record is populated separately for each logger from it,
+    /// based on the configuration of that logger.
+    pub async fn submit(self, record: JsonLogRecord, meta: Option<&Value>) {
+        if self.tailing {
+            LogTailManager::submit(&record).await;
+        }
+
+        for logger in self.loggers.iter() {
+            if !logger.record_is_enabled(record.kind) {
+                continue;
+            }
+
+            let record = match meta {
+                Some(meta) => JsonLogRecord {
+                    meta: logger.extract_meta(meta),
+                    ..record.clone()
+                },
+                None => record.clone(),
+            };
+            if let Err(err) = logger.log(record, None).await {
+                tracing::error!("failed to log: {err:#}");
+            }
+        }
+    }
+}
+
 pub fn register(lua: &Lua) -> anyhow::Result<()> {
     let kumo_mod = get_or_create_module(lua, "kumo")?;
 
@@ -458,6 +626,30 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         })?,
     )?;
 
+    kumo_mod.set(
+        "configure_log_sink",
+        lua.create_async_function(|lua, params: LuaValue| async move {
+            let params: LogSinkParams = from_lua_value(&lua, params)?;
+            Logger::init_sink(params).await.map_err(any_err)
+        })?,
+    )?;
+
+    kumo_mod.set(
+        "configure_log_webhook",
+        lua.create_async_function(|lua, params: LuaValue| async move {
+            let params: LogWebhookParams = from_lua_value(&lua, params)?;
+            Logger::init_webhook(params).await.map_err(any_err)
+        })?,
+    )?;
+
+    kumo_mod.set(
+        "configure_message_tracking",
+        lua.create_async_function(|lua, params: LuaValue| async move {
+            let params: MessageTrackingParams = from_lua_value(&lua, params)?;
+            Logger::init_tracking(params).await.map_err(any_err)
+        })?,
+    )?;
+
     kumo_mod.set(
         "configure_log_hook",
         lua.create_async_function(|lua, params: LuaValue| async move {
@@ -1,4 +1,4 @@
-use crate::logging::Logger;
+use crate::logging::RecordSubmitter;
 use bounce_classify::BounceClass;
 use chrono::Utc;
 pub use kumo_log_types::*;
@@ -16,53 +16,48 @@ pub struct LogRejection {
 }
 
 pub async fn log_rejection(args: LogRejection) {
-    let loggers = Logger::get_loggers();
-    if loggers.is_empty() {
+    let Some(submitter) = RecordSubmitter::new() else {
         return;
-    }
+    };
     let now = Utc::now();
     let nodeid = kumo_server_common::nodeid::NodeId::get_uuid();
 
     let kind = RecordType::Rejection;
 
-    for logger in loggers.iter() {
-        if !logger.record_is_enabled(kind) {
-            continue;
-        }
+    // The meta is populated separately for each logger,
+    // based on its configuration
+    let record = JsonLogRecord {
+        kind,
+        id: "".to_string(),
+        size: 0,
+        sender: args.sender.clone().unwrap_or_default(),
+        recipient: vec![args.recipient.clone().unwrap_or_default()],
+        queue: "".to_string(),
+        site: "".to_string(),
+        peer_address: Some(args.peer_address.clone()),
+        response: args.response.clone(),
+        timestamp: now,
+        created: now,
+        num_attempts: 0,
+        egress_pool: None,
+        egress_source: None,
+        bounce_classification: BounceClass::default(),
+        bounce_classifier_rule: None,
+        feedback_report: None,
+        headers: HashMap::new(),
+        meta: HashMap::new(),
+        delivery_protocol: None,
+        reception_protocol: None,
+        nodeid,
+        tls_cipher: None,
+        tls_protocol_version: None,
+        tls_peer_subject_name: None,
+        source_address: None,
+        provider_name: None,
+        session_id: args.session_id,
+        server_session: None,
+        client_session: None,
+    };
 
-        let meta = logger.extract_meta(&args.meta);
-
-        let record = JsonLogRecord {
-            kind,
-            id: "".to_string(),
-            size: 0,
-            sender: args.sender.clone().unwrap_or_default(),
-            recipient: vec![args.recipient.clone().unwrap_or_default()],
-            queue: "".to_string(),
-            site: "".to_string(),
-            peer_address: Some(args.peer_address.clone()),
-            response: args.response.clone(),
-            timestamp: now,
-            created: now,
-            num_attempts: 0,
-            egress_pool: None,
-            egress_source: None,
-            bounce_classification: BounceClass::default(),
-            feedback_report: None,
-            headers: HashMap::new(),
-            meta,
-            delivery_protocol: None,
-            reception_protocol: None,
-            nodeid,
-            tls_cipher: None,
-            tls_protocol_version: None,
-            tls_peer_subject_name: None,
-            source_address: None,
-            provider_name: None,
-            session_id: args.session_id,
-        };
-        if let Err(err) = logger.log(record, None).await {
-            tracing::error!("failed to log: {err:#}");
-        }
-    }
+    submitter.submit(record, Some(&args.meta)).await;
 }
@@ -0,0 +1,318 @@
+use crate::logging::RecordSubmitter;
+use bounce_classify::BounceClass;
+use chrono::{DateTime, Utc};
+pub use kumo_log_types::*;
+use rfc5321::{Response, TlsInformation};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub struct LogSmtpServerSession {
+    pub peer_address: ResolvedAddress,
+    /// The final response that was sent to the client
+    pub response: Response,
+    pub meta: serde_json::Value,
+    pub tls_info: Option<TlsInformation>,
+    pub session_id: Uuid,
+    /// When the connection was established
+    pub started: DateTime<Utc>,
+    pub summary: SmtpServerSessionSummary,
+}
+
+pub async fn log_smtp_server_session(args: LogSmtpServerSession) {
+    let Some(submitter) = RecordSubmitter::new() else {
+        return;
+    };
+    // The meta is populated separately for each logger,
+    // based on its configuration
+    let record = server_session_record(&args, Utc::now());
+    submitter.submit(record, Some(&args.meta)).await;
+}
+
+fn server_session_record(args: &LogSmtpServerSession, now: DateTime<Utc>) -> JsonLogRecord {
+    let nodeid = kumo_server_common::nodeid::NodeId::get_uuid();
+
+    let mut summary = args.summary.clone();
+    summary.duration = (now - args.started)
+        .to_std()
+        .unwrap_or_default()
+        .as_secs_f64();
+
+    let (tls_cipher, tls_protocol_version, tls_peer_subject_name) = match &args.tls_info {
+        Some(info) => (
+            Some(info.cipher.clone()),
+            Some(info.protocol_version.clone()),
+            Some(info.subject_name.clone()),
+        ),
+        None => (None, None, None),
+    };
+
+    JsonLogRecord {
+        kind: RecordType::SmtpServerSession,
+        id: "".to_string(),
+        size: 0,
+        sender: "".to_string(),
+        recipient: vec![],
+        queue: "".to_string(),
+        site: "".to_string(),
+        peer_address: Some(args.peer_address.clone()),
+        response: args.response.clone(),
+        timestamp: now,
+        created: args.started,
+        num_attempts: 0,
+        egress_pool: None,
+        egress_source: None,
+        bounce_classification: BounceClass::default(),
+        bounce_classifier_rule: None,
+        feedback_report: None,
+        headers: HashMap::new(),
+        meta: HashMap::new(),
+        delivery_protocol: None,
+        reception_protocol: args
+            .meta
+            .get("reception_protocol")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+        nodeid,
+        tls_cipher,
+        tls_protocol_version,
+        tls_peer_subject_name,
+        source_address: None,
+        provider_name: None,
+        session_id: Some(args.session_id),
+        server_session: Some(Box::new(summary)),
+        client_session: None,
+    }
+}
+
+pub struct LogSmtpClientSession<'a> {
+    pub site: &'a str,
+    pub peer_address: ResolvedAddress,
+    /// The last response received from the peer, or a synthesized
+    /// response explaining why the connection was closed
+    pub response: Response,
+    pub egress_pool: &'a str,
+    pub egress_source: &'a str,
+    pub source_address: Option<MaybeProxiedSourceAddress>,
+    pub delivery_protocol: &'a str,
+    pub tls_info: Option<TlsInformation>,
+    pub provider: Option<&'a str>,
+    pub session_id: Uuid,
+    /// When the connection was established
+    pub started: DateTime<Utc>,
+    pub summary: SmtpClientSessionSummary,
+}
+
+pub async fn log_smtp_client_session(args: LogSmtpClientSession<'_>) {
+    let Some(submitter) = RecordSubmitter::new() else {
+        return;
+    };
+    let record = client_session_record(args, Utc::now());
+    submitter.submit(record, None).await;
+}
+
+fn client_session_record(args: LogSmtpClientSession, now: DateTime<Utc>) -> JsonLogRecord {
+    let nodeid = kumo_server_common::nodeid::NodeId::get_uuid();
+
+    let mut summary = args.summary;
+    summary.duration = (now - args.started)
+        .to_std()
+        .unwrap_or_default()
+        .as_secs_f64();
+
+    let (tls_cipher, tls_protocol_version, tls_peer_subject_name) = match args.tls_info {
+        Some(info) => (
+            Some(info.cipher),
+            Some(info.protocol_version),
+            Some(info.subject_name),
+        ),
+        None => (None, None, None),
+    };
+
+    JsonLogRecord {
+        kind: RecordType::SmtpClientSession,
+        id: "".to_string(),
+        size: 0,
+        sender: "".to_string(),
+        recipient: vec![],
+        queue: "".to_string(),
+        site: args.site.to_string(),
+        peer_address: Some(args.peer_address),
+        response: args.response,
+        timestamp: now,
+        created: args.started,
+        num_attempts: 0,
+        egress_pool: Some(args.egress_pool.to_string()),
+        egress_source: Some(args.egress_source.to_string()),
+        bounce_classification: BounceClass::default(),
+        bounce_classifier_rule: None,
+        feedback_report: None,
+        headers: HashMap::new(),
+        meta: HashMap::new(),
+        delivery_protocol: Some(args.delivery_protocol.to_string()),
+        reception_protocol: None,
+        nodeid,
+        tls_cipher,
+        tls_protocol_version,
+        tls_peer_subject_name,
+        source_address: args.source_address,
+        provider_name: args.provider.map(|s| s.to_string()),
+        session_id: Some(args.session_id),
+        server_session: None,
+        client_session: Some(Box::new(summary)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeDelta;
+    use std::net::Ipv4Addr;
+
+    fn peer_address() -> ResolvedAddress {
+        ResolvedAddress {
+            name: "mx.example.com".to_string(),
+            addr: Ipv4Addr::new(10, 0, 0, 1).into(),
+            is_secure: false,
+        }
+    }
+
+    fn response(code: u16, content: &str) -> Response {
+        Response {
+            code,
+            enhanced_code: None,
+            content: content.to_string(),
+            command: None,
+        }
+    }
+
+    #[test]
+    fn server_session() {
+        let now = Utc::now();
+        let session_id = Uuid::new_v4();
+        let args = LogSmtpServerSession {
+            peer_address: peer_address(),
+            response: response(221, "So long, and thanks for all the fish!"),
+            meta: serde_json::json!({"reception_protocol": "ESMTP"}),
+            tls_info: Some(TlsInformation {
+                cipher: "TLS13_AES_256_GCM_SHA384".to_string(),
+                protocol_version: "TLSv1_3".to_string(),
+                subject_name: vec!["mx.example.com".to_string()],
+                provider_name: "rustls".to_string(),
+                authenticated: false,
+            }),
+            session_id,
+            started: now - TimeDelta::seconds(5),
+            summary: SmtpServerSessionSummary {
+                authn_id: Some("scott".to_string()),
+                commands: [("EHLO".to_string(), 1), ("MAIL".to_string(), 2)]
+                    .into_iter()
+                    .collect(),
+                messages_accepted: 1,
+                messages_rejected: 1,
+                bytes_read: 1024,
+                bytes_written: 256,
+                ..Default::default()
+            },
+        };
+
+        let record = server_session_record(&args, now);
+        assert_eq!(record.kind, RecordType::SmtpServerSession);
+        assert_eq!(record.created, args.started);
+        assert_eq!(record.timestamp, now);
+        assert_eq!(record.response.code, 221);
+        assert_eq!(record.reception_protocol.as_deref(), Some("ESMTP"));
+        assert_eq!(record.tls_protocol_version.as_deref(), Some("TLSv1_3"));
+        assert!(record.client_session.is_none());
+
+        let summary = record.server_session.expect("server_session is set");
+        assert_eq!(summary.duration, 5.0);
+        assert_eq!(summary.authn_id.as_deref(), Some("scott"));
+        assert_eq!(summary.commands.get("MAIL"), Some(&2));
+        assert_eq!(summary.messages_accepted, 1);
+        assert_eq!(summary.messages_rejected, 1);
+        assert_eq!(summary.bytes_read, 1024);
+        assert_eq!(summary.bytes_written, 256);
+    }
+
+    #[test]
+    fn client_session() {
+        let now = Utc::now();
+        let session_id = Uuid::new_v4();
+        let args = LogSmtpClientSession {
+            site: "(alt1|alt2|alt3|alt4)?.gmail-smtp-in.l.google.com",
+            peer_address: peer_address(),
+            response: response(221, "closing connection"),
+            egress_pool: "pool",
+            egress_source: "source",
+            source_address: None,
+            delivery_protocol: "ESMTP",
+            tls_info: None,
+            provider: Some("google"),
+            session_id,
+            started: now - TimeDelta::milliseconds(1500),
+            summary: SmtpClientSessionSummary {
+                mx_host: "mx.example.com".to_string(),
+                tls_policy: "Opportunistic".to_string(),
+                messages_delivered: 3,
+                close_reason: SmtpClientSessionCloseReason::PeerClosed,
+                ..Default::default()
+            },
+        };
+
+        let record = client_session_record(args, now);
+        assert_eq!(record.kind, RecordType::SmtpClientSession);
+        assert_eq!(
+            record.site,
+            "(alt1|alt2|alt3|alt4)?.gmail-smtp-in.l.google.com"
+        );
+        assert_eq!(record.egress_pool.as_deref(), Some("pool"));
+        assert_eq!(record.egress_source.as_deref(), Some("source"));
+        assert_eq!(record.provider_name.as_deref(), Some("google"));
+        assert_eq!(record.delivery_protocol.as_deref(), Some("ESMTP"));
+        assert!(record.tls_cipher.is_none());
+        assert!(record.server_session.is_none());
+
+        let summary = record.client_session.expect("client_session is set");
+        assert_eq!(summary.duration, 1.5);
+        assert_eq!(summary.mx_host, "mx.example.com");
+        assert_eq!(summary.messages_delivered, 3);
+        assert_eq!(
+            summary.close_reason,
+            SmtpClientSessionCloseReason::PeerClosed
+        );
+    }
+
+    #[test]
+    fn session_id_links_records() {
+        // The session record carries the same session_id as the
+        // Reception and Delivery records produced by that session,
+        // which is how consumers correlate them
+        let now = Utc::now();
+        let session_id = Uuid::new_v4();
+        let args = LogSmtpServerSession {
+            peer_address: peer_address(),
+            response: response(221, "bye"),
+            meta: serde_json::Value::Null,
+            tls_info: None,
+            session_id,
+            started: now,
+            summary: SmtpServerSessionSummary::default(),
+        };
+        let session = server_session_record(&args, now);
+        assert_eq!(session.session_id, Some(session_id));
+        assert!(session.reception_protocol.is_none());
+
+        let reception = JsonLogRecord {
+            session_id: Some(session_id),
+            ..JsonLogRecord::empty(RecordType::Reception)
+        };
+
+        let session = serde_json::to_value(&session).unwrap();
+        let reception = serde_json::to_value(&reception).unwrap();
+        assert_eq!(
+            session["session_id"],
+            serde_json::Value::String(session_id.to_string())
+        );
+        assert_eq!(session["session_id"], reception["session_id"]);
+    }
+}
@@ -0,0 +1,500 @@
+use crate::logging::files::LogFileParams;
+use crate::logging::{resolve_template, LogCommand, LogRecordParams};
+use anyhow::Context;
+use chrono::SecondsFormat;
+use flume::Receiver;
+pub use kumo_log_types::*;
+use kumo_template::TemplateEngine;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket, UnixDatagram};
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LogSinkParams {
+    /// The unique name to identify this instance of the log sink
+    pub name: String,
+
+    /// Maximum number of outstanding items to be logged before
+    /// the submission will block; helps to avoid runaway issues
+    /// spiralling out of control.
+    #[serde(default = "LogFileParams::default_back_pressure")]
+    pub back_pressure: usize,
+
+    /// List of meta fields to capture in the log
+    #[serde(default)]
+    pub meta: Vec<String>,
+
+    /// List of message headers to capture in the log
+    #[serde(default)]
+    pub headers: Vec<String>,
+
+    #[serde(default)]
+    pub per_record: HashMap<RecordType, LogRecordParams>,
+
+    /// The name of an event which can be used to filter
+    /// out log records which should not be logged to this
+    /// sink
+    #[serde(default)]
+    pub filter_event: Option<String>,
+
+    /// Send records to a syslog server
+    #[serde(default)]
+    pub syslog: Option<SyslogParams>,
+
+    /// Send records to the systemd journal
+    #[serde(default)]
+    pub journald: Option<JournaldParams>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyslogProtocol {
+    #[default]
+    Udp,
+    Tcp,
+    Unix,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyslogFacility {
+    Kern = 0,
+    User = 1,
+    #[default]
+    Mail = 2,
+    Daemon = 3,
+    Auth = 4,
+    Syslog = 5,
+    Lpr = 6,
+    News = 7,
+    Uucp = 8,
+    Cron = 9,
+    AuthPriv = 10,
+    Ftp = 11,
+    Local0 = 16,
+    Local1 = 17,
+    Local2 = 18,
+    Local3 = 19,
+    Local4 = 20,
+    Local5 = 21,
+    Local6 = 22,
+    Local7 = 23,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SyslogParams {
+    /// The transport used to reach the syslog server
+    #[serde(default)]
+    pub protocol: SyslogProtocol,
+
+    /// `host:port` for Udp and Tcp, or the path to the
+    /// socket for Unix
+    pub address: String,
+
+    #[serde(default)]
+    pub facility: SyslogFacility,
+
+    /// The APP-NAME field of the syslog message
+    #[serde(default = "SyslogParams::default_app_name")]
+    pub app_name: String,
+
+    /// The HOSTNAME field of the syslog message.
+    /// Defaults to the local host name
+    #[serde(default)]
+    pub hostname: Option<String>,
+}
+
+impl SyslogParams {
+    fn default_app_name() -> String {
+        "kumod".to_string()
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct JournaldParams {
+    /// The path to the journald native protocol socket
+    #[serde(default = "JournaldParams::default_socket_path")]
+    pub socket_path: PathBuf,
+
+    /// The SYSLOG_IDENTIFIER field of the journal entry
+    #[serde(default = "SyslogParams::default_app_name")]
+    pub syslog_identifier: String,
+}
+
+impl JournaldParams {
+    fn default_socket_path() -> PathBuf {
+        "/run/systemd/journal/socket".into()
+    }
+}
+
+/// Maps the record type to a syslog severity
+fn severity_for_kind(kind: RecordType) -> u8 {
+    match kind {
+        RecordType::Bounce
+        | RecordType::Expiration
+        | RecordType::AdminBounce
+        | RecordType::OOB
+        | RecordType::Feedback
+        | RecordType::Rejection
+        | RecordType::Quarantine => 4, // Warning
+        RecordType::TransientFailure | RecordType::DelayWarning => 5, // Notice
+        _ => 6,                                                       // Informational
+    }
+}
+
+/// Replace characters that are not permitted in an RFC 5424 header
+/// field, and limit its length.  Empty values are represented by
+/// the NILVALUE `-`.
+fn header_field(value: &str, max_len: usize) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(max_len)
+        .collect();
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+/// Format a record as an RFC 5424 syslog message
+fn format_rfc5424(
+    facility: SyslogFacility,
+    hostname: &str,
+    app_name: &str,
+    record: &JsonLogRecord,
+    text: &[u8],
+) -> Vec<u8> {
+    let pri = (facility as u8) * 8 + severity_for_kind(record.kind);
+    let mut result = format!(
+        "<{pri}>1 {timestamp} {hostname} {app_name} {procid} {msgid} - ",
+        timestamp = record
+            .timestamp
+            .to_rfc3339_opts(SecondsFormat::Micros, true),
+        hostname = header_field(hostname, 255),
+        app_name = header_field(app_name, 48),
+        procid = std::process::id(),
+        msgid = header_field(&format!("{:?}", record.kind), 32),
+    )
+    .into_bytes();
+    result.extend_from_slice(trim_newline(text));
+    result
+}
+
+/// Encode a field using the journald native protocol
+fn append_journal_field(buf: &mut Vec<u8>, name: &str, value: &[u8]) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value);
+    buf.push(b'\n');
+}
+
+fn format_journal_entry(identifier: &str, record: &JsonLogRecord, text: &[u8]) -> Vec<u8> {
+    let mut buf = vec![];
+    append_journal_field(&mut buf, "MESSAGE", trim_newline(text));
+    append_journal_field(
+        &mut buf,
+        "PRIORITY",
+        severity_for_kind(record.kind).to_string().as_bytes(),
+    );
+    append_journal_field(&mut buf, "SYSLOG_IDENTIFIER", identifier.as_bytes());
+    append_journal_field(
+        &mut buf,
+        "KUMO_RECORD_TYPE",
+        format!("{:?}", record.kind).as_bytes(),
+    );
+    append_journal_field(&mut buf, "KUMO_MESSAGE_ID", record.id.as_bytes());
+    append_journal_field(&mut buf, "KUMO_QUEUE", record.queue.as_bytes());
+    if !record.site.is_empty() {
+        append_journal_field(&mut buf, "KUMO_SITE", record.site.as_bytes());
+    }
+    buf
+}
+
+fn trim_newline(text: &[u8]) -> &[u8] {
+    text.strip_suffix(b"\n").unwrap_or(text)
+}
+
+enum SyslogConnection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Unix(UnixDatagram),
+}
+
+impl SyslogConnection {
+    async fn connect(params: &SyslogParams) -> anyhow::Result<Self> {
+        match params.protocol {
+            SyslogProtocol::Udp => {
+                let addr = tokio::net::lookup_host(&params.address)
+                    .await
+                    .with_context(|| format!("resolving {}", params.address))?
+                    .next()
+                    .with_context(|| format!("no addresses for {}", params.address))?;
+                let bind_addr = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(bind_addr).await?;
+                socket
+                    .connect(addr)
+                    .await
+                    .with_context(|| format!("connecting to {addr}"))?;
+                Ok(Self::Udp(socket))
+            }
+            SyslogProtocol::Tcp => Ok(Self::Tcp(
+                TcpStream::connect(&params.address)
+                    .await
+                    .with_context(|| format!("connecting to {}", params.address))?,
+            )),
+            SyslogProtocol::Unix => {
+                let socket = UnixDatagram::unbound()?;
+                socket
+                    .connect(&params.address)
+                    .with_context(|| format!("connecting to {}", params.address))?;
+                Ok(Self::Unix(socket))
+            }
+        }
+    }
+
+    async fn send(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            Self::Udp(socket) => socket.send(data).await.map(|_| ()),
+            Self::Unix(socket) => socket.send(data).await.map(|_| ()),
+            Self::Tcp(stream) => {
+                // RFC 6587 octet-counting framing
+                let mut framed = format!("{} ", data.len()).into_bytes();
+                framed.extend_from_slice(data);
+                stream.write_all(&framed).await
+            }
+        }
+    }
+}
+
+enum SinkTarget {
+    Syslog {
+        params: SyslogParams,
+        hostname: String,
+        connection: Option<SyslogConnection>,
+    },
+    Journald {
+        params: JournaldParams,
+        socket: UnixDatagram,
+    },
+}
+
+impl SinkTarget {
+    async fn send(&mut self, record: &JsonLogRecord, text: &[u8]) -> anyhow::Result<()> {
+        match self {
+            Self::Syslog {
+                params,
+                hostname,
+                connection,
+            } => {
+                let data =
+                    format_rfc5424(params.facility, hostname, &params.app_name, record, text);
+
+                // If the server went away, we allow a single reconnection
+                // attempt before giving up on this record
+                for attempt in 0..2 {
+                    let conn = match connection {
+                        Some(conn) => conn,
+                        None => connection.insert(SyslogConnection::connect(params).await?),
+                    };
+                    match conn.send(&data).await {
+                        Ok(()) => return Ok(()),
+                        Err(err) => {
+                            connection.take();
+                            if attempt > 0 {
+                                return Err(err)
+                                    .with_context(|| format!("sending to {}", params.address));
+                            }
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Self::Journald { params, socket } => {
+                let data = format_journal_entry(&params.syslog_identifier, record, text);
+                socket
+                    .send_to(&data, &params.socket_path)
+                    .await
+                    .with_context(|| format!("sending to {}", params.socket_path.display()))?;
+                Ok(())
+            }
+        }
+    }
+}
+
+pub struct LogSinkState {
+    params: LogSinkParams,
+    receiver: Receiver<LogCommand>,
+    template_engine: TemplateEngine,
+    target: SinkTarget,
+}
+
+impl LogSinkState {
+    pub fn new(
+        params: LogSinkParams,
+        receiver: Receiver<LogCommand>,
+        template_engine: TemplateEngine,
+    ) -> anyhow::Result<Self> {
+        let target = match (&params.syslog, &params.journald) {
+            (Some(syslog), None) => SinkTarget::Syslog {
+                params: syslog.clone(),
+                hostname: syslog.hostname.clone().unwrap_or_else(|| {
+                    gethostname::gethostname()
+                        .to_str()
+                        .unwrap_or("localhost")
+                        .to_string()
+                }),
+                connection: None,
+            },
+            (None, Some(journald)) => SinkTarget::Journald {
+                params: journald.clone(),
+                socket: UnixDatagram::unbound()?,
+            },
+            _ => anyhow::bail!(
+                "log sink `{}` must specify exactly one of `syslog` or `journald`",
+                params.name
+            ),
+        };
+
+        Ok(Self {
+            params,
+            receiver,
+            template_engine,
+            target,
+        })
+    }
+
+    pub async fn logger_thread(&mut self) {
+        tracing::debug!("LogSinkParams: {:#?}", self.params);
+
+        loop {
+            let cmd = match self.receiver.recv_async().await {
+                Ok(cmd) => cmd,
+                other => {
+                    tracing::debug!("logging channel closed {other:?}");
+                    return;
+                }
+            };
+            match cmd {
+                LogCommand::Terminate => {
+                    tracing::debug!("LogCommand::Terminate received. Stopping writing logs");
+                    break;
+                }
+                LogCommand::Record(record, _msg) => {
+                    if let Err(err) = self.do_record(record).await {
+                        tracing::error!("failed to log to sink {}: {err:#}", self.params.name);
+                    };
+                }
+            }
+        }
+    }
+
+    async fn do_record(&mut self, record: JsonLogRecord) -> anyhow::Result<()> {
+        tracing::trace!("do_record {record:?}");
+
+        let mut record_text = Vec::new();
+        self.template_engine.add_global("log_record", &record)?;
+
+        if let Some(template) =
+            resolve_template(&self.params.per_record, &self.template_engine, record.kind)
+        {
+            template.render_to_write(&record, &mut record_text)?;
+        } else {
+            serde_json::to_writer(&mut record_text, &record).context("serializing record")?;
+        }
+
+        self.target.send(&record, &record_text).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    fn make_record(kind: RecordType) -> JsonLogRecord {
+        let timestamp = Utc.with_ymd_and_hms(2026, 10, 18, 12, 30, 45).unwrap();
+        JsonLogRecord {
+            id: "d7ef132b5d7711eea8c8000c29c33806".to_string(),
+            queue: "example.com".to_string(),
+            timestamp,
+            ..JsonLogRecord::empty(kind)
+        }
+    }
+
+    #[test]
+    fn rfc5424() {
+        let record = make_record(RecordType::Bounce);
+        let data = format_rfc5424(SyslogFacility::Mail, "mta 1", "kumod", &record, b"hello\n");
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            format!(
+                "<20>1 2026-10-18T12:30:45.000000Z mta_1 kumod {} Bounce - hello",
+                std::process::id()
+            )
+        );
+
+        let record = make_record(RecordType::Delivery);
+        let data = format_rfc5424(SyslogFacility::Local0, "", "", &record, b"hello");
+        assert!(String::from_utf8(data)
+            .unwrap()
+            .starts_with("<134>1 2026-10-18T12:30:45.000000Z - - "));
+    }
+
+    #[test]
+    fn journal_fields() {
+        let mut buf = vec![];
+        append_journal_field(&mut buf, "SIMPLE", b"value");
+        append_journal_field(&mut buf, "MULTI", b"line1\nline2");
+        assert_eq!(
+            buf,
+            b"SIMPLE=value\nMULTI\n\x0b\x00\x00\x00\x00\x00\x00\x00line1\nline2\n"
+        );
+
+        let record = make_record(RecordType::Delivery);
+        let entry = format_journal_entry("kumod", &record, b"{}\n");
+        assert_eq!(
+            String::from_utf8(entry).unwrap(),
+            "MESSAGE={}\nPRIORITY=6\nSYSLOG_IDENTIFIER=kumod\nKUMO_RECORD_TYPE=Delivery\n\
+             KUMO_MESSAGE_ID=d7ef132b5d7711eea8c8000c29c33806\nKUMO_QUEUE=example.com\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn syslog_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let params = SyslogParams {
+            protocol: SyslogProtocol::Udp,
+            address: server.local_addr().unwrap().to_string(),
+            facility: SyslogFacility::Mail,
+            app_name: "kumod".to_string(),
+            hostname: Some("mta".to_string()),
+        };
+        let mut target = SinkTarget::Syslog {
+            params,
+            hostname: "mta".to_string(),
+            connection: None,
+        };
+        let record = make_record(RecordType::Delivery);
+        target.send(&record, b"delivered\n").await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        let len = server.recv(&mut buf).await.unwrap();
+        let received = String::from_utf8_lossy(&buf[..len]);
+        assert!(received.starts_with("<22>1 "), "{received}");
+        assert!(received.ends_with(" Delivery - delivered"), "{received}");
+    }
+}
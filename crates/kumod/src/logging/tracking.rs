@@ -0,0 +1,634 @@
+//! Maintains an indexed store of recent log records, so that the
+//! history of a given message or recipient can be retrieved via
+//! the `/api/admin/trace-message/v1` endpoint without having to
+//! search through the log files.
+use crate::logging::files::LogFileParams;
+use crate::logging::{LogCommand, LogRecordParams};
+use anyhow::Context;
+use chrono::Utc;
+use flume::Receiver;
+use kumo_api_types::tracking::{TraceMessageV1Request, TraceMessageV1Response};
+use kumo_log_types::{JsonLogRecord, RecordType};
+use message::queue_name::QueueNameComponents;
+use parking_lot::FairMutex as Mutex;
+use serde::Deserialize;
+use sqlite::{Connection, ConnectionThreadSafe, State, Value};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+/// The path to the tracking database, if message tracking
+/// has been configured
+static TRACKING_DB_PATH: LazyLock<Mutex<Option<String>>> = LazyLock::new(Mutex::default);
+
+/// How often to prune records that are older than the retention period
+const PRUNE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+const DEFAULT_QUERY_LIMIT: usize = 1000;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MessageTrackingParams {
+    /// Path to the sqlite database that holds the tracking data
+    #[serde(default = "MessageTrackingParams::default_path")]
+    pub path: String,
+
+    /// How long to keep records in the database
+    #[serde(
+        default = "MessageTrackingParams::default_retention",
+        with = "duration_serde"
+    )]
+    pub retention: Duration,
+
+    /// Maximum number of outstanding items to be logged before
+    /// the submission will block; helps to avoid runaway issues
+    /// spiralling out of control.
+    #[serde(default = "LogFileParams::default_back_pressure")]
+    pub back_pressure: usize,
+
+    /// List of meta fields to capture in the stored records
+    #[serde(default)]
+    pub meta: Vec<String>,
+
+    /// List of message headers to capture in the stored records
+    #[serde(default)]
+    pub headers: Vec<String>,
+
+    #[serde(default)]
+    pub per_record: HashMap<RecordType, LogRecordParams>,
+
+    /// The name of an event which can be used to filter
+    /// out log records which should not be tracked
+    #[serde(default)]
+    pub filter_event: Option<String>,
+
+    /// The maximum number of records to write in a single transaction
+    #[serde(default = "MessageTrackingParams::default_batch_size")]
+    pub batch_size: usize,
+
+    /// How long to wait for a batch to fill up before writing it
+    #[serde(
+        default = "MessageTrackingParams::default_max_batch_latency",
+        with = "duration_serde"
+    )]
+    pub max_batch_latency: Duration,
+}
+
+impl MessageTrackingParams {
+    fn default_path() -> String {
+        "/var/spool/kumomta/message-tracking.db".to_string()
+    }
+    fn default_retention() -> Duration {
+        Duration::from_secs(7 * 86400)
+    }
+    fn default_batch_size() -> usize {
+        1000
+    }
+    fn default_max_batch_latency() -> Duration {
+        Duration::from_secs(1)
+    }
+}
+
+pub struct MessageTrackingState {
+    params: MessageTrackingParams,
+    receiver: Receiver<LogCommand>,
+    db: Option<ConnectionThreadSafe>,
+    batch: Vec<JsonLogRecord>,
+    batch_deadline: Option<Instant>,
+    next_prune: Instant,
+}
+
+impl MessageTrackingState {
+    pub fn new(
+        params: MessageTrackingParams,
+        receiver: Receiver<LogCommand>,
+    ) -> anyhow::Result<Self> {
+        if is_enabled() {
+            anyhow::bail!("message tracking has already been configured");
+        }
+        let db = open_tracking_db(&params.path)?;
+
+        Ok(Self {
+            params,
+            receiver,
+            db: Some(db),
+            batch: vec![],
+            batch_deadline: None,
+            next_prune: Instant::now(),
+        })
+    }
+
+    pub async fn logger_thread(&mut self) {
+        tracing::debug!("MessageTrackingParams: {:#?}", self.params);
+
+        loop {
+            let cmd = match self.batch_deadline {
+                Some(deadline) => {
+                    tokio::select! {
+                        cmd = self.receiver.recv_async() => cmd,
+                        _ = tokio::time::sleep_until(deadline.into()) => {
+                            self.flush().await;
+                            continue;
+                        }
+                    }
+                }
+                None => self.receiver.recv_async().await,
+            };
+            let cmd = match cmd {
+                Ok(cmd) => cmd,
+                other => {
+                    tracing::debug!("logging channel closed {other:?}");
+                    break;
+                }
+            };
+            match cmd {
+                LogCommand::Terminate => {
+                    tracing::debug!("LogCommand::Terminate received. Stopping tracking");
+                    break;
+                }
+                LogCommand::Record(record, _msg) => {
+                    // Don't track the batches generated by log webhooks
+                    if record.reception_protocol.as_deref() == Some("LogRecord") {
+                        continue;
+                    }
+                    self.batch.push(record);
+                    if self.batch_deadline.is_none() {
+                        self.batch_deadline
+                            .replace(Instant::now() + self.params.max_batch_latency);
+                    }
+                    if self.batch.len() >= self.params.batch_size {
+                        self.flush().await;
+                    }
+                }
+            }
+        }
+
+        self.flush().await;
+    }
+
+    async fn flush(&mut self) {
+        self.batch_deadline.take();
+
+        let prune = Instant::now() >= self.next_prune;
+        if self.batch.is_empty() && !prune {
+            return;
+        }
+        let db = match self.db.take() {
+            Some(db) => db,
+            None => match open_tracking_db(&self.params.path) {
+                Ok(db) => db,
+                Err(err) => {
+                    tracing::error!(
+                        "message tracking: discarding {} records: {err:#}",
+                        self.batch.len()
+                    );
+                    self.batch.clear();
+                    return;
+                }
+            },
+        };
+        let batch = std::mem::take(&mut self.batch);
+        let retention = self.params.retention;
+        let count = batch.len();
+
+        let result = tokio::task::spawn_blocking(move || {
+            let result = insert_records(&db, &batch).and_then(|()| {
+                if prune {
+                    prune_records(&db, retention)
+                } else {
+                    Ok(())
+                }
+            });
+            (db, result)
+        })
+        .await;
+
+        match result {
+            Ok((db, result)) => {
+                self.db.replace(db);
+                if let Err(err) = result {
+                    tracing::error!("message tracking: failed to store {count} records: {err:#}");
+                }
+            }
+            Err(err) => {
+                // The connection was lost along with the task;
+                // it will be re-opened by the next flush
+                tracing::error!("message tracking: failed to store {count} records: {err:#}");
+            }
+        }
+
+        if prune {
+            self.next_prune = Instant::now() + PRUNE_INTERVAL;
+        }
+    }
+}
+
+fn open_tracking_db(path: &str) -> anyhow::Result<ConnectionThreadSafe> {
+    let mut db = Connection::open_thread_safe(path)
+        .with_context(|| format!("opening message tracking database {path}"))?;
+    db.set_busy_timeout(30_000)?;
+
+    let query = r#"
+PRAGMA journal_mode=WAL;
+
+CREATE TABLE IF NOT EXISTS tracking_events (
+    id INTEGER PRIMARY KEY,
+    timestamp int NOT NULL,
+    message_id text NOT NULL,
+    sender text NOT NULL,
+    tenant text,
+    campaign text,
+    record text NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS tracking_events_timestamp ON tracking_events (timestamp);
+CREATE INDEX IF NOT EXISTS tracking_events_message_id ON tracking_events (message_id);
+CREATE INDEX IF NOT EXISTS tracking_events_sender ON tracking_events (sender);
+CREATE INDEX IF NOT EXISTS tracking_events_tenant ON tracking_events (tenant);
+CREATE INDEX IF NOT EXISTS tracking_events_campaign ON tracking_events (campaign);
+
+CREATE TABLE IF NOT EXISTS tracking_recipients (
+    event_id int NOT NULL,
+    recipient text NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS tracking_recipients_recipient ON tracking_recipients (recipient);
+CREATE INDEX IF NOT EXISTS tracking_recipients_event_id ON tracking_recipients (event_id);
+    "#;
+
+    db.execute(query)?;
+
+    Ok(db)
+}
+
+fn insert_records(db: &ConnectionThreadSafe, records: &[JsonLogRecord]) -> anyhow::Result<()> {
+    if records.is_empty() {
+        return Ok(());
+    }
+
+    db.execute("BEGIN").context("begin")?;
+
+    let result = (|| -> anyhow::Result<()> {
+        let mut insert_event = db
+            .prepare(
+                "INSERT INTO tracking_events
+                (timestamp, message_id, sender, tenant, campaign, record)
+                VALUES ($timestamp, $message_id, $sender, $tenant, $campaign, $record)",
+            )
+            .context("prepare event insert")?;
+        let mut last_id = db
+            .prepare("SELECT last_insert_rowid() AS id")
+            .context("prepare last_insert_rowid")?;
+        let mut insert_recipient = db
+            .prepare(
+                "INSERT INTO tracking_recipients (event_id, recipient)
+                VALUES ($event_id, $recipient)",
+            )
+            .context("prepare recipient insert")?;
+
+        for record in records {
+            let components = QueueNameComponents::parse(&record.queue);
+            let json = serde_json::to_string(record).context("serializing record")?;
+
+            insert_event.reset()?;
+            insert_event.bind(("$timestamp", record.timestamp.timestamp_micros()))?;
+            insert_event.bind(("$message_id", record.id.as_str()))?;
+            insert_event.bind(("$sender", record.sender.to_lowercase().as_str()))?;
+            insert_event.bind(("$tenant", components.tenant))?;
+            insert_event.bind(("$campaign", components.campaign))?;
+            insert_event.bind(("$record", json.as_str()))?;
+            insert_event.next()?;
+
+            last_id.reset()?;
+            let event_id: i64 = match last_id.next()? {
+                State::Row => last_id.read("id")?,
+                State::Done => anyhow::bail!("failed to retrieve the new row id"),
+            };
+
+            for recipient in &record.recipient {
+                insert_recipient.reset()?;
+                insert_recipient.bind(("$event_id", event_id))?;
+                insert_recipient.bind(("$recipient", recipient.to_lowercase().as_str()))?;
+                insert_recipient.next()?;
+            }
+        }
+
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => {
+            db.execute("COMMIT").context("commit")?;
+            Ok(())
+        }
+        Err(err) => {
+            db.execute("ROLLBACK").ok();
+            Err(err)
+        }
+    }
+}
+
+/// Remove records that are older than the retention period
+fn prune_records(db: &ConnectionThreadSafe, retention: Duration) -> anyhow::Result<()> {
+    let Some(cutoff) = chrono::Duration::from_std(retention)
+        .ok()
+        .and_then(|retention| Utc::now().checked_sub_signed(retention))
+    else {
+        return Ok(());
+    };
+    let cutoff = cutoff.timestamp_micros();
+
+    db.execute("BEGIN").context("begin")?;
+    let result = (|| -> anyhow::Result<()> {
+        let mut prune = db
+            .prepare(
+                "DELETE FROM tracking_recipients WHERE event_id IN
+                (SELECT id FROM tracking_events WHERE timestamp < $cutoff)",
+            )
+            .context("prepare recipient prune")?;
+        prune.bind(("$cutoff", cutoff))?;
+        prune.next()?;
+
+        let mut prune = db
+            .prepare("DELETE FROM tracking_events WHERE timestamp < $cutoff")
+            .context("prepare event prune")?;
+        prune.bind(("$cutoff", cutoff))?;
+        prune.next()?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => {
+            db.execute("COMMIT").context("commit")?;
+            Ok(())
+        }
+        Err(err) => {
+            db.execute("ROLLBACK").ok();
+            Err(err)
+        }
+    }
+}
+
+/// Makes the tracking database at path available for queries.
+/// This is called once the task that populates it has been started.
+pub fn activate(path: &str) -> anyhow::Result<()> {
+    let mut db_path = TRACKING_DB_PATH.lock();
+    if db_path.is_some() {
+        anyhow::bail!("message tracking has already been configured");
+    }
+    db_path.replace(path.to_string());
+    Ok(())
+}
+
+/// Returns true if message tracking has been configured
+pub fn is_enabled() -> bool {
+    TRACKING_DB_PATH.lock().is_some()
+}
+
+/// Build the query for the provided request.
+/// Only the conditions that were specified are included, so that
+/// sqlite is able to make use of the appropriate index.
+fn build_query(request: &TraceMessageV1Request, limit: usize) -> (String, Vec<(String, Value)>) {
+    let mut conditions = vec![];
+    let mut params = vec![];
+
+    let mut add = |condition: &str, name: &str, value: Value| {
+        conditions.push(condition.to_string());
+        params.push((name.to_string(), value));
+    };
+
+    if let Some(message_id) = &request.message_id {
+        add(
+            "message_id = $message_id",
+            "$message_id",
+            Value::String(message_id.to_string()),
+        );
+    }
+    if let Some(recipient) = &request.recipient {
+        add(
+            "id IN (SELECT event_id FROM tracking_recipients WHERE recipient = $recipient)",
+            "$recipient",
+            Value::String(recipient.to_lowercase()),
+        );
+    }
+    if let Some(sender) = &request.sender {
+        add(
+            "sender = $sender",
+            "$sender",
+            Value::String(sender.to_lowercase()),
+        );
+    }
+    if let Some(tenant) = &request.tenant {
+        add(
+            "tenant = $tenant",
+            "$tenant",
+            Value::String(tenant.to_string()),
+        );
+    }
+    if let Some(campaign) = &request.campaign {
+        add(
+            "campaign = $campaign",
+            "$campaign",
+            Value::String(campaign.to_string()),
+        );
+    }
+    if let Some(since) = &request.since {
+        add(
+            "timestamp >= $since",
+            "$since",
+            Value::Integer(since.timestamp_micros()),
+        );
+    }
+    if let Some(until) = &request.until {
+        add(
+            "timestamp < $until",
+            "$until",
+            Value::Integer(until.timestamp_micros()),
+        );
+    }
+
+    let mut query = "SELECT record FROM tracking_events".to_string();
+    if !conditions.is_empty() {
+        query.push_str(" WHERE ");
+        query.push_str(&conditions.join(" AND "));
+    }
+    // Fetch one more than the limit so that we can tell
+    // whether the results were truncated
+    query.push_str(&format!(
+        " ORDER BY timestamp, id LIMIT {}",
+        limit.saturating_add(1)
+    ));
+
+    (query, params)
+}
+
+/// Query the tracking database for the records that match the request
+pub async fn query_message_trace(
+    request: TraceMessageV1Request,
+) -> anyhow::Result<TraceMessageV1Response> {
+    let path = TRACKING_DB_PATH
+        .lock()
+        .clone()
+        .context("message tracking has not been configured")?;
+    let limit = request.limit.unwrap_or(DEFAULT_QUERY_LIMIT);
+
+    tokio::task::spawn_blocking(move || {
+        let db = open_tracking_db(&path)?;
+        query_records(&db, &request, limit)
+    })
+    .await?
+}
+
+fn query_records(
+    db: &ConnectionThreadSafe,
+    request: &TraceMessageV1Request,
+    limit: usize,
+) -> anyhow::Result<TraceMessageV1Response> {
+    let (query, params) = build_query(request, limit);
+
+    let mut statement = db.prepare(&query).context("prepare")?;
+    for (name, value) in params {
+        statement.bind((name.as_str(), value))?;
+    }
+
+    let mut records = vec![];
+    let mut truncated = false;
+    while let State::Row = statement.next()? {
+        if records.len() == limit {
+            truncated = true;
+            break;
+        }
+        let record: String = statement.read("record")?;
+        records.push(
+            serde_json::from_str(&record)
+                .with_context(|| format!("parsing stored record {record}"))?,
+        );
+    }
+
+    Ok(TraceMessageV1Response { records, truncated })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn query_building() {
+        let (query, params) = build_query(
+            &TraceMessageV1Request {
+                recipient: Some("Bob@Example.com".to_string()),
+                campaign: Some("newsletter".to_string()),
+                ..Default::default()
+            },
+            10,
+        );
+        assert_eq!(
+            query,
+            "SELECT record FROM tracking_events \
+            WHERE id IN (SELECT event_id FROM tracking_recipients WHERE recipient = $recipient) \
+            AND campaign = $campaign \
+            ORDER BY timestamp, id LIMIT 11"
+        );
+        assert_eq!(
+            params,
+            vec![
+                (
+                    "$recipient".to_string(),
+                    Value::String("bob@example.com".to_string())
+                ),
+                (
+                    "$campaign".to_string(),
+                    Value::String("newsletter".to_string())
+                ),
+            ]
+        );
+    }
+
+    fn make_record(id: &str, recipient: &str, age: chrono::Duration) -> JsonLogRecord {
+        JsonLogRecord {
+            id: id.to_string(),
+            sender: "Sender@example.com".to_string(),
+            recipient: vec![recipient.to_string()],
+            queue: "newsletter:acme@example.com".to_string(),
+            timestamp: Utc::now() - age,
+            ..JsonLogRecord::empty(RecordType::Delivery)
+        }
+    }
+
+    fn ids(response: &TraceMessageV1Response) -> Vec<&str> {
+        response.records.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn insert_query_prune() {
+        let db = open_tracking_db(":memory:").unwrap();
+        let hour = chrono::Duration::hours(1);
+        insert_records(
+            &db,
+            &[
+                make_record("old", "bob@example.com", hour * 48),
+                make_record("first", "Bob@Example.com", hour * 2),
+                make_record("second", "alice@example.com", hour),
+            ],
+        )
+        .unwrap();
+
+        let query = |request: TraceMessageV1Request, limit: usize| {
+            query_records(&db, &request, limit).unwrap()
+        };
+
+        // Recipients and senders are matched case insensitively,
+        // and the results are ordered by time
+        let result = query(
+            TraceMessageV1Request {
+                recipient: Some("BOB@example.com".to_string()),
+                ..Default::default()
+            },
+            10,
+        );
+        assert_eq!(ids(&result), vec!["old", "first"]);
+        assert!(!result.truncated);
+
+        let result = query(
+            TraceMessageV1Request {
+                sender: Some("sender@EXAMPLE.com".to_string()),
+                tenant: Some("acme".to_string()),
+                campaign: Some("newsletter".to_string()),
+                ..Default::default()
+            },
+            10,
+        );
+        assert_eq!(ids(&result), vec!["old", "first", "second"]);
+
+        let result = query(
+            TraceMessageV1Request {
+                message_id: Some("second".to_string()),
+                ..Default::default()
+            },
+            10,
+        );
+        assert_eq!(ids(&result), vec!["second"]);
+        assert_eq!(result.records[0].recipient, vec!["alice@example.com"]);
+
+        // The limit truncates the results
+        let result = query(TraceMessageV1Request::default(), 2);
+        assert_eq!(ids(&result), vec!["old", "first"]);
+        assert!(result.truncated);
+
+        // Pruning removes the records, and their recipients,
+        // that are older than the retention period
+        prune_records(&db, Duration::from_secs(86400)).unwrap();
+        let result = query(TraceMessageV1Request::default(), 10);
+        assert_eq!(ids(&result), vec!["first", "second"]);
+
+        let mut orphans = db
+            .prepare(
+                "SELECT count(*) AS n FROM tracking_recipients
+                WHERE event_id NOT IN (SELECT id FROM tracking_events)",
+            )
+            .unwrap();
+        assert!(matches!(orphans.next().unwrap(), State::Row));
+        let count: i64 = orphans.read("n").unwrap();
+        assert_eq!(count, 0);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::logging::RecordSubmitter;
+use bounce_classify::BounceClass;
+use chrono::Utc;
+pub use kumo_log_types::*;
+use rfc5321::Response;
+use std::collections::HashMap;
+
+pub struct LogUnsubscribe {
+    /// The spool id of the message that carried the token
+    pub id: Option<String>,
+    pub recipient: String,
+    /// The queue name synthesized from the tenant and campaign
+    /// of the original message
+    pub queue: String,
+    pub peer_address: Option<ResolvedAddress>,
+    pub response: Response,
+    pub meta: serde_json::Value,
+    pub reception_protocol: Option<String>,
+}
+
+pub async fn log_unsubscribe(args: LogUnsubscribe) {
+    let Some(submitter) = RecordSubmitter::new() else {
+        return;
+    };
+    let now = Utc::now();
+    let nodeid = kumo_server_common::nodeid::NodeId::get_uuid();
+
+    let kind = RecordType::Unsubscribe;
+
+    // The meta is populated separately for each logger,
+    // based on its configuration
+    let record = JsonLogRecord {
+        kind,
+        id: args.id.unwrap_or_default(),
+        size: 0,
+        sender: "".to_string(),
+        recipient: vec![args.recipient],
+        queue: args.queue,
+        site: "".to_string(),
+        peer_address: args.peer_address,
+        response: args.response,
+        timestamp: now,
+        created: now,
+        num_attempts: 0,
+        egress_pool: None,
+        egress_source: None,
+        bounce_classification: BounceClass::default(),
+        bounce_classifier_rule: None,
+        feedback_report: None,
+        headers: HashMap::new(),
+        meta: HashMap::new(),
+        delivery_protocol: None,
+        reception_protocol: args.reception_protocol,
+        nodeid,
+        tls_cipher: None,
+        tls_protocol_version: None,
+        tls_peer_subject_name: None,
+        source_address: None,
+        provider_name: None,
+        session_id: None,
+        server_session: None,
+        client_session: None,
+    };
+
+    submitter.submit(record, Some(&args.meta)).await;
+}
@@ -0,0 +1,694 @@
+use crate::delivery_metrics::MetricsWrappedConnection;
+use crate::logging::disposition::{log_disposition, LogDisposition};
+use crate::logging::files::LogFileParams;
+use crate::logging::{resolve_template, LogCommand, LogRecordParams};
+use crate::queue::{DeliveryProto, IncrementAttempts, InsertReason, QueueConfig, QueueManager};
+use crate::ready_queue::{AttemptConnectionDisposition, Dispatcher, QueueDispatcher};
+use crate::spool::SpoolManager;
+use anyhow::Context;
+use async_trait::async_trait;
+use data_encoding::HEXLOWER;
+use data_loader::KeySource;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use flume::Receiver;
+pub use kumo_log_types::*;
+use kumo_server_runtime::spawn;
+use kumo_template::TemplateEngine;
+use lruttl::declare_cache;
+use message::Message;
+use parking_lot::FairMutex as Mutex;
+use rfc5321::parser::EnvelopeAddress;
+use rfc5321::Response;
+use serde::Deserialize;
+use serde_json::json;
+use spool::SpoolId;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Batches for a log webhook named `NAME` are queued to
+/// `NAME.log_webhook.kumomta.internal`
+pub const LOG_WEBHOOK_QUEUE_SUFFIX: &str = ".log_webhook.kumomta.internal";
+
+static WEBHOOKS: LazyLock<Mutex<HashMap<String, Arc<WebhookEndpoint>>>> =
+    LazyLock::new(Mutex::default);
+
+declare_cache! {
+/// Caches log webhook hmac keys based on their KeySource spec
+static KEY_CACHE: LruCacheWithTtl<KeySource, Arc<Vec<u8>>>::new("log_webhook_key_cache", 16);
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct LogWebhookParams {
+    /// The unique name to identify this instance of the log webhook
+    pub name: String,
+
+    /// The URL to which batches of log records will be POSTed
+    pub url: String,
+
+    /// Maximum number of outstanding items to be logged before
+    /// the submission will block; helps to avoid runaway issues
+    /// spiralling out of control.
+    #[serde(default = "LogFileParams::default_back_pressure")]
+    pub back_pressure: usize,
+
+    /// List of meta fields to capture in the log
+    #[serde(default)]
+    pub meta: Vec<String>,
+
+    /// List of message headers to capture in the log
+    #[serde(default)]
+    pub headers: Vec<String>,
+
+    #[serde(default)]
+    pub per_record: HashMap<RecordType, LogRecordParams>,
+
+    /// The name of an event which can be used to filter
+    /// out log records which should not be sent to this webhook
+    #[serde(default)]
+    pub filter_event: Option<String>,
+
+    /// Additional HTTP headers to add to each request
+    #[serde(default)]
+    pub http_headers: HashMap<String, String>,
+
+    /// The maximum number of records in a batch
+    #[serde(default = "LogWebhookParams::default_batch_size")]
+    pub batch_size: usize,
+
+    /// The maximum size of the (uncompressed) payload of a batch.
+    /// A record that would take the batch beyond this size causes
+    /// the pending batch to be sent first; a single record that is
+    /// larger than this is sent in a batch of its own.
+    #[serde(default = "LogWebhookParams::default_batch_max_bytes")]
+    pub batch_max_bytes: usize,
+
+    /// How long to wait for a batch to fill up before sending it
+    #[serde(
+        default = "LogWebhookParams::default_max_batch_latency",
+        with = "duration_serde"
+    )]
+    pub max_batch_latency: Duration,
+
+    /// Whether to gzip compress the request body
+    #[serde(default)]
+    pub gzip: bool,
+
+    /// How to sign the request body
+    #[serde(default)]
+    pub hmac: Option<WebhookHmac>,
+
+    /// The maximum number of concurrent requests to the url
+    #[serde(default = "LogWebhookParams::default_max_concurrency")]
+    pub max_concurrency: usize,
+
+    /// How long to wait for the request to complete
+    #[serde(default = "LogWebhookParams::default_timeout", with = "duration_serde")]
+    pub timeout: Duration,
+
+    /// If true, batches are not saved to the spool before
+    /// they are queued
+    #[serde(default)]
+    pub deferred_spool: bool,
+
+    /// Base retry interval for failed batches
+    #[serde(
+        default = "LogWebhookParams::default_retry_interval",
+        with = "duration_serde"
+    )]
+    pub retry_interval: Duration,
+
+    /// Optional cap on the computed retry interval
+    #[serde(default, with = "duration_serde")]
+    pub max_retry_interval: Option<Duration>,
+
+    /// Limits how long a failing batch will be retried
+    #[serde(default = "LogWebhookParams::default_max_age", with = "duration_serde")]
+    pub max_age: Duration,
+}
+
+impl LogWebhookParams {
+    fn default_batch_size() -> usize {
+        100
+    }
+    fn default_batch_max_bytes() -> usize {
+        1024 * 1024
+    }
+    fn default_max_batch_latency() -> Duration {
+        Duration::from_secs(1)
+    }
+    fn default_max_concurrency() -> usize {
+        4
+    }
+    fn default_timeout() -> Duration {
+        Duration::from_secs(60)
+    }
+    fn default_retry_interval() -> Duration {
+        Duration::from_secs(60)
+    }
+    fn default_max_age() -> Duration {
+        Duration::from_secs(86400)
+    }
+
+    fn queue_name(&self) -> String {
+        format!("{}{LOG_WEBHOOK_QUEUE_SUFFIX}", self.name)
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookHmac {
+    /// The shared secret used to compute the signature
+    pub key: KeySource,
+
+    /// The digest algorithm; one of the algorithms supported
+    /// by the kumo.digest.hmac_XXX functions
+    #[serde(default = "WebhookHmac::default_algorithm")]
+    pub algorithm: String,
+
+    /// The name of the HTTP header that will hold the signature
+    #[serde(default = "WebhookHmac::default_header")]
+    pub header: String,
+
+    /// How long to cache the loaded key
+    #[serde(default = "WebhookHmac::default_key_ttl", with = "duration_serde")]
+    pub key_ttl: Duration,
+}
+
+impl WebhookHmac {
+    fn default_algorithm() -> String {
+        "sha256".to_string()
+    }
+    fn default_header() -> String {
+        "X-Kumo-Signature".to_string()
+    }
+    fn default_key_ttl() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    async fn load_key(&self) -> anyhow::Result<Arc<Vec<u8>>> {
+        KEY_CACHE
+            .get_or_try_insert(&self.key, |_| self.key_ttl, async {
+                Ok::<Arc<Vec<u8>>, anyhow::Error>(Arc::new(self.key.get().await?))
+            })
+            .await
+            .map_err(|err| anyhow::anyhow!("loading log webhook hmac key: {err:#}"))
+            .map(|lookup| lookup.item)
+    }
+}
+
+/// The registered state for a log webhook, used by the dispatcher
+/// to send the queued batches
+struct WebhookEndpoint {
+    params: LogWebhookParams,
+    client: reqwest::Client,
+    concurrency: Arc<Semaphore>,
+}
+
+impl WebhookEndpoint {
+    fn lookup(name: &str) -> Option<Arc<Self>> {
+        WEBHOOKS.lock().get(name).cloned()
+    }
+
+    fn register(params: &LogWebhookParams) -> anyhow::Result<()> {
+        if let Some(hmac) = &params.hmac {
+            anyhow::ensure!(
+                mod_digest::hmac_algorithm_by_name(&hmac.algorithm).is_some(),
+                "log webhook `{}`: unsupported hmac algorithm `{}`",
+                params.name,
+                hmac.algorithm
+            );
+        }
+        anyhow::ensure!(
+            params.max_concurrency > 0,
+            "log webhook `{}`: max_concurrency must be greater than 0",
+            params.name
+        );
+
+        let client = reqwest::Client::builder()
+            .timeout(params.timeout)
+            .build()
+            .context("building http client")?;
+
+        let mut webhooks = WEBHOOKS.lock();
+        if webhooks.contains_key(&params.name) {
+            anyhow::bail!(
+                "A log webhook with name `{}` has already been registered",
+                params.name
+            );
+        }
+        webhooks.insert(
+            params.name.clone(),
+            Arc::new(Self {
+                params: params.clone(),
+                client,
+                concurrency: Arc::new(Semaphore::new(params.max_concurrency)),
+            }),
+        );
+        Ok(())
+    }
+
+    async fn send(&self, payload: &[u8]) -> anyhow::Result<Response> {
+        let _permit = self.concurrency.acquire().await?;
+
+        let body = if self.params.gzip {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(payload)?;
+            encoder.finish()?
+        } else {
+            payload.to_vec()
+        };
+
+        let mut request = self
+            .client
+            .post(&self.params.url)
+            .header("Content-Type", "application/x-ndjson");
+        if self.params.gzip {
+            request = request.header("Content-Encoding", "gzip");
+        }
+        for (name, value) in &self.params.http_headers {
+            request = request.header(name, value);
+        }
+        if let Some(hmac) = &self.params.hmac {
+            let key = hmac.load_key().await?;
+            let signature = mod_digest::hmac_sign(&hmac.algorithm, &key, &body)?;
+            request = request.header(
+                &hmac.header,
+                format!("{}={}", hmac.algorithm, HEXLOWER.encode(&signature)),
+            );
+        }
+
+        let response = match request.body(body).send().await {
+            Ok(response) => response,
+            Err(err) => {
+                return Ok(Response {
+                    code: 451,
+                    enhanced_code: None,
+                    content: format!("{err:#}"),
+                    command: None,
+                })
+            }
+        };
+
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let content = format!(
+            "{} {}: {}",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or(""),
+            body.chars().take(256).collect::<String>()
+        );
+
+        Ok(Response {
+            code: response_code_for_status(status),
+            enhanced_code: None,
+            content,
+            command: None,
+        })
+    }
+}
+
+/// Maps the HTTP status returned by the endpoint to an SMTP style
+/// response code.  Only the statuses that indicate that the endpoint
+/// will never accept the batch are treated as permanent failures;
+/// everything else, including authentication failures and rate
+/// limiting, may be resolved by the time the batch is retried.
+fn response_code_for_status(status: reqwest::StatusCode) -> u16 {
+    use reqwest::StatusCode;
+    if status.is_success() {
+        return 250;
+    }
+    match status {
+        StatusCode::BAD_REQUEST
+        | StatusCode::GONE
+        | StatusCode::PAYLOAD_TOO_LARGE
+        | StatusCode::UNSUPPORTED_MEDIA_TYPE
+        | StatusCode::UNPROCESSABLE_ENTITY => 554,
+        _ => 451,
+    }
+}
+
+/// Returns the queue config for a log webhook batch queue
+pub fn make_log_webhook_queue(queue_name: &str) -> Option<QueueConfig> {
+    let name = queue_name.strip_suffix(LOG_WEBHOOK_QUEUE_SUFFIX)?;
+    let mut config = QueueConfig {
+        protocol: DeliveryProto::LogWebhook {
+            log_webhook: name.to_string(),
+        },
+        retry_interval: LogWebhookParams::default_retry_interval(),
+        max_age: LogWebhookParams::default_max_age(),
+        ..QueueConfig::default()
+    };
+    if let Some(endpoint) = WebhookEndpoint::lookup(name) {
+        config.retry_interval = endpoint.params.retry_interval;
+        config.max_retry_interval = endpoint.params.max_retry_interval;
+        config.max_age = endpoint.params.max_age;
+    }
+    Some(config)
+}
+
+pub struct LogWebhookState {
+    params: LogWebhookParams,
+    receiver: Receiver<LogCommand>,
+    template_engine: TemplateEngine,
+    batch: Vec<u8>,
+    batch_count: usize,
+    batch_deadline: Option<Instant>,
+}
+
+impl LogWebhookState {
+    pub fn new(
+        params: LogWebhookParams,
+        receiver: Receiver<LogCommand>,
+        template_engine: TemplateEngine,
+    ) -> anyhow::Result<Self> {
+        WebhookEndpoint::register(&params)?;
+        Ok(Self {
+            params,
+            receiver,
+            template_engine,
+            batch: vec![],
+            batch_count: 0,
+            batch_deadline: None,
+        })
+    }
+
+    pub async fn logger_thread(&mut self) {
+        tracing::debug!("LogWebhookParams: {:#?}", self.params);
+
+        loop {
+            let cmd = match self.batch_deadline {
+                Some(deadline) => {
+                    tokio::select! {
+                        cmd = self.receiver.recv_async() => cmd,
+                        _ = tokio::time::sleep_until(deadline.into()) => {
+                            self.flush().await;
+                            continue;
+                        }
+                    }
+                }
+                None => self.receiver.recv_async().await,
+            };
+            let cmd = match cmd {
+                Ok(cmd) => cmd,
+                other => {
+                    tracing::debug!("logging channel closed {other:?}");
+                    break;
+                }
+            };
+            match cmd {
+                LogCommand::Terminate => {
+                    tracing::debug!("LogCommand::Terminate received. Stopping writing logs");
+                    break;
+                }
+                LogCommand::Record(record, _msg) => {
+                    if let Err(err) = self.do_record(record).await {
+                        tracing::error!("failed to log: {err:#}");
+                    };
+                }
+            }
+        }
+
+        self.flush().await;
+    }
+
+    async fn do_record(&mut self, record: JsonLogRecord) -> anyhow::Result<()> {
+        tracing::trace!("do_record {record:?}");
+
+        // Don't log the batches that we generate, or those
+        // from log hooks
+        if record.reception_protocol.as_deref() == Some("LogRecord") {
+            return Ok(());
+        }
+
+        let mut record_text = Vec::new();
+        self.template_engine.add_global("log_record", &record)?;
+
+        if let Some(template) =
+            resolve_template(&self.params.per_record, &self.template_engine, record.kind)
+        {
+            template.render_to_write(&record, &mut record_text)?;
+        } else {
+            serde_json::to_writer(&mut record_text, &record).context("serializing record")?;
+        }
+        if record_text.last() != Some(&b'\n') {
+            record_text.push(b'\n');
+        }
+
+        if self.would_exceed_max_bytes(record_text.len()) {
+            self.flush().await;
+        }
+
+        self.batch.extend_from_slice(&record_text);
+        self.batch_count += 1;
+        if self.batch_deadline.is_none() {
+            self.batch_deadline
+                .replace(Instant::now() + self.params.max_batch_latency);
+        }
+
+        if self.batch_count >= self.params.batch_size
+            || self.batch.len() >= self.params.batch_max_bytes
+        {
+            self.flush().await;
+        }
+        Ok(())
+    }
+
+    /// Returns true if appending a record of `len` bytes would take
+    /// the pending batch beyond batch_max_bytes.  An empty batch
+    /// always accepts the record, so that a record that is larger
+    /// than batch_max_bytes is sent in a batch of its own.
+    fn would_exceed_max_bytes(&self, len: usize) -> bool {
+        self.batch_count > 0 && self.batch.len() + len > self.params.batch_max_bytes
+    }
+
+    async fn flush(&mut self) {
+        self.batch_deadline.take();
+        if self.batch_count == 0 {
+            return;
+        }
+        let batch = std::mem::take(&mut self.batch);
+        let count = std::mem::take(&mut self.batch_count);
+
+        if let Err(err) = self.enqueue_batch(batch, count).await {
+            tracing::error!(
+                "log webhook {}: failed to queue batch of {count} records: {err:#}",
+                self.params.name
+            );
+        }
+    }
+
+    async fn enqueue_batch(&self, batch: Vec<u8>, count: usize) -> anyhow::Result<()> {
+        let queue_name = self.params.queue_name();
+        let msg = Message::new_dirty(
+            SpoolId::new(),
+            EnvelopeAddress::null_sender(),
+            vec![EnvelopeAddress::parse("log_webhook@kumomta.internal")?],
+            json!({
+                "queue": queue_name,
+                "reception_protocol": "LogRecord",
+                "log_webhook": self.params.name,
+                "log_webhook_records": count,
+            }),
+            Arc::new(batch.into_boxed_slice()),
+        )?;
+
+        if !self.params.deferred_spool {
+            msg.save(None).await.context("save")?;
+        }
+        QueueManager::insert(&queue_name, msg, InsertReason::Received.into())
+            .await
+            .context("insert")
+    }
+}
+
+#[derive(Debug)]
+pub struct LogWebhookDispatcher {
+    name: String,
+    connection: Option<MetricsWrappedConnection<()>>,
+}
+
+impl LogWebhookDispatcher {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            connection: None,
+        }
+    }
+}
+
+#[async_trait]
+impl QueueDispatcher for LogWebhookDispatcher {
+    async fn close_connection(&mut self, _dispatcher: &mut Dispatcher) -> anyhow::Result<bool> {
+        match self.connection.take() {
+            Some(_) => Ok(true),
+            None => Ok(false),
+        }
+    }
+
+    async fn attempt_connection(
+        &mut self,
+        dispatcher: &mut Dispatcher,
+    ) -> anyhow::Result<AttemptConnectionDisposition> {
+        if self.connection.is_none() {
+            self.connection
+                .replace(dispatcher.metrics.wrap_connection(()));
+            Ok(AttemptConnectionDisposition::ConnectedNew)
+        } else {
+            Ok(AttemptConnectionDisposition::ReusedExisting)
+        }
+    }
+
+    async fn have_more_connection_candidates(&mut self, _dispatcher: &mut Dispatcher) -> bool {
+        false
+    }
+
+    async fn deliver_message(
+        &mut self,
+        mut msgs: Vec<Message>,
+        dispatcher: &mut Dispatcher,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            msgs.len() == 1,
+            "LogWebhookDispatcher only supports a batch size of 1"
+        );
+        let msg = msgs.pop().expect("just verified that there is one");
+
+        let response = match WebhookEndpoint::lookup(&self.name) {
+            Some(endpoint) => {
+                dispatcher.set_detail(format!("log webhook POST {}", endpoint.params.url));
+                let payload = msg.data().await?;
+                endpoint.send(&payload).await?
+            }
+            None => Response {
+                code: 451,
+                enhanced_code: None,
+                content: format!("log webhook `{}` is not configured", self.name),
+                command: None,
+            },
+        };
+
+        let kind = if response.code == 250 {
+            RecordType::Delivery
+        } else if response.code >= 500 {
+            RecordType::Bounce
+        } else {
+            RecordType::TransientFailure
+        };
+
+        if let Some(msg) = dispatcher.msgs.pop() {
+            log_disposition(LogDisposition {
+                kind,
+                msg: msg.clone(),
+                site: &dispatcher.name,
+                peer_address: None,
+                response: response.clone(),
+                egress_pool: None,
+                egress_source: None,
+                relay_disposition: None,
+                delivery_protocol: Some("LogWebhook"),
+                tls_info: None,
+                source_address: None,
+                provider: None,
+                session_id: None,
+                recipient_list: None,
+            })
+            .await;
+
+            match kind {
+                RecordType::TransientFailure => {
+                    spawn(
+                        "requeue message".to_string(),
+                        QueueManager::requeue_message(
+                            msg,
+                            IncrementAttempts::Yes,
+                            None,
+                            response,
+                            InsertReason::LoggedTransientFailure.into(),
+                        ),
+                    )?;
+                    dispatcher.metrics.inc_transfail();
+                }
+                RecordType::Bounce => {
+                    SpoolManager::remove_from_spool(*msg.id()).await?;
+                    dispatcher.metrics.inc_fail();
+                }
+                _ => {
+                    SpoolManager::remove_from_spool(*msg.id()).await?;
+                    dispatcher.metrics.inc_delivered();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn webhook_queue_config() {
+        assert!(make_log_webhook_queue("example.com").is_none());
+
+        let config = make_log_webhook_queue("events.log_webhook.kumomta.internal").unwrap();
+        assert_eq!(
+            config.protocol,
+            DeliveryProto::LogWebhook {
+                log_webhook: "events".to_string()
+            }
+        );
+        assert_eq!(config.protocol.ready_queue_name(), "logwebhook:events");
+        assert_eq!(config.retry_interval, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn batch_max_bytes() {
+        let params: LogWebhookParams = serde_json::from_value(json!({
+            "name": "batch_max_bytes",
+            "url": "http://localhost/",
+            "batch_max_bytes": 100,
+        }))
+        .unwrap();
+        let (_sender, receiver) = flume::bounded(1);
+        let mut state = LogWebhookState::new(params, receiver, TemplateEngine::new()).unwrap();
+
+        // An empty batch accepts a record of any size
+        assert!(!state.would_exceed_max_bytes(200));
+
+        state.batch = vec![b'x'; 60];
+        state.batch_count = 1;
+        assert!(!state.would_exceed_max_bytes(40));
+        assert!(state.would_exceed_max_bytes(41));
+    }
+
+    #[test]
+    fn status_codes() {
+        for (status, code) in [
+            (200, 250),
+            (204, 250),
+            (400, 554),
+            (401, 451),
+            (403, 451),
+            (404, 451),
+            (408, 451),
+            (410, 554),
+            (413, 554),
+            (422, 554),
+            (429, 451),
+            (500, 451),
+            (503, 451),
+        ] {
+            assert_eq!(
+                response_code_for_status(reqwest::StatusCode::from_u16(status).unwrap()),
+                code,
+                "{status}"
+            );
+        }
+    }
+}
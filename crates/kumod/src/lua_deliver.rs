@@ -351,6 +351,7 @@ impl QueueDispatcher for LuaQueueDispatcher {
                         recipient_list: None, // FIXME: multi recipient?
                     })
                     .await;
+                    dispatcher.metrics.observe_delivery_age(msg.id());
                     SpoolManager::remove_from_spool(*msg.id()).await?;
                     dispatcher.metrics.inc_delivered();
                 }
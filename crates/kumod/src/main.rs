@@ -4,7 +4,9 @@ use anyhow::Context;
 use chrono::Utc;
 use clap::Parser;
 use config::{declare_event, CallbackSignature};
-use kumo_server_common::diagnostic_logging::{DiagnosticFormat, LoggingConfig};
+use kumo_server_common::diagnostic_logging::{
+    DiagnosticFormat, LoggingConfig, OtlpConfig, OtlpProtocol,
+};
 use kumo_server_common::start::StartConfig;
 use kumo_server_lifecycle::LifeCycle;
 use kumo_server_runtime::{available_parallelism, rt_spawn};
@@ -22,20 +24,27 @@ pub static VALIDATE_SIG: Multiple("validate_config") -> ();
 }
 
 mod accounting;
+mod bounce_processor;
 mod delivery_metrics;
 mod dmarc;
 mod egress_source;
+mod feedback_processor;
 mod http_server;
+mod list_unsubscribe;
 mod logging;
 mod lua_deliver;
+mod message_trace;
 mod metrics_helper;
 mod mod_kumo;
+mod quarantine;
 mod queue;
+mod quota;
 mod ready_queue;
 mod smtp_dispatcher;
 mod smtp_server;
 mod spf;
 mod spool;
+mod suppression;
 mod xfer;
 
 /// KumoMTA Daemon.
@@ -74,6 +83,19 @@ struct Opt {
     #[arg(long, default_value = "full")]
     diag_format: DiagnosticFormat,
 
+    /// When set, traces of the lifecycle of each message are exported
+    /// to the OpenTelemetry collector at the specified endpoint.
+    ///
+    /// For grpc, this is typically `http://127.0.0.1:4317`.
+    /// For http, the full url is required, typically
+    /// `http://127.0.0.1:4318/v1/traces`.
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+
+    /// The OTLP transport protocol to use with --otlp-endpoint
+    #[arg(long, default_value = "grpc")]
+    otlp_protocol: OtlpProtocol,
+
     /// Instead of running the daemon, output the openapi spec json
     /// to stdout.
     #[arg(long)]
@@ -356,6 +378,11 @@ async fn run(opts: Opt) -> anyhow::Result<()> {
             } else {
                 "kumod=info,config=info,kumo_server_common=info,kumo_server_runtime=info,lruttl=info,mod_memoize=info,spool=info,lua=info,kumo_api_types=info"
             },
+            otlp: opts.otlp_endpoint.clone().map(|endpoint| OtlpConfig {
+                endpoint,
+                protocol: opts.otlp_protocol,
+                service_name: "kumod".to_string(),
+            }),
         },
         lua_funcs: &[
             kumo_server_common::register,
@@ -363,8 +390,10 @@ async fn run(opts: Opt) -> anyhow::Result<()> {
             crate::spool::register,
             crate::logging::register,
             message::dkim::register,
+            message::verp::register,
             crate::spf::register,
             crate::dmarc::register,
+            crate::list_unsubscribe::register,
             crate::xfer::lua::register,
         ],
         policy: &opts.policy,
@@ -0,0 +1,410 @@
+//! This module exports the lifecycle of individual messages as
+//! OpenTelemetry traces, when OTLP export has been enabled via
+//! the `--otlp-endpoint` command line option.
+//!
+//! Since a message can remain in the queues for days, and may be
+//! reloaded from spool after a restart, we don't hold a span open
+//! for its lifetime.  Instead, each message is assigned a span
+//! context that is recorded in its metadata, and the stages of its
+//! lifecycle are emitted as child spans with explicit start and end
+//! times.  When the message is removed from the spool, the root span
+//! covering the entire lifetime of the message is emitted.
+use crate::logging::disposition::RecordType;
+use dashmap::DashMap;
+use kumo_server_common::diagnostic_logging::otlp_enabled;
+use message::Message;
+use opentelemetry::global::BoxedTracer;
+use opentelemetry::trace::{
+    Span, SpanBuilder, SpanContext, SpanId, SpanKind, Status, TraceContextExt, TraceFlags, TraceId,
+    TraceState, Tracer,
+};
+use opentelemetry::{Context, KeyValue};
+use rfc5321::Response;
+use spool::SpoolId;
+use std::sync::LazyLock;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// Holds a W3C `traceparent` supplied by the injector of the message.
+/// The trace for the message will be linked as a child of it.
+pub const TRACEPARENT_META_KEY: &str = "traceparent";
+/// Holds the W3C `traceparent` representation of the root span
+/// of the trace for the message
+const TRACE_SPAN_META_KEY: &str = "trace_span";
+
+static TRACER: LazyLock<BoxedTracer> = LazyLock::new(|| opentelemetry::global::tracer("kumod"));
+static TRACKED: LazyLock<DashMap<SpoolId, TrackedMessage>> = LazyLock::new(DashMap::new);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Reception,
+    ScheduledQueue,
+    ReadyQueue,
+    DeliveryAttempt,
+}
+
+impl Stage {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Reception => "reception",
+            Self::ScheduledQueue => "scheduled_queue",
+            Self::ReadyQueue => "ready_queue",
+            Self::DeliveryAttempt => "delivery_attempt",
+        }
+    }
+}
+
+struct TrackedMessage {
+    span: SpanContext,
+    parent: Option<SpanContext>,
+    stage: Option<(Stage, SystemTime)>,
+    outcome: Option<RecordType>,
+}
+
+/// Parse a W3C traceparent header value.
+/// Returns None if it is not valid.
+pub fn parse_traceparent(value: &str) -> Option<SpanContext> {
+    let fields: Vec<&str> = value.trim().split('-').collect();
+    let [version, trace_id, span_id, flags] = fields.as_slice() else {
+        return None;
+    };
+    // Version ff is forbidden; future versions may append fields,
+    // which is why we only accept version 00 here
+    if *version != "00" || trace_id.len() != 32 || span_id.len() != 16 || flags.len() != 2 {
+        return None;
+    }
+    let trace_id = TraceId::from_hex(trace_id).ok()?;
+    let span_id = SpanId::from_hex(span_id).ok()?;
+    let flags = u8::from_str_radix(flags, 16).ok()?;
+    let sc = SpanContext::new(
+        trace_id,
+        span_id,
+        TraceFlags::new(flags),
+        true,
+        TraceState::default(),
+    );
+    sc.is_valid().then_some(sc)
+}
+
+/// Format a span context as a W3C traceparent header value
+pub fn format_traceparent(sc: &SpanContext) -> String {
+    format!(
+        "00-{}-{}-{:02x}",
+        sc.trace_id(),
+        sc.span_id(),
+        sc.trace_flags().to_u8()
+    )
+}
+
+fn new_span_id() -> SpanId {
+    let bytes = Uuid::new_v4().into_bytes();
+    SpanId::from_bytes(bytes[0..8].try_into().expect("8 bytes"))
+}
+
+fn new_trace_id() -> TraceId {
+    TraceId::from_bytes(Uuid::new_v4().into_bytes())
+}
+
+async fn get_span_context(msg: &Message, key: &str) -> Option<SpanContext> {
+    msg.get_meta_string(key)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|value| parse_traceparent(&value))
+}
+
+fn id_attribute(id: &SpoolId) -> KeyValue {
+    KeyValue::new("kumo.message_id", id.to_string())
+}
+
+fn emit_span(
+    parent: &SpanContext,
+    name: &'static str,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<KeyValue>,
+    status: Status,
+) {
+    let mut span = TRACER.build_with_context(
+        SpanBuilder::from_name(name)
+            .with_start_time(start)
+            .with_attributes(attributes),
+        &Context::new().with_remote_span_context(parent.clone()),
+    );
+    span.set_status(status);
+    span.end_with_timestamp(end);
+}
+
+/// Assign a trace context to a newly received message.
+/// `supplied` is an optional W3C traceparent provided by the injector;
+/// if it is not set, then any traceparent already present in the
+/// metadata of the message (eg: assigned by policy) will be used.
+/// Invalid traceparent values are ignored.
+pub async fn assign(msg: &Message, supplied: Option<&str>) -> anyhow::Result<()> {
+    if !otlp_enabled() {
+        return Ok(());
+    }
+
+    let parent = match supplied.and_then(parse_traceparent) {
+        Some(parent) => {
+            msg.set_meta(TRACEPARENT_META_KEY, format_traceparent(&parent))
+                .await?;
+            Some(parent)
+        }
+        None => get_span_context(msg, TRACEPARENT_META_KEY).await,
+    };
+
+    if let Some(parent) = &parent {
+        if !parent.is_sampled() {
+            return Ok(());
+        }
+    }
+
+    let span = SpanContext::new(
+        parent
+            .as_ref()
+            .map(|p| p.trace_id())
+            .unwrap_or_else(new_trace_id),
+        new_span_id(),
+        TraceFlags::SAMPLED,
+        false,
+        TraceState::default(),
+    );
+    msg.set_meta(TRACE_SPAN_META_KEY, format_traceparent(&span))
+        .await?;
+    Ok(())
+}
+
+/// Resume tracking the trace for a message that was loaded
+/// from the spool
+pub async fn resume(msg: &Message) {
+    if !otlp_enabled() {
+        return;
+    }
+    let Some(span) = get_span_context(msg, TRACE_SPAN_META_KEY).await else {
+        return;
+    };
+    let parent = get_span_context(msg, TRACEPARENT_META_KEY).await;
+    TRACKED.insert(
+        *msg.id(),
+        TrackedMessage {
+            span,
+            parent,
+            stage: None,
+            outcome: None,
+        },
+    );
+}
+
+/// Emit a span for an operation, such as a lua event handler or
+/// a spool write, performed on behalf of a message
+pub async fn record_span(
+    msg: &Message,
+    name: &'static str,
+    start: SystemTime,
+    mut attributes: Vec<KeyValue>,
+    result: Result<(), String>,
+) {
+    if !otlp_enabled() {
+        return;
+    }
+    let Some(span) = get_span_context(msg, TRACE_SPAN_META_KEY).await else {
+        return;
+    };
+    attributes.push(id_attribute(msg.id()));
+    emit_span(
+        &span,
+        name,
+        start,
+        SystemTime::now(),
+        attributes,
+        match result {
+            Ok(()) => Status::Unset,
+            Err(err) => Status::error(err),
+        },
+    );
+}
+
+/// Transition the message to the specified stage of its lifecycle,
+/// emitting a span for the stage that it was previously in
+pub fn enter_stage(id: &SpoolId, stage: Stage) {
+    if !otlp_enabled() {
+        return;
+    }
+    if let Some(mut entry) = TRACKED.get_mut(id) {
+        let now = SystemTime::now();
+        if let Some((prior, start)) = entry.stage.replace((stage, now)) {
+            emit_span(
+                &entry.span,
+                prior.name(),
+                start,
+                now,
+                vec![id_attribute(id)],
+                Status::Unset,
+            );
+        }
+    }
+}
+
+/// Called by log_disposition. A Reception record begins tracking the
+/// stages of the message.  Records that conclude a delivery attempt
+/// end the delivery attempt span, while other records are emitted
+/// as a zero-length span that marks the event.
+pub async fn log_record(msg: &Message, kind: RecordType, response: &Response, site: &str) {
+    if !otlp_enabled() {
+        return;
+    }
+    let id = *msg.id();
+    let now = SystemTime::now();
+
+    if kind == RecordType::Reception {
+        if let Some(span) = get_span_context(msg, TRACE_SPAN_META_KEY).await {
+            let parent = get_span_context(msg, TRACEPARENT_META_KEY).await;
+            TRACKED.insert(
+                id,
+                TrackedMessage {
+                    span,
+                    parent,
+                    stage: Some((Stage::Reception, msg.id().created().into())),
+                    outcome: None,
+                },
+            );
+        }
+        return;
+    }
+
+    let Some(mut entry) = TRACKED.get_mut(&id) else {
+        return;
+    };
+
+    let attributes = vec![
+        id_attribute(&id),
+        KeyValue::new("kumo.record_type", format!("{kind:?}")),
+        KeyValue::new("kumo.site", site.to_string()),
+        KeyValue::new("kumo.response", response.to_single_line()),
+    ];
+    let status = match kind {
+        RecordType::Delivery => Status::Ok,
+        RecordType::TransientFailure
+        | RecordType::Bounce
+        | RecordType::Expiration
+        | RecordType::AdminBounce => Status::error(response.to_single_line()),
+        _ => Status::Unset,
+    };
+
+    match (kind, entry.stage) {
+        (
+            RecordType::Delivery | RecordType::TransientFailure | RecordType::Bounce,
+            Some((Stage::DeliveryAttempt, start)),
+        ) => {
+            entry.stage.take();
+            emit_span(
+                &entry.span,
+                Stage::DeliveryAttempt.name(),
+                start,
+                now,
+                attributes,
+                status,
+            );
+        }
+        _ => {
+            emit_span(&entry.span, "event", now, now, attributes, status);
+        }
+    }
+
+    if matches!(
+        kind,
+        RecordType::Delivery
+            | RecordType::Bounce
+            | RecordType::Expiration
+            | RecordType::AdminBounce
+            | RecordType::QuarantineDelete
+    ) {
+        entry.outcome.replace(kind);
+    }
+}
+
+/// Called when the message is removed from the spool.
+/// Emits the root span for the message and stops tracking it.
+pub fn finish(id: &SpoolId) {
+    if !otlp_enabled() {
+        return;
+    }
+    let Some((_, entry)) = TRACKED.remove(id) else {
+        return;
+    };
+    let now = SystemTime::now();
+
+    if let Some((stage, start)) = entry.stage {
+        emit_span(
+            &entry.span,
+            stage.name(),
+            start,
+            now,
+            vec![id_attribute(id)],
+            Status::Unset,
+        );
+    }
+
+    let mut attributes = vec![id_attribute(id)];
+    let status = match entry.outcome {
+        Some(RecordType::Delivery) => Status::Ok,
+        Some(kind) => {
+            attributes.push(KeyValue::new("kumo.outcome", format!("{kind:?}")));
+            Status::error(format!("{kind:?}"))
+        }
+        None => Status::Unset,
+    };
+
+    let mut builder = SpanBuilder::from_name("message")
+        .with_kind(SpanKind::Consumer)
+        .with_start_time(SystemTime::from(id.created()))
+        .with_attributes(attributes);
+    builder.trace_id = Some(entry.span.trace_id());
+    builder.span_id = Some(entry.span.span_id());
+
+    let parent = match &entry.parent {
+        Some(parent) => Context::new().with_remote_span_context(parent.clone()),
+        None => Context::new(),
+    };
+    let mut span = TRACER.build_with_context(builder, &parent);
+    span.set_status(status);
+    span.end_with_timestamp(now);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn traceparent_round_trip() {
+        let value = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let sc = parse_traceparent(value).unwrap();
+        assert!(sc.is_sampled());
+        assert_eq!(format_traceparent(&sc), value);
+
+        let sc =
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap();
+        assert!(!sc.is_sampled());
+    }
+
+    #[test]
+    fn traceparent_invalid() {
+        for value in [
+            "",
+            "garbage",
+            // all zero trace id
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            // all zero span id
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            // unsupported version
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            // truncated
+            "00-4bf92f3577b34da6a3ce929d0e0e47-00f067aa0ba902b7-01",
+            // non-hex
+            "00-4bf92f3577b34da6a3ce929d0e0e473z-00f067aa0ba902b7-01",
+        ] {
+            assert!(parse_traceparent(value).is_none(), "{value}");
+        }
+    }
+}
@@ -87,6 +87,16 @@ pub static TOTAL_MSGS_DELIVERED_BY_PROVIDER: PruningCounterRegistry<ProviderKey>
         "total_messages_delivered_by_provider");
 }
 
+declare_metric! {
+/// total number of complaint feedback reports received by the
+/// feedback processor, keyed by the provider that sent them.
+///
+/// Compare against `total_messages_delivered_by_provider` to
+/// determine the complaint rate for a provider.
+pub static TOTAL_FEEDBACK_REPORTS_BY_PROVIDER: CounterRegistry<ProviderKey>(
+        "total_feedback_reports_by_provider");
+}
+
 declare_metric! {
 /// total number of message delivery attempts that transiently failed
 pub static TOTAL_MSGS_TRANSFAIL_BY_PROVIDER: PruningCounterRegistry<ProviderKey>(
@@ -138,6 +148,43 @@ pub static QUEUED_COUNT_GAUGE_BY_PROVIDER_AND_POOL:
     PruningGaugeRegistry<ProviderAndPoolKey>("queued_count_by_provider_and_pool");
 }
 
+declare_metric! {
+/// The current day of the warm-up schedule for a ready queue.
+///
+/// {{since('dev')}}
+///
+/// This is `0` before the schedule starts, and one more than
+/// the number of days in the schedule once it has completed.
+/// The metric is only present for ready queues whose egress
+/// path has a [warmup](../../kumo/make_egress_path/warmup.md)
+/// schedule configured.
+pub static WARMUP_DAY: PruningGaugeRegistry<ServiceKey>("warmup_day");
+}
+
+declare_metric! {
+/// The effective daily message volume permitted by the warm-up
+/// schedule for a ready queue.
+///
+/// {{since('dev')}}
+///
+/// This reflects any slowdown that is currently in effect, and
+/// is `0` once the schedule has completed and no longer limits
+/// the queue.
+pub static WARMUP_DAILY_VOLUME: PruningGaugeRegistry<ServiceKey>("warmup_daily_volume");
+}
+
+declare_metric! {
+/// Whether the warm-up schedule for a ready queue is currently
+/// slowed down.
+///
+/// {{since('dev')}}
+///
+/// The value is `1` while the
+/// [warmup_slowdown](../../kumo/make_egress_path/warmup_slowdown.md)
+/// option is in effect for the queue, and `0` otherwise.
+pub static WARMUP_SLOWED_DOWN: PruningGaugeRegistry<ServiceKey>("warmup_slowed_down");
+}
+
 declare_metric! {
 /// total number of messages ever received
 pub static TOTAL_MSGS_RECVD: CounterRegistry<ServiceKey>("total_messages_received");
@@ -160,6 +207,69 @@ pub static DELIVER_MESSAGE_LATENCY_ROLLUP: HistogramVec(
     );
 }
 
+/// Bucket thresholds, in seconds, for the message age histograms.
+/// These span from one second through to three days, to cover
+/// both the fast path and messages that sat in the scheduled queue
+/// through several retries.
+const MESSAGE_AGE_BUCKETS: &[f64] = &[
+    1.0, 5.0, 15.0, 30.0, 60.0, 300.0, 900.0, 1800.0, 3600.0, 7200.0, 14400.0, 43200.0, 86400.0,
+    259200.0,
+];
+
+declare_metric! {
+/// how many seconds had elapsed between the reception of a message
+/// and its first delivery attempt, by provider and egress pool.
+///
+/// {{since('dev')}}
+///
+/// The `provider` label is the name of the matching provider from
+/// the shaping configuration, or empty if no provider matched.
+/// Scheduled queue names are not used as a label because of their
+/// potentially unbounded cardinality.
+pub static MESSAGE_AGE_AT_FIRST_ATTEMPT: HistogramVec(
+        "message_age_at_first_attempt",
+        &["provider", "pool"],
+        MESSAGE_AGE_BUCKETS.to_vec()
+    );
+}
+
+declare_metric! {
+/// how many seconds had elapsed between the reception of a message
+/// and its successful delivery, by provider and egress pool.
+///
+/// {{since('dev')}}
+///
+/// This is recorded for each successfully delivered recipient
+/// of messages delivered via `smtp_client` or a custom lua
+/// delivery protocol.
+///
+/// The `provider` label is the name of the matching provider from
+/// the shaping configuration, or empty if no provider matched.
+pub static MESSAGE_AGE_AT_DELIVERY: HistogramVec(
+        "message_age_at_delivery",
+        &["provider", "pool"],
+        MESSAGE_AGE_BUCKETS.to_vec()
+    );
+}
+
+declare_metric! {
+/// how many seconds an outbound connection session lasted,
+/// by service type, provider and egress pool.
+///
+/// {{since('dev')}}
+///
+/// The duration is measured from the start of the connection
+/// attempt through to the point where the connection is closed.
+///
+/// The `provider` label is the name of the matching provider from
+/// the shaping configuration, or empty if no provider matched.
+pub static CONNECTION_SESSION_DURATION: HistogramVec(
+        "connection_session_duration",
+        &["service_type", "provider", "pool"],
+        vec![0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1800.0, 3600.0]
+    );
+}
+
 declare_metric! {
 /// total number of times a readyq maintainer was run
 pub static TOTAL_READYQ_RUNS: IntCounter("total_readyq_runs");
@@ -176,6 +286,11 @@ pub fn smtp_rejected_for_service(service: &str) -> AtomicCounter {
     SMTP_SERVER_REJECTIONS.get_or_create(&service as &dyn ServiceKeyTrait)
 }
 
+pub fn feedback_reports_for_provider(provider: &str) -> AtomicCounter {
+    let provider = BorrowedProviderKey { provider };
+    TOTAL_FEEDBACK_REPORTS_BY_PROVIDER.get_or_create(&provider as &dyn ProviderKeyTrait)
+}
+
 pub fn connection_denied_for_service(service: &str) -> AtomicCounter {
     let service = BorrowedServiceKey { service };
     CONN_DENIED.get_or_create(&service as &dyn ServiceKeyTrait)
@@ -186,6 +301,21 @@ pub fn ready_full_counter_for_service(service: &str) -> AtomicCounter {
     READY_FULL_COUNTER.get_or_create(&service as &dyn ServiceKeyTrait)
 }
 
+pub fn warmup_day_gauge_for_service(service: &str) -> AtomicCounter {
+    let service = BorrowedServiceKey { service };
+    WARMUP_DAY.get_or_create(&service as &dyn ServiceKeyTrait)
+}
+
+pub fn warmup_daily_volume_gauge_for_service(service: &str) -> AtomicCounter {
+    let service = BorrowedServiceKey { service };
+    WARMUP_DAILY_VOLUME.get_or_create(&service as &dyn ServiceKeyTrait)
+}
+
+pub fn warmup_slowed_down_gauge_for_service(service: &str) -> AtomicCounter {
+    let service = BorrowedServiceKey { service };
+    WARMUP_SLOWED_DOWN.get_or_create(&service as &dyn ServiceKeyTrait)
+}
+
 pub fn ready_count_gauge_for_service(service: &str) -> AtomicCounter {
     let service = BorrowedServiceKey { service };
     READY_COUNT_GAUGE.get_or_create(&service as &dyn ServiceKeyTrait)
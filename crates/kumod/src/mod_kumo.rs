@@ -20,6 +20,7 @@ use num_format::{Locale, ToFormattedString};
 use rfc5321::parser::EnvelopeAddress;
 use spool::SpoolId;
 use std::sync::Arc;
+use std::time::Duration;
 use throttle::ThrottleSpec;
 
 pub fn register(lua: &Lua) -> anyhow::Result<()> {
@@ -301,6 +302,42 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         })?,
     )?;
 
+    kumo_mod.set(
+        "configure_accounting_retention",
+        lua.create_function(|lua, duration: Value| {
+            let duration: duration_serde::Wrap<Duration> = lua.from_value(duration)?;
+            *crate::accounting::RETENTION.lock() = duration.into_inner();
+            Ok(())
+        })?,
+    )?;
+
+    kumo_mod.set(
+        "set_message_quotas",
+        lua.create_function(|lua, rules: Value| {
+            let rules: Vec<crate::quota::QuotaRule> = from_lua_value(lua, rules)?;
+            crate::quota::set_message_quotas(rules).map_err(any_err)
+        })?,
+    )?;
+
+    kumo_mod.set(
+        "configure_suppression",
+        lua.create_async_function(|lua, params: Value| async move {
+            let params: crate::suppression::SuppressionParams = from_lua_value(&lua, params)?;
+            crate::suppression::configure(params).await.map_err(any_err)
+        })?,
+    )?;
+
+    kumo_mod.set(
+        "configure_list_unsubscribe",
+        lua.create_async_function(|lua, params: Value| async move {
+            let params: crate::list_unsubscribe::ListUnsubscribeParams =
+                from_lua_value(&lua, params)?;
+            crate::list_unsubscribe::configure(params)
+                .await
+                .map_err(any_err)
+        })?,
+    )?;
+
     kumo_mod.set(
         "make_throttle",
         lua.create_function(move |_lua, (name, spec): (String, String)| {
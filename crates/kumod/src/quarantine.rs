@@ -0,0 +1,258 @@
+//! Holds messages that have been quarantined via `msg:quarantine`.
+//!
+//! A quarantined message is persisted in the spool with its
+//! quarantine state recorded in its metadata, but rather than being
+//! placed into its scheduled queue it is kept here, where it is not
+//! eligible for delivery and will not expire. The message remains
+//! held until an administrator either releases it back into its
+//! scheduled queue, or deletes it.
+use crate::http_server::queue_name_multi_index::Criteria;
+use crate::logging::disposition::{log_disposition, LogDisposition, RecordType};
+use crate::queue::{InsertContext, InsertReason, QueueManager};
+use crate::spool::SpoolManager;
+use dashmap::DashMap;
+use kumo_api_types::quarantine::{QuarantineV1ListEntry, QuarantineV1Selector};
+use kumo_prometheus::declare_metric;
+use message::quarantine::QuarantineInfo;
+use message::queue_name::QueueNameComponents;
+use message::Message;
+use rfc5321::{EnhancedStatusCode, Response};
+use spool::SpoolId;
+use std::sync::LazyLock;
+
+static HELD: LazyLock<DashMap<SpoolId, HeldMessage>> = LazyLock::new(DashMap::new);
+
+declare_metric! {
+/// The number of messages that are currently being held in quarantine.
+static QUARANTINED_COUNT: IntGauge("quarantined_message_count");
+}
+
+#[derive(Clone)]
+struct HeldMessage {
+    msg: Message,
+    queue: String,
+    info: QuarantineInfo,
+    sender: String,
+    recipient: Vec<String>,
+}
+
+impl HeldMessage {
+    fn matches(&self, selector: &QuarantineV1Selector) -> bool {
+        if !selector.ids.is_empty() && !selector.ids.contains(self.msg.id()) {
+            return false;
+        }
+        if let Some(reason) = &selector.reason {
+            if *reason != self.info.reason {
+                return false;
+            }
+        }
+
+        let criteria = Criteria {
+            campaign: selector.campaign.clone(),
+            tenant: selector.tenant.clone(),
+            domain: selector.domain.clone(),
+            routing_domain: selector.routing_domain.clone(),
+            queue_names: Default::default(),
+        };
+        let components = QueueNameComponents::parse(&self.queue);
+        criteria.matches(
+            components.campaign,
+            components.tenant,
+            Some(components.domain),
+            components.routing_domain,
+            Some(&self.queue),
+        )
+    }
+
+    fn to_entry(&self) -> QuarantineV1ListEntry {
+        QuarantineV1ListEntry {
+            id: *self.msg.id(),
+            queue: self.queue.clone(),
+            reason: self.info.reason.clone(),
+            since: self.info.since,
+            sender: self.sender.clone(),
+            recipient: self.recipient.clone(),
+        }
+    }
+}
+
+/// If msg has been quarantined, take ownership of it and hold it
+/// until it is released or deleted, returning true.
+/// Returns false if the message is not quarantined, in which case
+/// the caller should continue to queue it as normal.
+pub async fn hold_if_quarantined(
+    msg: &Message,
+    queue_name: &str,
+    context: &InsertContext,
+) -> anyhow::Result<bool> {
+    let Some(info) = msg.get_quarantine().await? else {
+        return Ok(false);
+    };
+
+    // Ensure that the quarantine state is persisted before
+    // we log that the message has been quarantined
+    if msg.needs_save() {
+        msg.save(None).await?;
+    }
+
+    let held = HeldMessage {
+        msg: msg.clone(),
+        queue: queue_name.to_string(),
+        sender: msg.sender().await?.to_string(),
+        recipient: msg.recipient_list_string().await?,
+        info,
+    };
+
+    // Don't log Enumerated, as the Quarantine record was
+    // already logged when the message was first held
+    if !context.only(InsertReason::Enumerated) {
+        log_transition(RecordType::Quarantine, &held, None).await;
+    }
+
+    HELD.insert(*msg.id(), held);
+    QUARANTINED_COUNT.set(HELD.len() as i64);
+
+    // A held message doesn't count against the quotas of its queue
+    // until it is released
+    crate::quota::release(*msg.id());
+
+    msg.shrink()?;
+    Ok(true)
+}
+
+/// Returns up to `limit` entries matching the selector
+pub fn list(selector: &QuarantineV1Selector, limit: Option<usize>) -> Vec<QuarantineV1ListEntry> {
+    let mut entries: Vec<QuarantineV1ListEntry> = HELD
+        .iter()
+        .filter(|item| item.matches(selector))
+        .map(|item| item.to_entry())
+        .collect();
+    entries.sort_by_key(|entry| entry.since);
+    if let Some(limit) = limit {
+        entries.truncate(limit);
+    }
+    entries
+}
+
+/// Returns the summary entry and the held message for the specified id
+pub fn get(id: &SpoolId) -> Option<(QuarantineV1ListEntry, Message)> {
+    HELD.get(id).map(|item| (item.to_entry(), item.msg.clone()))
+}
+
+fn take_matching(selector: &QuarantineV1Selector) -> Vec<HeldMessage> {
+    let ids: Vec<SpoolId> = HELD
+        .iter()
+        .filter(|item| item.matches(selector))
+        .map(|item| *item.key())
+        .collect();
+
+    let taken: Vec<HeldMessage> = ids
+        .into_iter()
+        .filter_map(|id| HELD.remove(&id).map(|(_id, held)| held))
+        .collect();
+    QUARANTINED_COUNT.set(HELD.len() as i64);
+    taken
+}
+
+/// Clear the quarantine state of the matching messages and
+/// insert them into their scheduled queues.
+/// Returns the number of messages that were released.
+pub async fn release(selector: &QuarantineV1Selector, comment: Option<&str>) -> usize {
+    let mut count = 0;
+    for held in take_matching(selector) {
+        let id = *held.msg.id();
+        let result = async {
+            held.msg.release_quarantine().await?;
+            held.msg.save(None).await?;
+            log_transition(RecordType::QuarantineRelease, &held, comment).await;
+            QueueManager::insert(
+                &held.queue,
+                held.msg.clone(),
+                InsertReason::QuarantineReleased.into(),
+            )
+            .await
+        }
+        .await;
+
+        match result {
+            Ok(()) => count += 1,
+            Err(err) => {
+                tracing::error!("failed to release {id} from quarantine: {err:#}");
+                // Put it back so that the operator can try again
+                HELD.insert(id, held);
+                QUARANTINED_COUNT.set(HELD.len() as i64);
+            }
+        }
+    }
+    count
+}
+
+/// Remove the matching messages from the spool.
+/// Returns the number of messages that were deleted.
+pub async fn delete(selector: &QuarantineV1Selector, comment: Option<&str>) -> usize {
+    let mut count = 0;
+    for held in take_matching(selector) {
+        let id = *held.msg.id();
+        let result = async {
+            // The meta is needed for logging, and cannot be loaded
+            // once the message has been removed from the spool
+            held.msg.load_meta_if_needed().await?;
+            SpoolManager::try_remove_from_spool(id).await
+        }
+        .await;
+
+        match result {
+            Ok(()) => {
+                log_transition(RecordType::QuarantineDelete, &held, comment).await;
+                count += 1;
+            }
+            Err(err) => {
+                tracing::error!("failed to remove quarantined {id} from spool: {err:#}");
+                // Put it back so that the operator can try again
+                HELD.insert(id, held);
+                QUARANTINED_COUNT.set(HELD.len() as i64);
+            }
+        }
+    }
+    count
+}
+
+async fn log_transition(kind: RecordType, held: &HeldMessage, comment: Option<&str>) {
+    let (code, class, action) = match kind {
+        RecordType::QuarantineRelease => (250, 2, "Released from quarantine"),
+        RecordType::QuarantineDelete => (551, 5, "Deleted from quarantine"),
+        _ => (250, 2, "Quarantined"),
+    };
+    let content = match (kind, comment) {
+        (RecordType::Quarantine, _) => format!("{action}: {}", held.info.reason),
+        (_, Some(comment)) => format!("{action}: {comment}"),
+        (_, None) => action.to_string(),
+    };
+
+    log_disposition(LogDisposition {
+        kind,
+        msg: held.msg.clone(),
+        site: "localhost",
+        peer_address: None,
+        response: Response {
+            code,
+            enhanced_code: Some(EnhancedStatusCode {
+                class,
+                subject: 7,
+                detail: 1,
+            }),
+            content,
+            command: None,
+        },
+        egress_pool: None,
+        egress_source: None,
+        relay_disposition: None,
+        delivery_protocol: None,
+        provider: None,
+        tls_info: None,
+        source_address: None,
+        session_id: None,
+        recipient_list: Some(held.recipient.clone()),
+    })
+    .await;
+}
@@ -28,10 +28,21 @@ pub struct QueueConfig {
     #[serde(default, with = "duration_serde")]
     pub max_retry_interval: Option<Duration>,
 
+    /// How the retry interval grows with each successive attempt.
+    /// The default is to double it each time.
+    #[serde(default)]
+    pub retry_schedule: RetrySchedule,
+
     /// Limits how long a message can remain in the queue
     #[serde(default = "QueueConfig::default_max_age", with = "duration_serde")]
     pub max_age: Duration,
 
+    /// When set, a DelayWarning record is logged the first time
+    /// that a message is found to have been queued for longer
+    /// than this duration
+    #[serde(default, with = "duration_serde")]
+    pub delay_warning: Option<Duration>,
+
     /// Specifies which egress pool should be used when
     /// delivering these messages
     #[serde(default)]
@@ -92,6 +103,57 @@ pub struct QueueShrinkPolicy {
     pub policy: MemoryReductionPolicy,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub enum RetrySchedule {
+    /// retry_interval * 2^attempt
+    #[default]
+    Exponential,
+    /// retry_interval * (attempt + 1)
+    Linear,
+    /// retry_interval multiplied by successive fibonacci numbers
+    /// (1, 2, 3, 5, 8, ...)
+    Fibonacci,
+    /// An explicit list of intervals, indexed by attempt.
+    /// The final entry is repeated for subsequent attempts.
+    Steps(#[serde(with = "duration_serde")] Vec<Duration>),
+    /// Call the named event to compute the delay, passing in
+    /// the message, the most recent response and its bounce
+    /// classification. If the event returns nil, or when the
+    /// delay must be computed without a response (eg: when
+    /// estimating the schedule for messages loaded from spool),
+    /// the Exponential schedule is used.
+    Event(String),
+}
+
+impl RetrySchedule {
+    /// Returns the un-capped delay, in seconds, for the specified
+    /// zero-based attempt number
+    fn delay_seconds_for_attempt(&self, retry_interval: Duration, attempt: u16) -> u64 {
+        let interval = retry_interval.as_secs();
+        match self {
+            Self::Exponential | Self::Event(_) => {
+                interval.saturating_mul(2u64.saturating_pow(attempt as u32))
+            }
+            Self::Linear => interval.saturating_mul(attempt as u64 + 1),
+            Self::Fibonacci => {
+                let (mut a, mut b) = (1u64, 2u64);
+                for _ in 0..attempt {
+                    if a == u64::MAX {
+                        break;
+                    }
+                    (a, b) = (b, a.saturating_add(b));
+                }
+                interval.saturating_mul(a)
+            }
+            Self::Steps(steps) => steps
+                .get(attempt as usize)
+                .or_else(|| steps.last())
+                .map(|d| d.as_secs())
+                .unwrap_or(interval),
+        }
+    }
+}
+
 impl LuaUserData for QueueConfig {
     fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
         config::impl_pairs_and_index(methods);
@@ -103,7 +165,9 @@ impl Default for QueueConfig {
         Self {
             retry_interval: Self::default_retry_interval(),
             max_retry_interval: None,
+            retry_schedule: RetrySchedule::default(),
             max_age: Self::default_max_age(),
+            delay_warning: None,
             egress_pool: None,
             protocol: DeliveryProto::default(),
             max_message_rate: None,
@@ -195,16 +259,39 @@ impl QueueConfig {
 
     pub fn delay_for_attempt(&self, attempt: u16) -> chrono::Duration {
         let delay = self
-            .retry_interval
-            .as_secs()
-            .saturating_mul(2u64.saturating_pow(attempt as u32));
+            .retry_schedule
+            .delay_seconds_for_attempt(self.retry_interval, attempt);
+        self.clamp_retry_delay(delay)
+    }
+
+    /// Compute the magnitude of the jitter, in seconds, to apply to
+    /// `delay`. The default retry_interval is 20 minutes for which 1 minute
+    /// is desired. To accomodate different intervals we translate that to
+    /// allowing up to 1/20th of the interval as jitter, but we cap it to
+    /// 1 minute so that it doesn't result in excessive divergence for very
+    /// large intervals.
+    /// The schedules that are multiples of retry_interval derive the jitter
+    /// from retry_interval, while explicit steps and delays returned by
+    /// an event (`from_event`) derive it from the delay itself, so that
+    /// short steps are not outweighed by the jitter.
+    pub fn jitter_magnitude(&self, delay: chrono::Duration, from_event: bool) -> f32 {
+        let basis = match &self.retry_schedule {
+            RetrySchedule::Steps(_) => delay.num_seconds() as f32,
+            RetrySchedule::Event(_) if from_event => delay.num_seconds() as f32,
+            _ => self.retry_interval.as_secs_f32(),
+        };
+        (basis / 20.0).min(60.0)
+    }
 
+    /// Apply max_retry_interval to a delay expressed in seconds,
+    /// and convert it to a chrono::Duration
+    pub fn clamp_retry_delay(&self, delay: u64) -> chrono::Duration {
         let delay = match self.max_retry_interval.map(|d| d.as_secs()) {
             None => delay,
             Some(limit) => delay.min(limit),
         };
 
-        chrono::Duration::try_seconds((delay as i64).min(MAX_CHRONO_SECONDS))
+        chrono::Duration::try_seconds(delay.min(MAX_CHRONO_SECONDS as u64) as i64)
             .expect("seconds to always be <= MAX_CHRONO_SECONDS")
     }
 
@@ -20,6 +20,9 @@ pub enum DeliveryProto {
     Xfer {
         xfer: XferProtocol,
     },
+    LogWebhook {
+        log_webhook: String,
+    },
     HttpInjectionGenerator,
     DeferredSmtpInjection,
     Null,
@@ -35,6 +38,7 @@ impl DeliveryProto {
             Self::DeferredSmtpInjection { .. } => "defersmtpinject",
             Self::Null { .. } => "null",
             Self::Xfer { .. } => "xfer",
+            Self::LogWebhook { .. } => "logwebhook",
         }
     }
 
@@ -49,6 +53,7 @@ impl DeliveryProto {
             }
             Self::Lua { custom_lua } => format!("{proto_name}:{}", custom_lua.constructor),
             Self::HttpInjectionGenerator => format!("{proto_name}:generator"),
+            Self::LogWebhook { log_webhook } => format!("{proto_name}:{log_webhook}"),
         }
     }
 }
@@ -90,6 +90,8 @@ pub enum InsertReason {
     /// The egress source is unhealthy and has been auto-suspended via
     /// one of its `suspend_when_*` rules
     SourceIsUnhealthyAndSuspended,
+    /// The message was administratively released from quarantine
+    QuarantineReleased,
 }
 
 #[cfg(test)]
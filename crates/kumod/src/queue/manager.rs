@@ -293,7 +293,7 @@ impl QueueManager {
 
         let queue = QueueManager::resolve(&queue_name).await?;
         queue
-            .requeue_message_internal(msg, increment_attempts, delay, context)
+            .requeue_message_internal(msg, increment_attempts, delay, Some(&response), context)
             .await
     }
 
@@ -5,7 +5,8 @@ use crate::http_server::admin_suspend_v1::AdminSuspendEntry;
 use crate::http_server::inject_v1::{make_generate_queue_config, GENERATOR_QUEUE_NAME};
 use crate::http_server::queue_name_multi_index::CachedEntry;
 use crate::logging::disposition::{log_disposition, LogDisposition, RecordType};
-use crate::queue::config::QueueConfig;
+use crate::logging::webhook::make_log_webhook_queue;
+use crate::queue::config::{QueueConfig, RetrySchedule};
 use crate::queue::delivery_proto::DeliveryProto;
 use crate::queue::insert_context::{InsertContext, InsertReason};
 use crate::queue::maintainer::{maintain_named_queue, QMAINT_RUNTIME};
@@ -22,7 +23,7 @@ use anyhow::Context;
 use arc_swap::ArcSwap;
 use chrono::Utc;
 use config::epoch::{get_current_epoch, ConfigEpoch};
-use config::{declare_event, load_config, LuaConfig};
+use config::{declare_event, load_config, CallbackSignature, LuaConfig, SerdeWrappedValue};
 use humantime::format_duration;
 use kumo_api_types::egress_path::{ConfigRefreshStrategy, MemoryReductionPolicy};
 use kumo_api_types::xfer::XferProtocol;
@@ -48,6 +49,10 @@ use uuid::Uuid;
 
 pub type QueueHandle = Arc<Queue>;
 
+/// The message metadata key that records when a DelayWarning
+/// record was logged for a message
+const DELAY_WARNING_META_KEY: &str = "delay_warning";
+
 pub struct Queue {
     pub name: Arc<String>,
     pub queue: QueueStructure,
@@ -81,6 +86,10 @@ impl Queue {
             return Ok(xfer);
         }
 
+        if let Some(webhook) = make_log_webhook_queue(name) {
+            return Ok(webhook);
+        }
+
         if name == "null" {
             return Ok(QueueConfig {
                 protocol: DeliveryProto::Null,
@@ -429,6 +438,7 @@ impl Queue {
                         msg,
                         IncrementAttempts::No,
                         delay,
+                        None,
                         context.add(InsertReason::MessageGetQueueNameFailed),
                     )
                     .await
@@ -494,7 +504,7 @@ impl Queue {
         }
 
         if let Err(err) = queue
-            .requeue_message_internal(msg, IncrementAttempts::No, delay, context)
+            .requeue_message_internal(msg, IncrementAttempts::No, delay, None, context)
             .await
         {
             tracing::error!(
@@ -583,6 +593,7 @@ impl Queue {
                 msg,
                 IncrementAttempts::No,
                 Some(chrono::Duration::seconds(0)),
+                None,
                 context,
             )
             .await
@@ -618,6 +629,7 @@ impl Queue {
                 msg,
                 IncrementAttempts::No,
                 Some(chrono::Duration::seconds(0)),
+                None,
                 context,
             )
             .await
@@ -689,6 +701,7 @@ impl Queue {
                 msg,
                 IncrementAttempts::No,
                 Some(chrono::Duration::seconds(0)),
+                None,
                 context,
             )
             .await
@@ -749,47 +762,109 @@ impl Queue {
         &self,
         msg: &Message,
     ) -> anyhow::Result<Option<DateTime<Utc>>> {
-        // Pre-calculate the delay, prior to incrementing the number of attempts,
-        // as the delay_for_attempt uses a zero-based attempt number to figure
-        // the interval
-        let num_attempts = msg.get_num_attempts();
-        let delay = self.queue_config.borrow().delay_for_attempt(num_attempts);
+        let delay = self.compute_retry_delay(msg, None).await?;
         msg.increment_num_attempts();
+        msg.delay_by(delay).await
+    }
 
-        // Compute some jitter. The default retry_interval is 20 minutes for
-        // which 1 minute is desired. To accomodate different intervals we translate
-        // that to allowing up to 1/20th of the retry_interval as jitter, but we
-        // cap it to 1 minute so that it doesn't result in excessive divergence
-        // for very large intervals.
-        let jitter_magnitude =
-            (self.queue_config.borrow().retry_interval.as_secs_f32() / 20.0).min(60.0);
+    /// Compute the delay until the next delivery attempt, based on
+    /// the retry_schedule and the number of attempts made so far.
+    /// Must be called prior to incrementing the number of attempts,
+    /// as delay_for_attempt uses a zero-based attempt number to figure
+    /// the interval.
+    /// `response` is the response from the most recent attempt, if known,
+    /// and is passed to the retry_schedule event when one is configured.
+    async fn compute_retry_delay(
+        &self,
+        msg: &Message,
+        response: Option<&Response>,
+    ) -> anyhow::Result<chrono::Duration> {
+        let num_attempts = msg.get_num_attempts();
+        let schedule = self.queue_config.borrow().retry_schedule.clone();
+
+        let event_delay = match (&schedule, response) {
+            (RetrySchedule::Event(name), Some(response)) => {
+                self.call_retry_schedule_event(name, msg, response, num_attempts)
+                    .await
+            }
+            _ => None,
+        };
+
+        let config = self.queue_config.borrow();
+        let from_event = event_delay.is_some();
+        let delay = match event_delay {
+            Some(delay) => config.clamp_retry_delay(delay.as_secs()),
+            None => config.delay_for_attempt(num_attempts),
+        };
+
+        let jitter_magnitude = config.jitter_magnitude(delay, from_event);
         let jitter = (rand::random::<f32>() * jitter_magnitude) - (jitter_magnitude / 2.0);
-        let delay = kumo_chrono_helper::seconds(delay.num_seconds() + jitter as i64)?;
-        msg.delay_by(delay).await
+        kumo_chrono_helper::seconds(delay.num_seconds() + jitter as i64)
+    }
+
+    /// Call the event named by a `RetrySchedule::Event` schedule.
+    /// Returns None if the event returned nil, or if it failed,
+    /// in which case the caller should fall back to the default
+    /// schedule.
+    /// The site and provider of the attempt are not available at
+    /// the point where the retry is scheduled, so the response is
+    /// classified with an empty site and no provider; classifier
+    /// rules that are restricted to a site or provider will not
+    /// match the bounce_class that is passed to the event.
+    async fn call_retry_schedule_event(
+        &self,
+        name: &str,
+        msg: &Message,
+        response: &Response,
+        num_attempts: u16,
+    ) -> Option<Duration> {
+        let bounce_class = crate::logging::classify::classify_response(response, "", None).await;
+        let sig = CallbackSignature::<
+            (Message, String, String, u16),
+            Option<SerdeWrappedValue<duration_serde::Wrap<Duration>>>,
+        >::new(name.to_string());
+
+        let result = async {
+            let mut config = load_config().await?;
+            let delay = config
+                .async_call_callback(
+                    &sig,
+                    (
+                        msg.clone(),
+                        response.to_single_line(),
+                        String::from(bounce_class),
+                        num_attempts,
+                    ),
+                )
+                .await?;
+            config.put();
+            anyhow::Result::<_>::Ok(delay)
+        }
+        .await;
+
+        match result {
+            Ok(delay) => delay.map(|d| d.0.into_inner()),
+            Err(err) => {
+                tracing::error!(
+                    "{}: error while calling retry_schedule event {name}: {err:#}. \
+                     Using the Exponential schedule instead",
+                    self.name
+                );
+                None
+            }
+        }
     }
 
     async fn increment_attempts_and_update_delay(
         &self,
         msg: Message,
+        response: Option<&Response>,
     ) -> anyhow::Result<Option<Message>> {
         let id = *msg.id();
-        // Pre-calculate the delay, prior to incrementing the number of attempts,
-        // as the delay_for_attempt uses a zero-based attempt number to figure
-        // the interval
         let num_attempts = msg.get_num_attempts();
-        let delay = self.queue_config.borrow().delay_for_attempt(num_attempts);
+        let delay = self.compute_retry_delay(&msg, response).await?;
         msg.increment_num_attempts();
 
-        // Compute some jitter. The default retry_interval is 20 minutes for
-        // which 1 minute is desired. To accomodate different intervals we translate
-        // that to allowing up to 1/20th of the retry_interval as jitter, but we
-        // cap it to 1 minute so that it doesn't result in excessive divergence
-        // for very large intervals.
-        let jitter_magnitude =
-            (self.queue_config.borrow().retry_interval.as_secs_f32() / 20.0).min(60.0);
-        let jitter = (rand::random::<f32>() * jitter_magnitude) - (jitter_magnitude / 2.0);
-        let delay = kumo_chrono_helper::seconds(delay.num_seconds() + jitter as i64)?;
-
         match msg.get_scheduling().await?.and_then(|sched| sched.expires) {
             Some(expires) => {
                 // Per-message expiry
@@ -902,6 +977,72 @@ impl Queue {
         Ok(Some(msg))
     }
 
+    /// If the queue has a delay_warning threshold configured and
+    /// the message has been queued for longer than that threshold,
+    /// log a DelayWarning record for it and note that in its
+    /// metadata, so that the warning is logged at most once
+    /// for each message.
+    async fn log_delay_warning_if_needed(
+        &self,
+        msg: &Message,
+        response: Option<&Response>,
+    ) -> anyhow::Result<()> {
+        let Some(threshold) = self.queue_config.borrow().delay_warning else {
+            return Ok(());
+        };
+
+        let age = msg.age(Utc::now());
+        if age < chrono::Duration::from_std(threshold)? {
+            return Ok(());
+        }
+
+        if !msg.get_meta(DELAY_WARNING_META_KEY).await?.is_null() {
+            return Ok(());
+        }
+
+        msg.set_meta(DELAY_WARNING_META_KEY, Utc::now().to_rfc3339())
+            .await?;
+        msg.save(None).await?;
+
+        let response = match response {
+            Some(response) => response.clone(),
+            None => Response {
+                code: 451,
+                enhanced_code: Some(EnhancedStatusCode {
+                    class: 4,
+                    subject: 4,
+                    detail: 7,
+                }),
+                content: format!(
+                    "Message has been queued for {}, which exceeds delay_warning={}",
+                    format_duration(age.to_std().unwrap_or(Duration::ZERO)),
+                    format_duration(threshold)
+                ),
+                command: None,
+            },
+        };
+
+        log_disposition(LogDisposition {
+            kind: RecordType::DelayWarning,
+            msg: msg.clone(),
+            site: "",
+            peer_address: None,
+            response,
+            egress_pool: self.queue_config.borrow().egress_pool.as_deref(),
+            egress_source: None,
+            relay_disposition: None,
+            delivery_protocol: None,
+            tls_info: None,
+            source_address: None,
+            provider: self.queue_config.borrow().provider_name.as_deref(),
+            session_id: None,
+            recipient_list: None,
+        })
+        .await;
+
+        Ok(())
+    }
+
     /// Performs the raw re-insertion of a message into a scheduled queue.
     /// The requeue_message event is NOT called by this function.
     #[instrument(skip(self, msg))]
@@ -910,11 +1051,16 @@ impl Queue {
         msg: Message,
         increment_attempts: IncrementAttempts,
         delay: Option<chrono::Duration>,
+        response: Option<&Response>,
         context: InsertContext,
     ) -> anyhow::Result<()> {
         if increment_attempts == IncrementAttempts::Yes {
-            match self.increment_attempts_and_update_delay(msg).await? {
+            match self
+                .increment_attempts_and_update_delay(msg, response)
+                .await?
+            {
                 Some(msg) => {
+                    self.log_delay_warning_if_needed(&msg, response).await?;
                     return self.insert(msg, context, None).await;
                 }
                 None => {
@@ -966,6 +1112,7 @@ impl Queue {
             }
         }
 
+        self.log_delay_warning_if_needed(&msg, response).await?;
         self.insert(msg, context, None).await?;
 
         Ok(())
@@ -990,6 +1137,10 @@ impl Queue {
 
                     match self.timeq_insert(msg.clone()) {
                         Ok(_) => {
+                            crate::message_trace::enter_stage(
+                                msg.id(),
+                                crate::message_trace::Stage::ScheduledQueue,
+                            );
                             if let Err(err) = self.did_insert_delayed(msg.clone(), context).await {
                                 tracing::error!("while shrinking: {}: {err:#}", msg.id());
                             }
@@ -1359,6 +1510,7 @@ impl Queue {
             DeliveryProto::Smtp { .. }
             | DeliveryProto::Lua { .. }
             | DeliveryProto::Xfer { .. }
+            | DeliveryProto::LogWebhook { .. }
             | DeliveryProto::HttpInjectionGenerator => {
                 let source_selector = self.source_selector.load();
                 match source_selector
@@ -1725,6 +1877,15 @@ impl Queue {
         *self.last_change.lock() = Instant::now();
 
         tracing::trace!("insert msg {}", msg.id());
+        // Quarantined messages are not charged against the quotas
+        // while they are held; they are charged when they are
+        // released and inserted again
+        if crate::quarantine::hold_if_quarantined(&msg, &self.name, &context).await? {
+            return Ok(());
+        }
+
+        crate::quota::charge(&msg, &self.name).await?;
+
         if let Some(b) = AdminBounceEntry::get_for_queue_name(&self.name) {
             let id = *msg.id();
             b.log(msg, Some(&self.name)).await;
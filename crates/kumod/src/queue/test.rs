@@ -1,4 +1,5 @@
 #![cfg(test)]
+use super::config::RetrySchedule;
 use super::*;
 
 /// Returns the list of delays up until the max_age would be reached
@@ -61,6 +62,113 @@ fn calc_due_defaults() {
     );
 }
 
+#[test]
+fn calc_due_linear() {
+    let config = QueueConfig {
+        retry_interval: Duration::from_secs(2),
+        retry_schedule: RetrySchedule::Linear,
+        max_age: Duration::from_secs(64),
+        ..Default::default()
+    };
+
+    assert_eq!(compute_schedule(&config), vec![2, 4, 6, 8, 10, 12, 14]);
+}
+
+#[test]
+fn calc_due_fibonacci() {
+    let config = QueueConfig {
+        retry_interval: Duration::from_secs(2),
+        retry_schedule: RetrySchedule::Fibonacci,
+        max_age: Duration::from_secs(128),
+        ..Default::default()
+    };
+
+    assert_eq!(compute_schedule(&config), vec![2, 4, 6, 10, 16, 26, 42]);
+}
+
+#[test]
+fn calc_due_steps() {
+    let config = QueueConfig {
+        retry_schedule: RetrySchedule::Steps(vec![
+            Duration::from_secs(300),
+            Duration::from_secs(600),
+            Duration::from_secs(1800),
+            Duration::from_secs(3600),
+        ]),
+        max_retry_interval: Some(Duration::from_secs(2400)),
+        max_age: Duration::from_secs(4 * 3600),
+        ..Default::default()
+    };
+
+    assert_eq!(
+        compute_schedule(&config),
+        vec![300, 600, 1800, 2400, 2400, 2400, 2400]
+    );
+}
+
+#[test]
+fn jitter_magnitude() {
+    let one_minute = chrono::Duration::try_seconds(60).unwrap();
+
+    // Multiples of retry_interval use the retry_interval
+    let config = QueueConfig {
+        retry_interval: Duration::from_secs(600),
+        ..Default::default()
+    };
+    assert_eq!(config.jitter_magnitude(one_minute, false), 30.0);
+
+    // Explicit steps use the step itself, even with a large retry_interval
+    let config = QueueConfig {
+        retry_interval: Duration::from_secs(3600),
+        retry_schedule: RetrySchedule::Steps(vec![
+            Duration::from_secs(60),
+            Duration::from_secs(300),
+        ]),
+        ..Default::default()
+    };
+    assert_eq!(config.jitter_magnitude(one_minute, false), 3.0);
+
+    // Event provided delays use the delay, but falling back to
+    // Exponential uses the retry_interval
+    let config = QueueConfig {
+        retry_interval: Duration::from_secs(600),
+        retry_schedule: RetrySchedule::Event("my_retry".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(config.jitter_magnitude(one_minute, true), 3.0);
+    assert_eq!(config.jitter_magnitude(one_minute, false), 30.0);
+
+    // Capped at one minute
+    let config = QueueConfig {
+        retry_interval: Duration::from_secs(86400),
+        ..Default::default()
+    };
+    assert_eq!(config.jitter_magnitude(one_minute, false), 60.0);
+}
+
+#[test]
+fn retry_schedule_serde() {
+    let config: QueueConfig =
+        serde_json::from_str(r#"{"retry_schedule": {"Steps": ["5m", "10m", "1h"]}}"#).unwrap();
+    assert_eq!(
+        config.retry_schedule,
+        RetrySchedule::Steps(vec![
+            Duration::from_secs(300),
+            Duration::from_secs(600),
+            Duration::from_secs(3600),
+        ])
+    );
+
+    let config: QueueConfig =
+        serde_json::from_str(r#"{"retry_schedule": {"Event": "my_retry"}}"#).unwrap();
+    assert_eq!(
+        config.retry_schedule,
+        RetrySchedule::Event("my_retry".to_string())
+    );
+    // Without a response to pass to the event, it behaves like Exponential
+    assert_eq!(config.delay_for_attempt(2).num_seconds(), 1200 * 4);
+}
+
 #[test]
 fn spool_in_delay() {
     let config = QueueConfig {
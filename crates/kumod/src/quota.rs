@@ -0,0 +1,500 @@
+//! Admission control for the number and size of messages that a
+//! tenant, campaign or scheduled queue may have in the spool.
+//!
+//! Usage is charged when a message is inserted into a scheduled
+//! queue (including when it is loaded from the spool at startup),
+//! and released when the message is removed from the spool.
+//! Messages that are held in quarantine are not charged until
+//! they are released back into their scheduled queue.
+//! Limits are checked at reception time, prior to accepting
+//! responsibility for a message, and the usage of each accepted
+//! message is reserved until it has been charged to its queue.
+//!
+//! Tracking is only active once quota rules have been configured
+//! via `kumo.set_message_quotas`, so there is no overhead for
+//! deployments that do not use quotas.
+use crate::queue::metrics::{BorrowedTenantKey, TenantKey, TenantKeyTrait};
+use arc_swap::ArcSwap;
+use dashmap::DashMap;
+use kumo_prometheus::{declare_metric, AtomicCounter};
+use message::queue_name::QueueNameComponents;
+use message::Message;
+use serde::Deserialize;
+use spool::SpoolId;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use thiserror::Error;
+
+/// The tenant name that can be used in a rule to apply
+/// a limit individually to every tenant that doesn't have
+/// its own explicit rule
+const ANY_TENANT: &str = "*";
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static RULES: LazyLock<ArcSwap<QuotaRules>> = LazyLock::new(Default::default);
+static USAGE: LazyLock<DashMap<QuotaScope, Usage>> = LazyLock::new(DashMap::new);
+static QUEUE_SCOPES: LazyLock<DashMap<String, Arc<QueueScopes>>> = LazyLock::new(DashMap::new);
+static CHARGES: LazyLock<DashMap<SpoolId, Charge>> = LazyLock::new(DashMap::new);
+
+declare_metric! {
+/// number of spooled messages charged against the quota of a tenant.
+///
+/// This is only tracked when quotas have been configured via
+/// [kumo.set_message_quotas](../../kumo/set_message_quotas.md).
+static TENANT_MESSAGES_GAUGE: PruningGaugeRegistry<TenantKey>("quota_usage_messages_by_tenant");
+}
+
+declare_metric! {
+/// total size in bytes of the spooled messages charged against the quota of a tenant.
+///
+/// This is only tracked when quotas have been configured via
+/// [kumo.set_message_quotas](../../kumo/set_message_quotas.md).
+static TENANT_BYTES_GAUGE: PruningGaugeRegistry<TenantKey>("quota_usage_bytes_by_tenant");
+}
+
+/// Defines a limit on the number and/or total size of messages
+/// that may be spooled for a given tenant, campaign or queue
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct QuotaRule {
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(default)]
+    pub campaign: Option<String>,
+    #[serde(default)]
+    pub queue: Option<String>,
+    #[serde(default)]
+    pub max_messages: Option<usize>,
+    #[serde(default)]
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Limits {
+    max_messages: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+enum QuotaScope {
+    Tenant(String),
+    Campaign {
+        tenant: Option<String>,
+        campaign: String,
+    },
+    Queue(String),
+}
+
+impl std::fmt::Display for QuotaScope {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Tenant(tenant) => write!(fmt, "tenant '{tenant}'"),
+            Self::Campaign {
+                tenant: Some(tenant),
+                campaign,
+            } => write!(fmt, "campaign '{campaign}' of tenant '{tenant}'"),
+            Self::Campaign {
+                tenant: None,
+                campaign,
+            } => write!(fmt, "campaign '{campaign}'"),
+            Self::Queue(queue) => write!(fmt, "queue '{queue}'"),
+        }
+    }
+}
+
+impl QuotaRule {
+    fn scope(&self) -> anyhow::Result<QuotaScope> {
+        match (&self.tenant, &self.campaign, &self.queue) {
+            (None, None, Some(queue)) => Ok(QuotaScope::Queue(queue.to_string())),
+            (tenant, Some(campaign), None) => {
+                if tenant.as_deref() == Some(ANY_TENANT) {
+                    anyhow::bail!("tenant='{ANY_TENANT}' cannot be combined with campaign");
+                }
+                Ok(QuotaScope::Campaign {
+                    tenant: tenant.clone(),
+                    campaign: campaign.to_string(),
+                })
+            }
+            (Some(tenant), None, None) => Ok(QuotaScope::Tenant(tenant.to_string())),
+            (None, None, None) => {
+                anyhow::bail!("quota rule must specify one of tenant, campaign or queue")
+            }
+            _ => anyhow::bail!("queue cannot be combined with tenant or campaign"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct QuotaRules {
+    limits: HashMap<QuotaScope, Limits>,
+    any_tenant: Option<Limits>,
+    any_byte_limit: bool,
+}
+
+impl QuotaRules {
+    fn new(rules: Vec<QuotaRule>) -> anyhow::Result<Self> {
+        let mut result = Self::default();
+        for rule in rules {
+            let scope = rule.scope()?;
+            if rule.max_messages.is_none() && rule.max_bytes.is_none() {
+                anyhow::bail!("quota rule for {scope} must specify max_messages and/or max_bytes");
+            }
+            let limits = Limits {
+                max_messages: rule.max_messages,
+                max_bytes: rule.max_bytes,
+            };
+            result.any_byte_limit |= limits.max_bytes.is_some();
+            match &scope {
+                QuotaScope::Tenant(tenant) if tenant == ANY_TENANT => {
+                    result.any_tenant.replace(limits);
+                }
+                _ => {
+                    result.limits.insert(scope, limits);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn limits_for(&self, scope: &QuotaScope) -> Option<Limits> {
+        match self.limits.get(scope) {
+            Some(limits) => Some(*limits),
+            None if matches!(scope, QuotaScope::Tenant(_)) => self.any_tenant,
+            None => None,
+        }
+    }
+
+    /// Returns an error if admitting a message of the specified size
+    /// would take the usage of the scope beyond its limits
+    fn check(
+        &self,
+        scope: &QuotaScope,
+        messages: usize,
+        bytes: usize,
+        size: usize,
+    ) -> Result<(), QuotaExceeded> {
+        let Some(limits) = self.limits_for(scope) else {
+            return Ok(());
+        };
+        if let Some(max_messages) = limits.max_messages {
+            if messages >= max_messages {
+                return Err(QuotaExceeded(format!(
+                    "{scope} has reached its quota of {max_messages} queued messages"
+                )));
+            }
+        }
+        if let Some(max_bytes) = limits.max_bytes {
+            if bytes.saturating_add(size) > max_bytes {
+                return Err(QuotaExceeded(format!(
+                    "{scope} has reached its quota of {max_bytes} queued bytes"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug, Clone)]
+#[error("{0}")]
+pub struct QuotaExceeded(String);
+
+#[derive(Clone)]
+struct Usage {
+    messages: AtomicCounter,
+    bytes: AtomicCounter,
+}
+
+impl Usage {
+    fn for_scope(scope: &QuotaScope) -> Self {
+        USAGE
+            .entry(scope.clone())
+            .or_insert_with(|| match scope {
+                QuotaScope::Tenant(tenant) => {
+                    let key = BorrowedTenantKey { tenant };
+                    Usage {
+                        messages: TENANT_MESSAGES_GAUGE.get_or_create(&key as &dyn TenantKeyTrait),
+                        bytes: TENANT_BYTES_GAUGE.get_or_create(&key as &dyn TenantKeyTrait),
+                    }
+                }
+                _ => Usage {
+                    messages: AtomicCounter::new(),
+                    bytes: AtomicCounter::new(),
+                },
+            })
+            .clone()
+    }
+}
+
+/// The set of scopes that apply to a given scheduled queue
+struct QueueScopes {
+    scopes: Vec<(QuotaScope, Usage)>,
+}
+
+impl QueueScopes {
+    fn get(queue_name: &str) -> Arc<Self> {
+        if let Some(entry) = QUEUE_SCOPES.get(queue_name) {
+            return entry.clone();
+        }
+
+        let components = QueueNameComponents::parse(queue_name);
+        let mut scopes = vec![QuotaScope::Queue(queue_name.to_string())];
+        if let Some(tenant) = components.tenant {
+            scopes.push(QuotaScope::Tenant(tenant.to_string()));
+        }
+        if let Some(campaign) = components.campaign {
+            scopes.push(QuotaScope::Campaign {
+                tenant: components.tenant.map(|t| t.to_string()),
+                campaign: campaign.to_string(),
+            });
+        }
+
+        let entry = Arc::new(Self {
+            scopes: scopes
+                .into_iter()
+                .map(|scope| {
+                    let usage = Usage::for_scope(&scope);
+                    (scope, usage)
+                })
+                .collect(),
+        });
+        QUEUE_SCOPES
+            .entry(queue_name.to_string())
+            .or_insert(entry)
+            .clone()
+    }
+
+    fn add(&self, bytes: usize) {
+        for (_scope, usage) in &self.scopes {
+            usage.messages.inc();
+            usage.bytes.inc_by(bytes);
+        }
+    }
+
+    fn sub(&self, bytes: usize) {
+        for (_scope, usage) in &self.scopes {
+            usage.messages.dec();
+            usage.bytes.sub(bytes);
+        }
+    }
+}
+
+struct Charge {
+    queue: Arc<QueueScopes>,
+    bytes: usize,
+}
+
+/// Replace the set of quota rules
+pub fn set_message_quotas(rules: Vec<QuotaRule>) -> anyhow::Result<()> {
+    let rules = QuotaRules::new(rules)?;
+    RULES.store(Arc::new(rules));
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Usage that has been provisionally set aside for a message that
+/// is in the process of being received, so that the other messages
+/// in the same batch, and those being received concurrently, are
+/// checked against it.  The reservation is released when it is
+/// dropped; by that time the message will either have been charged
+/// to its queue, or it was not accepted.
+#[must_use]
+pub struct QuotaReservation {
+    queue: Option<(Arc<QueueScopes>, usize)>,
+}
+
+impl Drop for QuotaReservation {
+    fn drop(&mut self) {
+        if let Some((queue, bytes)) = self.queue.take() {
+            queue.sub(bytes);
+        }
+    }
+}
+
+/// Check whether a message of the specified size may be admitted
+/// into the named scheduled queue, and if so, reserve space for it.
+pub fn reserve(queue_name: &str, size: usize) -> Result<QuotaReservation, QuotaExceeded> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(QuotaReservation { queue: None });
+    }
+    let rules = RULES.load();
+    let queue = QueueScopes::get(queue_name);
+    for (scope, usage) in &queue.scopes {
+        rules.check(scope, usage.messages.get(), usage.bytes.get(), size)?;
+    }
+    queue.add(size);
+    Ok(QuotaReservation {
+        queue: Some((queue, size)),
+    })
+}
+
+/// Charge msg against the quotas that apply to the named queue.
+/// If the message is already charged to that queue, this is a no-op.
+/// If it was charged to a different queue, that charge is released
+/// in favor of the new queue.
+pub async fn charge(msg: &Message, queue_name: &str) -> anyhow::Result<()> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+
+    let queue = QueueScopes::get(queue_name);
+    let id = *msg.id();
+
+    let prior_bytes = match CHARGES.get(&id) {
+        Some(charge) if Arc::ptr_eq(&charge.queue, &queue) => return Ok(()),
+        Some(charge) => Some(charge.bytes),
+        None => None,
+    };
+
+    let bytes = match prior_bytes {
+        Some(bytes) => bytes,
+        None if msg.is_data_loaded() => msg.get_data_maybe_not_loaded().len(),
+        None if RULES.load().any_byte_limit => {
+            // Loaded from spool; we need to read the data to
+            // learn its size, but we don't want to keep it
+            // in memory
+            let len = msg.data().await?.len();
+            msg.shrink_data()?;
+            len
+        }
+        None => 0,
+    };
+
+    queue.add(bytes);
+    if let Some(prior) = CHARGES.insert(id, Charge { queue, bytes }) {
+        prior.queue.sub(prior.bytes);
+    }
+    Ok(())
+}
+
+/// Release any charge associated with id.
+/// Called when the message is removed from the spool.
+pub fn release(id: SpoolId) {
+    if let Some((_id, charge)) = CHARGES.remove(&id) {
+        charge.queue.sub(charge.bytes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rfc5321::parser::EnvelopeAddress;
+
+    fn rule(
+        tenant: Option<&str>,
+        campaign: Option<&str>,
+        queue: Option<&str>,
+        max_messages: Option<usize>,
+        max_bytes: Option<usize>,
+    ) -> QuotaRule {
+        QuotaRule {
+            tenant: tenant.map(|s| s.to_string()),
+            campaign: campaign.map(|s| s.to_string()),
+            queue: queue.map(|s| s.to_string()),
+            max_messages,
+            max_bytes,
+        }
+    }
+
+    /// The rules are global, so the tests that depend on them
+    /// share the same set of rules
+    fn set_test_quotas() {
+        set_message_quotas(vec![
+            rule(None, None, Some("reserve.example.com"), Some(2), None),
+            rule(None, None, Some("held.example.com"), Some(1), None),
+        ])
+        .unwrap();
+    }
+
+    #[test]
+    fn rule_validation() {
+        assert!(QuotaRules::new(vec![rule(None, None, None, Some(1), None)]).is_err());
+        assert!(QuotaRules::new(vec![rule(Some("t"), None, None, None, None)]).is_err());
+        assert!(QuotaRules::new(vec![rule(Some("t"), None, Some("q"), Some(1), None)]).is_err());
+        assert!(QuotaRules::new(vec![rule(Some("*"), Some("c"), None, Some(1), None)]).is_err());
+        assert!(QuotaRules::new(vec![rule(Some("t"), Some("c"), None, Some(1), None)]).is_ok());
+    }
+
+    #[test]
+    fn check_limits() {
+        let rules = QuotaRules::new(vec![
+            rule(Some("noisy"), None, None, Some(10), Some(1000)),
+            rule(Some("*"), None, None, Some(100), None),
+            rule(None, None, Some("example.com"), None, Some(50)),
+        ])
+        .unwrap();
+
+        let noisy = QuotaScope::Tenant("noisy".to_string());
+        assert!(rules.check(&noisy, 9, 0, 1000).is_ok());
+        k9::assert_equal!(
+            rules.check(&noisy, 10, 0, 1).unwrap_err().to_string(),
+            "tenant 'noisy' has reached its quota of 10 queued messages"
+        );
+        k9::assert_equal!(
+            rules.check(&noisy, 0, 900, 101).unwrap_err().to_string(),
+            "tenant 'noisy' has reached its quota of 1000 queued bytes"
+        );
+
+        let other = QuotaScope::Tenant("other".to_string());
+        assert!(rules.check(&other, 99, 1_000_000, 1).is_ok());
+        assert!(rules.check(&other, 100, 0, 1).is_err());
+
+        let queue = QuotaScope::Queue("example.com".to_string());
+        assert!(rules.check(&queue, 1_000_000, 10, 40).is_ok());
+        assert!(rules.check(&queue, 0, 10, 41).is_err());
+
+        let unlimited = QuotaScope::Queue("other.example.com".to_string());
+        assert!(rules.check(&unlimited, 1_000_000, 1_000_000, 1).is_ok());
+    }
+
+    #[test]
+    fn reservations() {
+        set_test_quotas();
+
+        // Each message in a batch is checked against the
+        // reservations made for the earlier messages
+        let first = reserve("reserve.example.com", 10).unwrap();
+        let second = reserve("reserve.example.com", 10).unwrap();
+        k9::assert_equal!(
+            reserve("reserve.example.com", 10)
+                .err()
+                .unwrap()
+                .to_string(),
+            "queue 'reserve.example.com' has reached its quota of 2 queued messages"
+        );
+
+        // Releasing a reservation makes room for another
+        drop(first);
+        let _third = reserve("reserve.example.com", 10).unwrap();
+        drop(second);
+    }
+
+    #[tokio::test]
+    async fn quarantine_releases_charge() {
+        set_test_quotas();
+
+        let msg = Message::new_dirty(
+            SpoolId::new(),
+            EnvelopeAddress::parse("sender@example.com").unwrap(),
+            vec![EnvelopeAddress::parse("recip@held.example.com").unwrap()],
+            serde_json::json!({}),
+            Arc::new(b"Subject: hello\r\n\r\nbody".to_vec().into_boxed_slice()),
+        )
+        .unwrap();
+
+        // Charging the same message twice only counts it once
+        charge(&msg, "held.example.com").await.unwrap();
+        charge(&msg, "held.example.com").await.unwrap();
+        assert!(reserve("held.example.com", 10).is_err());
+
+        // Holding the message in quarantine releases its charge,
+        // so that it doesn't prevent the queue from accepting mail
+        release(*msg.id());
+        drop(reserve("held.example.com", 10).unwrap());
+
+        // Releasing it from quarantine charges it again
+        charge(&msg, "held.example.com").await.unwrap();
+        assert!(reserve("held.example.com", 10).is_err());
+        release(*msg.id());
+    }
+}
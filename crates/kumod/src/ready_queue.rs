@@ -1,4 +1,6 @@
-use crate::delivery_metrics::{DeliveryMetrics, DispatcherDispositionCounters, ReadyCountBundle};
+use crate::delivery_metrics::{
+    DeliveryMetrics, DispatcherDispositionCounters, ReadyCountBundle, WarmupGauges,
+};
 use crate::egress_source::{
     err_match_anyhow, BindError, ConnectError, EgressSource, ProxyBindError,
 };
@@ -10,6 +12,7 @@ use crate::http_server::admin_suspend_v1::AdminSuspendEntry;
 use crate::http_server::inject_v1::HttpInjectionGeneratorDispatcher;
 use crate::http_server::queue_name_multi_index::CachedEntry;
 use crate::logging::disposition::{log_disposition, LogDisposition, RecordType};
+use crate::logging::webhook::LogWebhookDispatcher;
 use crate::lua_deliver::LuaQueueDispatcher;
 use crate::metrics_helper::TOTAL_READYQ_RUNS;
 use crate::queue::{
@@ -21,7 +24,7 @@ use crate::smtp_server::DeferredSmtpInjectionDispatcher;
 use crate::spool::SpoolManager;
 use crate::xfer::XferDispatcher;
 use anyhow::Context;
-use arc_swap::ArcSwap;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use async_trait::async_trait;
 use config::epoch::ConfigEpoch;
 use config::{declare_event, load_config};
@@ -30,6 +33,7 @@ use dns_resolver::MailExchanger;
 use kumo_api_types::egress_path::{
     ConfigRefreshStrategy, EgressPathConfig, MemoryReductionPolicy, WakeupStrategy,
 };
+use kumo_api_types::warmup::{WarmupSchedule, WarmupStage};
 use kumo_prometheus::declare_metric;
 use kumo_server_common::config_handle::ConfigHandle;
 use kumo_server_common::hashable_weak::HashableWeak;
@@ -477,6 +481,7 @@ impl ReadyQueueManager {
                 config_epoch: FairMutex::new(config_epoch),
                 states: Arc::new(FairMutex::new(ReadyQueueStates::default())),
                 scheduled_queues: FairMutex::new(HashSet::new()),
+                warmup_gauges: ArcSwapOption::empty(),
             })
         });
         Ok(handle.clone())
@@ -871,6 +876,8 @@ pub struct ReadyQueue {
     /// diagnostic tooling. Pruned by the maintainer when entries
     /// no longer upgrade.
     scheduled_queues: FairMutex<HashSet<HashableWeak<Queue>>>,
+    /// Created on demand when the path has a warm-up schedule
+    warmup_gauges: ArcSwapOption<WarmupGauges>,
 }
 
 impl ReadyQueue {
@@ -1068,6 +1075,7 @@ impl ReadyQueue {
                 longest.saturating_mul(2).max(floor)
             }
             DeliveryProto::Lua { .. }
+            | DeliveryProto::LogWebhook { .. }
             | DeliveryProto::HttpInjectionGenerator
             | DeliveryProto::DeferredSmtpInjection => Duration::from_secs(600),
             DeliveryProto::Maildir { .. } | DeliveryProto::Null => floor,
@@ -1128,6 +1136,32 @@ impl ReadyQueue {
         &self.path_config
     }
 
+    /// Reflect the current warm-up stage into the warm-up metrics
+    /// for this queue.  Passing None removes the metrics, which
+    /// is appropriate when the path no longer has a warm-up schedule.
+    pub fn update_warmup_gauges(&self, warmup: Option<(&WarmupSchedule, &WarmupStage)>) {
+        match warmup {
+            Some((schedule, stage)) => {
+                let gauges = match self.warmup_gauges.load_full() {
+                    Some(gauges) => gauges,
+                    None => {
+                        let service =
+                            format!("{}:{}", self.protocol.metrics_protocol_name(), self.name);
+                        let gauges = Arc::new(WarmupGauges::new(&service));
+                        self.warmup_gauges.store(Some(gauges.clone()));
+                        gauges
+                    }
+                };
+                gauges.update(schedule, stage);
+            }
+            None => {
+                if self.warmup_gauges.load().is_some() {
+                    self.warmup_gauges.store(None);
+                }
+            }
+        }
+    }
+
     pub async fn redeem_reservation(
         &self,
         msg: Message,
@@ -1169,8 +1203,10 @@ impl ReadyQueue {
                 msg.save_and_shrink_data().await.ok();
             }
         }
+        let id = *msg.id();
         match self.ready.push(msg) {
             Ok(()) => {
+                crate::message_trace::enter_stage(&id, crate::message_trace::Stage::ReadyQueue);
                 self.wakeup_dispatcher_or_maintainer();
                 Ok(())
             }
@@ -1902,6 +1938,7 @@ impl Dispatcher {
             DeliveryProto::Maildir { .. } => "Maildir".to_string(),
             DeliveryProto::DeferredSmtpInjection => "DeferredSmtpInjection".to_string(),
             DeliveryProto::HttpInjectionGenerator => "HttpInjectionGenerator".to_string(),
+            DeliveryProto::LogWebhook { .. } => "LogWebhook".to_string(),
             DeliveryProto::Null => {
                 anyhow::bail!("Should not have a ready_queue for the null queue")
             }
@@ -1958,6 +1995,9 @@ impl Dispatcher {
             DeliveryProto::Xfer { xfer } => {
                 Box::new(XferDispatcher::init(&mut dispatcher, xfer).await?)
             }
+            DeliveryProto::LogWebhook { log_webhook } => {
+                Box::new(LogWebhookDispatcher::new(log_webhook))
+            }
             DeliveryProto::Null => {
                 anyhow::bail!("Should not have a ready_queue for the null queue")
             }
@@ -2328,6 +2368,9 @@ impl Dispatcher {
         for msg in &self.msgs {
             msg.load_meta_if_needed().await?;
             msg.data().await?;
+            if msg.get_num_attempts() == 0 {
+                self.metrics.observe_first_attempt_age(msg.id());
+            }
         }
 
         let activity = Activity::get(format!(
@@ -2607,6 +2650,10 @@ impl Dispatcher {
                         continue;
                     }
                 }
+                crate::message_trace::enter_stage(
+                    msg.id(),
+                    crate::message_trace::Stage::DeliveryAttempt,
+                );
                 self.msgs.push(msg);
             } else {
                 break;
@@ -3,12 +3,14 @@ use crate::http_server::admin_trace_smtp_client_v1::{
     SmtpClientTraceEventPayload, SmtpClientTracerImpl,
 };
 use crate::logging::disposition::{log_disposition, LogDisposition, RecordType};
+use crate::logging::session::{log_smtp_client_session, LogSmtpClientSession};
 use crate::queue::{IncrementAttempts, InsertReason, QueueManager, QueueState};
 use crate::ready_queue::{AttemptConnectionDisposition, Dispatcher, QueueDispatcher};
 use crate::spool::SpoolManager;
 use anyhow::Context;
 use async_trait::async_trait;
 use bounce_classify::{BounceClass, PreDefinedBounceClass};
+use chrono::{DateTime, Utc};
 use config::{load_config, CallbackSignature};
 use data_loader::KeySource;
 use dns_resolver::{
@@ -17,7 +19,10 @@ use dns_resolver::{
 };
 use kumo_address::socket::SocketAddress;
 use kumo_api_types::egress_path::{EgressPathConfig, ReconnectStrategy, Tls};
-use kumo_log_types::{MaybeProxiedSourceAddress, ResolvedAddress};
+use kumo_log_types::{
+    MaybeProxiedSourceAddress, ResolvedAddress, SmtpClientSessionCloseReason,
+    SmtpClientSessionSummary,
+};
 use kumo_prometheus::declare_metric;
 use kumo_server_lifecycle::{ShutdownSubcription, ShuttingDownError};
 use kumo_server_runtime::spawn;
@@ -195,6 +200,11 @@ pub struct SmtpDispatcher {
     attempted_message_send: bool,
     treat_mx_list_as_secure: bool,
     recips_last_txn: HashMap<(SpoolId, ForwardPath), u8>,
+    /// When the current connection was established; used
+    /// together with session_summary to produce the
+    /// SmtpClientSession log record
+    connected_at: Option<DateTime<Utc>>,
+    session_summary: SmtpClientSessionSummary,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -358,6 +368,8 @@ impl SmtpDispatcher {
             attempted_message_send: false,
             treat_mx_list_as_secure: proto_config.treat_mx_list_as_secure,
             recips_last_txn: HashMap::new(),
+            connected_at: None,
+            session_summary: SmtpClientSessionSummary::default(),
         }))
     }
 
@@ -402,7 +414,12 @@ impl SmtpDispatcher {
                             dispatcher.name
                         );
                         // Decide whether this is a successful plan
-                        self.update_state_for_reconnect(dispatcher);
+                        self.update_state_for_reconnect(
+                            dispatcher,
+                            SmtpClientSessionCloseReason::PeerClosed,
+                            Some(response),
+                        )
+                        .await;
 
                         // if so, we can/should close the current session and start
                         // a new one with a fresh plan
@@ -435,7 +452,12 @@ impl SmtpDispatcher {
                             while checking for liveness, treating it as closed",
                             dispatcher.name
                         );
-                        self.update_state_for_reconnect(dispatcher);
+                        self.update_state_for_reconnect(
+                            dispatcher,
+                            SmtpClientSessionCloseReason::Error,
+                            None,
+                        )
+                        .await;
                         return Ok(
                             AttemptConnectionDisposition::PeerClosedConnectionContinueSession,
                         );
@@ -631,10 +653,12 @@ impl SmtpDispatcher {
 
         // Use STARTTLS if available.
         let has_tls = pretls_caps.contains_key("STARTTLS");
+        let mut ehlo_capabilities = capability_names(pretls_caps);
         let broken_tls = self.has_broken_tls(&dispatcher.name);
 
         let mut dane_tlsa = vec![];
         let mut mta_sts_eligible = true;
+        let mut mta_sts_mode = None;
         // Set when DANE published TLSA records that turned out to be unusable:
         // STARTTLS is then mandatory (the host committed to TLS) even though we
         // cannot authenticate it, so MTA-STS may add authentication but must not
@@ -787,6 +811,7 @@ impl SmtpDispatcher {
                         self.tracer.diagnostic(Level::INFO, || {
                             format!("MTA-STS policy for {} is {:?}", mx.domain_name, policy.mode)
                         });
+                        mta_sts_mode.replace(format!("{:?}", policy.mode));
 
                         match policy.mode {
                             PolicyMode::Enforce => {
@@ -846,6 +871,7 @@ impl SmtpDispatcher {
         }
 
         let has_tls = if has_tls { AdvTls::Yes } else { AdvTls::No };
+        let have_dane_tlsa = !dane_tlsa.is_empty();
 
         let broken_tls = if broken_tls {
             BrokenTls::Yes
@@ -920,7 +946,10 @@ impl SmtpDispatcher {
                 // and we want to consider those as connection errors rather than
                 // having them show up per-message in MAIL FROM
                 match client.ehlo_lhlo(&ehlo_name, path_config.use_lmtp).await {
-                    Ok(_) => enabled,
+                    Ok(caps) => {
+                        ehlo_capabilities = capability_names(caps);
+                        enabled
+                    }
                     Err(error) => {
                         self.remember_broken_tls(&dispatcher.name, &path_config)
                             .await;
@@ -1004,7 +1033,10 @@ impl SmtpDispatcher {
                     .await
                     .with_context(|| format!("{address:?}:{port}: {helo_verb} after STARTTLS"))
                 {
-                    Ok(_) => true,
+                    Ok(caps) => {
+                        ehlo_capabilities = capability_names(caps);
+                        true
+                    }
                     Err(err) => {
                         self.remember_broken_tls(&dispatcher.name, &path_config)
                             .await;
@@ -1072,6 +1104,16 @@ impl SmtpDispatcher {
                 })?;
         }
 
+        self.connected_at.replace(Utc::now());
+        self.session_summary = SmtpClientSessionSummary {
+            mx_host: address.name.to_string(),
+            tls_policy: format!("{enable_tls:?}"),
+            tls_enabled,
+            dane: tls_enabled && have_dane_tlsa,
+            mta_sts_mode,
+            ehlo_capabilities,
+            ..Default::default()
+        };
         self.client
             .replace(connection_wrapper.map_connection(client));
         self.client_address.replace(address);
@@ -1149,10 +1191,68 @@ impl SmtpDispatcher {
         .await
     }
 
+    /// Emit an SmtpClientSession record for the current connection,
+    /// if one was established and session logging is enabled for
+    /// this path.  `response` is the last response that we received
+    /// from the peer, if known.
+    async fn log_session(
+        &mut self,
+        dispatcher: &Dispatcher,
+        close_reason: SmtpClientSessionCloseReason,
+        response: Option<Response>,
+    ) {
+        let Some(started) = self.connected_at.take() else {
+            return;
+        };
+        let mut summary = std::mem::take(&mut self.session_summary);
+        let path_config = dispatcher.path_config.borrow();
+        if !path_config.log_session {
+            return;
+        }
+        let Some(peer_address) = self.client_address.clone() else {
+            return;
+        };
+        summary.close_reason = close_reason;
+
+        let response = response.unwrap_or_else(|| Response {
+            code: 0,
+            enhanced_code: None,
+            content: format!("KumoMTA internal: connection closed: {close_reason:?}"),
+            command: None,
+        });
+
+        log_smtp_client_session(LogSmtpClientSession {
+            site: &dispatcher.name,
+            peer_address,
+            response,
+            egress_pool: &dispatcher.egress_pool,
+            egress_source: &dispatcher.egress_source.name,
+            source_address: self.source_address.clone(),
+            delivery_protocol: &dispatcher.delivery_protocol,
+            tls_info: if summary.tls_enabled {
+                self.tls_info.clone()
+            } else {
+                None
+            },
+            provider: path_config.provider_name.as_deref(),
+            session_id: dispatcher.session_id,
+            started,
+            summary,
+        })
+        .await
+    }
+
     /// Prepare for a potential reconnect.
     /// Returns true if there are potential addresses that we could
     /// reconnect to
-    fn update_state_for_reconnect(&mut self, dispatcher: &mut Dispatcher) -> bool {
+    async fn update_state_for_reconnect(
+        &mut self,
+        dispatcher: &mut Dispatcher,
+        close_reason: SmtpClientSessionCloseReason,
+        response: Option<Response>,
+    ) -> bool {
+        self.log_session(dispatcher, close_reason, response).await;
+
         match dispatcher.path_config.borrow().reconnect_strategy {
             ReconnectStrategy::TerminateSession => {
                 self.addresses.clear();
@@ -1185,12 +1285,29 @@ impl SmtpDispatcher {
 
 #[async_trait]
 impl QueueDispatcher for SmtpDispatcher {
-    async fn close_connection(&mut self, _dispatcher: &mut Dispatcher) -> anyhow::Result<bool> {
+    async fn close_connection(&mut self, dispatcher: &mut Dispatcher) -> anyhow::Result<bool> {
         if let Some(mut client) = self.client.take() {
-            client
+            let response = client
                 .send_command(&rfc5321::parser::Command::Quit)
                 .await
                 .ok();
+
+            let close_reason = if dispatcher.activity.is_shutting_down() {
+                SmtpClientSessionCloseReason::ShuttingDown
+            } else if self.terminated_ok {
+                SmtpClientSessionCloseReason::SessionTerminated
+            } else if dispatcher.delivered_this_connection
+                >= dispatcher
+                    .path_config
+                    .borrow()
+                    .max_deliveries_per_connection
+            {
+                SmtpClientSessionCloseReason::MaxDeliveriesPerConnection
+            } else {
+                SmtpClientSessionCloseReason::Idle
+            };
+            self.log_session(dispatcher, close_reason, response).await;
+
             // Close out this dispatcher and let the maintainer spawn
             // a new connection
             Ok(true)
@@ -1497,13 +1614,15 @@ impl QueueDispatcher for SmtpDispatcher {
         let mut by_class = HashMap::new();
 
         let mut transport_error = false;
+        let provider = dispatcher.path_config.borrow().provider_name.clone();
 
         for (batch_idx, (recipient, response)) in recipients_this_batch
             .iter()
             .zip(result_per_rcpt.iter())
             .enumerate()
         {
-            let (record_type, too_many) = classify_record(&response).await;
+            let (record_type, too_many) =
+                classify_record(&response, &dispatcher.name, provider.as_deref()).await;
 
             // Determine effective too-many recip state: if the batch is size 1,
             // or this is the first recipient, it cannot be too-many
@@ -1539,6 +1658,7 @@ impl QueueDispatcher for SmtpDispatcher {
                 }
                 RecordType::Delivery => {
                     dispatcher.metrics.inc_delivered();
+                    dispatcher.metrics.observe_delivery_age(&spool_id);
                 }
                 RecordType::Bounce => {
                     dispatcher.metrics.inc_fail();
@@ -1567,6 +1687,9 @@ impl QueueDispatcher for SmtpDispatcher {
         }
 
         self.recips_last_txn = recips_this_txn;
+        if by_class.contains_key(&RecordType::Delivery) {
+            self.session_summary.messages_delivered += 1;
+        }
 
         let mut logged_transient = false;
 
@@ -1614,7 +1737,12 @@ impl QueueDispatcher for SmtpDispatcher {
                 .map(|c| c.is_connected())
                 .unwrap_or(false);
             if !is_connected {
-                self.update_state_for_reconnect(dispatcher);
+                self.update_state_for_reconnect(
+                    dispatcher,
+                    SmtpClientSessionCloseReason::Error,
+                    overall_response.take(),
+                )
+                .await;
                 anyhow::bail!(
                     "after previous send attempt, client is unexpectedly no longer connected"
                 );
@@ -1633,7 +1761,13 @@ impl QueueDispatcher for SmtpDispatcher {
             }
 
             if break_connection && try_next_host_on_transport_error {
-                let have_more_connection_candidates = self.update_state_for_reconnect(dispatcher);
+                let have_more_connection_candidates = self
+                    .update_state_for_reconnect(
+                        dispatcher,
+                        SmtpClientSessionCloseReason::Error,
+                        overall_response.clone(),
+                    )
+                    .await;
                 if have_more_connection_candidates {
                     // Try it on the next connection
                     dispatcher.msgs.push(msg);
@@ -1673,7 +1807,12 @@ impl QueueDispatcher for SmtpDispatcher {
             )?;
 
             if !is_connected {
-                self.update_state_for_reconnect(dispatcher);
+                self.update_state_for_reconnect(
+                    dispatcher,
+                    SmtpClientSessionCloseReason::Error,
+                    overall_response.take(),
+                )
+                .await;
                 anyhow::bail!(
                     "after previous send attempt, client is unexpectedly no longer connected"
                 );
@@ -1683,11 +1822,23 @@ impl QueueDispatcher for SmtpDispatcher {
     }
 }
 
-async fn classify_record(response: &Response) -> (RecordType, IsTooManyRecipients) {
+/// Returns the sorted list of ESMTP extension keywords from
+/// an EHLO/LHLO response
+fn capability_names<V>(caps: &HashMap<String, V>) -> Vec<String> {
+    let mut names: Vec<String> = caps.keys().cloned().collect();
+    names.sort();
+    names
+}
+
+async fn classify_record(
+    response: &Response,
+    site: &str,
+    provider: Option<&str>,
+) -> (RecordType, IsTooManyRecipients) {
     let too_many = match response.is_too_many_recipients() {
         as_is @ (IsTooManyRecipients::Yes | IsTooManyRecipients::No) => as_is,
         IsTooManyRecipients::Maybe => {
-            match crate::logging::classify::classify_response(&response).await {
+            match crate::logging::classify::classify_response(response, site, provider).await {
                 BounceClass::UserDefined(_) => IsTooManyRecipients::Maybe,
                 BounceClass::PreDefined(bc) => match bc {
                     PreDefinedBounceClass::TooManyRecipients => IsTooManyRecipients::Yes,
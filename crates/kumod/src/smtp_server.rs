@@ -1,16 +1,19 @@
+use crate::bounce_processor::BounceProcessorParams;
 use crate::delivery_metrics::MetricsWrappedConnection;
+use crate::feedback_processor::FeedbackProcessorParams;
 use crate::http_server::admin_trace_smtp_server_v1::{
     SmtpServerTraceEvent, SmtpServerTraceEventPayload, SmtpServerTraceManager,
 };
 use crate::logging::disposition::{log_disposition, LogDisposition, RecordType};
 use crate::logging::rejection::{log_rejection, LogRejection};
+use crate::logging::session::{log_smtp_server_session, LogSmtpServerSession};
 use crate::metrics_helper::smtp_rejected_for_service;
 use crate::queue::{DeliveryProto, IncrementAttempts, InsertReason, QueueConfig, QueueManager};
 use crate::ready_queue::{AttemptConnectionDisposition, Dispatcher, QueueDispatcher};
 use crate::spool::SpoolManager;
 use anyhow::{anyhow, Context};
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use cidr_map::{CidrMap, CidrSet};
 use config::{
     any_err, declare_event, load_config, serialize_options, CallbackSignature, SerdeWrappedValue,
@@ -18,7 +21,7 @@ use config::{
 use data_encoding::BASE64;
 use data_loader::KeySource;
 use derive_where::derive_where;
-use kumo_log_types::ResolvedAddress;
+use kumo_log_types::{ResolvedAddress, SmtpServerSessionSummary};
 use kumo_prometheus::prometheus::HistogramTimer;
 use kumo_prometheus::{declare_metric, AtomicCounter};
 use kumo_server_common::acct::{log_authn, AuthnAuditRecord};
@@ -48,7 +51,7 @@ use std::fmt::Debug;
 use std::net::{IpAddr, SocketAddr};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, LazyLock};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use thiserror::Error;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
@@ -278,6 +281,16 @@ pub struct EsmtpDomain {
     pub log_oob: LogReportDisposition,
     #[serde(default)]
     pub log_arf: LogReportDisposition,
+    /// When set, all messages addressed to this domain are treated
+    /// as bounces or feedback reports; they are logged and then
+    /// discarded
+    #[serde(default)]
+    pub bounce_processor: Option<BounceProcessorParams>,
+    /// When set, all messages addressed to this domain are treated
+    /// as complaint feedback reports; they are attributed to the
+    /// original message, logged and then discarded
+    #[serde(default)]
+    pub feedback_processor: Option<FeedbackProcessorParams>,
     #[serde(default)]
     pub relay_to: bool,
     #[serde(default)]
@@ -318,6 +331,12 @@ pub struct TraceHeaders {
     /// supplemental header
     #[serde(default = "TraceHeaders::default_meta")]
     pub include_meta_names: Vec<String>,
+
+    /// The name of a header holding a W3C traceparent that
+    /// should be used as the parent of the OpenTelemetry
+    /// trace for the message
+    #[serde(default)]
+    pub trace_context_header: Option<String>,
 }
 
 impl Default for TraceHeaders {
@@ -327,6 +346,7 @@ impl Default for TraceHeaders {
             supplemental_header: true,
             header_name: Self::default_header_name(),
             include_meta_names: vec![],
+            trace_context_header: None,
         }
     }
 }
@@ -340,6 +360,27 @@ impl TraceHeaders {
         vec![]
     }
 
+    /// Assign the OpenTelemetry trace context for the message.
+    /// The parent context is taken from `supplied`, if set,
+    /// otherwise from the trace_context_header, if configured.
+    pub async fn assign_trace_context(
+        &self,
+        message: &Message,
+        supplied: Option<&str>,
+    ) -> anyhow::Result<()> {
+        if !kumo_server_common::diagnostic_logging::otlp_enabled() {
+            return Ok(());
+        }
+        let from_header = match (supplied, &self.trace_context_header) {
+            (None, Some(name)) => message
+                .get_first_named_header_value(name)
+                .await?
+                .map(|value| value.to_string()),
+            _ => None,
+        };
+        crate::message_trace::assign(message, supplied.or(from_header.as_deref())).await
+    }
+
     pub async fn apply_supplemental(&self, message: &Message) -> anyhow::Result<()> {
         if !self.supplemental_header {
             return Ok(());
@@ -411,6 +452,8 @@ pub struct ConcreteEsmtpListenerParams {
     pub allow_xclient: bool,
     pub require_proxy_protocol: bool,
 
+    pub log_session: bool,
+
     pub trace_headers: TraceHeaders,
 
     pub client_timeout: Duration,
@@ -523,6 +566,9 @@ impl ConcreteEsmtpListenerParams {
         if let Some(require_proxy_protocol) = base.require_proxy_protocol {
             self.require_proxy_protocol = require_proxy_protocol;
         }
+        if let Some(log_session) = base.log_session {
+            self.log_session = log_session;
+        }
 
         if let Some(map) = base.meta {
             for (k, v) in map.into_iter() {
@@ -587,6 +633,7 @@ impl Default for ConcreteEsmtpListenerParams {
             line_length_hard_limit: MAX_LINE_LEN,
             allow_xclient: false,
             require_proxy_protocol: false,
+            log_session: false,
         }
     }
 }
@@ -668,6 +715,9 @@ pub struct GenericEsmtpListenerParams {
 
     #[serde(default)]
     require_proxy_protocol: Option<bool>,
+
+    #[serde(default)]
+    log_session: Option<bool>,
 }
 
 impl mlua::FromLua for GenericEsmtpListenerParams {
@@ -912,6 +962,9 @@ pub struct SmtpServerSession {
     session_id: Uuid,
     domains: HashMap<String, Option<EsmtpDomain>>,
     config_params: EsmtpListenerParams,
+    connected_at: DateTime<Utc>,
+    session_summary: SmtpServerSessionSummary,
+    last_response: Option<Response>,
 }
 
 #[derive_where(Debug)]
@@ -922,18 +975,24 @@ struct TransactionState {
     _timer: HistogramTimer,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct RelayDisposition {
     /// Should queue for onward delivery
     pub relay: bool,
     /// Should accept to process ARF reports
     pub log_arf: LogReportDisposition,
     pub log_oob: LogReportDisposition,
+    pub bounce_processor: Option<Arc<BounceProcessorParams>>,
+    pub feedback_processor: Option<Arc<FeedbackProcessorParams>>,
 }
 
 impl RelayDisposition {
     pub fn accept_rcpt_to(&self) -> bool {
-        self.relay || self.log_arf.should_log() || self.log_oob.should_log()
+        self.relay
+            || self.log_arf.should_log()
+            || self.log_oob.should_log()
+            || self.bounce_processor.is_some()
+            || self.feedback_processor.is_some()
     }
 }
 
@@ -987,6 +1046,9 @@ impl SmtpServerSession {
             session_id: Uuid::new_v4(),
             domains: HashMap::new(),
             config_params: params,
+            connected_at: Utc::now(),
+            session_summary: SmtpServerSessionSummary::default(),
+            last_response: None,
         };
 
         connection_gauge().inc();
@@ -1029,6 +1091,10 @@ impl SmtpServerSession {
         }
         connection_gauge().dec();
 
+        if server.params.log_session {
+            server.log_session().await;
+        }
+
         SmtpServerTraceManager::submit(|| SmtpServerTraceEvent {
             conn_meta: server.meta.clone_inner(),
             payload: SmtpServerTraceEventPayload::Closed,
@@ -1038,6 +1104,34 @@ impl SmtpServerSession {
         Ok(())
     }
 
+    async fn log_session(&mut self) {
+        let mut summary = std::mem::take(&mut self.session_summary);
+        summary.authz_id = self.authorization_id.clone();
+        summary.authn_id = self.authentication_id.clone();
+
+        log_smtp_server_session(LogSmtpServerSession {
+            peer_address: ResolvedAddress {
+                name: self.said_hello.as_deref().unwrap_or("").to_string(),
+                addr: self.peer_address.ip().into(),
+                is_secure: false,
+            },
+            // If the peer disconnected without us sending anything,
+            // there is no final response to report
+            response: self.last_response.take().unwrap_or_else(|| Response {
+                code: 0,
+                enhanced_code: None,
+                content: "".to_string(),
+                command: None,
+            }),
+            meta: self.meta.clone_inner(),
+            tls_info: self.tls_active.clone(),
+            session_id: self.session_id,
+            started: self.connected_at,
+            summary,
+        })
+        .await;
+    }
+
     fn peer_in_cidr_list(&self, cidr: &CidrSet) -> bool {
         cidr.contains(self.peer_address.ip())
     }
@@ -1131,11 +1225,15 @@ impl SmtpServerSession {
         let mut relay_to_allowed = None;
         let mut log_arf = LogReportDisposition::Ignore;
         let mut log_oob = LogReportDisposition::Ignore;
+        let mut bounce_processor = None;
+        let mut feedback_processor = None;
 
         if let Some(dom) = self.lookup_listener_domain(&recipient_domain).await? {
             relay_to_allowed.replace(dom.relay_to);
             log_arf = dom.log_arf;
             log_oob = dom.log_oob;
+            bounce_processor = dom.bounce_processor.map(Arc::new);
+            feedback_processor = dom.feedback_processor.map(Arc::new);
         }
 
         // Check the rules for relaying-from first; that allows
@@ -1154,13 +1252,18 @@ impl SmtpServerSession {
              recip={recipient_domain} relay_to_allowed={relay_to_allowed:?} \
              relay_hosts_allowed={relay_hosts_allowed} \
              relay_from_allowed={relay_from_allowed} \
-             -> log_arf={log_arf:?} log_oob={log_oob:?} relay={relay}"
+             -> log_arf={log_arf:?} log_oob={log_oob:?} \
+             bounce_processor={} feedback_processor={} relay={relay}",
+            bounce_processor.is_some(),
+            feedback_processor.is_some()
         );
 
         Ok(RelayDisposition {
             relay,
             log_arf,
             log_oob,
+            bounce_processor,
+            feedback_processor,
         })
     }
 
@@ -1199,6 +1302,23 @@ impl SmtpServerSession {
             }
         };
 
+        if self.params.log_session {
+            let mut response = Response::with_code_and_message(status, &message);
+            response.command = command.clone();
+            if command
+                .as_deref()
+                .map(|c| c.trim().eq_ignore_ascii_case("DATA"))
+                .unwrap_or(false)
+            {
+                if response.is_transient() || response.is_permanent() {
+                    self.session_summary.messages_rejected += 1;
+                } else {
+                    self.session_summary.messages_accepted += 1;
+                }
+            }
+            self.last_response.replace(response);
+        }
+
         if let Some(socket) = self.socket.as_mut() {
             if (400..600).contains(&status)
                 // Don't log the shutting down message, or load shedding messages.
@@ -1252,6 +1372,7 @@ impl SmtpServerSession {
                     .write(text.as_bytes())
                     .await
                     .map_err(|_| WriteError {})?;
+                self.session_summary.bytes_written += text.len() as u64;
             }
 
             let close_connection = match disconnect {
@@ -1383,6 +1504,7 @@ impl SmtpServerSession {
                                 payload: SmtpServerTraceEventPayload::Read(data[0..size].to_vec()),
                                 when: Utc::now(),
                             });
+                            self.session_summary.bytes_read += size as u64;
                             self.read_buffer.extend_from_slice(&data[0..size]);
                         }
                     }
@@ -1474,6 +1596,7 @@ impl SmtpServerSession {
                                 payload: SmtpServerTraceEventPayload::Read(data[0..size].to_vec()),
                                 when: Utc::now(),
                             });
+                            self.session_summary.bytes_read += size as u64;
                             self.read_buffer.extend_from_slice(&data[0..size]);
                         }
                     }
@@ -1728,7 +1851,20 @@ impl SmtpServerSession {
                 }
             };
 
-            match Command::parse(&line) {
+            let parsed = Command::parse(&line);
+            if self.params.log_session {
+                let verb = match &parsed {
+                    Ok(MaybePartialCommand::Full(command)) => command.verb(),
+                    _ => "INVALID",
+                };
+                *self
+                    .session_summary
+                    .commands
+                    .entry(verb.to_string())
+                    .or_default() += 1;
+            }
+
+            match parsed {
                 Err(err) => {
                     self.write_response(
                         501,
@@ -1796,6 +1932,9 @@ impl SmtpServerSession {
                                 None => String::new(),
                             };
 
+                            self.session_summary.tls_sni =
+                                conn.server_name().map(|name| name.to_string());
+
                             if let Some(certs) = conn.peer_certificates() {
                                 let peer_cert = &certs[0];
                                 if let Ok(cert) = X509::from_der(peer_cert.as_ref()) {
@@ -1987,6 +2126,38 @@ impl SmtpServerSession {
                         continue;
                     }
 
+                    if relay_disposition.relay {
+                        // Entries that are scoped to a tenant are checked
+                        // once the message has been assigned to its queue
+                        if let Err(suppressed) =
+                            crate::suppression::check_recipient(&address.to_string(), None)
+                        {
+                            self.write_response(
+                                suppressed.code,
+                                suppressed.text,
+                                Some(line),
+                                RejectDisconnect::If421,
+                            )
+                            .await?;
+                            continue;
+                        }
+                    }
+
+                    if let Some(processor) = &relay_disposition.bounce_processor {
+                        // Reject bounces sent to VERP addresses with a
+                        // missing or invalid signature
+                        if let Err(err) = processor.verp.decode(&address.user()).await {
+                            self.write_response(
+                                550,
+                                format!("5.1.1 invalid bounce address: {err:#}"),
+                                Some(line),
+                                RejectDisconnect::If421,
+                            )
+                            .await?;
+                            continue;
+                        }
+                    }
+
                     if let Some(state) = &self.state {
                         if state.recipients.len() == self.params.max_recipients_per_message {
                             self.write_response(
@@ -2223,6 +2394,7 @@ impl SmtpServerSession {
                                 payload: SmtpServerTraceEventPayload::Read(data[0..size].to_vec()),
                                 when: Utc::now(),
                             });
+                            self.session_summary.bytes_read += size as u64;
                             self.read_buffer.extend_from_slice(&data[0..size]);
                         }
                     }
@@ -3035,18 +3207,37 @@ impl SmtpServerSession {
                 body,
             )?;
 
+            self.params
+                .trace_headers
+                .assign_trace_context(&message, None)
+                .await?;
+
             if self.params.deferred_queue {
                 message.set_meta("queue", DEFERRED_QUEUE_NAME).await?;
             } else {
-                match timeout_at(
+                let event_start = SystemTime::now();
+                let result = timeout_at(
                     deadline.into(),
                     Box::pin(self.call_callback_sig(
                         &SMTP_SERVER_MSG_RX,
                         (message.clone(), self.meta.clone()),
                     )),
                 )
-                .await
-                {
+                .await;
+                crate::message_trace::record_span(
+                    &message,
+                    "smtp_server_message_received",
+                    event_start,
+                    vec![],
+                    match &result {
+                        Ok(Ok(Ok(_))) => Ok(()),
+                        Ok(Ok(Err(rej))) => Err(format!("{} {}", rej.code, rej.message)),
+                        Ok(Err(err)) => Err(format!("{err:#}")),
+                        Err(_) => Err("data_processing_timeout exceeded".to_string()),
+                    },
+                )
+                .await;
+                match result {
                     Ok(Ok(Ok(_))) => {}
                     Err(_) => {
                         self.write_response(
@@ -3092,7 +3283,14 @@ impl SmtpServerSession {
         // to resolution that can cause this to take a non-trivial amount of time,
         // so let's get that out of the way before we start writing to spool,
         // to make it less complex to unwind if we exceed the allowed time.
-        for message in &accepted_messages {
+        // Quota is reserved for each message as it is checked, so that
+        // the later messages in the batch are checked against the usage
+        // of the earlier ones; the reservations are held until the
+        // messages have been charged to their queues below.
+        let mut quota_reservations = vec![];
+        let mut suppressed = None;
+        let mut unsuppressed_messages = vec![];
+        for message in accepted_messages {
             let queue_name = message.get_queue_name().await?;
             match timeout_at(deadline.into(), QueueManager::resolve(&queue_name)).await {
                 Err(_) => {
@@ -3123,6 +3321,70 @@ impl SmtpServerSession {
                 }
                 Ok(Ok(_handle)) => {}
             }
+
+            // Entries that are scoped to a tenant can only be checked
+            // now that the queue is known.  Rather than rejecting the
+            // whole transaction, just the suppressed recipients are
+            // removed, and the message is discarded if none remain.
+            if let Some((rejection, recipients)) =
+                crate::suppression::remove_suppressed_recipients(&message, &queue_name).await?
+            {
+                let sender = message.sender().await?.to_string();
+                for recipient in recipients {
+                    let mut response =
+                        Response::with_code_and_message(rejection.code, &rejection.text);
+                    response.command.replace("DATA".to_string());
+                    log_rejection(LogRejection {
+                        meta: self.meta.clone_inner(),
+                        peer_address: ResolvedAddress {
+                            name: self.said_hello.as_deref().unwrap_or("").to_string(),
+                            addr: self.peer_address.ip().into(),
+                            is_secure: false,
+                        },
+                        response,
+                        sender: Some(sender.clone()),
+                        recipient: Some(recipient),
+                        session_id: Some(self.session_id),
+                    })
+                    .await;
+                }
+                let discard = message.recipient_list().await?.is_empty();
+                suppressed.replace(rejection);
+                if discard {
+                    continue;
+                }
+            }
+
+            let size = message.get_data_maybe_not_loaded().len();
+            match crate::quota::reserve(&queue_name, size) {
+                Ok(reservation) => quota_reservations.push(reservation),
+                Err(exceeded) => {
+                    self.write_response(
+                        452,
+                        format!("4.3.1 {exceeded}"),
+                        Some("DATA".into()),
+                        RejectDisconnect::If421,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+            }
+
+            unsuppressed_messages.push(message);
+        }
+
+        let accepted_messages = unsuppressed_messages;
+        if accepted_messages.is_empty() {
+            if let Some(rejection) = suppressed {
+                self.write_response(
+                    rejection.code,
+                    rejection.text,
+                    Some("DATA".into()),
+                    RejectDisconnect::If421,
+                )
+                .await?;
+                return Ok(());
+            }
         }
 
         let mut messages: Vec<(/* queue_name */ String, Message)> = vec![];
@@ -3146,7 +3408,14 @@ impl SmtpServerSession {
 
             let mut relay_this_one = relay_disposition.relay;
 
-            if relay_disposition.log_arf.should_log()
+            if relay_disposition.bounce_processor.is_some()
+                || relay_disposition.feedback_processor.is_some()
+            {
+                // The bounce and feedback processors log whatever they
+                // can extract from the message, and always discard it
+                was_arf_or_oob = true;
+                relay_this_one = false;
+            } else if relay_disposition.log_arf.should_log()
                 && matches!(message.parse_rfc5965().await, Ok(Some(_)))
             {
                 was_arf_or_oob = true;
@@ -3190,7 +3459,20 @@ impl SmtpServerSession {
             });
 
             if relay_this_one && queue_name != "null" && !self.params.deferred_spool {
-                match message.save(Some(deadline)).await {
+                let save_start = SystemTime::now();
+                let save_result = message.save(Some(deadline)).await;
+                crate::message_trace::record_span(
+                    &message,
+                    "spool_write",
+                    save_start,
+                    vec![],
+                    save_result
+                        .as_ref()
+                        .map(|_| ())
+                        .map_err(|err| format!("{err:#}")),
+                )
+                .await;
+                match save_result {
                     Err(err) => {
                         // Assume that any other saves that we try right now
                         // are likely to fail for similar reasons, and since
@@ -3290,12 +3572,14 @@ impl SmtpServerSession {
                 recipient_list: None,
             })
             .await;
-            if queue_name != "null" {
-                if relay_this_one {
-                    messages.push((queue_name, message));
-                }
+            if queue_name != "null" && relay_this_one {
+                messages.push((queue_name, message));
             } else {
-                black_holed = true;
+                if queue_name == "null" {
+                    black_holed = true;
+                }
+                // The message won't be queued, so its trace is complete
+                crate::message_trace::finish(message.id());
             }
         }
 
@@ -3331,6 +3615,8 @@ impl SmtpServerSession {
                 }
             }
         }
+        // The queued messages are now charged to their queues
+        drop(quota_reservations);
 
         if !black_holed && !relayed_any && !was_arf_or_oob {
             self.write_response(
@@ -91,6 +91,45 @@ pub struct DefineSpoolParams {
     pub min_free_space: MinFree,
     #[serde(default)]
     pub min_free_inodes: MinFree,
+
+    #[serde(default)]
+    pub migrate_from: Option<SpoolMigrationParams>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpoolMigrationParams {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub kind: SpoolKind,
+    #[serde(default)]
+    pub rocks_params: Option<RocksSpoolParams>,
+    #[serde(default)]
+    pub remove_source: bool,
+}
+
+fn open_spool(
+    name: &str,
+    path: &std::path::Path,
+    kind: &SpoolKind,
+    flush: bool,
+    rocks_params: Option<RocksSpoolParams>,
+) -> anyhow::Result<Arc<dyn SpoolTrait + Send + Sync>> {
+    Ok(match kind {
+        SpoolKind::LocalDisk => Arc::new(
+            LocalDiskSpool::new(path, flush, kumo_server_runtime::get_main_runtime())
+                .with_context(|| format!("Opening spool {name}"))?,
+        ),
+        SpoolKind::RocksDB => Arc::new(
+            RocksSpool::new(
+                path,
+                flush,
+                rocks_params,
+                kumo_server_runtime::get_main_runtime(),
+            )
+            .with_context(|| format!("Opening spool {name}"))?,
+        ),
+    })
 }
 
 async fn define_spool(params: DefineSpoolParams) -> anyhow::Result<()> {
@@ -263,29 +302,54 @@ impl SpoolManager {
             params.name,
             params.path.display()
         );
+        let spool = open_spool(
+            &params.name,
+            &params.path,
+            &params.kind,
+            params.flush,
+            params.rocks_params,
+        )?;
+
+        if let Some(migrate) = params.migrate_from {
+            tracing::info!(
+                "Migrating spool '{}' from {} to {}",
+                params.name,
+                migrate.path.display(),
+                params.path.display()
+            );
+            let source = open_spool(
+                &format!("{} migration source", params.name),
+                &migrate.path,
+                &migrate.kind,
+                false,
+                migrate.rocks_params,
+            )?;
+            let stats = spool::migrate::migrate_spool(&*source, &*spool, migrate.remove_source)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Migrating spool {} from {}",
+                        params.name,
+                        migrate.path.display()
+                    )
+                })?;
+            source.shutdown().await?;
+            tracing::info!(
+                "Migrated spool '{}': copied={} skipped={} corrupt={} removed={} bytes={}",
+                params.name,
+                stats.copied,
+                stats.skipped,
+                stats.corrupt,
+                stats.removed,
+                stats.bytes
+            );
+        }
+
         self.named.lock().await.insert(
             params.name.to_string(),
             SpoolHandle(Arc::new(Spool {
                 maintainer: StdMutex::new(None),
-                spool: match params.kind {
-                    SpoolKind::LocalDisk => Arc::new(
-                        LocalDiskSpool::new(
-                            &params.path,
-                            params.flush,
-                            kumo_server_runtime::get_main_runtime(),
-                        )
-                        .with_context(|| format!("Opening spool {}", params.name))?,
-                    ),
-                    SpoolKind::RocksDB => Arc::new(
-                        RocksSpool::new(
-                            &params.path,
-                            params.flush,
-                            params.rocks_params,
-                            kumo_server_runtime::get_main_runtime(),
-                        )
-                        .with_context(|| format!("Opening spool {}", params.name))?,
-                    ),
-                },
+                spool,
             })),
         );
         Ok(())
@@ -332,6 +396,8 @@ impl SpoolManager {
     }
 
     pub async fn remove_from_spool(id: SpoolId) -> anyhow::Result<()> {
+        crate::quota::release(id);
+        crate::message_trace::finish(&id);
         let (data_spool, meta_spool) = Self::get_data_meta();
         let res_data = data_spool.remove(id).await;
         let res_meta = meta_spool.remove(id).await;
@@ -346,6 +412,25 @@ impl SpoolManager {
         Ok(())
     }
 
+    /// Like remove_from_spool, but returns an error if the meta could
+    /// not be removed, in which case the message remains intact in
+    /// the spool.  Once the meta has been removed the message can no
+    /// longer be recovered, so a failure to remove the data is only
+    /// logged.
+    pub async fn try_remove_from_spool(id: SpoolId) -> anyhow::Result<()> {
+        let (data_spool, meta_spool) = Self::get_data_meta();
+        meta_spool
+            .remove(id)
+            .await
+            .with_context(|| format!("removing meta for {id}"))?;
+        crate::quota::release(id);
+        crate::message_trace::finish(&id);
+        if let Err(err) = data_spool.remove(id).await {
+            tracing::error!("Error removing data for {id}: {err:#}");
+        }
+        Ok(())
+    }
+
     pub async fn remove_from_spool_impl(&self, id: SpoolId) -> anyhow::Result<()> {
         let (data_spool, meta_spool) = Self::get_data_meta();
         let res_data = data_spool.remove(id).await;
@@ -518,6 +603,34 @@ impl SpoolManager {
                                     failed_spool_in.fetch_add(1, Ordering::SeqCst);
                                 }
                                 Ok(queue) => {
+                                    crate::message_trace::resume(&msg).await;
+
+                                    // Quarantined messages are not subject to expiry,
+                                    // so hold them before considering their due time
+                                    match crate::quarantine::hold_if_quarantined(
+                                        &msg,
+                                        &queue_name,
+                                        &InsertReason::Enumerated.into(),
+                                    )
+                                    .await
+                                    {
+                                        Ok(true) => continue,
+                                        Ok(false) => {}
+                                        Err(err) => {
+                                            tracing::error!(
+                                                "failed to check quarantine state \
+                                                 of Message {id}: {err:#}"
+                                            );
+                                        }
+                                    }
+
+                                    if let Err(err) = crate::quota::charge(&msg, &queue_name).await
+                                    {
+                                        tracing::error!(
+                                            "failed to charge quota for Message {id}: {err:#}"
+                                        );
+                                    }
+
                                     let Some(msg) =
                                         self.update_next_due(id, msg, &queue, now).await?
                                     else {
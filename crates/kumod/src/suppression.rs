@@ -0,0 +1,744 @@
+//! Maintains a list of recipients and domains to which mail should
+//! no longer be sent.
+//!
+//! Entries are added automatically from `Bounce` and `OOB` records
+//! whose bounce classification is one of the configured classes,
+//! and from `Feedback` records, as well as explicitly via the admin
+//! API. Recipients are checked against the list at reception time.
+//!
+//! The list is persisted in a sqlite database and loaded into memory
+//! when `kumo.configure_suppression` is called, so that checking a
+//! recipient doesn't require a database query.
+use crate::logging::classify::apply_classification;
+use anyhow::Context;
+use bounce_classify::{BounceClass, PreDefinedBounceClass};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use kumo_api_types::suppression::{
+    SuppressionSource, SuppressionV1AddRequest, SuppressionV1DeleteRequest, SuppressionV1Entry,
+    SuppressionV1ListRequest,
+};
+use kumo_log_types::{JsonLogRecord, RecordType};
+use kumo_prometheus::declare_metric;
+use kumo_server_lifecycle::ShutdownSubcription;
+use kumo_server_runtime::get_main_runtime;
+use message::queue_name::QueueNameComponents;
+use message::Message;
+use serde::Deserialize;
+use sqlite::{Connection, ConnectionThreadSafe, State};
+use std::sync::{Arc, LazyLock, OnceLock};
+use std::time::Duration;
+use thiserror::Error;
+
+static CONFIG: OnceLock<SuppressionParams> = OnceLock::new();
+static DB: OnceLock<Arc<ConnectionThreadSafe>> = OnceLock::new();
+static ENTRIES: LazyLock<DashMap<SuppressionKey, SuppressionV1Entry>> = LazyLock::new(DashMap::new);
+
+/// How often to remove expired entries
+const PRUNE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+declare_metric! {
+/// The number of entries in the suppression list.
+static SUPPRESSION_ENTRY_COUNT: IntGauge("suppression_entry_count");
+}
+
+declare_metric! {
+/// The number of recipients that were rejected at reception
+/// because they are in the suppression list.
+static SUPPRESSION_REJECTIONS: IntCounter("suppression_rejections");
+}
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SuppressionParams {
+    /// Path to the sqlite database that holds the entries
+    #[serde(default = "SuppressionParams::default_path")]
+    pub path: String,
+
+    /// Bounce and OOB records with these classifications
+    /// cause their recipients to be suppressed
+    #[serde(default = "SuppressionParams::default_bounce_classes")]
+    pub bounce_classes: Vec<BounceClass>,
+
+    /// How long entries added because of a bounce remain active.
+    /// If not set, they remain until they are deleted.
+    #[serde(default, with = "duration_serde")]
+    pub bounce_duration: Option<Duration>,
+
+    /// Whether Feedback records cause their recipients to be suppressed
+    #[serde(default = "SuppressionParams::default_true")]
+    pub complaints: bool,
+
+    /// How long entries added because of a complaint remain active.
+    /// If not set, they remain until they are deleted.
+    #[serde(default, with = "duration_serde")]
+    pub complaint_duration: Option<Duration>,
+
+    /// Whether automatically added entries are scoped to the
+    /// tenant of the message that produced them
+    #[serde(default = "SuppressionParams::default_true")]
+    pub per_tenant: bool,
+
+    /// The SMTP status code used to reject suppressed recipients
+    #[serde(default = "SuppressionParams::default_rejection_code")]
+    pub rejection_code: u16,
+
+    /// The text used to reject suppressed recipients, including
+    /// the enhanced status code
+    #[serde(default = "SuppressionParams::default_rejection_text")]
+    pub rejection_text: String,
+}
+
+impl SuppressionParams {
+    fn default_path() -> String {
+        "/var/spool/kumomta/suppression.db".to_string()
+    }
+
+    fn default_bounce_classes() -> Vec<BounceClass> {
+        vec![
+            PreDefinedBounceClass::InvalidRecipient.into(),
+            PreDefinedBounceClass::BadDomain.into(),
+            PreDefinedBounceClass::InactiveMailbox.into(),
+        ]
+    }
+
+    fn default_true() -> bool {
+        true
+    }
+
+    fn default_rejection_code() -> u16 {
+        550
+    }
+
+    fn default_rejection_text() -> String {
+        "5.7.1 recipient is suppressed".to_string()
+    }
+}
+
+/// The recipient address or domain, and the tenant (which is
+/// empty for entries that apply to all tenants), both lowercased
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct SuppressionKey {
+    target: String,
+    tenant: String,
+}
+
+impl SuppressionKey {
+    fn new(
+        recipient: Option<&str>,
+        domain: Option<&str>,
+        tenant: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let target = match (recipient, domain) {
+            (Some(recipient), None) => {
+                anyhow::ensure!(
+                    recipient.contains('@'),
+                    "recipient {recipient} is not an email address"
+                );
+                recipient
+            }
+            (None, Some(domain)) => {
+                anyhow::ensure!(!domain.contains('@'), "domain {domain} is not a domain");
+                domain
+            }
+            _ => anyhow::bail!("exactly one of recipient or domain must be specified"),
+        };
+        Ok(Self {
+            target: target.to_ascii_lowercase(),
+            tenant: tenant.unwrap_or("").to_ascii_lowercase(),
+        })
+    }
+
+    fn for_entry(entry: &SuppressionV1Entry) -> anyhow::Result<Self> {
+        Self::new(
+            entry.recipient.as_deref(),
+            entry.domain.as_deref(),
+            entry.tenant.as_deref(),
+        )
+    }
+}
+
+/// Returned when a recipient is in the suppression list
+#[derive(Error, Debug, Clone)]
+#[error("{code} {text}")]
+pub struct Suppressed {
+    pub code: u16,
+    pub text: String,
+}
+
+fn is_expired(entry: &SuppressionV1Entry, now: DateTime<Utc>) -> bool {
+    entry.expires.map(|expires| expires <= now).unwrap_or(false)
+}
+
+/// Returns true if the suppression list has been configured
+pub fn is_enabled() -> bool {
+    CONFIG.get().is_some()
+}
+
+/// Returns the active entry that applies to recipient, if any.
+/// When tenant is None, only entries that apply to all tenants
+/// are considered.
+pub fn lookup(recipient: &str, tenant: Option<&str>) -> Option<SuppressionV1Entry> {
+    if ENTRIES.is_empty() {
+        return None;
+    }
+
+    let recipient = recipient.to_ascii_lowercase();
+    let domain = recipient.rsplit_once('@').map(|(_, domain)| domain);
+    let now = Utc::now();
+
+    let tenants = tenant
+        .map(|t| t.to_ascii_lowercase())
+        .into_iter()
+        .chain(std::iter::once(String::new()));
+    for tenant in tenants {
+        for target in std::iter::once(recipient.as_str()).chain(domain) {
+            let key = SuppressionKey {
+                target: target.to_string(),
+                tenant: tenant.clone(),
+            };
+            if let Some(entry) = ENTRIES.get(&key) {
+                if !is_expired(&entry, now) {
+                    return Some(entry.clone());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Check the recipient against the suppression list,
+/// returning the configured rejection if it is suppressed
+pub fn check_recipient(recipient: &str, tenant: Option<&str>) -> Result<(), Suppressed> {
+    let Some(config) = CONFIG.get() else {
+        return Ok(());
+    };
+    match lookup(recipient, tenant) {
+        Some(entry) => {
+            SUPPRESSION_REJECTIONS.inc();
+            tracing::debug!("{recipient} is suppressed: {entry:?}");
+            Err(Suppressed {
+                code: config.rejection_code,
+                text: config.rejection_text.clone(),
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+/// Check each of the recipients of msg against the suppression
+/// list, including the entries that are scoped to the tenant
+/// of its scheduled queue
+pub async fn check_message(msg: &Message, queue_name: &str) -> anyhow::Result<()> {
+    if !is_enabled() || ENTRIES.is_empty() {
+        return Ok(());
+    }
+    let components = QueueNameComponents::parse(queue_name);
+    for recipient in msg.recipient_list_string().await? {
+        check_recipient(&recipient, components.tenant)?;
+    }
+    Ok(())
+}
+
+/// Remove the recipients of msg that are in the suppression list,
+/// including the entries that are scoped to the tenant of its
+/// scheduled queue.  Returns the configured rejection and the
+/// recipients that were removed, or None if none were removed.
+/// The caller should discard msg if it has no remaining recipients.
+pub async fn remove_suppressed_recipients(
+    msg: &Message,
+    queue_name: &str,
+) -> anyhow::Result<Option<(Suppressed, Vec<String>)>> {
+    if !is_enabled() || ENTRIES.is_empty() {
+        return Ok(None);
+    }
+    let components = QueueNameComponents::parse(queue_name);
+    let mut rejection = None;
+    let mut kept = vec![];
+    let mut removed = vec![];
+    for recipient in msg.recipient_list().await? {
+        let address = recipient.to_string();
+        match check_recipient(&address, components.tenant) {
+            Ok(()) => kept.push(recipient),
+            Err(suppressed) => {
+                rejection.replace(suppressed);
+                removed.push(address);
+            }
+        }
+    }
+    let Some(rejection) = rejection else {
+        return Ok(None);
+    };
+    msg.set_recipient_list(kept).await?;
+    Ok(Some((rejection, removed)))
+}
+
+/// Returns up to `limit` entries matching the request
+pub fn list(request: &SuppressionV1ListRequest) -> Vec<SuppressionV1Entry> {
+    let now = Utc::now();
+    let recipient = request.recipient.as_deref().map(str::to_ascii_lowercase);
+    let recipient_domain = recipient
+        .as_deref()
+        .and_then(|r| r.rsplit_once('@').map(|(_, domain)| domain.to_string()));
+    let domain = request.domain.as_deref().map(str::to_ascii_lowercase);
+    let tenant = request.tenant.as_deref().map(str::to_ascii_lowercase);
+
+    let mut entries: Vec<SuppressionV1Entry> = ENTRIES
+        .iter()
+        .filter(|item| {
+            let key = item.key();
+            let entry = item.value();
+            if is_expired(entry, now) {
+                return false;
+            }
+            if let Some(recipient) = &recipient {
+                if key.target != *recipient && Some(&key.target) != recipient_domain.as_ref() {
+                    return false;
+                }
+            }
+            if let Some(domain) = &domain {
+                let entry_domain = match key.target.rsplit_once('@') {
+                    Some((_, entry_domain)) => entry_domain,
+                    None => &key.target,
+                };
+                if entry_domain != domain {
+                    return false;
+                }
+            }
+            if let Some(tenant) = &tenant {
+                if key.tenant != *tenant {
+                    return false;
+                }
+            }
+            if let Some(source) = &request.source {
+                if entry.source != *source {
+                    return false;
+                }
+            }
+            true
+        })
+        .map(|item| item.value().clone())
+        .collect();
+
+    entries.sort_by_key(|entry| entry.created);
+    if let Some(limit) = request.limit {
+        entries.truncate(limit);
+    }
+    entries
+}
+
+/// Add an entry on behalf of an administrator
+pub async fn add_admin(request: SuppressionV1AddRequest) -> anyhow::Result<SuppressionV1Entry> {
+    anyhow::ensure!(is_enabled(), "the suppression list has not been configured");
+    let created = Utc::now();
+    let expires = match request.duration {
+        Some(duration) => Some(
+            created
+                + chrono::Duration::from_std(duration)
+                    .with_context(|| format!("invalid duration {duration:?}"))?,
+        ),
+        None => None,
+    };
+    let entry = SuppressionV1Entry {
+        recipient: request.recipient,
+        domain: request.domain,
+        tenant: request.tenant,
+        source: SuppressionSource::Admin,
+        reason: request.reason,
+        created,
+        expires,
+    };
+    insert(entry.clone()).await?;
+    Ok(entry)
+}
+
+/// Persist and activate an entry, replacing any existing
+/// entry with the same recipient/domain and tenant
+async fn insert(entry: SuppressionV1Entry) -> anyhow::Result<()> {
+    let key = SuppressionKey::for_entry(&entry)?;
+    let db = get_db()?;
+
+    let db_key = key.clone();
+    let db_entry = entry.clone();
+    get_main_runtime()
+        .spawn_blocking(move || store_entry(&db, &db_key, &db_entry))
+        .await??;
+
+    ENTRIES.insert(key, entry);
+    SUPPRESSION_ENTRY_COUNT.set(ENTRIES.len() as i64);
+    Ok(())
+}
+
+/// Delete the entry that exactly matches the request.
+/// Returns the number of entries that were deleted.
+pub async fn delete(request: SuppressionV1DeleteRequest) -> anyhow::Result<usize> {
+    let key = SuppressionKey::new(
+        request.recipient.as_deref(),
+        request.domain.as_deref(),
+        request.tenant.as_deref(),
+    )?;
+    let db = get_db()?;
+
+    let db_key = key.clone();
+    get_main_runtime()
+        .spawn_blocking(move || -> anyhow::Result<()> {
+            let mut delete = db
+                .prepare("DELETE FROM suppression WHERE target = $target AND tenant = $tenant")
+                .context("prepare delete")?;
+            delete.bind(("$target", db_key.target.as_str()))?;
+            delete.bind(("$tenant", db_key.tenant.as_str()))?;
+            delete.next()?;
+            Ok(())
+        })
+        .await??;
+
+    let count = if ENTRIES.remove(&key).is_some() { 1 } else { 0 };
+    SUPPRESSION_ENTRY_COUNT.set(ENTRIES.len() as i64);
+    Ok(count)
+}
+
+/// Called by the logging layer for each record that is produced,
+/// in order to add the recipients of hard bounces and complaints
+/// to the suppression list.  `recipients` are the addresses that
+/// the record is about.
+pub async fn process_record(record: &JsonLogRecord, recipients: &[String]) {
+    let Some(config) = CONFIG.get() else {
+        return;
+    };
+
+    let (source, reason, duration) = match record.kind {
+        RecordType::Bounce | RecordType::OOB => {
+            let mut record = record.clone();
+            apply_classification(&mut record).await;
+            if !config
+                .bounce_classes
+                .contains(&record.bounce_classification)
+            {
+                return;
+            }
+            (
+                SuppressionSource::Bounce,
+                record.response.to_single_line(),
+                config.bounce_duration,
+            )
+        }
+        RecordType::Feedback if config.complaints => {
+            let feedback_type = record
+                .feedback_report
+                .as_ref()
+                .map(|report| report.feedback_type.as_str())
+                .unwrap_or("abuse");
+            if feedback_type.eq_ignore_ascii_case("not-spam") {
+                return;
+            }
+            (
+                SuppressionSource::Complaint,
+                format!("{feedback_type} report"),
+                config.complaint_duration,
+            )
+        }
+        _ => return,
+    };
+
+    let tenant = if config.per_tenant {
+        QueueNameComponents::parse(&record.queue)
+            .tenant
+            .map(|t| t.to_string())
+    } else {
+        None
+    };
+
+    let created = Utc::now();
+    let expires = duration
+        .and_then(|duration| chrono::Duration::from_std(duration).ok())
+        .map(|duration| created + duration);
+
+    for recipient in recipients {
+        if !recipient.contains('@') || lookup(recipient, tenant.as_deref()).is_some() {
+            continue;
+        }
+        let entry = SuppressionV1Entry {
+            recipient: Some(recipient.to_ascii_lowercase()),
+            domain: None,
+            tenant: tenant.clone(),
+            source,
+            reason: reason.clone(),
+            created,
+            expires,
+        };
+        tracing::debug!("adding {recipient} to the suppression list: {reason}");
+        if let Err(err) = insert(entry).await {
+            tracing::error!("failed to add {recipient} to the suppression list: {err:#}");
+        }
+    }
+}
+
+/// Called when a recipient unsubscribes via List-Unsubscribe.
+/// The entry is scoped to `tenant` when the suppression list is
+/// configured with `per_tenant`.  Does nothing if the suppression
+/// list has not been configured, or the recipient is already
+/// suppressed.
+pub async fn process_unsubscribe(
+    recipient: &str,
+    tenant: Option<&str>,
+    duration: Option<Duration>,
+) -> anyhow::Result<()> {
+    let Some(config) = CONFIG.get() else {
+        return Ok(());
+    };
+    let tenant = if config.per_tenant { tenant } else { None };
+    if lookup(recipient, tenant).is_some() {
+        return Ok(());
+    }
+
+    let created = Utc::now();
+    let expires = match duration {
+        Some(duration) => Some(
+            created
+                + chrono::Duration::from_std(duration)
+                    .with_context(|| format!("invalid duration {duration:?}"))?,
+        ),
+        None => None,
+    };
+    insert(SuppressionV1Entry {
+        recipient: Some(recipient.to_ascii_lowercase()),
+        domain: None,
+        tenant: tenant.map(|t| t.to_string()),
+        source: SuppressionSource::Unsubscribe,
+        reason: "unsubscribed via List-Unsubscribe".to_string(),
+        created,
+        expires,
+    })
+    .await
+}
+
+/// Configure the suppression list, loading any persisted entries
+pub async fn configure(params: SuppressionParams) -> anyhow::Result<()> {
+    if config::is_validating() {
+        return Ok(());
+    }
+    anyhow::ensure!(
+        !is_enabled(),
+        "the suppression list has already been configured"
+    );
+
+    // The connection is opened once here and shared by all
+    // subsequent updates
+    let path = params.path.clone();
+    let (db, entries) = get_main_runtime()
+        .spawn_blocking(move || {
+            let db = open_suppression_db(&path)?;
+            prune_entries(&db)?;
+            let entries = load_entries(&db)?;
+            Ok::<_, anyhow::Error>((db, entries))
+        })
+        .await??;
+
+    for (key, entry) in entries {
+        ENTRIES.insert(key, entry);
+    }
+    SUPPRESSION_ENTRY_COUNT.set(ENTRIES.len() as i64);
+
+    DB.set(Arc::new(db))
+        .map_err(|_| anyhow::anyhow!("the suppression list has already been configured"))?;
+    CONFIG
+        .set(params)
+        .map_err(|_| anyhow::anyhow!("the suppression list has already been configured"))?;
+
+    get_main_runtime().spawn(pruner());
+    Ok(())
+}
+
+fn get_db() -> anyhow::Result<Arc<ConnectionThreadSafe>> {
+    DB.get()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("the suppression list has not been configured"))
+}
+
+fn open_suppression_db(path: &str) -> anyhow::Result<ConnectionThreadSafe> {
+    let mut db = Connection::open_thread_safe(path)
+        .with_context(|| format!("opening suppression database {path}"))?;
+    db.set_busy_timeout(30_000)?;
+
+    let query = r#"
+CREATE TABLE IF NOT EXISTS suppression (
+    target text NOT NULL,
+    tenant text NOT NULL,
+    recipient text,
+    domain text,
+    entry_tenant text,
+    source text NOT NULL,
+    reason text NOT NULL,
+    created int NOT NULL,
+    expires int,
+    PRIMARY KEY (target, tenant)
+);
+
+CREATE INDEX IF NOT EXISTS suppression_expires ON suppression (expires);
+    "#;
+
+    db.execute(query)?;
+
+    Ok(db)
+}
+
+fn store_entry(
+    db: &ConnectionThreadSafe,
+    key: &SuppressionKey,
+    entry: &SuppressionV1Entry,
+) -> anyhow::Result<()> {
+    let mut insert = db
+        .prepare(
+            "INSERT OR REPLACE INTO suppression
+            (target, tenant, recipient, domain, entry_tenant, source, reason, created, expires)
+            VALUES ($target, $tenant, $recipient, $domain, $entry_tenant, $source, $reason,
+                $created, $expires)",
+        )
+        .context("prepare insert")?;
+    insert.bind(("$target", key.target.as_str()))?;
+    insert.bind(("$tenant", key.tenant.as_str()))?;
+    insert.bind(("$recipient", entry.recipient.as_deref()))?;
+    insert.bind(("$domain", entry.domain.as_deref()))?;
+    insert.bind(("$entry_tenant", entry.tenant.as_deref()))?;
+    insert.bind(("$source", entry.source.as_str()))?;
+    insert.bind(("$reason", entry.reason.as_str()))?;
+    insert.bind(("$created", entry.created.timestamp_micros()))?;
+    insert.bind((
+        "$expires",
+        entry.expires.map(|expires| expires.timestamp_micros()),
+    ))?;
+    insert.next()?;
+    Ok(())
+}
+
+fn load_entries(
+    db: &ConnectionThreadSafe,
+) -> anyhow::Result<Vec<(SuppressionKey, SuppressionV1Entry)>> {
+    let mut query = db
+        .prepare("SELECT * FROM suppression")
+        .context("prepare select")?;
+
+    let from_micros = |micros: i64| {
+        DateTime::<Utc>::from_timestamp_micros(micros)
+            .ok_or_else(|| anyhow::anyhow!("invalid timestamp {micros}"))
+    };
+
+    let mut entries = vec![];
+    while let State::Row = query.next()? {
+        let source: String = query.read("source")?;
+        let expires: Option<i64> = query.read("expires")?;
+        let key = SuppressionKey {
+            target: query.read("target")?,
+            tenant: query.read("tenant")?,
+        };
+        let entry = SuppressionV1Entry {
+            recipient: query.read("recipient")?,
+            domain: query.read("domain")?,
+            tenant: query.read("entry_tenant")?,
+            source: source.parse().map_err(|err: String| anyhow::anyhow!(err))?,
+            reason: query.read("reason")?,
+            created: from_micros(query.read("created")?)?,
+            expires: expires.map(from_micros).transpose()?,
+        };
+        entries.push((key, entry));
+    }
+    Ok(entries)
+}
+
+/// Remove expired entries from the database
+fn prune_entries(db: &ConnectionThreadSafe) -> anyhow::Result<()> {
+    let mut prune = db
+        .prepare("DELETE FROM suppression WHERE expires IS NOT NULL AND expires <= $now")
+        .context("prepare prune")?;
+    prune.bind(("$now", Utc::now().timestamp_micros()))?;
+    prune.next()?;
+    Ok(())
+}
+
+async fn pruner() {
+    let mut shutdown = ShutdownSubcription::get();
+    loop {
+        tokio::select! {
+            _ = shutdown.shutting_down() => {
+                break;
+            },
+            _ = tokio::time::sleep(PRUNE_INTERVAL) => {}
+        };
+
+        let now = Utc::now();
+        ENTRIES.retain(|_key, entry| !is_expired(entry, now));
+        SUPPRESSION_ENTRY_COUNT.set(ENTRIES.len() as i64);
+
+        let result = match get_db() {
+            Ok(db) => get_main_runtime()
+                .spawn_blocking(move || prune_entries(&db))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|result| result),
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            tracing::error!("Error pruning the suppression list: {err:#}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key() {
+        let key = SuppressionKey::new(Some("User@Example.com"), None, Some("Tenant")).unwrap();
+        assert_eq!(key.target, "user@example.com");
+        assert_eq!(key.tenant, "tenant");
+
+        let key = SuppressionKey::new(None, Some("example.com"), None).unwrap();
+        assert_eq!(key.target, "example.com");
+        assert_eq!(key.tenant, "");
+
+        assert!(SuppressionKey::new(None, None, None).is_err());
+        assert!(SuppressionKey::new(Some("user@example.com"), Some("example.com"), None).is_err());
+        assert!(SuppressionKey::new(Some("user"), None, None).is_err());
+        assert!(SuppressionKey::new(None, Some("user@example.com"), None).is_err());
+    }
+
+    #[test]
+    fn lookup_scopes() {
+        let now = Utc::now();
+        let entry = |recipient: Option<&str>, domain: Option<&str>, tenant: Option<&str>| {
+            SuppressionV1Entry {
+                recipient: recipient.map(|s| s.to_string()),
+                domain: domain.map(|s| s.to_string()),
+                tenant: tenant.map(|s| s.to_string()),
+                source: SuppressionSource::Admin,
+                reason: "test".to_string(),
+                created: now,
+                expires: None,
+            }
+        };
+        for e in [
+            entry(Some("global@lookup.example.com"), None, None),
+            entry(Some("scoped@lookup.example.com"), None, Some("tenant")),
+            entry(None, Some("domain.lookup.example.com"), None),
+            SuppressionV1Entry {
+                expires: Some(now - chrono::Duration::seconds(1)),
+                ..entry(Some("expired@lookup.example.com"), None, None)
+            },
+        ] {
+            ENTRIES.insert(SuppressionKey::for_entry(&e).unwrap(), e);
+        }
+
+        assert!(lookup("Global@lookup.example.com", None).is_some());
+        assert!(lookup("global@lookup.example.com", Some("tenant")).is_some());
+
+        assert!(lookup("scoped@lookup.example.com", None).is_none());
+        assert!(lookup("scoped@lookup.example.com", Some("other")).is_none());
+        assert!(lookup("scoped@lookup.example.com", Some("Tenant")).is_some());
+
+        assert!(lookup("anyone@domain.lookup.example.com", None).is_some());
+        assert!(lookup("anyone@lookup.example.com", None).is_none());
+
+        assert!(lookup("expired@lookup.example.com", None).is_none());
+    }
+}
@@ -0,0 +1,199 @@
+use anyhow::Context;
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use clap::{Parser, ValueEnum};
+use futures::StreamExt;
+use kumo_jsonl::LogTailerConfig;
+use kumo_log_types::JsonLogRecord;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use schema::LogSchema;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+mod schema;
+
+/// Convert KumoMTA logs into hourly partitioned Parquet files
+/// that can be queried by DuckDB, Spark and similar tools.
+///
+/// Each batch of log records is written to
+/// `OUTPUT/date=YYYY-MM-DD/hour=HH/part-UUID.parquet` based
+/// on the timestamp of each record, and the checkpoint is
+/// only advanced once those files have been written.
+#[derive(Parser, Debug)]
+#[command(about)]
+struct Opt {
+    /// Glob expression used to select matching log filenames.
+    #[arg(long, default_value = "*")]
+    pattern: String,
+
+    /// The name of the checkpoint file that will be stored
+    /// in the log directory
+    #[arg(long, default_value = "log-parquet-checkpoint")]
+    checkpoint: String,
+
+    /// The maximum number of log records to accumulate before
+    /// writing them out.  Larger values produce fewer, larger
+    /// parquet files.
+    #[arg(long, default_value = "100000")]
+    batch_size: usize,
+
+    /// Maximum time to wait for a partial batch to fill before
+    /// writing it out.  Accepts human-readable durations like "30s",
+    /// "5m".
+    #[arg(long, default_value = "1m", value_parser = humantime::parse_duration)]
+    batch_latency: Duration,
+
+    /// Add a `meta_NAME` column populated from the named
+    /// meta value of each record.  Can be specified multiple times.
+    #[arg(long = "meta", value_name = "NAME")]
+    meta: Vec<String>,
+
+    /// Add a `header_NAME` column populated from the named
+    /// header of each record.  Only headers that were captured
+    /// via the `headers` option of `kumo.configure_local_logs`
+    /// will be present in the logs.  Can be specified multiple times.
+    #[arg(long = "header", value_name = "NAME")]
+    headers: Vec<String>,
+
+    /// The compression codec to use for the parquet files
+    #[arg(long, value_enum, default_value = "zstd")]
+    compression: Codec,
+
+    /// Process the logs that are currently present and then
+    /// exit, rather than waiting for new logs to be written.
+    #[arg(long)]
+    oneshot: bool,
+
+    /// The directory which contains the logs
+    directory: Utf8PathBuf,
+
+    /// The directory into which the parquet files will be written
+    output: Utf8PathBuf,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Codec {
+    Zstd,
+    Snappy,
+    Uncompressed,
+}
+
+impl From<Codec> for Compression {
+    fn from(codec: Codec) -> Compression {
+        match codec {
+            Codec::Zstd => Compression::ZSTD(ZstdLevel::default()),
+            Codec::Snappy => Compression::SNAPPY,
+            Codec::Uncompressed => Compression::UNCOMPRESSED,
+        }
+    }
+}
+
+/// Write records to a parquet file in the appropriate hourly
+/// partition of the output directory.
+/// The data is written to a temporary file which is then renamed
+/// into place, so that a partially written file is never visible
+/// to a query engine.
+fn write_partition(
+    output: &Utf8Path,
+    hour: DateTime<Utc>,
+    schema: &LogSchema,
+    props: &WriterProperties,
+    records: &[JsonLogRecord],
+) -> anyhow::Result<Utf8PathBuf> {
+    let dir = output
+        .join(hour.format("date=%Y-%m-%d").to_string())
+        .join(hour.format("hour=%H").to_string());
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {dir}"))?;
+
+    let name = format!("part-{}.parquet", uuid::Uuid::new_v4().simple());
+    let temp_path = dir.join(format!(".{name}.tmp"));
+    let path = dir.join(name);
+
+    let batch = schema.to_record_batch(records)?;
+    let file =
+        std::fs::File::create(&temp_path).with_context(|| format!("creating {temp_path}"))?;
+    let mut writer = ArrowWriter::try_new(file, schema.schema(), Some(props.clone()))?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    std::fs::rename(&temp_path, &path)
+        .with_context(|| format!("renaming {temp_path} -> {path}"))?;
+
+    Ok(path)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opts = Opt::parse();
+
+    let schema = LogSchema::new(&opts.meta, &opts.headers);
+    let props = WriterProperties::builder()
+        .set_compression(opts.compression.into())
+        .build();
+
+    let tailer = LogTailerConfig::new(opts.directory.clone())
+        .pattern(&opts.pattern)
+        .max_batch_size(opts.batch_size)
+        .max_batch_latency(opts.batch_latency)
+        .checkpoint_name(&opts.checkpoint)
+        .build()
+        .await
+        .context("building log tailer")?;
+    let close_handle = tailer.close_handle();
+
+    tokio::pin!(tailer);
+
+    loop {
+        let next = if opts.oneshot {
+            // Once we have caught up and nothing new has arrived
+            // within the batch latency, we're done
+            match tokio::time::timeout(opts.batch_latency * 2, tailer.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    close_handle.close();
+                    break;
+                }
+            }
+        } else {
+            tailer.next().await
+        };
+
+        let Some(result) = next else {
+            break;
+        };
+        let mut batch = result?;
+
+        let mut by_hour: BTreeMap<DateTime<Utc>, Vec<JsonLogRecord>> = BTreeMap::new();
+        let mut skipped = 0;
+        for value in batch.records() {
+            match serde_json::from_value::<JsonLogRecord>(value.clone()) {
+                Ok(record) => {
+                    let hour = record.timestamp.duration_trunc(TimeDelta::hours(1))?;
+                    by_hour.entry(hour).or_default().push(record);
+                }
+                Err(err) => {
+                    skipped += 1;
+                    if skipped == 1 {
+                        eprintln!("Skipping record that failed to parse: {err:#}");
+                    }
+                }
+            }
+        }
+        if skipped > 1 {
+            eprintln!("Skipped {skipped} records in total that failed to parse");
+        }
+
+        for (hour, records) in &by_hour {
+            let path = tokio::task::block_in_place(|| {
+                write_partition(&opts.output, *hour, &schema, &props, records)
+            })?;
+            println!("Wrote {} records to {path}", records.len());
+        }
+
+        batch.commit()?;
+    }
+
+    Ok(())
+}
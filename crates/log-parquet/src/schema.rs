@@ -0,0 +1,316 @@
+use arrow_array::builder::{ListBuilder, StringBuilder};
+use arrow_array::{
+    ArrayRef, RecordBatch, StringArray, TimestampMicrosecondArray, UInt16Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use kumo_log_types::JsonLogRecord;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Describes the columnar layout used for the exported log records.
+///
+/// The fixed portion of the schema mirrors the fields of `JsonLogRecord`,
+/// with nested structures flattened into scalar columns so that the
+/// resulting files can be queried without any JSON functions.
+/// The `meta` and `headers` maps are open-ended, so only the keys
+/// explicitly listed by the operator are projected into their own
+/// `meta_<name>` and `header_<name>` columns.
+pub struct LogSchema {
+    schema: SchemaRef,
+    meta: Vec<String>,
+    headers: Vec<String>,
+}
+
+fn utf8(name: &str) -> Field {
+    Field::new(name, DataType::Utf8, true)
+}
+
+fn timestamp(name: &str) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        false,
+    )
+}
+
+impl LogSchema {
+    pub fn new(meta: &[String], headers: &[String]) -> Self {
+        let mut fields = vec![
+            Field::new("type", DataType::Utf8, false),
+            Field::new("id", DataType::Utf8, false),
+            Field::new("sender", DataType::Utf8, false),
+            Field::new(
+                "recipient",
+                DataType::List(Arc::new(Field::new_list_field(DataType::Utf8, true))),
+                false,
+            ),
+            Field::new("queue", DataType::Utf8, false),
+            Field::new("site", DataType::Utf8, false),
+            Field::new("size", DataType::UInt64, false),
+            Field::new("response_code", DataType::UInt16, false),
+            utf8("response_enhanced_code"),
+            Field::new("response_content", DataType::Utf8, false),
+            utf8("response_command"),
+            utf8("peer_address_name"),
+            utf8("peer_address_addr"),
+            timestamp("timestamp"),
+            timestamp("created"),
+            Field::new("num_attempts", DataType::UInt16, false),
+            Field::new("bounce_classification", DataType::Utf8, false),
+            utf8("bounce_classifier_rule"),
+            utf8("egress_pool"),
+            utf8("egress_source"),
+            utf8("source_address"),
+            utf8("delivery_protocol"),
+            utf8("reception_protocol"),
+            Field::new("nodeid", DataType::Utf8, false),
+            utf8("tls_cipher"),
+            utf8("tls_protocol_version"),
+            utf8("tls_peer_subject_name"),
+            utf8("provider_name"),
+            utf8("session_id"),
+            utf8("feedback_report"),
+            utf8("server_session"),
+            utf8("client_session"),
+        ];
+
+        for name in meta {
+            fields.push(utf8(&format!("meta_{name}")));
+        }
+        for name in headers {
+            fields.push(utf8(&format!("header_{name}")));
+        }
+
+        Self {
+            schema: Arc::new(Schema::new(fields)),
+            meta: meta.to_vec(),
+            headers: headers.to_vec(),
+        }
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Convert a set of log records into a RecordBatch that matches
+    /// the schema.
+    pub fn to_record_batch(&self, records: &[JsonLogRecord]) -> anyhow::Result<RecordBatch> {
+        fn strings<'a>(
+            records: &'a [JsonLogRecord],
+            f: impl Fn(&'a JsonLogRecord) -> Option<String>,
+        ) -> ArrayRef {
+            Arc::new(records.iter().map(f).collect::<StringArray>())
+        }
+
+        fn timestamps(records: &[JsonLogRecord], f: impl Fn(&JsonLogRecord) -> i64) -> ArrayRef {
+            Arc::new(
+                TimestampMicrosecondArray::from_iter_values(records.iter().map(f))
+                    .with_timezone("UTC"),
+            )
+        }
+
+        let mut recipient = ListBuilder::new(StringBuilder::new());
+        for record in records {
+            for r in &record.recipient {
+                recipient.values().append_value(r);
+            }
+            recipient.append(true);
+        }
+
+        let mut columns: Vec<ArrayRef> = vec![
+            strings(records, |r| Some(format!("{:?}", r.kind))),
+            strings(records, |r| Some(r.id.clone())),
+            strings(records, |r| Some(r.sender.clone())),
+            Arc::new(recipient.finish()),
+            strings(records, |r| Some(r.queue.clone())),
+            strings(records, |r| Some(r.site.clone())),
+            Arc::new(UInt64Array::from_iter_values(
+                records.iter().map(|r| r.size),
+            )),
+            Arc::new(UInt16Array::from_iter_values(
+                records.iter().map(|r| r.response.code),
+            )),
+            strings(records, |r| {
+                r.response
+                    .enhanced_code
+                    .as_ref()
+                    .map(|e| format!("{}.{}.{}", e.class, e.subject, e.detail))
+            }),
+            strings(records, |r| Some(r.response.content.clone())),
+            strings(records, |r| r.response.command.clone()),
+            strings(records, |r| r.peer_address.as_ref().map(|p| p.name.clone())),
+            strings(records, |r| {
+                r.peer_address.as_ref().map(|p| p.addr.to_string())
+            }),
+            timestamps(records, |r| r.timestamp.timestamp_micros()),
+            timestamps(records, |r| r.created.timestamp_micros()),
+            Arc::new(UInt16Array::from_iter_values(
+                records.iter().map(|r| r.num_attempts),
+            )),
+            strings(records, |r| {
+                Some(String::from(r.bounce_classification.clone()))
+            }),
+            strings(records, |r| r.bounce_classifier_rule.clone()),
+            strings(records, |r| r.egress_pool.clone()),
+            strings(records, |r| r.egress_source.clone()),
+            strings(records, |r| {
+                r.source_address.as_ref().map(|s| s.address.to_string())
+            }),
+            strings(records, |r| r.delivery_protocol.clone()),
+            strings(records, |r| r.reception_protocol.clone()),
+            strings(records, |r| Some(r.nodeid.to_string())),
+            strings(records, |r| r.tls_cipher.clone()),
+            strings(records, |r| r.tls_protocol_version.clone()),
+            strings(records, |r| {
+                r.tls_peer_subject_name.as_ref().map(|s| s.join(", "))
+            }),
+            strings(records, |r| r.provider_name.clone()),
+            strings(records, |r| r.session_id.map(|s| s.to_string())),
+            strings(records, |r| {
+                r.feedback_report
+                    .as_ref()
+                    .and_then(|f| serde_json::to_string(f).ok())
+            }),
+            strings(records, |r| {
+                r.server_session
+                    .as_ref()
+                    .and_then(|s| serde_json::to_string(s).ok())
+            }),
+            strings(records, |r| {
+                r.client_session
+                    .as_ref()
+                    .and_then(|s| serde_json::to_string(s).ok())
+            }),
+        ];
+
+        for name in &self.meta {
+            columns.push(strings(records, |r| value_to_string(r.meta.get(name))));
+        }
+        for name in &self.headers {
+            columns.push(strings(records, |r| {
+                value_to_string(r.headers.get(name).or_else(|| {
+                    r.headers
+                        .iter()
+                        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                        .map(|(_, v)| v)
+                }))
+            }));
+        }
+
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+/// String values are stored as-is; anything else is stored
+/// as its JSON representation so that it can still be
+/// parsed by the query engine if required.
+fn value_to_string(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::TimestampMicrosecondType;
+    use arrow_array::Array;
+    use kumo_log_types::{RecordType, SmtpServerSessionSummary};
+
+    const RECORD: &str = r#"{
+        "type": "Delivery",
+        "id": "1d98076abbbc11ed940250ebf67f93bd",
+        "sender": "noreply@example.com",
+        "recipient": "recipient@example.com",
+        "queue": "campaign:tenant@example.com",
+        "site": "source2->(alt1|alt2|alt3|alt4)?.gmail-smtp-in.l.google.com@smtp_client",
+        "size": 1047,
+        "response": {
+            "code": 250,
+            "enhanced_code": {"class": 2, "subject": 0, "detail": 0},
+            "content": "OK ok",
+            "command": "."
+        },
+        "peer_address": {"name": "gmail-smtp-in.l.google.com.", "addr": "142.251.2.27"},
+        "timestamp": 1678069691,
+        "created": 1678069690,
+        "num_attempts": 0,
+        "bounce_classification": "Uncategorized",
+        "egress_pool": "pool0",
+        "egress_source": "source2",
+        "feedback_report": null,
+        "meta": {"tenant": "mytenant", "campaign": 42},
+        "headers": {"Subject": "Hello"},
+        "delivery_protocol": "ESMTP",
+        "reception_protocol": "ESMTP",
+        "nodeid": "557f3ad4-2c8c-11ee-976e-782d7e12e173"
+    }"#;
+
+    #[test]
+    fn record_conversion() {
+        let record: JsonLogRecord = serde_json::from_str(RECORD).unwrap();
+        let schema = LogSchema::new(
+            &["tenant".to_string(), "campaign".to_string()],
+            &["subject".to_string()],
+        );
+        let batch = schema.to_record_batch(&[record]).unwrap();
+        assert_eq!(batch.num_rows(), 1);
+
+        let column = |name: &str| -> Option<String> {
+            let array = batch.column_by_name(name).unwrap().as_string::<i32>();
+            if array.is_null(0) {
+                None
+            } else {
+                Some(array.value(0).to_string())
+            }
+        };
+
+        assert_eq!(column("type").as_deref(), Some("Delivery"));
+        assert_eq!(column("response_enhanced_code").as_deref(), Some("2.0.0"));
+        assert_eq!(column("peer_address_addr").as_deref(), Some("142.251.2.27"));
+        assert_eq!(column("meta_tenant").as_deref(), Some("mytenant"));
+        assert_eq!(column("meta_campaign").as_deref(), Some("42"));
+        assert_eq!(column("header_subject").as_deref(), Some("Hello"));
+        assert_eq!(column("source_address"), None);
+        assert_eq!(column("feedback_report"), None);
+        assert_eq!(column("server_session"), None);
+        assert_eq!(column("client_session"), None);
+
+        let recipients = batch.column_by_name("recipient").unwrap().as_list::<i32>();
+        assert_eq!(
+            recipients.value(0).as_string::<i32>().value(0),
+            "recipient@example.com"
+        );
+
+        let timestamps = batch
+            .column_by_name("timestamp")
+            .unwrap()
+            .as_primitive::<TimestampMicrosecondType>();
+        assert_eq!(timestamps.value(0), 1678069691 * 1_000_000);
+    }
+
+    #[test]
+    fn session_summary() {
+        let mut record: JsonLogRecord = serde_json::from_str(RECORD).unwrap();
+        record.kind = RecordType::SmtpServerSession;
+        record.server_session = Some(Box::new(SmtpServerSessionSummary {
+            messages_accepted: 2,
+            ..Default::default()
+        }));
+        let schema = LogSchema::new(&[], &[]);
+        let batch = schema.to_record_batch(&[record]).unwrap();
+
+        let summary = batch
+            .column_by_name("server_session")
+            .unwrap()
+            .as_string::<i32>()
+            .value(0)
+            .to_string();
+        let summary: Value = serde_json::from_str(&summary).unwrap();
+        assert_eq!(summary["messages_accepted"], 2);
+        assert!(batch.column_by_name("client_session").unwrap().is_null(0));
+    }
+}
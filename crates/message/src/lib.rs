@@ -2,9 +2,12 @@ pub mod address;
 #[cfg(feature = "impl")]
 pub mod dkim;
 pub mod message;
+pub mod quarantine;
 pub mod queue_name;
 pub mod scheduling;
 pub mod timeq;
+#[cfg(feature = "impl")]
+pub mod verp;
 pub mod xfer;
 
 pub use crate::message::Message;
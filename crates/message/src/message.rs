@@ -748,6 +748,23 @@ impl Message {
         }
     }
 
+    /// Replace the envelope sender with a VERP address that encodes
+    /// the id and first recipient of this message
+    #[cfg(feature = "impl")]
+    pub async fn apply_verp(
+        &self,
+        scheme: &crate::verp::VerpScheme,
+    ) -> anyhow::Result<EnvelopeAddress> {
+        let data = crate::verp::VerpData {
+            id: Some(*self.id()),
+            recipient: Some(self.first_recipient().await?.to_string()),
+        };
+        let sender_domain = self.sender().await?.domain();
+        let sender = scheme.encode(&data, &sender_domain).await?;
+        self.set_sender(sender.clone()).await?;
+        Ok(sender)
+    }
+
     #[deprecated = "use recipient_list or first_recipient instead"]
     pub async fn recipient(&self) -> anyhow::Result<EnvelopeAddress> {
         self.first_recipient().await
@@ -1681,6 +1698,9 @@ impl UserData for Message {
             let value = this.get_meta(name).await.map_err(any_err)?;
             Ok(Some(lua.to_value_with(&value, serialize_options())?))
         });
+        methods.add_async_method("quarantine", move |_, this, reason: String| async move {
+            this.quarantine(reason).await.map_err(any_err)
+        });
         methods.add_async_method("get_data", |lua, this, _: ()| async move {
             let data = this.data().await.map_err(any_err)?;
             lua.create_string(&*data)
@@ -1741,6 +1761,15 @@ impl UserData for Message {
             },
         );
 
+        methods.add_async_method(
+            "apply_verp",
+            move |lua, this, scheme: mlua::Value| async move {
+                let scheme: crate::verp::VerpScheme = from_lua_value(&lua, scheme)?;
+                let sender = this.apply_verp(&scheme).await.map_err(any_err)?;
+                Ok(sender.to_string())
+            },
+        );
+
         methods.add_async_method("recipient", move |lua, this, _: ()| async move {
             let mut recipients = this.recipient_list().await.map_err(any_err)?;
             match recipients.len() {
@@ -0,0 +1,63 @@
+use crate::Message;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The meta key under which the quarantine state of a message
+/// is recorded. Because it is part of the metadata, it is persisted
+/// to the spool alongside the rest of the message and survives
+/// a restart.
+pub const QUARANTINE_META_KEY: &str = "quarantine";
+
+/// Describes why and when a message was placed into quarantine
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct QuarantineInfo {
+    pub reason: String,
+    pub since: DateTime<Utc>,
+}
+
+impl Message {
+    /// Mark the message as quarantined for the specified reason.
+    /// The message will be held by kumod rather than being
+    /// inserted into its scheduled queue the next time that
+    /// it is inserted.
+    pub async fn quarantine<S: Into<String>>(&self, reason: S) -> anyhow::Result<()> {
+        let info = QuarantineInfo {
+            reason: reason.into(),
+            since: Utc::now(),
+        };
+        self.set_meta(QUARANTINE_META_KEY, serde_json::to_value(&info)?)
+            .await
+    }
+
+    /// Returns the quarantine state of the message, if it has
+    /// been quarantined
+    pub async fn get_quarantine(&self) -> anyhow::Result<Option<QuarantineInfo>> {
+        match self.get_meta(QUARANTINE_META_KEY).await? {
+            serde_json::Value::Null => Ok(None),
+            value => Ok(Some(serde_json::from_value(value)?)),
+        }
+    }
+
+    /// Clear the quarantine state of the message
+    pub async fn release_quarantine(&self) -> anyhow::Result<()> {
+        self.unset_meta(QUARANTINE_META_KEY).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::message::test::new_msg_body;
+
+    #[tokio::test]
+    async fn quarantine_round_trip() {
+        let msg = new_msg_body("Subject: hello\r\n\r\nbody");
+        assert!(msg.get_quarantine().await.unwrap().is_none());
+
+        msg.quarantine("suspicious content").await.unwrap();
+        let info = msg.get_quarantine().await.unwrap().unwrap();
+        k9::assert_equal!(info.reason, "suspicious content");
+
+        msg.release_quarantine().await.unwrap();
+        assert!(msg.get_quarantine().await.unwrap().is_none());
+    }
+}
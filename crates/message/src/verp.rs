@@ -0,0 +1,482 @@
+//! Variable Envelope Return Path (VERP) addressing.
+//!
+//! A VERP address encodes the message id and the recipient of the
+//! original message into the local part of its envelope sender,
+//! so that a bounce sent to that address can be correlated with
+//! the original message:
+//!
+//! `bounce-1d98076abbbc11ed940250ebf67f93bd-user=example.com@bounce.example.com`
+//!
+//! When an hmac key is configured, a truncated signature of the
+//! encoded portion follows the prefix, so that bounces sent to
+//! forged addresses can be detected:
+//!
+//! `bounce-5f1e0c9a2b-1d98076abbbc11ed940250ebf67f93bd-user=example.com@bounce.example.com`
+use config::{any_err, from_lua_value, get_or_create_sub_module, serialize_options};
+use data_encoding::HEXLOWER;
+use data_loader::KeySource;
+use lruttl::declare_cache;
+use mlua::{Lua, LuaSerdeExt, Value};
+use rfc5321::parser::EnvelopeAddress;
+use serde::{Deserialize, Serialize};
+use spool::SpoolId;
+use std::sync::Arc;
+use std::time::Duration;
+
+declare_cache! {
+/// Caches VERP hmac keys based on their KeySource spec
+static KEY_CACHE: LruCacheWithTtl<KeySource, Arc<Vec<u8>>>::new("verp_key_cache", 128);
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct VerpScheme {
+    /// The local part of a VERP address starts with this prefix
+    #[serde(default = "VerpScheme::default_prefix")]
+    pub prefix: String,
+
+    /// Separates the prefix, signature, message id and the
+    /// encoded recipient
+    #[serde(default = "VerpScheme::default_separator")]
+    pub separator: String,
+
+    /// The domain of generated VERP addresses.  If not set,
+    /// the domain of the current envelope sender is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+
+    /// How to sign generated addresses
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hmac: Option<VerpHmac>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct VerpHmac {
+    /// The shared secret used to compute the signature
+    pub key: KeySource,
+
+    /// The digest algorithm; one of the algorithms supported
+    /// by the kumo.digest.hmac_XXX functions
+    #[serde(default = "VerpHmac::default_algorithm")]
+    pub algorithm: String,
+
+    /// The number of hex digits of the signature to include
+    /// in the address
+    #[serde(default = "VerpHmac::default_length")]
+    pub length: usize,
+
+    /// How long to cache the loaded key
+    #[serde(default = "VerpHmac::default_ttl", with = "duration_serde")]
+    pub ttl: Duration,
+}
+
+impl VerpHmac {
+    fn default_algorithm() -> String {
+        "sha256".to_string()
+    }
+
+    fn default_length() -> usize {
+        10
+    }
+
+    fn default_ttl() -> Duration {
+        Duration::from_secs(300)
+    }
+
+    async fn load_key(&self) -> anyhow::Result<Arc<Vec<u8>>> {
+        KEY_CACHE
+            .get_or_try_insert(&self.key, |_| self.ttl, async {
+                Ok::<Arc<Vec<u8>>, anyhow::Error>(Arc::new(self.key.get().await?))
+            })
+            .await
+            .map_err(|err| anyhow::anyhow!("loading VERP hmac key: {err:#}"))
+            .map(|lookup| lookup.item)
+    }
+
+    fn sign(&self, key: &[u8], payload: &str) -> anyhow::Result<String> {
+        // Some MTAs change the case of the local part, so the
+        // signature is computed over the lowercased payload
+        let signature = mod_digest::hmac_sign(
+            &self.algorithm,
+            key,
+            payload.to_ascii_lowercase().as_bytes(),
+        )?;
+        let mut signature = HEXLOWER.encode(&signature);
+        signature.truncate(self.length.max(1));
+        Ok(signature)
+    }
+
+    /// Check the signature of payload in constant time.  The signature
+    /// may have been truncated to an odd number of hex digits, so it is
+    /// decoded and compared one digit (nibble) at a time.
+    fn verify(&self, key: &[u8], payload: &str, signature: &str) -> anyhow::Result<bool> {
+        let Some(signature) = signature
+            .chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<u8>>>()
+        else {
+            return Ok(false);
+        };
+        let expected = mod_digest::hmac_sign(
+            &self.algorithm,
+            key,
+            payload.to_ascii_lowercase().as_bytes(),
+        )?;
+        let expected: Vec<u8> = expected
+            .iter()
+            .flat_map(|b| [b >> 4, b & 0xf])
+            .take(self.length.max(1))
+            .collect();
+        Ok(mod_digest::constant_time_eq(&signature, &expected))
+    }
+}
+
+impl Default for VerpScheme {
+    fn default() -> Self {
+        Self {
+            prefix: Self::default_prefix(),
+            separator: Self::default_separator(),
+            domain: None,
+            hmac: None,
+        }
+    }
+}
+
+/// The information recovered from a VERP address
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct VerpData {
+    pub id: Option<SpoolId>,
+    pub recipient: Option<String>,
+}
+
+impl VerpScheme {
+    fn default_prefix() -> String {
+        "bounce".to_string()
+    }
+
+    fn default_separator() -> String {
+        "-".to_string()
+    }
+
+    async fn load_key(&self) -> anyhow::Result<Option<Arc<Vec<u8>>>> {
+        match &self.hmac {
+            Some(hmac) => Ok(Some(hmac.load_key().await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Produce a VERP address that encodes the id and recipient.
+    /// `sender_domain` is used as the domain of the address if
+    /// the scheme doesn't specify one.
+    pub async fn encode(
+        &self,
+        data: &VerpData,
+        sender_domain: &str,
+    ) -> anyhow::Result<EnvelopeAddress> {
+        let key = self.load_key().await?;
+        self.encode_with_key(data, sender_domain, key.as_ref().map(|k| k.as_slice()))
+    }
+
+    fn encode_with_key(
+        &self,
+        data: &VerpData,
+        sender_domain: &str,
+        key: Option<&[u8]>,
+    ) -> anyhow::Result<EnvelopeAddress> {
+        anyhow::ensure!(
+            !self.separator.is_empty(),
+            "VERP separator must not be empty"
+        );
+
+        let mut fields = vec![];
+        if let Some(id) = &data.id {
+            fields.push(id.to_string());
+        }
+        if let Some(recipient) = &data.recipient {
+            let (local, domain) = recipient
+                .rsplit_once('@')
+                .ok_or_else(|| anyhow::anyhow!("invalid recipient {recipient}"))?;
+            fields.push(format!("{local}={domain}"));
+        }
+        anyhow::ensure!(
+            !fields.is_empty(),
+            "VERP address requires an id and/or a recipient"
+        );
+        let payload = fields.join(&self.separator);
+
+        let local_part = match (&self.hmac, key) {
+            (Some(hmac), Some(key)) => {
+                let signature = hmac.sign(key, &payload)?;
+                format!(
+                    "{}{sep}{signature}{sep}{payload}",
+                    self.prefix,
+                    sep = self.separator
+                )
+            }
+            (Some(_), None) => anyhow::bail!("VERP hmac key is not loaded"),
+            (None, _) => format!("{}{}{payload}", self.prefix, self.separator),
+        };
+
+        let domain = self.domain.as_deref().unwrap_or(sender_domain);
+        EnvelopeAddress::parse(&format!("{local_part}@{domain}"))
+    }
+
+    /// Decode the local part of a VERP address.
+    /// Returns Ok(None) if the local part doesn't use this scheme,
+    /// or an error if the scheme is signed and the signature is
+    /// missing or invalid.
+    pub async fn decode(&self, local_part: &str) -> anyhow::Result<Option<VerpData>> {
+        if self.strip_prefix(local_part).is_none() {
+            return Ok(None);
+        }
+        let key = self.load_key().await?;
+        self.decode_with_key(local_part, key.as_ref().map(|k| k.as_slice()))
+    }
+
+    fn strip_prefix<'a>(&self, local_part: &'a str) -> Option<&'a str> {
+        if self.separator.is_empty() {
+            return None;
+        }
+        let prefix_len = self.prefix.len();
+        if local_part.len() < prefix_len
+            || !local_part.is_char_boundary(prefix_len)
+            || !local_part[..prefix_len].eq_ignore_ascii_case(&self.prefix)
+        {
+            return None;
+        }
+        local_part[prefix_len..].strip_prefix(&self.separator)
+    }
+
+    fn decode_with_key(
+        &self,
+        local_part: &str,
+        key: Option<&[u8]>,
+    ) -> anyhow::Result<Option<VerpData>> {
+        let Some(mut remainder) = self.strip_prefix(local_part) else {
+            return Ok(None);
+        };
+
+        if let Some(hmac) = &self.hmac {
+            let key = key.ok_or_else(|| anyhow::anyhow!("VERP hmac key is not loaded"))?;
+            let (signature, payload) = remainder
+                .split_once(&self.separator)
+                .ok_or_else(|| anyhow::anyhow!("VERP address is not signed"))?;
+            anyhow::ensure!(
+                hmac.verify(key, payload, signature)?,
+                "VERP address signature is invalid"
+            );
+            remainder = payload;
+        }
+
+        let (id, encoded_recipient) = match remainder.split_once(&self.separator) {
+            Some((id, recip)) => match SpoolId::from_str(id) {
+                Some(id) => (Some(id), recip),
+                None => (None, remainder),
+            },
+            None => match SpoolId::from_str(remainder) {
+                Some(id) => (Some(id), ""),
+                None => (None, remainder),
+            },
+        };
+
+        let recipient = encoded_recipient
+            .rsplit_once('=')
+            .filter(|(local, domain)| !local.is_empty() && !domain.is_empty())
+            .map(|(local, domain)| format!("{local}@{domain}"));
+
+        if id.is_none() && recipient.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(VerpData { id, recipient }))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EncodeParams {
+    #[serde(default)]
+    id: Option<SpoolId>,
+    #[serde(default)]
+    recipient: Option<String>,
+    #[serde(default)]
+    domain: Option<String>,
+}
+
+pub fn register(lua: &Lua) -> anyhow::Result<()> {
+    let verp_mod = get_or_create_sub_module(lua, "verp")?;
+
+    verp_mod.set(
+        "encode",
+        lua.create_async_function(|lua, (scheme, params): (Value, Value)| async move {
+            let scheme: VerpScheme = from_lua_value(&lua, scheme)?;
+            let params: EncodeParams = from_lua_value(&lua, params)?;
+            let domain = params
+                .domain
+                .or_else(|| scheme.domain.clone())
+                .ok_or_else(|| mlua::Error::external("a domain is required"))?;
+            let data = VerpData {
+                id: params.id,
+                recipient: params.recipient,
+            };
+            let address = scheme.encode(&data, &domain).await.map_err(any_err)?;
+            Ok(address.to_string())
+        })?,
+    )?;
+
+    verp_mod.set(
+        "decode",
+        lua.create_async_function(|lua, (scheme, address): (Value, String)| async move {
+            let scheme: VerpScheme = from_lua_value(&lua, scheme)?;
+            let local_part = match address.rsplit_once('@') {
+                Some((local, _domain)) => local,
+                None => &address,
+            };
+            match scheme.decode(local_part).await.map_err(any_err)? {
+                Some(data) => lua.to_value_with(&data, serialize_options()),
+                None => Ok(Value::Nil),
+            }
+        })?,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ID: &str = "1d98076abbbc11ed940250ebf67f93bd";
+
+    #[test]
+    fn decode() {
+        let scheme = VerpScheme::default();
+        let decode = |local_part: &str| scheme.decode_with_key(local_part, None).unwrap();
+
+        let data = decode(&format!("bounce-{ID}-user=example.com")).unwrap();
+        assert_eq!(data.id.unwrap().to_string(), ID);
+        assert_eq!(data.recipient.as_deref(), Some("user@example.com"));
+
+        // The separator may appear in the recipient
+        let data = decode(&format!("Bounce-{ID}-first-last=example.com")).unwrap();
+        assert_eq!(data.recipient.as_deref(), Some("first-last@example.com"));
+
+        let data = decode("bounce-user=example.com").unwrap();
+        assert_eq!(data.id, None);
+        assert_eq!(data.recipient.as_deref(), Some("user@example.com"));
+
+        let data = decode(&format!("bounce-{ID}")).unwrap();
+        assert_eq!(data.id.unwrap().to_string(), ID);
+        assert_eq!(data.recipient, None);
+
+        assert_eq!(decode("postmaster"), None);
+        assert_eq!(decode("bounce-nothing"), None);
+        assert_eq!(decode("bouncer-user=example.com"), None);
+    }
+
+    #[test]
+    fn custom_scheme() {
+        let scheme = VerpScheme {
+            prefix: "b".to_string(),
+            separator: "+".to_string(),
+            ..VerpScheme::default()
+        };
+        let data = scheme
+            .decode_with_key(&format!("b+{ID}+user=example.com"), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.id.unwrap().to_string(), ID);
+        assert_eq!(data.recipient.as_deref(), Some("user@example.com"));
+        assert_eq!(
+            scheme
+                .decode_with_key(&format!("bounce-{ID}-user=example.com"), None)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let scheme = VerpScheme::default();
+        let data = VerpData {
+            id: SpoolId::from_str(ID),
+            recipient: Some("user@example.com".to_string()),
+        };
+        let address = scheme
+            .encode_with_key(&data, "bounce.example.com", None)
+            .unwrap();
+        assert_eq!(
+            address.to_string(),
+            format!("bounce-{ID}-user=example.com@bounce.example.com")
+        );
+        assert_eq!(
+            scheme.decode_with_key(&address.user(), None).unwrap(),
+            Some(data)
+        );
+    }
+
+    #[test]
+    fn signed() {
+        let scheme = VerpScheme {
+            domain: Some("bounce.example.com".to_string()),
+            hmac: Some(VerpHmac {
+                key: KeySource::Data {
+                    key_data: b"secret".to_vec(),
+                },
+                algorithm: "sha256".to_string(),
+                length: 10,
+                ttl: Duration::from_secs(60),
+            }),
+            ..VerpScheme::default()
+        };
+        let key = b"secret".as_slice();
+        let data = VerpData {
+            id: SpoolId::from_str(ID),
+            recipient: Some("User@example.com".to_string()),
+        };
+        let address = scheme
+            .encode_with_key(&data, "ignored.example.com", Some(key))
+            .unwrap();
+        assert_eq!(address.domain(), "bounce.example.com");
+
+        let local_part = address.user();
+        let (signature, _) = local_part
+            .strip_prefix("bounce-")
+            .unwrap()
+            .split_once('-')
+            .unwrap();
+        assert_eq!(signature.len(), 10);
+
+        assert_eq!(
+            scheme.decode_with_key(&local_part, Some(key)).unwrap(),
+            Some(data.clone())
+        );
+        // The signature is insensitive to the case of the local part
+        let upper = local_part.replacen(signature, &signature.to_ascii_uppercase(), 1);
+        assert_eq!(
+            scheme.decode_with_key(&upper, Some(key)).unwrap(),
+            Some(data)
+        );
+        let data = scheme
+            .decode_with_key(&local_part.to_ascii_lowercase(), Some(key))
+            .unwrap()
+            .unwrap();
+        assert_eq!(data.recipient.as_deref(), Some("user@example.com"));
+
+        // Tampering with the address invalidates the signature
+        let forged = local_part.replace("User=", "other=");
+        assert!(scheme.decode_with_key(&forged, Some(key)).is_err());
+        assert!(scheme
+            .decode_with_key("bounce-user=example.com", Some(key))
+            .is_err());
+        assert!(scheme.decode_with_key(&local_part, Some(b"wrong")).is_err());
+        let truncated = local_part.replacen(signature, &signature[..9], 1);
+        assert!(scheme.decode_with_key(&truncated, Some(key)).is_err());
+        let garbage = local_part.replacen(signature, "zzzzzzzzzz", 1);
+        assert!(scheme.decode_with_key(&garbage, Some(key)).is_err());
+
+        // Addresses that don't use the scheme are not an error
+        assert_eq!(scheme.decode_with_key("fbl", Some(key)).unwrap(), None);
+    }
+}
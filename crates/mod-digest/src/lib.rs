@@ -79,6 +79,48 @@ fn hmac_signer(
     Ok(BinaryResult(tag.as_ref().to_vec()))
 }
 
+/// Returns the HMAC algorithm corresponding to `name`, which is one of
+/// the suffixes of the `kumo.digest.hmac_XXX` functions, such as `sha256`
+pub fn hmac_algorithm_by_name(name: &str) -> Option<aws_lc_rs::hmac::Algorithm> {
+    match name {
+        "sha1" => Some(aws_lc_rs::hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY),
+        "sha224" => Some(aws_lc_rs::hmac::HMAC_SHA224),
+        "sha256" => Some(aws_lc_rs::hmac::HMAC_SHA256),
+        "sha384" => Some(aws_lc_rs::hmac::HMAC_SHA384),
+        "sha512" => Some(aws_lc_rs::hmac::HMAC_SHA512),
+        _ => None,
+    }
+}
+
+/// Computes the HMAC of `msg` using `key` and the algorithm named `name`,
+/// as accepted by [hmac_algorithm_by_name]
+pub fn hmac_sign(name: &str, key: &[u8], msg: &[u8]) -> anyhow::Result<Vec<u8>> {
+    use aws_lc_rs::hmac::Key;
+
+    let algo = hmac_algorithm_by_name(name)
+        .ok_or_else(|| anyhow::anyhow!("unsupported hmac algorithm `{name}`"))?;
+    let key = Key::new(algo, key);
+    Ok(aws_lc_rs::hmac::sign(&key, msg).as_ref().to_vec())
+}
+
+/// Returns true if `tag` is the HMAC of `msg` using `key` and the
+/// algorithm named `name`, as produced by [hmac_sign].
+/// The comparison is performed in constant time.
+pub fn hmac_verify(name: &str, key: &[u8], msg: &[u8], tag: &[u8]) -> anyhow::Result<bool> {
+    use aws_lc_rs::hmac::Key;
+
+    let algo = hmac_algorithm_by_name(name)
+        .ok_or_else(|| anyhow::anyhow!("unsupported hmac algorithm `{name}`"))?;
+    let key = Key::new(algo, key);
+    Ok(aws_lc_rs::hmac::verify(&key, msg, tag).is_ok())
+}
+
+/// Compares a and b in an amount of time that depends only on their
+/// lengths, for use when checking signatures
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    aws_lc_rs::constant_time::verify_slices_are_equal(a, b).is_ok()
+}
+
 pub fn register(lua: &Lua) -> anyhow::Result<()> {
     let digest_mod = get_or_create_sub_module(lua, "digest")?;
 
@@ -166,6 +166,7 @@ async fn run(opts: Opt) -> anyhow::Result<()> {
             diag_format: opts.diag_format,
             filter_env_var: "KUMO_PROXY_LOG",
             default_filter: "proxy_server=info,kumo_server_common=info,kumo_server_runtime=info",
+            otlp: None,
         },
         lua_funcs: &[kumo_server_common::register, mod_proxy::register],
         policy: &policy_path,
@@ -5,6 +5,7 @@ use crate::{AsyncReadAndWrite, BoxedAsyncReadAndWrite};
 use bstr::ByteSlice;
 use hickory_proto::rr::rdata::TLSA;
 use hickory_proto::rr::Name;
+use kumo_prometheus::declare_metric;
 use memchr::memmem::Finder;
 use nom_utils::DomainString;
 use openssl::x509::{X509Ref, X509};
@@ -27,6 +28,22 @@ pub use tokio_rustls;
 
 const MAX_LINE_LEN: usize = 4096;
 
+declare_metric! {
+/// how long the SMTP client waited for the response to a command,
+/// in seconds, by command verb.
+///
+/// {{since('dev')}}
+///
+/// The time is measured from the point at which the client starts to
+/// wait for the response, which is after the command has been written.
+/// When commands are pipelined, the response times for the later
+/// commands in the batch include the time spent waiting for the
+/// responses to the earlier commands.
+static COMMAND_LATENCY: HistogramVec(
+        "smtp_client_command_response_latency",
+        &["command"]);
+}
+
 #[derive(Error, Debug, Clone)]
 pub enum ClientError {
     #[error("response is not UTF8")]
@@ -432,7 +449,8 @@ impl SmtpClient {
         command: Option<&Command>,
         timeout_duration: Duration,
     ) -> Result<Response, ClientError> {
-        let deadline = Instant::now() + timeout_duration;
+        let start = Instant::now();
+        let deadline = start + timeout_duration;
 
         if let Some(sock) = self.socket.as_mut() {
             match timeout_at(deadline, sock.flush()).await {
@@ -488,6 +506,12 @@ impl SmtpClient {
 
         tracing::trace!("{}: {response:?}", self.hostname);
 
+        if let Some(command) = command {
+            COMMAND_LATENCY
+                .with_label_values(&[command.verb()])
+                .observe(start.elapsed().as_secs_f64());
+        }
+
         Ok(response)
     }
 
@@ -1031,6 +1031,31 @@ impl Command {
         .parse(input)
     }
 
+    /// Returns the verb for the command, such as `"MAIL"` for
+    /// `MAIL FROM`, suitable for use in metrics and logs.
+    /// Unrecognized commands are reported as `"UNKNOWN"`.
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Self::Ehlo(_) => "EHLO",
+            Self::Helo(_) => "HELO",
+            Self::Lhlo(_) => "LHLO",
+            Self::Noop(_) => "NOOP",
+            Self::Help(_) => "HELP",
+            Self::Vrfy(_) => "VRFY",
+            Self::Expn(_) => "EXPN",
+            Self::Data => "DATA",
+            Self::DataDot => ".",
+            Self::Rset => "RSET",
+            Self::Quit => "QUIT",
+            Self::StartTls => "STARTTLS",
+            Self::MailFrom { .. } => "MAIL",
+            Self::RcptTo { .. } => "RCPT",
+            Self::Auth { .. } => "AUTH",
+            Self::XClient(_) => "XCLIENT",
+            Self::Unknown(_) => "UNKNOWN",
+        }
+    }
+
     /// Re-encode the command as a single line of text ready to send on the
     /// wire, including the trailing `\r\n`.
     ///
@@ -1,9 +1,11 @@
 use chrono::Utc;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use human_bytes::human_bytes;
+use spool::local_disk::LocalDiskSpool;
+use spool::migrate::migrate_spool;
 use spool::rocks::RocksSpool;
 use spool::{Spool, SpoolEntry};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::runtime::Handle;
 
 /// KumoMTA Spool Utility
@@ -13,10 +15,12 @@ use tokio::runtime::Handle;
 /// and has the spool open.
 #[derive(Debug, Parser)]
 struct Opt {
+    /// Path to the RocksDB meta spool. Required by meta-size.
     #[arg(long)]
-    meta: PathBuf,
+    meta: Option<PathBuf>,
+    /// Path to the RocksDB data spool. Required by data-size.
     #[arg(long)]
-    data: PathBuf,
+    data: Option<PathBuf>,
 
     #[command(subcommand)]
     cmd: SubCommand,
@@ -26,6 +30,50 @@ struct Opt {
 enum SubCommand {
     MetaSize,
     DataSize,
+    /// Copy the contents of one spool into another, preserving
+    /// the spool ids. The copy of each entry is verified by
+    /// comparing checksums. Entries that are already present in
+    /// the destination are skipped, so an interrupted migration
+    /// can be resumed by running the same command again.
+    ///
+    /// The meta and data spools must be migrated separately.
+    Migrate {
+        /// Path to the spool to copy from
+        #[arg(long)]
+        src: PathBuf,
+        /// The type of the source spool
+        #[arg(long, value_enum)]
+        src_kind: SpoolKind,
+        /// Path to the spool to copy into. It will be created
+        /// if it does not already exist.
+        #[arg(long)]
+        dest: PathBuf,
+        /// The type of the destination spool
+        #[arg(long, value_enum)]
+        dest_kind: SpoolKind,
+        /// Remove each entry from the source once it has been
+        /// copied and verified
+        #[arg(long)]
+        remove_source: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SpoolKind {
+    LocalDisk,
+    RocksDb,
+}
+
+fn open_spool(path: &Path, kind: SpoolKind) -> anyhow::Result<Box<dyn Spool>> {
+    Ok(match kind {
+        SpoolKind::LocalDisk => Box::new(LocalDiskSpool::new(path, false, Handle::current())?),
+        SpoolKind::RocksDb => Box::new(RocksSpool::new(path, false, None, Handle::current())?),
+    })
+}
+
+fn required<'a>(path: &'a Option<PathBuf>, name: &str) -> anyhow::Result<&'a Path> {
+    path.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("--{name} is required for this command"))
 }
 
 async fn show_size_stats(label: &str, spool: &dyn Spool) -> anyhow::Result<()> {
@@ -82,16 +130,47 @@ async fn show_size_stats(label: &str, spool: &dyn Spool) -> anyhow::Result<()> {
 async fn main() -> anyhow::Result<()> {
     let opts = Opt::parse();
 
-    let meta_spool = RocksSpool::new(&opts.meta, false, None, Handle::current())?;
-    let data_spool = RocksSpool::new(&opts.data, false, None, Handle::current())?;
-
     match opts.cmd {
         SubCommand::MetaSize => {
+            let meta_spool = RocksSpool::new(
+                required(&opts.meta, "meta")?,
+                false,
+                None,
+                Handle::current(),
+            )?;
             show_size_stats("meta", &meta_spool).await?;
         }
         SubCommand::DataSize => {
+            let data_spool = RocksSpool::new(
+                required(&opts.data, "data")?,
+                false,
+                None,
+                Handle::current(),
+            )?;
             show_size_stats("data", &data_spool).await?;
         }
+        SubCommand::Migrate {
+            src,
+            src_kind,
+            dest,
+            dest_kind,
+            remove_source,
+        } => {
+            let source = open_spool(&src, src_kind)?;
+            let destination = open_spool(&dest, dest_kind)?;
+            let start = std::time::Instant::now();
+            eprintln!("migrating...");
+            let stats = migrate_spool(&*source, &*destination, remove_source).await?;
+            destination.shutdown().await?;
+            source.shutdown().await?;
+
+            println!("migration completed in {:?}", start.elapsed());
+            println!("copied = {}", stats.copied);
+            println!("skipped = {}", stats.skipped);
+            println!("corrupt = {}", stats.corrupt);
+            println!("removed = {}", stats.removed);
+            println!("bytes = {}", human_bytes(stats.bytes as f64));
+        }
     }
 
     Ok(())
@@ -5,6 +5,7 @@ use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
 
 pub mod local_disk;
+pub mod migrate;
 #[cfg(feature = "rocksdb")]
 pub mod rocks;
 pub mod spool_id;
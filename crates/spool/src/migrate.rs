@@ -0,0 +1,165 @@
+use crate::{Spool, SpoolEntry, SpoolId};
+use chrono::Utc;
+use std::sync::Arc;
+
+/// Summarizes the outcome of a call to [migrate_spool]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MigrationStats {
+    /// The number of entries that were copied into the destination
+    pub copied: usize,
+    /// The number of entries that were already present in the
+    /// destination with a matching checksum, most likely because
+    /// a prior migration was interrupted
+    pub skipped: usize,
+    /// The number of corrupt entries that could not be read from
+    /// the source. They are left in place in the source.
+    pub corrupt: usize,
+    /// The number of entries that were removed from the source
+    pub removed: usize,
+    /// The total size of the copied entries
+    pub bytes: usize,
+}
+
+/// Copy every entry from `source` into `dest`, preserving the SpoolId
+/// of each entry.
+///
+/// After each entry is written to `dest`, it is read back and its
+/// checksum compared against the checksum of the source data; a
+/// mismatch aborts the migration with an error.
+///
+/// Entries that are already present in `dest` with a matching checksum
+/// are not re-written, which allows an interrupted migration to resume
+/// by simply running it again.
+///
+/// If `remove_source` is true, the destination writes are synced to
+/// storage and each migrated entry is removed from `source` once
+/// enumeration of `source` is complete.
+///
+/// Neither spool may be in use by anything else for the duration
+/// of the migration.
+pub async fn migrate_spool(
+    source: &dyn Spool,
+    dest: &dyn Spool,
+    remove_source: bool,
+) -> anyhow::Result<MigrationStats> {
+    let mut stats = MigrationStats::default();
+    let mut migrated: Vec<SpoolId> = vec![];
+
+    let (tx, rx) = flume::bounded(1024);
+    source.enumerate(tx, Utc::now())?;
+
+    while let Ok(entry) = rx.recv_async().await {
+        match entry {
+            SpoolEntry::Item { id, data } => {
+                let checksum = crc32fast::hash(&data);
+
+                let already_present = match dest.load(id).await {
+                    Ok(existing) => crc32fast::hash(&existing) == checksum,
+                    Err(_) => false,
+                };
+
+                if already_present {
+                    stats.skipped += 1;
+                } else {
+                    let len = data.len();
+                    dest.store(id, Arc::new(data.into_boxed_slice()), remove_source, None)
+                        .await?;
+
+                    let stored = dest.load(id).await?;
+                    let stored_checksum = crc32fast::hash(&stored);
+                    if stored_checksum != checksum {
+                        anyhow::bail!(
+                            "checksum mismatch for {id} after copying: \
+                             source={checksum:08x} dest={stored_checksum:08x}"
+                        );
+                    }
+                    stats.copied += 1;
+                    stats.bytes += len;
+                }
+
+                if remove_source {
+                    migrated.push(id);
+                }
+            }
+            SpoolEntry::Corrupt { id, error } => {
+                tracing::error!("spool migration: entry {id} is corrupt: {error}");
+                stats.corrupt += 1;
+            }
+        }
+    }
+
+    // Removal is deferred until enumeration is complete, because
+    // the results of enumerating while modifying are undefined
+    for id in migrated {
+        source.remove(id).await?;
+        stats.removed += 1;
+    }
+
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::local_disk::LocalDiskSpool;
+    use tokio::runtime::Handle;
+
+    #[tokio::test]
+    async fn migrate_and_resume() -> anyhow::Result<()> {
+        let src_location = tempfile::tempdir()?;
+        let dest_location = tempfile::tempdir()?;
+        let source = LocalDiskSpool::new(src_location.path(), false, Handle::current())?;
+        let dest = LocalDiskSpool::new(dest_location.path(), false, Handle::current())?;
+
+        let mut ids = vec![];
+        for i in 0..20 {
+            let id = SpoolId::new();
+            source
+                .store(
+                    id,
+                    Arc::new(format!("I am {i}").as_bytes().to_vec().into_boxed_slice()),
+                    false,
+                    None,
+                )
+                .await?;
+            ids.push(id);
+        }
+
+        // Simulate a prior, interrupted, migration
+        for id in &ids[0..5] {
+            let data = source.load(*id).await?;
+            dest.store(*id, Arc::new(data.into_boxed_slice()), false, None)
+                .await?;
+        }
+
+        let stats = migrate_spool(&source, &dest, true).await?;
+        assert_eq!(
+            stats,
+            MigrationStats {
+                copied: 15,
+                skipped: 5,
+                corrupt: 0,
+                removed: 20,
+                bytes: ids[5..]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, _)| format!("I am {}", i + 5).len())
+                    .sum(),
+            }
+        );
+
+        for (i, id) in ids.iter().enumerate() {
+            assert_eq!(
+                String::from_utf8(dest.load(*id).await?)?,
+                format!("I am {i}")
+            );
+            assert!(source.load(*id).await.is_err());
+        }
+
+        // Running again is a no-op, as the source is now empty
+        let stats = migrate_spool(&source, &dest, true).await?;
+        assert_eq!(stats, MigrationStats::default());
+
+        Ok(())
+    }
+}
@@ -90,6 +90,21 @@ CREATE TABLE IF NOT EXISTS sched_q_suspensions (
     PRIMARY KEY (rule_hash, campaign, tenant, domain)
 );
 
+CREATE TABLE IF NOT EXISTS adaptive_rates (
+    rule_hash text,
+    site_name text,
+    domain text,
+    mx_rollup bool,
+    source text,
+    name text,
+    floor integer,
+    ceiling integer,
+    value integer,
+    updated DATETIME,
+    expires DATETIME,
+    PRIMARY KEY (rule_hash, site_name)
+);
+
 CREATE TABLE IF NOT EXISTS sched_q_bounces (
     rule_hash text,
     campaign text,
@@ -1,9 +1,11 @@
 use crate::database::Database;
 use crate::publish::submit_record;
+use crate::replication::replicate_record;
 use crate::shaping_config::get_shaping;
 use crate::state::{
-    ActionHash, ConfigurationOverride, MatchingScope, ReadyQSuspensionEntry, SchedQBounceEntry,
-    SchedQBounceKey, SchedQSuspensionEntry, SchedQSuspensionKey, TsaState, TSA_STATE,
+    ActionHash, AdaptiveRateState, ConfigurationOverride, MatchingScope, ReadyQSuspensionEntry,
+    SchedQBounceEntry, SchedQBounceKey, SchedQSuspensionEntry, SchedQSuspensionKey, TsaState,
+    TSA_STATE,
 };
 use anyhow::anyhow;
 use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
@@ -46,8 +48,10 @@ pub fn make_router() -> RouterAndDocs {
         handlers = [
             get_bounce_v1,
             get_config_v1,
+            get_state_v1,
             get_suspension_v1,
             publish_log_v1,
+            replicate_log_v1,
             subscribe_event_v1,
             subscribe_suspension_v1,
             tsa_status,
@@ -291,6 +295,10 @@ async fn publish_log_v1_impl(
     events: &mut Vec<SubscriptionItem>,
 ) -> anyhow::Result<()> {
     tracing::trace!("got record: {record:?}");
+
+    consider_warmup_slowdown(now, shaping, &record).await?;
+    apply_adaptive_rates(shaping, &record).await?;
+
     // Extract the domain from the recipient.
     for recip in &record.recipient {
         let recipient = ForwardPath::try_from(recip.as_str())
@@ -443,6 +451,10 @@ async fn publish_log_v1_impl(
                             )
                             .await?;
                         }
+                        Action::AdaptiveRate(_) => {
+                            // Handled by apply_adaptive_rates, which needs
+                            // to see every attempt, not just matches
+                        }
                     }
                 }
             }
@@ -452,6 +464,97 @@ async fn publish_log_v1_impl(
     Ok(())
 }
 
+/// Track the deferral ratio for egress paths that have a warm-up
+/// schedule with a max_deferral_ratio, and slow down the schedule
+/// when that ratio is exceeded
+async fn consider_warmup_slowdown(
+    now: &DateTime<Utc>,
+    shaping: &Shaping,
+    record: &JsonLogRecord,
+) -> anyhow::Result<()> {
+    let deferred = match record.kind {
+        RecordType::Delivery => false,
+        RecordType::TransientFailure => true,
+        _ => return Ok(()),
+    };
+
+    let Some(schedule) = shaping.get_warmup_schedule(record).await? else {
+        return Ok(());
+    };
+    let Some(max_deferral_ratio) = schedule.max_deferral_ratio else {
+        return Ok(());
+    };
+
+    let state = TSA_STATE.get().expect("tsa_state missing");
+    let counts = state.record_warmup_outcome(record, deferred, &schedule);
+    if counts.attempts < schedule.min_deferral_samples || counts.ratio() <= max_deferral_ratio {
+        return Ok(());
+    }
+
+    let expires = record.timestamp + chrono::Duration::from_std(schedule.deferral_window)?;
+    if expires <= *now {
+        return Ok(());
+    }
+
+    let Some(domain) = record_domain(record)? else {
+        return Ok(());
+    };
+    let source = record.egress_source.as_deref().unwrap_or("unspecified");
+
+    tracing::debug!("warm-up slowdown {counts:?} for {record:?}");
+    state.create_warmup_slowdown(record, &domain, source, counts, expires);
+
+    Ok(())
+}
+
+/// Feed the outcome of a delivery attempt to any AdaptiveRate
+/// controllers that apply to its egress path
+async fn apply_adaptive_rates(shaping: &Shaping, record: &JsonLogRecord) -> anyhow::Result<()> {
+    let matches = shaping.match_adaptive_rules(record).await?;
+    if matches.is_empty() {
+        return Ok(());
+    }
+
+    let Some(domain) = record_domain(record)? else {
+        return Ok(());
+    };
+    let source = record.egress_source.as_deref().unwrap_or("unspecified");
+    let state = TSA_STATE.get().expect("tsa_state missing");
+
+    for m in &matches {
+        for action in &m.rule.action {
+            if let Action::AdaptiveRate(spec) = action {
+                let action_hash = ActionHash::from_rule_and_record(&m.rule, action, record);
+                state.record_adaptive_outcome(
+                    &action_hash,
+                    &m.rule,
+                    spec,
+                    record,
+                    &domain,
+                    source,
+                    m.is_deferral,
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the domain of the first recipient of the record,
+/// or None if it is the local postmaster address
+fn record_domain(record: &JsonLogRecord) -> anyhow::Result<Option<String>> {
+    let Some(recip) = record.recipient.first() else {
+        return Ok(None);
+    };
+    match ForwardPath::try_from(recip.as_str())
+        .map_err(|err| anyhow!("parsing record.recipient: {err}"))?
+    {
+        ForwardPath::Postmaster => Ok(None),
+        ForwardPath::Path(path) => Ok(Some(path.mailbox.domain.to_string())),
+    }
+}
+
 /// A helper for computing a hash of a rust struct via the
 /// derived Hash trait
 pub struct Sha256Hasher {
@@ -499,6 +602,7 @@ async fn publish_log_v1(
     // Note: Json<> must be last in the param list
     Json(record): Json<JsonLogRecord>,
 ) -> Result<(), AppError> {
+    replicate_record(&record);
     submit_record(record).await.map_err(|err| {
         tracing::error!("while processing /publish_log_v1: {err:#}");
         let app_err: AppError = err.into();
@@ -506,6 +610,35 @@ async fn publish_log_v1(
     })
 }
 
+/// Accepts a batch of records that were originally published to
+/// a peer tsa-daemon instance.  The records are processed in the
+/// same way as those received via /publish_log_v1, but are not
+/// forwarded to any other peers.
+#[utoipa::path(post, path="/replicate_log_v1", request_body=Object)]
+async fn replicate_log_v1(
+    // Note: Json<> must be last in the param list
+    Json(records): Json<Vec<JsonLogRecord>>,
+) -> Result<(), AppError> {
+    for record in records {
+        submit_record(record).await.map_err(|err| {
+            tracing::error!("while processing /replicate_log_v1: {err:#}");
+            let app_err: AppError = err.into();
+            app_err
+        })?;
+    }
+    Ok(())
+}
+
+/// Returns a snapshot of the state of this instance, which is
+/// used to bootstrap a peer tsa-daemon instance when it starts up.
+#[utoipa::path(get, path = "/get_state_v1")]
+async fn get_state_v1() -> Result<Vec<u8>, AppError> {
+    let state = TSA_STATE
+        .get()
+        .ok_or_else(|| anyhow!("state not initialized"))?;
+    Ok(state.snapshot()?)
+}
+
 fn json_to_toml_value(item_value: &JsonValue) -> anyhow::Result<toml::Value> {
     Ok(match item_value {
         JsonValue::Bool(b) => toml::Value::Boolean(*b),
@@ -562,6 +695,103 @@ pub fn toml_to_toml_edit_value(v: toml::Value) -> toml_edit::Value {
     }
 }
 
+pub async fn import_adaptive_rates_from_sqlite(
+    database: &Database,
+    state: Arc<TsaState>,
+) -> anyhow::Result<()> {
+    database
+        .perform("import adaptive rates", move |db| {
+            let mut stmt = db.prepare(
+                "SELECT * from adaptive_rates where
+                                   unixepoch(expires) - unixepoch() > 0",
+            )?;
+
+            while let Ok(sqlite::State::Row) = stmt.next() {
+                let rule_hash: String = stmt.read("rule_hash")?;
+                let site_name: String = stmt.read("site_name")?;
+                let domain: String = stmt.read("domain")?;
+                let mx_rollup: i64 = stmt.read("mx_rollup")?;
+                let source: String = stmt.read("source")?;
+                let name: String = stmt.read("name")?;
+                let floor: i64 = stmt.read("floor")?;
+                let ceiling: i64 = stmt.read("ceiling")?;
+                let value: i64 = stmt.read("value")?;
+                let updated: String = stmt.read("updated")?;
+                let expires: String = stmt.read("expires")?;
+
+                state.insert_adaptive_rate(
+                    ActionHash::from_legacy_hash_and_site(&rule_hash, &site_name),
+                    AdaptiveRateState {
+                        domain,
+                        mx_rollup: mx_rollup != 0,
+                        source,
+                        name,
+                        floor: floor as u64,
+                        ceiling: ceiling as u64,
+                        value: value as u64,
+                        interval_start: Utc::now(),
+                        attempts: 0,
+                        deferrals: 0,
+                        last_ratio: None,
+                        updated: updated.parse()?,
+                        expires: expires.parse()?,
+                    },
+                );
+            }
+
+            Ok(())
+        })
+        .await
+}
+
+pub async fn save_adaptive_rates_to_sqlite(
+    database: &Database,
+    entries: Vec<(ActionHash, AdaptiveRateState)>,
+) -> anyhow::Result<()> {
+    database
+        .perform("save adaptive rates", move |db| {
+            db.execute("BEGIN")?;
+            let result = (|| -> anyhow::Result<()> {
+                db.execute("DELETE FROM adaptive_rates")?;
+                let mut stmt = db.prepare(
+                    "INSERT INTO adaptive_rates
+                    (rule_hash, site_name, domain, mx_rollup, source, name,
+                     floor, ceiling, value, updated, expires)
+                    VALUES ($rule_hash, $site_name, $domain, $mx_rollup, $source, $name,
+                     $floor, $ceiling, $value, $updated, $expires)",
+                )?;
+                for (scope, rate) in &entries {
+                    stmt.reset()?;
+                    stmt.bind(("$rule_hash", scope.hash_portion().as_str()))?;
+                    stmt.bind(("$site_name", scope.site_name()))?;
+                    stmt.bind(("$domain", rate.domain.as_str()))?;
+                    stmt.bind(("$mx_rollup", rate.mx_rollup as i64))?;
+                    stmt.bind(("$source", rate.source.as_str()))?;
+                    stmt.bind(("$name", rate.name.as_str()))?;
+                    stmt.bind(("$floor", rate.floor as i64))?;
+                    stmt.bind(("$ceiling", rate.ceiling as i64))?;
+                    stmt.bind(("$value", rate.value as i64))?;
+                    stmt.bind(("$updated", rate.updated.to_rfc3339().as_str()))?;
+                    stmt.bind(("$expires", rate.expires.to_rfc3339().as_str()))?;
+                    stmt.next()?;
+                }
+                Ok(())
+            })();
+
+            match result {
+                Ok(()) => {
+                    db.execute("COMMIT")?;
+                    Ok(())
+                }
+                Err(err) => {
+                    db.execute("ROLLBACK").ok();
+                    Err(err)
+                }
+            }
+        })
+        .await
+}
+
 pub async fn import_bounces_from_sqlite(
     database: &Database,
     state: Arc<TsaState>,
@@ -870,7 +1100,8 @@ pub async fn subscribe_event_v1(ws: WebSocketUpgrade) -> impl IntoResponse {
 }
 
 /// Simple health check endpoint for the TSA Daemon.
-/// Returns basic status information.
+/// Returns basic status information, followed by the state
+/// of any adaptive rate controllers.
 #[utoipa::path(
     get,
     tag = "status",
@@ -879,6 +1110,38 @@ pub async fn subscribe_event_v1(ws: WebSocketUpgrade) -> impl IntoResponse {
         (status = 200, description = "TSA is healthy", body = String)
     ),
 )]
-async fn tsa_status() -> &'static str {
-    "TSA Daemon OK"
+async fn tsa_status() -> String {
+    let mut status = "TSA Daemon OK\n".to_string();
+
+    let adaptive_rates = TSA_STATE
+        .get()
+        .map(|state| state.export_adaptive_rates())
+        .unwrap_or_default();
+    if !adaptive_rates.is_empty() {
+        status.push_str(&format!(
+            "\nAdaptive rate controllers: {}\n",
+            adaptive_rates.len()
+        ));
+        for (scope, rate) in adaptive_rates {
+            let ratio = match rate.last_ratio {
+                Some(ratio) => format!("{ratio:.3}"),
+                None => "-".to_string(),
+            };
+            status.push_str(&format!(
+                "  {} {}={} (floor={} ceiling={}) last_ratio={ratio} \
+                 pending={}/{} updated={} expires={}\n",
+                scope.site_name(),
+                rate.name,
+                rate.value,
+                rate.floor,
+                rate.ceiling,
+                rate.deferrals,
+                rate.attempts,
+                rate.updated.to_rfc3339(),
+                rate.expires.to_rfc3339(),
+            ));
+        }
+    }
+
+    status
 }
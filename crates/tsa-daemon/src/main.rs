@@ -12,6 +12,7 @@ mod database;
 mod http_server;
 mod mod_auto;
 mod publish;
+mod replication;
 mod shaping_config;
 mod state;
 
@@ -85,6 +86,7 @@ async fn perform_init() -> anyhow::Result<()> {
     config.put();
 
     crate::state::load_state().await?;
+    crate::replication::start_replication()?;
 
     spawn_shaping_updater()?;
     tokio::spawn(state_pruner());
@@ -107,6 +109,7 @@ async fn run(opts: Opt) -> anyhow::Result<()> {
             diag_format: opts.diag_format,
             filter_env_var: "KUMO_TSA_LOG",
             default_filter: "tsa_daemon=info,kumo_server_common=info,kumo_server_runtime=info",
+            otlp: None,
         },
         lua_funcs: &[kumo_server_common::register, mod_auto::register],
         policy: &opts.policy,
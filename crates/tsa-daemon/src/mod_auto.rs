@@ -18,6 +18,14 @@ pub fn register(lua: &Lua) -> anyhow::Result<()> {
         })?,
     )?;
 
+    tsa_mod.set(
+        "configure_replication",
+        lua.create_function(|lua, params: Value| {
+            let params = from_lua_value(lua, params)?;
+            crate::replication::configure_replication(params).map_err(any_err)
+        })?,
+    )?;
+
     tsa_mod.set(
         "configure_tsa_db_path",
         lua.create_function(|_lua, file_name: String| {
@@ -0,0 +1,362 @@
+use crate::state::TsaState;
+use anyhow::Context;
+use kumo_log_types::JsonLogRecord;
+use kumo_server_runtime::spawn;
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::sync::{LazyLock, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+/// The maximum number of records sent to a peer in a single request
+const BATCH_SIZE: usize = 1024;
+
+static CONFIG: LazyLock<Mutex<Option<ReplicationParams>>> = LazyLock::new(Mutex::default);
+static PEERS: OnceLock<Vec<Peer>> = OnceLock::new();
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ReplicationParams {
+    /// The base urls of the other tsa-daemon instances,
+    /// eg: `http://10.0.0.2:8008`
+    pub peers: Vec<String>,
+
+    /// How long to wait for a request to a peer to complete
+    #[serde(
+        default = "ReplicationParams::default_timeout",
+        with = "duration_serde"
+    )]
+    pub timeout: Duration,
+
+    /// How long to wait before retrying a failed request to a peer
+    #[serde(
+        default = "ReplicationParams::default_retry_interval",
+        with = "duration_serde"
+    )]
+    pub retry_interval: Duration,
+
+    /// The maximum number of records that can be waiting to be
+    /// sent to each peer.  Records beyond this limit are discarded.
+    #[serde(default = "ReplicationParams::default_max_backlog")]
+    pub max_backlog: usize,
+
+    /// Whether to fetch the state from a peer on startup
+    #[serde(default = "ReplicationParams::default_true")]
+    pub bootstrap: bool,
+
+    /// Whether to forward the records received from kumod
+    /// to the peers.  This should be disabled when kumod is
+    /// already publishing each record to every instance.
+    #[serde(default = "ReplicationParams::default_true")]
+    pub forward_records: bool,
+}
+
+impl ReplicationParams {
+    fn default_timeout() -> Duration {
+        Duration::from_secs(10)
+    }
+    fn default_retry_interval() -> Duration {
+        Duration::from_secs(5)
+    }
+    fn default_max_backlog() -> usize {
+        128 * 1024
+    }
+    fn default_true() -> bool {
+        true
+    }
+}
+
+struct Peer {
+    url: String,
+    tx: Sender<JsonLogRecord>,
+    last_overflow_warning: Mutex<Option<Instant>>,
+}
+
+pub fn configure_replication(params: ReplicationParams) -> anyhow::Result<()> {
+    if PEERS.get().is_some() {
+        anyhow::bail!("replication has already been started");
+    }
+    CONFIG.lock().replace(params);
+    Ok(())
+}
+
+fn get_config() -> Option<ReplicationParams> {
+    CONFIG
+        .lock()
+        .clone()
+        .filter(|config| !config.peers.is_empty())
+}
+
+fn make_client(config: &ReplicationParams) -> anyhow::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(config.timeout)
+        .build()
+        .context("failed to build http client")
+}
+
+/// Fetch the state from the first peer that responds,
+/// and merge it into state
+pub async fn bootstrap_state(state: &TsaState) {
+    let Some(config) = get_config() else {
+        return;
+    };
+    if !config.bootstrap {
+        return;
+    }
+    let client = match make_client(&config) {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!("{err:#}");
+            return;
+        }
+    };
+
+    for peer in &config.peers {
+        let url = format!("{peer}/get_state_v1");
+        match fetch_snapshot(&client, &url).await {
+            Ok(data) => match state.merge_snapshot(&data) {
+                Ok(num_merged) => {
+                    tracing::info!(
+                        "Merged {num_merged} entries from {} of state data from {url}",
+                        humansize::format_size(data.len(), humansize::DECIMAL)
+                    );
+                    return;
+                }
+                Err(err) => {
+                    tracing::warn!("Failed to merge state from {url}: {err:#}");
+                }
+            },
+            Err(err) => {
+                tracing::warn!("Failed to fetch state from {url}: {err:#}");
+            }
+        }
+    }
+
+    tracing::warn!("Unable to fetch state from any peer, proceeding with local state");
+}
+
+async fn fetch_snapshot(client: &reqwest::Client, url: &str) -> anyhow::Result<Vec<u8>> {
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Spawn the tasks that forward records to each of the configured peers
+pub fn start_replication() -> anyhow::Result<()> {
+    let Some(config) = get_config() else {
+        return Ok(());
+    };
+    if !config.forward_records {
+        return Ok(());
+    }
+    let client = make_client(&config)?;
+
+    let mut peers = vec![];
+    for url in &config.peers {
+        let (tx, rx) = channel(config.max_backlog.max(1));
+        spawn(
+            format!("replicate to {url}"),
+            run_forwarder(url.to_string(), rx, client.clone(), config.retry_interval),
+        )?;
+        peers.push(Peer {
+            url: url.to_string(),
+            tx,
+            last_overflow_warning: Mutex::new(None),
+        });
+    }
+
+    tracing::info!("Replicating records to {:?}", config.peers);
+    PEERS.set(peers).ok();
+    Ok(())
+}
+
+/// Queue a record that was received from kumod to be
+/// sent to each of the configured peers
+pub fn replicate_record(record: &JsonLogRecord) {
+    let Some(peers) = PEERS.get() else {
+        return;
+    };
+    for peer in peers {
+        peer.enqueue(record);
+    }
+}
+
+impl Peer {
+    /// Queue a record to be sent to this peer, discarding
+    /// it if the backlog for the peer is full
+    fn enqueue(&self, record: &JsonLogRecord) {
+        match self.tx.try_send(record.clone()) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                let mut last = self.last_overflow_warning.lock();
+                let should_warn = last
+                    .map(|when| when.elapsed() > Duration::from_secs(60))
+                    .unwrap_or(true);
+                if should_warn {
+                    tracing::warn!(
+                        "Replication backlog for {} is full; \
+                         discarding records until it catches up",
+                        self.url
+                    );
+                    last.replace(Instant::now());
+                }
+            }
+            Err(TrySendError::Closed(_)) => {
+                tracing::error!("Replication task for {} has stopped", self.url);
+            }
+        }
+    }
+}
+
+async fn run_forwarder(
+    url: String,
+    mut rx: Receiver<JsonLogRecord>,
+    client: reqwest::Client,
+    retry_interval: Duration,
+) {
+    let endpoint = format!("{url}/replicate_log_v1");
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+
+    while rx.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+        loop {
+            match send_batch(&client, &endpoint, &batch).await {
+                Ok(()) => {
+                    tracing::trace!("replicated {} records to {endpoint}", batch.len());
+                    batch.clear();
+                    break;
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "Failed to replicate {} records to {endpoint}: {err:#}. \
+                         Will retry in {retry_interval:?}",
+                        batch.len()
+                    );
+                    tokio::time::sleep(retry_interval).await;
+                }
+            }
+        }
+    }
+}
+
+async fn send_batch(
+    client: &reqwest::Client,
+    endpoint: &str,
+    batch: &[JsonLogRecord],
+) -> anyhow::Result<()> {
+    client
+        .post(endpoint)
+        .json(batch)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::extract::Extension;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::{Json, Router};
+    use axum_server::Handle;
+    use kumo_log_types::RecordType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn make_record(id: &str) -> JsonLogRecord {
+        JsonLogRecord {
+            id: id.to_string(),
+            ..JsonLogRecord::empty(RecordType::TransientFailure)
+        }
+    }
+
+    #[test]
+    fn enqueue_discards_when_full() {
+        let (tx, mut rx) = channel(1);
+        let peer = Peer {
+            url: "http://127.0.0.1:8008".to_string(),
+            tx,
+            last_overflow_warning: Mutex::new(None),
+        };
+
+        peer.enqueue(&make_record("1"));
+        assert!(peer.last_overflow_warning.lock().is_none());
+
+        peer.enqueue(&make_record("2"));
+        assert!(peer.last_overflow_warning.lock().is_some());
+
+        assert_eq!(rx.try_recv().unwrap().id, "1");
+        assert!(rx.try_recv().is_err());
+
+        // Once there is room again, records are queued
+        peer.enqueue(&make_record("3"));
+        assert_eq!(rx.try_recv().unwrap().id, "3");
+
+        // A stopped forwarder is not fatal
+        drop(rx);
+        peer.enqueue(&make_record("4"));
+    }
+
+    #[derive(Default)]
+    struct Received {
+        attempts: AtomicUsize,
+        ids: Mutex<Vec<String>>,
+    }
+
+    /// Stands in for a peer, failing the first request
+    /// and recording the records sent in subsequent requests
+    async fn replicate(
+        Extension(received): Extension<Arc<Received>>,
+        Json(batch): Json<Vec<JsonLogRecord>>,
+    ) -> StatusCode {
+        if received.attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+            return StatusCode::SERVICE_UNAVAILABLE;
+        }
+        received
+            .ids
+            .lock()
+            .extend(batch.into_iter().map(|record| record.id));
+        StatusCode::OK
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn forwarder_retries_failed_batch() {
+        let received = Arc::new(Received::default());
+        let app = Router::new()
+            .route("/replicate_log_v1", post(replicate))
+            .layer(Extension(Arc::clone(&received)));
+
+        let socket = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let handle = Handle::new();
+        let server = axum_server::from_tcp(socket);
+        let handle_copy = handle.clone();
+        tokio::spawn(async move {
+            server
+                .handle(handle_copy)
+                .serve(app.into_make_service())
+                .await
+                .unwrap();
+        });
+
+        let (tx, rx) = channel(16);
+        for id in ["1", "2", "3"] {
+            tx.send(make_record(id)).await.unwrap();
+        }
+        // The forwarder returns once the queue is drained and closed
+        drop(tx);
+
+        run_forwarder(
+            format!("http://{addr}"),
+            rx,
+            reqwest::Client::new(),
+            Duration::from_millis(10),
+        )
+        .await;
+        handle.shutdown();
+
+        assert_eq!(received.attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(*received.ids.lock(), vec!["1", "2", "3"]);
+    }
+}
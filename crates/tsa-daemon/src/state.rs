@@ -1,15 +1,17 @@
 use crate::http_server::{
-    import_bounces_from_sqlite, import_configs_from_sqlite, import_suspensions_from_sqlite,
-    open_history_db, regex_list_to_string, toml_to_toml_edit_value, PreferRollup, Sha256Hasher,
-    DB_PATH,
+    import_adaptive_rates_from_sqlite, import_bounces_from_sqlite, import_configs_from_sqlite,
+    import_suspensions_from_sqlite, open_history_db, regex_list_to_string,
+    save_adaptive_rates_to_sqlite, toml_to_toml_edit_value, PreferRollup, Sha256Hasher, DB_PATH,
 };
 use anyhow::Context;
 use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use kumo_api_types::shaping::{
-    Action, EgressPathConfigValue, EgressPathConfigValueUnchecked, Rule,
+    Action, AdaptiveRate, EgressPathConfigValue, EgressPathConfigValueUnchecked, Rule,
 };
 use kumo_api_types::tsa::{ReadyQSuspension, SchedQBounce, SchedQSuspension};
+use kumo_api_types::warmup::WarmupSchedule;
 use kumo_log_types::JsonLogRecord;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -86,6 +88,14 @@ impl ActionHash {
         Self(hasher.get_binary(), SiteKey::from_record(record))
     }
 
+    /// Produce the hash used to record a warm-up slowdown for the
+    /// egress path described by record
+    pub fn for_warmup_slowdown(record: &JsonLogRecord) -> Self {
+        let mut hasher = Sha256Hasher::new();
+        "warmup_slowdown".hash(&mut hasher);
+        Self(hasher.get_binary(), SiteKey::from_record(record))
+    }
+
     pub fn from_legacy_hash_and_site(hash: &str, site: &str) -> Self {
         let mut bytes = [0u8; 32];
         if let Err(err) = hex::decode_to_slice(hash, &mut bytes) {
@@ -153,6 +163,132 @@ impl EventData {
     }
 }
 
+/// Tracks delivery attempts and deferrals for an egress path
+/// that has a warm-up schedule, in one minute buckets
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct DeferralData {
+    /// The deferral window of the schedule, in seconds.
+    /// Used to determine how to prune
+    window: i64,
+    buckets: Vec<DeferralBucket>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct DeferralBucket {
+    minute: UnixTimeStamp,
+    attempts: u64,
+    deferrals: u64,
+}
+
+/// The number of delivery attempts and deferrals observed
+/// within the deferral window of a warm-up schedule
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeferralCounts {
+    pub attempts: u64,
+    pub deferrals: u64,
+}
+
+impl DeferralCounts {
+    pub fn ratio(&self) -> f64 {
+        if self.attempts == 0 {
+            0.0
+        } else {
+            self.deferrals as f64 / self.attempts as f64
+        }
+    }
+}
+
+impl DeferralData {
+    fn insert_and_count(
+        &mut self,
+        record: &JsonLogRecord,
+        deferred: bool,
+        now_ts: UnixTimeStamp,
+    ) -> DeferralCounts {
+        let minute = to_unix_ts(&record.timestamp) / 60 * 60;
+        let idx = match self.buckets.binary_search_by_key(&minute, |b| b.minute) {
+            Ok(idx) => idx,
+            Err(idx) => {
+                self.buckets.insert(
+                    idx,
+                    DeferralBucket {
+                        minute,
+                        attempts: 0,
+                        deferrals: 0,
+                    },
+                );
+                idx
+            }
+        };
+        let bucket = &mut self.buckets[idx];
+        bucket.attempts += 1;
+        if deferred {
+            bucket.deferrals += 1;
+        }
+
+        let oldest_permitted = now_ts - self.window;
+        self.buckets.retain(|b| b.minute + 60 > oldest_permitted);
+
+        let mut counts = DeferralCounts {
+            attempts: 0,
+            deferrals: 0,
+        };
+        for b in &self.buckets {
+            counts.attempts += b.attempts;
+            counts.deferrals += b.deferrals;
+        }
+        counts
+    }
+
+    fn is_prunable(&self, now_ts: UnixTimeStamp) -> bool {
+        self.buckets
+            .last()
+            .map(|b| b.minute + 60 <= now_ts - self.window)
+            .unwrap_or(true)
+    }
+}
+
+/// The state of an AdaptiveRate controller for a specific
+/// egress path
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdaptiveRateState {
+    pub domain: String,
+    pub mx_rollup: bool,
+    pub source: String,
+    /// The name of the controlled option
+    pub name: String,
+    pub floor: u64,
+    pub ceiling: u64,
+    /// The current value of the option
+    pub value: u64,
+    /// The start of the current evaluation interval
+    pub interval_start: DateTime<Utc>,
+    pub attempts: u64,
+    pub deferrals: u64,
+    /// The deferral ratio observed in the most recently
+    /// completed interval
+    pub last_ratio: Option<f64>,
+    /// When the value was last changed
+    pub updated: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+}
+
+impl AdaptiveRateState {
+    fn reason(&self) -> String {
+        match self.last_ratio {
+            Some(ratio) => format!(
+                "adaptive rate control: deferral ratio {ratio:.3}, \
+                 range {}-{}",
+                self.floor, self.ceiling
+            ),
+            None => format!(
+                "adaptive rate control: range {}-{}",
+                self.floor, self.ceiling
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigurationOverride {
     pub domain: String,
@@ -207,6 +343,8 @@ pub struct TsaState {
     schedq_bounces: DashMap<SchedQBounceKey, SchedQBounceEntry>,
     readyq_suspensions: DashMap<ActionHash, ReadyQSuspensionEntry>,
     schedq_suspensions: DashMap<SchedQSuspensionKey, SchedQSuspensionEntry>,
+    warmup_deferrals: DashMap<SiteKey, DeferralData>,
+    adaptive_rates: DashMap<ActionHash, AdaptiveRateState>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -221,6 +359,47 @@ struct SerializableState {
     readyq_suspensions: HashMap<ActionHash, ReadyQSuspensionEntry>,
     #[serde(default)]
     schedq_suspensions: HashMap<SchedQSuspensionKey, SchedQSuspensionEntry>,
+    #[serde(default)]
+    warmup_deferrals: HashMap<SiteKey, DeferralData>,
+}
+
+/// The state that is transferred from one replica to another
+/// when a replica starts up
+#[derive(Serialize, Deserialize)]
+struct StateSnapshot {
+    state: SerializableState,
+    #[serde(default)]
+    adaptive_rates: Vec<(ActionHash, AdaptiveRateState)>,
+}
+
+/// Merge entries into map. Entries that are not present in map are
+/// inserted, while existing entries are replaced only when
+/// `prefer_new(new, existing)` returns true.
+/// Returns the number of entries that were inserted or replaced.
+fn merge_entries<K, V>(
+    map: &DashMap<K, V>,
+    entries: impl IntoIterator<Item = (K, V)>,
+    prefer_new: impl Fn(&V, &V) -> bool,
+) -> usize
+where
+    K: Eq + Hash,
+{
+    let mut num_merged = 0;
+    for (key, value) in entries {
+        match map.entry(key) {
+            Entry::Occupied(mut existing) => {
+                if prefer_new(&value, existing.get()) {
+                    existing.insert(value);
+                    num_merged += 1;
+                }
+            }
+            Entry::Vacant(vacant) => {
+                vacant.insert(value);
+                num_merged += 1;
+            }
+        }
+    }
+    num_merged
 }
 
 impl TsaState {
@@ -238,6 +417,180 @@ impl TsaState {
         series.insert_and_count(record) as u64
     }
 
+    /// Record the outcome of a delivery attempt for an egress path
+    /// that has a warm-up schedule, and return the number of attempts
+    /// and deferrals within the deferral window of the schedule
+    pub fn record_warmup_outcome(
+        &self,
+        record: &JsonLogRecord,
+        deferred: bool,
+        schedule: &WarmupSchedule,
+    ) -> DeferralCounts {
+        let window = schedule.deferral_window.as_secs() as i64;
+        let mut data = self
+            .warmup_deferrals
+            .entry(SiteKey::from_record(record))
+            .or_insert_with(|| DeferralData {
+                window,
+                buckets: vec![],
+            });
+        // Respect changes to the schedule
+        data.window = window;
+
+        data.insert_and_count(record, deferred, to_unix_ts(&Utc::now()))
+    }
+
+    /// Record the outcome of a delivery attempt against the
+    /// AdaptiveRate controller identified by scope, adjusting its value
+    /// and publishing it as a configuration override at the end of
+    /// each evaluation interval.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record_adaptive_outcome(
+        &self,
+        scope: &ActionHash,
+        rule: &Rule,
+        spec: &AdaptiveRate,
+        record: &JsonLogRecord,
+        domain: &str,
+        source: &str,
+        is_deferral: bool,
+    ) {
+        let ts = record.timestamp;
+        let mut is_new = false;
+        let mut entry = self.adaptive_rates.entry(scope.clone()).or_insert_with(|| {
+            is_new = true;
+            AdaptiveRateState {
+                domain: domain.to_string(),
+                mx_rollup: rule.was_rollup,
+                source: source.to_string(),
+                name: spec.name.name().to_string(),
+                floor: spec.floor,
+                ceiling: spec.ceiling,
+                value: spec.ceiling,
+                interval_start: ts,
+                attempts: 0,
+                deferrals: 0,
+                last_ratio: None,
+                updated: ts,
+                expires: ts + rule.duration,
+            }
+        });
+
+        // Respect changes to the rule
+        entry.floor = spec.floor;
+        entry.ceiling = spec.ceiling;
+        entry.value = entry.value.clamp(spec.floor, spec.ceiling.max(spec.floor));
+
+        entry.attempts += 1;
+        if is_deferral {
+            entry.deferrals += 1;
+        }
+
+        let interval_elapsed = (ts - entry.interval_start)
+            .to_std()
+            .map(|elapsed| elapsed >= spec.interval)
+            .unwrap_or(false);
+        if !interval_elapsed && !is_new {
+            return;
+        }
+
+        if interval_elapsed {
+            if entry.attempts > 0 {
+                entry.last_ratio = Some(entry.deferrals as f64 / entry.attempts as f64);
+            }
+            if let Some(value) = spec.next_value(entry.value, entry.attempts, entry.deferrals) {
+                if value != entry.value {
+                    tracing::debug!(
+                        "adaptive {scope:?} {} {} -> {value} \
+                         ({} of {} attempts deferred)",
+                        entry.name,
+                        entry.value,
+                        entry.deferrals,
+                        entry.attempts
+                    );
+                    entry.value = value;
+                    entry.updated = ts;
+                }
+            }
+            entry.interval_start = ts;
+            entry.attempts = 0;
+            entry.deferrals = 0;
+        }
+        entry.expires = ts + rule.duration;
+
+        let over = ConfigurationOverride {
+            domain: entry.domain.clone(),
+            mx_rollup: entry.mx_rollup,
+            source: entry.source.clone(),
+            reason: entry.reason(),
+            option: EgressPathConfigValueUnchecked {
+                name: entry.name.clone(),
+                value: spec.config_value(entry.value),
+            },
+            expires: entry.expires,
+        };
+        drop(entry);
+        self.insert_config_override(scope.clone(), over);
+    }
+
+    pub fn insert_adaptive_rate(&self, scope: ActionHash, state: AdaptiveRateState) {
+        if Utc::now() >= state.expires {
+            // Skip already expired entry
+            return;
+        }
+
+        tracing::debug!("adaptive rate {scope:?} = {state:?}");
+        self.adaptive_rates.insert(scope, state);
+    }
+
+    /// Returns a snapshot of the AdaptiveRate controllers,
+    /// ordered by site
+    pub fn export_adaptive_rates(&self) -> Vec<(ActionHash, AdaptiveRateState)> {
+        let now = Utc::now();
+        let mut entries: Vec<_> = self
+            .adaptive_rates
+            .iter()
+            .filter(|entry| now < entry.value().expires)
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        entries.sort_by(|(a_key, a), (b_key, b)| {
+            (a_key.site_name(), &a.name).cmp(&(b_key.site_name(), &b.name))
+        });
+        entries
+    }
+
+    /// Slow down the warm-up schedule for the egress path described
+    /// by record, by setting `warmup_slowdown` until `expires`
+    pub fn create_warmup_slowdown(
+        &self,
+        record: &JsonLogRecord,
+        domain: &str,
+        source: &str,
+        counts: DeferralCounts,
+        expires: DateTime<Utc>,
+    ) {
+        self.insert_config_override(
+            ActionHash::for_warmup_slowdown(record),
+            ConfigurationOverride {
+                domain: domain.to_string(),
+                mx_rollup: true,
+                source: source.to_string(),
+                reason: format!(
+                    "warm-up deferral ratio {:.3} ({} of {} attempts) \
+                     exceeds max_deferral_ratio",
+                    counts.ratio(),
+                    counts.deferrals,
+                    counts.attempts
+                ),
+                option: EgressPathConfigValueUnchecked {
+                    name: "warmup_slowdown".to_string(),
+                    value: toml::Value::Boolean(true),
+                },
+                expires,
+            },
+        );
+    }
+
     pub fn create_config_override(
         &self,
         scope: &ActionHash,
@@ -489,13 +842,89 @@ impl TsaState {
                 .iter()
                 .map(|entry| (entry.key().clone(), entry.value().clone()))
                 .collect(),
+            warmup_deferrals: self
+                .warmup_deferrals
+                .iter()
+                .map(|entry| (entry.key().clone(), entry.value().clone()))
+                .collect(),
         }
     }
 
+    /// Produce a snapshot of the state, suitable for passing
+    /// to `merge_snapshot` on another replica
+    pub fn snapshot(&self) -> anyhow::Result<Vec<u8>> {
+        let snapshot = StateSnapshot {
+            state: self.serializable(),
+            adaptive_rates: self.export_adaptive_rates(),
+        };
+        rmp_serde::to_vec_named(&snapshot).context("failed to serialize snapshot")
+    }
+
+    /// Merge a snapshot produced by another replica into this state.
+    /// Entries that we don't have are taken from the snapshot, and
+    /// entries that we do have are replaced if the snapshot has a
+    /// copy that expires later.  Event history is only taken for
+    /// scopes that we haven't seen.
+    /// Returns the number of entries that were merged.
+    pub fn merge_snapshot(&self, data: &[u8]) -> anyhow::Result<usize> {
+        let snapshot: StateSnapshot =
+            rmp_serde::from_slice(data).context("failed to deserialize snapshot")?;
+        let now = Utc::now();
+        let StateSnapshot {
+            state,
+            adaptive_rates,
+        } = snapshot;
+
+        let mut num_merged = 0;
+        num_merged += merge_entries(&self.event_history, state.event_history, |_, _| false);
+        num_merged += merge_entries(&self.warmup_deferrals, state.warmup_deferrals, |_, _| false);
+        num_merged += merge_entries(
+            &self.config_overrides,
+            state
+                .config_overrides
+                .into_iter()
+                .filter(|(_, v)| v.expires > now),
+            |new, existing| new.expires > existing.expires,
+        );
+        num_merged += merge_entries(
+            &self.schedq_bounces,
+            state
+                .schedq_bounces
+                .into_iter()
+                .filter(|(_, v)| v.expires > now),
+            |new, existing| new.expires > existing.expires,
+        );
+        num_merged += merge_entries(
+            &self.readyq_suspensions,
+            state
+                .readyq_suspensions
+                .into_iter()
+                .filter(|(_, v)| v.expires > now),
+            |new, existing| new.expires > existing.expires,
+        );
+        num_merged += merge_entries(
+            &self.schedq_suspensions,
+            state
+                .schedq_suspensions
+                .into_iter()
+                .filter(|(_, v)| v.expires > now),
+            |new, existing| new.expires > existing.expires,
+        );
+        num_merged += merge_entries(
+            &self.adaptive_rates,
+            adaptive_rates.into_iter().filter(|(_, v)| v.expires > now),
+            |new, existing| new.updated > existing.updated,
+        );
+
+        Ok(num_merged)
+    }
+
     async fn prune(&self, verbose: bool) {
         let now = Utc::now();
         let now_ts = to_unix_ts(&now);
         self.prune_events(now_ts, verbose).await;
+        self.prune_warmup_deferrals(now_ts, verbose).await;
+        self.prune_adaptive_rates(&now, verbose).await;
         self.prune_config_overrides(&now, verbose).await;
         self.prune_readyq_suspensions(&now, verbose).await;
         self.prune_schedq_suspensions(&now, verbose).await;
@@ -662,6 +1091,82 @@ impl TsaState {
         );
     }
 
+    async fn prune_adaptive_rates(&self, now: &DateTime<Utc>, verbose: bool) {
+        let mut visited = 0;
+        let start = Instant::now();
+
+        let is_prunable = |state: &AdaptiveRateState| *now >= state.expires;
+
+        let keys_to_prune: Vec<ActionHash> = self
+            .adaptive_rates
+            .iter()
+            .filter_map(|entry| {
+                visited += 1;
+                if is_prunable(entry.value()) {
+                    Some(entry.key().clone())
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let mut num_pruned = 0;
+        for key in keys_to_prune {
+            let pruned = self
+                .adaptive_rates
+                .remove_if(&key, |_key, state| is_prunable(state))
+                .is_some();
+            if pruned {
+                num_pruned += 1;
+            }
+        }
+        if verbose && num_pruned > 0 {
+            tracing::info!("Pruned {num_pruned} adaptive_rates");
+        }
+        tracing::debug!(
+            "visited {visited} and pruned {num_pruned} \
+            adaptive_rates in {:?}",
+            start.elapsed()
+        );
+    }
+
+    async fn prune_warmup_deferrals(&self, now_ts: UnixTimeStamp, verbose: bool) {
+        let mut visited = 0;
+        let start = Instant::now();
+
+        let keys_to_prune: Vec<SiteKey> = self
+            .warmup_deferrals
+            .iter()
+            .filter_map(|entry| {
+                visited += 1;
+                if entry.value().is_prunable(now_ts) {
+                    Some(entry.key().clone())
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let mut num_pruned = 0;
+        for key in keys_to_prune {
+            let pruned = self
+                .warmup_deferrals
+                .remove_if(&key, |_key, data| data.is_prunable(now_ts))
+                .is_some();
+            if pruned {
+                num_pruned += 1;
+            }
+        }
+        if verbose && num_pruned > 0 {
+            tracing::info!("Pruned {num_pruned} warmup_deferrals entries");
+        }
+        tracing::debug!(
+            "visited {visited} and pruned {num_pruned} \
+            warmup_deferrals entries in {:?}",
+            start.elapsed()
+        );
+    }
+
     async fn prune_events(&self, now_ts: UnixTimeStamp, verbose: bool) {
         let mut visited = 0;
         let start = Instant::now();
@@ -739,6 +1244,9 @@ pub async fn load_state() -> anyhow::Result<()> {
                     for (key, value) in loaded.schedq_suspensions.into_iter() {
                         state.schedq_suspensions.insert(key, value);
                     }
+                    for (key, value) in loaded.warmup_deferrals.into_iter() {
+                        state.warmup_deferrals.insert(key, value);
+                    }
                     state.prune(true).await;
 
                     tracing::info!(
@@ -829,6 +1337,23 @@ pub async fn load_state() -> anyhow::Result<()> {
         }
     }
 
+    // The adaptive rate controllers are always persisted in sqlite
+    match open_history_db() {
+        Ok(database) => {
+            if let Err(err) =
+                import_adaptive_rates_from_sqlite(&database, import_holder.clone()).await
+            {
+                tracing::warn!(
+                    "Failed to import adaptive rate entries from sqlite: {err:#}. \
+                    Proceeding without them"
+                );
+            }
+        }
+        Err(err) => {
+            tracing::warn!("Failed to open database to import adaptive rate entries: {err:#}");
+        }
+    }
+
     let state = Arc::into_inner(import_holder).expect("only we hold a ref");
 
     let num_config_overrides = state.config_overrides.len();
@@ -836,25 +1361,40 @@ pub async fn load_state() -> anyhow::Result<()> {
     let num_schedq_suspensions = state.schedq_suspensions.len();
     let num_readyq_suspensions = state.readyq_suspensions.len();
     let num_events = state.event_history.len();
+    let num_adaptive_rates = state.adaptive_rates.len();
 
     tracing::info!(
         "State has {num_config_overrides} config overrides, \
         {num_schedq_bounces} schedq bounces, {num_schedq_suspensions} schedq suspensions, \
-        {num_readyq_suspensions} readyq suspensions, {num_events} events."
+        {num_readyq_suspensions} readyq suspensions, {num_events} events, \
+        {num_adaptive_rates} adaptive rates."
     );
 
+    crate::replication::bootstrap_state(&state).await;
+
     TSA_STATE.set(state).ok();
     Ok(())
 }
 
 pub async fn save_state(background: bool) -> anyhow::Result<()> {
     let start = Instant::now();
-    let state = TSA_STATE
-        .get()
-        .expect("state not initialized")
-        .serializable();
+    let tsa_state = TSA_STATE.get().expect("state not initialized");
+    let state = tsa_state.serializable();
+    let adaptive_rates = tsa_state.export_adaptive_rates();
     let extract = start.elapsed();
 
+    let num_adaptive_rates = adaptive_rates.len();
+    match open_history_db() {
+        Ok(database) => {
+            if let Err(err) = save_adaptive_rates_to_sqlite(&database, adaptive_rates).await {
+                tracing::error!("Failed to save adaptive rate entries to sqlite: {err:#}");
+            }
+        }
+        Err(err) => {
+            tracing::error!("Failed to open database to save adaptive rate entries: {err:#}");
+        }
+    }
+
     let data = rmp_serde::to_vec_named(&state).context("failed to serialize state")?;
     let path = state_path();
 
@@ -873,7 +1413,8 @@ pub async fn save_state(background: bool) -> anyhow::Result<()> {
     let message = format!(
         "stored {} of data to {path}. State has {num_config_overrides} config overrides, \
         {num_schedq_bounces} schedq bounces, {num_schedq_suspensions} schedq suspensions, \
-        {num_readyq_suspensions} readyq suspensions, {num_events} events. \
+        {num_readyq_suspensions} readyq suspensions, {num_events} events, \
+        {num_adaptive_rates} adaptive rates. \
         (Extract took {extract:?}, write took {write:?})",
         humansize::format_size(data.len(), humansize::DECIMAL)
     );
@@ -904,3 +1445,100 @@ pub async fn state_pruner() -> anyhow::Result<()> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeDelta;
+
+    #[test]
+    fn merge_entries_prefers_newer() {
+        let map: DashMap<&str, i64> = DashMap::new();
+        map.insert("a", 10);
+        map.insert("b", 10);
+
+        let num_merged = merge_entries(&map, [("a", 20), ("b", 5), ("c", 1)], |new, existing| {
+            new > existing
+        });
+        assert_eq!(num_merged, 2);
+        assert_eq!(*map.get("a").unwrap(), 20);
+        assert_eq!(*map.get("b").unwrap(), 10);
+        assert_eq!(*map.get("c").unwrap(), 1);
+
+        // Existing entries are never replaced when prefer_new is false
+        let num_merged = merge_entries(&map, [("a", 30), ("d", 1)], |_, _| false);
+        assert_eq!(num_merged, 1);
+        assert_eq!(*map.get("a").unwrap(), 20);
+        assert_eq!(*map.get("d").unwrap(), 1);
+    }
+
+    fn action_hash(site: &str) -> ActionHash {
+        ActionHash::from_legacy_hash_and_site(&"00".repeat(32), site)
+    }
+
+    fn suspension(reason: &str, expires: DateTime<Utc>) -> ReadyQSuspensionEntry {
+        ReadyQSuspensionEntry {
+            reason: reason.to_string(),
+            source: "source".to_string(),
+            expires,
+        }
+    }
+
+    #[test]
+    fn merge_snapshot() {
+        let now = Utc::now();
+        let hour = TimeDelta::hours(1);
+
+        let peer = TsaState::default();
+        peer.readyq_suspensions
+            .insert(action_hash("newer"), suspension("peer", now + hour * 2));
+        peer.readyq_suspensions
+            .insert(action_hash("older"), suspension("peer", now + hour));
+        peer.readyq_suspensions
+            .insert(action_hash("missing"), suspension("peer", now + hour));
+        peer.readyq_suspensions
+            .insert(action_hash("expired"), suspension("peer", now - hour));
+        peer.event_history.insert(
+            MatchingScope(RuleHash([1; 32]), SiteKey("site".to_string())),
+            EventData {
+                duration: 3600,
+                series: vec![to_unix_ts(&now)],
+            },
+        );
+
+        let local = TsaState::default();
+        local
+            .readyq_suspensions
+            .insert(action_hash("newer"), suspension("local", now + hour));
+        local
+            .readyq_suspensions
+            .insert(action_hash("older"), suspension("local", now + hour * 3));
+
+        let num_merged = local.merge_snapshot(&peer.snapshot().unwrap()).unwrap();
+        // newer, missing and the event history
+        assert_eq!(num_merged, 3);
+
+        let reason = |site: &str| {
+            local
+                .readyq_suspensions
+                .get(&action_hash(site))
+                .map(|entry| entry.reason.clone())
+        };
+        // The peer copy expires later than ours
+        assert_eq!(reason("newer").as_deref(), Some("peer"));
+        // Our copy expires later than the peer copy
+        assert_eq!(reason("older").as_deref(), Some("local"));
+        assert_eq!(reason("missing").as_deref(), Some("peer"));
+        assert_eq!(reason("expired"), None);
+        assert_eq!(local.event_history.len(), 1);
+
+        // Merging the same snapshot again changes nothing
+        assert_eq!(local.merge_snapshot(&peer.snapshot().unwrap()).unwrap(), 0);
+    }
+
+    #[test]
+    fn merge_snapshot_rejects_garbage() {
+        let state = TsaState::default();
+        assert!(state.merge_snapshot(b"not a snapshot").is_err());
+    }
+}
@@ -2,6 +2,9 @@ use clap::Parser;
 use human_bytes::human_bytes;
 use kumo_api_types::shaping::{CheckLevel, Shaping, ShapingMergeOptions};
 use kumo_server_memory::tracking::counted_usage;
+use std::path::PathBuf;
+
+mod simulate;
 
 /// KumoMTA shaping configuration validator
 ///
@@ -15,6 +18,12 @@ use kumo_server_memory::tracking::counted_usage;
 /// by the sources helper, then you should run `kumod --validate`
 /// instead of using this utility.
 ///
+/// When `--replay` is used, the automation rules are additionally
+/// evaluated against the records in the specified log segments,
+/// using the timestamps of the records rather than the current time,
+/// and a report of the rules that would have matched and the effects
+/// of their actions is printed.
+///
 /// Full docs available at: <https://docs.kumomta.com>
 #[derive(Debug, Parser)]
 #[command(about)]
@@ -67,6 +76,33 @@ struct Opt {
     /// Skip loading remote shaping URLs
     #[arg(long)]
     skip_remote: bool,
+
+    /// Replay the zstd compressed log segments in PATH through the
+    /// automation rules.  If PATH is a directory, all of the segments
+    /// that it contains are replayed in name order.
+    /// Can be specified multiple times.
+    #[arg(long, value_name = "PATH")]
+    replay: Vec<PathBuf>,
+
+    /// Output the replay report as JSON rather than as text
+    #[arg(long)]
+    replay_json: bool,
+}
+
+async fn replay(shaping: &Shaping, opts: &Opt) -> anyhow::Result<()> {
+    let mut sim = simulate::Simulation::default();
+    for path in simulate::expand_paths(&opts.replay)? {
+        if let Err(err) = sim.replay_file(shaping, &path).await {
+            eprintln!("WARNING: skipping {}: {err:#}", path.display());
+        }
+    }
+
+    if opts.replay_json {
+        println!("{}", serde_json::to_string_pretty(&sim)?);
+    } else {
+        print!("{}", sim.report());
+    }
+    Ok(())
 }
 
 #[tokio::main]
@@ -102,6 +138,13 @@ async fn main() {
             if !failed {
                 eprintln!("OK");
             }
+
+            if !failed && !opts.replay.is_empty() {
+                if let Err(err) = replay(&merged, &opts).await {
+                    eprintln!("{err:#}");
+                    failed = true;
+                }
+            }
         }
         Err(err) => {
             eprintln!("{err:#}");
@@ -0,0 +1,563 @@
+//! Replays historical log records through the automation rules of a
+//! shaping configuration, in order to predict how often those rules
+//! would have fired and what effects they would have had.
+//!
+//! The logic here mirrors that of the tsa-daemon, except that the
+//! timestamp of each record is used in place of the wall clock.
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use kumo_api_types::shaping::{Action, AdaptiveRate, Regex, Rule, Shaping, Trigger};
+use kumo_jsonl::decompress::FileDecompressor;
+use kumo_log_types::JsonLogRecord;
+use message::queue_name::QueueNameComponents;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// The period of time over which an action would have been in effect
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct Episode {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Episode {
+    fn duration(&self) -> TimeDelta {
+        self.end - self.start
+    }
+}
+
+/// The effects of an action for a specific scope, such as a site
+/// or a tenant and domain
+#[derive(Serialize, Debug, Default)]
+pub struct Effect {
+    /// The number of times that the action was triggered
+    pub triggers: usize,
+    /// The distinct periods during which the action was in effect.
+    /// Triggers that occur while the action is already in effect
+    /// extend the current episode, just as they would extend the
+    /// expiration of the suspension, bounce or override in
+    /// the tsa-daemon.
+    pub episodes: Vec<Episode>,
+}
+
+impl Effect {
+    fn trigger(&mut self, ts: DateTime<Utc>, duration: TimeDelta) {
+        self.triggers += 1;
+        let end = ts + duration;
+        match self.episodes.last_mut() {
+            Some(episode) if ts <= episode.end => {
+                episode.end = episode.end.max(end);
+            }
+            _ => self.episodes.push(Episode { start: ts, end }),
+        }
+    }
+
+    fn total_duration(&self) -> TimeDelta {
+        self.episodes
+            .iter()
+            .map(Episode::duration)
+            .fold(TimeDelta::zero(), |a, b| a + b)
+    }
+
+    fn longest(&self) -> TimeDelta {
+        self.episodes
+            .iter()
+            .map(Episode::duration)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// The simulated state of an AdaptiveRate controller for a site
+#[derive(Serialize, Debug)]
+pub struct AdaptiveEffect {
+    pub name: &'static str,
+    pub attempts: u64,
+    pub deferrals: u64,
+    /// The number of times that the value was changed
+    pub changes: usize,
+    pub min_value: u64,
+    pub final_value: u64,
+    #[serde(skip)]
+    interval_start: DateTime<Utc>,
+    #[serde(skip)]
+    interval_attempts: u64,
+    #[serde(skip)]
+    interval_deferrals: u64,
+}
+
+impl AdaptiveEffect {
+    fn new(spec: &AdaptiveRate, ts: DateTime<Utc>) -> Self {
+        Self {
+            name: spec.name.name(),
+            attempts: 0,
+            deferrals: 0,
+            changes: 0,
+            min_value: spec.ceiling,
+            final_value: spec.ceiling,
+            interval_start: ts,
+            interval_attempts: 0,
+            interval_deferrals: 0,
+        }
+    }
+
+    fn record(&mut self, spec: &AdaptiveRate, ts: DateTime<Utc>, is_deferral: bool) {
+        self.attempts += 1;
+        self.interval_attempts += 1;
+        if is_deferral {
+            self.deferrals += 1;
+            self.interval_deferrals += 1;
+        }
+
+        let interval_elapsed = (ts - self.interval_start)
+            .to_std()
+            .map(|elapsed| elapsed >= spec.interval)
+            .unwrap_or(false);
+        if !interval_elapsed {
+            return;
+        }
+
+        if let Some(value) = spec.next_value(
+            self.final_value,
+            self.interval_attempts,
+            self.interval_deferrals,
+        ) {
+            if value != self.final_value {
+                self.changes += 1;
+                self.final_value = value;
+                self.min_value = self.min_value.min(value);
+            }
+        }
+        self.interval_start = ts;
+        self.interval_attempts = 0;
+        self.interval_deferrals = 0;
+    }
+}
+
+/// The simulated outcome of a rule
+#[derive(Serialize, Debug, Default)]
+pub struct RuleReport {
+    /// The number of records that matched the regex of the rule
+    pub matches: usize,
+    /// The number of times that the trigger condition was satisfied
+    pub triggers: usize,
+    /// Keyed by action description, then by scope
+    pub actions: BTreeMap<String, BTreeMap<String, Effect>>,
+    /// Keyed by action description, then by site
+    pub adaptive: BTreeMap<String, BTreeMap<String, AdaptiveEffect>>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Simulation {
+    pub num_files: usize,
+    pub num_records: usize,
+    pub num_unparsed: usize,
+    pub first_record: Option<DateTime<Utc>>,
+    pub last_record: Option<DateTime<Utc>>,
+    /// Keyed by rule description
+    pub rules: BTreeMap<String, RuleReport>,
+    /// Timestamps of the matches for rules with Threshold triggers,
+    /// keyed by rule description and site
+    #[serde(skip)]
+    events: HashMap<(String, String), Vec<DateTime<Utc>>>,
+}
+
+fn regex_list_to_string(list: &[Regex]) -> String {
+    if list.len() == 1 {
+        list[0].to_string()
+    } else {
+        let list: Vec<String> = list.iter().map(|r| r.to_string()).collect();
+        format!("({})", list.join(","))
+    }
+}
+
+fn describe_rule(rule: &Rule) -> String {
+    let trigger = match &rule.trigger {
+        Trigger::Immediate => "Immediate".to_string(),
+        Trigger::Threshold(spec) => format!("Threshold={}", spec.as_string()),
+    };
+    let duration = TimeDelta::from_std(rule.duration).unwrap_or_default();
+    let mut result = format!(
+        "regex={} trigger={trigger} duration={}",
+        regex_list_to_string(&rule.regex),
+        format_duration(duration)
+    );
+    if rule.was_rollup {
+        result.push_str(" (mx_rollup)");
+    }
+    result
+}
+
+fn describe_action(action: &Action) -> String {
+    match action {
+        Action::SetConfig(config) => format!("SetConfig {}={}", config.name, &*config.value),
+        Action::SetDomainConfig(config) => {
+            format!("SetDomainConfig {}={}", config.name, &*config.value)
+        }
+        Action::AdaptiveRate(spec) => format!(
+            "AdaptiveRate {} {}-{}",
+            spec.name.name(),
+            spec.floor,
+            spec.ceiling
+        ),
+        other => format!("{other:?}"),
+    }
+}
+
+/// Determine the scope of the effects of an action, in the same way
+/// that the tsa-daemon keys its suspensions, bounces and overrides.
+/// Returns None if the action would have no effect for this record.
+fn action_scope(action: &Action, record: &JsonLogRecord) -> Option<String> {
+    let components = QueueNameComponents::parse(&record.queue);
+    let domain = components.domain;
+    match action {
+        Action::Suspend | Action::SetConfig(_) | Action::SetDomainConfig(_) => {
+            Some(format!("site={}", record.site))
+        }
+        Action::Bounce => Some(format!("domain={domain}")),
+        Action::SuspendTenant | Action::BounceTenant => {
+            let tenant = components.tenant?;
+            Some(format!("domain={domain} tenant={tenant}"))
+        }
+        Action::SuspendCampaign | Action::BounceCampaign => {
+            let tenant = components.tenant?;
+            match components.campaign {
+                Some(campaign) => Some(format!(
+                    "domain={domain} tenant={tenant} campaign={campaign}"
+                )),
+                None => Some(format!("domain={domain} tenant={tenant}")),
+            }
+        }
+        Action::AdaptiveRate(_) => None,
+    }
+}
+
+pub fn format_duration(duration: TimeDelta) -> String {
+    let mut secs = duration.num_seconds().max(0);
+    if secs == 0 {
+        return "0s".to_string();
+    }
+    let mut parts = vec![];
+    for (unit, size) in [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)] {
+        if secs >= size {
+            parts.push(format!("{}{unit}", secs / size));
+            secs %= size;
+        }
+    }
+    parts.join(" ")
+}
+
+/// Expand the list of paths into the list of log segments to replay.
+/// Directories are expanded to the files that they contain, in name
+/// order, which is also the chronological order of the segments.
+pub fn expand_paths(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut result = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let mut files = vec![];
+            for entry in
+                std::fs::read_dir(path).with_context(|| format!("reading {}", path.display()))?
+            {
+                let entry = entry?;
+                let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+                if entry.file_type()?.is_file() && !is_hidden {
+                    files.push(entry.path());
+                }
+            }
+            files.sort();
+            result.append(&mut files);
+        } else {
+            result.push(path.clone());
+        }
+    }
+    Ok(result)
+}
+
+impl Simulation {
+    /// Decompress the log segment at path and process each of
+    /// the records that it contains
+    pub async fn replay_file(&mut self, shaping: &Shaping, path: &Path) -> anyhow::Result<()> {
+        let mut decompressor = FileDecompressor::open(path)?;
+        self.num_files += 1;
+        while let Some(line) = decompressor
+            .next_line(0)
+            .with_context(|| format!("reading {}", path.display()))?
+        {
+            match serde_json::from_str::<JsonLogRecord>(&line.text) {
+                Ok(record) => self.process_record(shaping, &record).await?,
+                Err(_) => {
+                    self.num_unparsed += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn process_record(
+        &mut self,
+        shaping: &Shaping,
+        record: &JsonLogRecord,
+    ) -> anyhow::Result<()> {
+        self.num_records += 1;
+        let ts = record.timestamp;
+        if self.first_record.map(|first| ts < first).unwrap_or(true) {
+            self.first_record.replace(ts);
+        }
+        if self.last_record.map(|last| ts > last).unwrap_or(true) {
+            self.last_record.replace(ts);
+        }
+
+        for m in shaping.match_adaptive_rules(record).await? {
+            let report = self.rules.entry(describe_rule(&m.rule)).or_default();
+            for action in &m.rule.action {
+                if let Action::AdaptiveRate(spec) = action {
+                    report
+                        .adaptive
+                        .entry(describe_action(action))
+                        .or_default()
+                        .entry(record.site.to_string())
+                        .or_insert_with(|| AdaptiveEffect::new(spec, ts))
+                        .record(spec, ts, m.is_deferral);
+                }
+            }
+        }
+
+        for rule in shaping.match_rules(record).await? {
+            if rule.action.iter().all(Action::is_adaptive) {
+                // Already accounted for above
+                continue;
+            }
+
+            let key = describe_rule(&rule);
+            let duration = TimeDelta::from_std(rule.duration)?;
+
+            let triggered = match &rule.trigger {
+                Trigger::Immediate => true,
+                Trigger::Threshold(spec) => {
+                    let series = self
+                        .events
+                        .entry((key.clone(), record.site.to_string()))
+                        .or_default();
+                    let idx = match series.binary_search(&ts) {
+                        Ok(idx) | Err(idx) => idx,
+                    };
+                    series.insert(idx, ts);
+                    let report_thresh = ts - duration;
+                    series.retain(|&t| t >= report_thresh);
+                    series.len() as u64 >= spec.limit
+                }
+            };
+
+            let report = self.rules.entry(key).or_default();
+            report.matches += 1;
+            if !triggered {
+                continue;
+            }
+            report.triggers += 1;
+
+            for action in &rule.action {
+                let Some(scope) = action_scope(action, record) else {
+                    continue;
+                };
+                report
+                    .actions
+                    .entry(describe_action(action))
+                    .or_default()
+                    .entry(scope)
+                    .or_default()
+                    .trigger(ts, duration);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Produce a human readable report
+    pub fn report(&self) -> String {
+        let mut result = String::new();
+        let fmt_ts = |ts: Option<DateTime<Utc>>| {
+            ts.map(|ts| ts.to_rfc3339())
+                .unwrap_or_else(|| "-".to_string())
+        };
+
+        result.push_str(&format!(
+            "Replayed {} records from {} files, spanning {} to {}\n",
+            self.num_records,
+            self.num_files,
+            fmt_ts(self.first_record),
+            fmt_ts(self.last_record)
+        ));
+        if self.num_unparsed > 0 {
+            result.push_str(&format!(
+                "{} lines could not be parsed as log records\n",
+                self.num_unparsed
+            ));
+        }
+        if self.rules.is_empty() {
+            result.push_str("No automation rules matched any records\n");
+            return result;
+        }
+
+        for (rule, report) in &self.rules {
+            result.push_str(&format!("\nRule: {rule}\n"));
+            if report.matches > 0 {
+                result.push_str(&format!(
+                    "  {} matches, {} triggers\n",
+                    report.matches, report.triggers
+                ));
+            }
+            for (action, scopes) in &report.actions {
+                result.push_str(&format!("  {action}\n"));
+                for (scope, effect) in scopes {
+                    result.push_str(&format!(
+                        "    {scope}: {} triggers, {} episodes, \
+                         {} in effect, longest {}\n",
+                        effect.triggers,
+                        effect.episodes.len(),
+                        format_duration(effect.total_duration()),
+                        format_duration(effect.longest()),
+                    ));
+                }
+            }
+            for (action, sites) in &report.adaptive {
+                result.push_str(&format!("  {action}\n"));
+                for (site, effect) in sites {
+                    result.push_str(&format!(
+                        "    site={site}: {} attempts, {} deferrals, \
+                         {} changes, min {}={}, final {}={}\n",
+                        effect.attempts,
+                        effect.deferrals,
+                        effect.changes,
+                        effect.name,
+                        effect.min_value,
+                        effect.name,
+                        effect.final_value,
+                    ));
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use kumo_api_types::shaping::ShapingMergeOptions;
+    use kumo_log_types::RecordType;
+    use rfc5321::Response;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    async fn make_shaping(content: &str) -> Shaping {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        let name = file.path().to_str().unwrap().to_string();
+        Shaping::merge_files(&[name], &ShapingMergeOptions::default())
+            .await
+            .unwrap()
+    }
+
+    fn make_record(queue: &str, content: &str, ts: &str) -> JsonLogRecord {
+        JsonLogRecord {
+            recipient: vec!["user@woot.provider".to_string()],
+            queue: queue.to_string(),
+            site: "ip-1->dummy_site@smtp_client".to_string(),
+            response: Response {
+                code: 421,
+                command: None,
+                enhanced_code: None,
+                content: content.to_string(),
+            },
+            timestamp: ts.parse().unwrap(),
+            num_attempts: 1,
+            egress_source: Some("ip-1".to_string()),
+            ..JsonLogRecord::empty(RecordType::TransientFailure)
+        }
+    }
+
+    #[test]
+    fn effect_episodes() {
+        let ts = |s: &str| s.parse::<DateTime<Utc>>().unwrap();
+        let hour = TimeDelta::hours(1);
+        let mut effect = Effect::default();
+        effect.trigger(ts("2026-10-01T00:00:00Z"), hour);
+        effect.trigger(ts("2026-10-01T00:30:00Z"), hour);
+        effect.trigger(ts("2026-10-01T03:00:00Z"), hour);
+
+        k9::assert_equal!(effect.triggers, 3);
+        k9::assert_equal!(
+            effect.episodes,
+            vec![
+                Episode {
+                    start: ts("2026-10-01T00:00:00Z"),
+                    end: ts("2026-10-01T01:30:00Z"),
+                },
+                Episode {
+                    start: ts("2026-10-01T03:00:00Z"),
+                    end: ts("2026-10-01T04:00:00Z"),
+                },
+            ]
+        );
+        k9::assert_equal!(format_duration(effect.total_duration()), "2h 30m");
+        k9::assert_equal!(format_duration(effect.longest()), "1h 30m");
+    }
+
+    #[tokio::test]
+    async fn threshold_uses_record_timestamps() {
+        let shaping = make_shaping(
+            r#"
+[provider."provider"]
+match=[{DomainSuffix=".provider"}]
+
+[[provider."provider".automation]]
+regex = "try later"
+action = ["Suspend", "SuspendTenant"]
+trigger = {Threshold="2/hr"}
+duration = "1 hour"
+"#,
+        )
+        .await;
+
+        let mut sim = Simulation::default();
+        for ts in [
+            "2026-10-01T00:00:00Z",
+            // Second match within the hour: triggers
+            "2026-10-01T00:10:00Z",
+            // Too long after the prior matches to trigger
+            "2026-10-01T05:00:00Z",
+        ] {
+            sim.process_record(
+                &shaping,
+                &make_record("campaign:tenant@woot.provider", "try later", ts),
+            )
+            .await
+            .unwrap();
+        }
+        sim.process_record(
+            &shaping,
+            &make_record(
+                "campaign:tenant@woot.provider",
+                "other",
+                "2026-10-01T05:01:00Z",
+            ),
+        )
+        .await
+        .unwrap();
+
+        k9::assert_equal!(sim.num_records, 4);
+        k9::assert_equal!(sim.rules.len(), 1);
+        let report = sim.rules.values().next().unwrap();
+        k9::assert_equal!(report.matches, 3);
+        k9::assert_equal!(report.triggers, 1);
+
+        let suspend = &report.actions["Suspend"]["site=ip-1->dummy_site@smtp_client"];
+        k9::assert_equal!(suspend.triggers, 1);
+        k9::assert_equal!(format_duration(suspend.total_duration()), "1h");
+
+        let tenant = &report.actions["SuspendTenant"];
+        assert!(tenant.contains_key("domain=woot.provider tenant=tenant"));
+    }
+}
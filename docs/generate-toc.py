@@ -513,6 +513,10 @@ TOC = [
                 "module: kumo.nats",
                 "reference/kumo.nats",
             ),
+            Gen(
+                "module: kumo.list_unsubscribe",
+                "reference/kumo.list_unsubscribe",
+            ),
             Gen(
                 "module: kumo.mimepart",
                 "reference/kumo.mimepart",
@@ -545,6 +549,10 @@ TOC = [
                 "module: kumo.uuid",
                 "reference/kumo.uuid",
             ),
+            Gen(
+                "module: kumo.verp",
+                "reference/kumo.verp",
+            ),
             Gen(
                 "module: redis",
                 "reference/redis",